@@ -0,0 +1,45 @@
+//! Benchmarks of parsing fetched feeds, with the real-world feeds in `tests/feeds`.
+
+#![warn(clippy::pedantic)]
+#![allow(clippy::redundant_closure_for_method_calls)]
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/excerpt.rs"]
+mod excerpt;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/feed.rs"]
+mod feed;
+
+fn parse_feeds(c: &mut Criterion) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/feeds");
+    let mut feed_paths: Vec<_> = fs::read_dir(&dir)
+        .expect("failed to read corpus directory")
+        .map(|entry| entry.expect("failed to read corpus entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "xml"))
+        .collect();
+    feed_paths.sort();
+    let mut group = c.benchmark_group("parse");
+    for feed_path in feed_paths {
+        let body = fs::read(&feed_path).expect("failed to read feed");
+        let name = feed_path
+            .file_stem()
+            .expect("feed has no name")
+            .to_string_lossy();
+        group.bench_function(name.as_ref(), |b| {
+            b.iter(|| {
+                let feed = feed::Feed::read_from(Cursor::new(&body)).expect("failed to parse feed");
+                feed.items().collect::<Vec<_>>()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_feeds);
+criterion_main!(benches);
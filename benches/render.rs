@@ -0,0 +1,54 @@
+//! Benchmarks of rendering digests: compiling templates, rendering them, and inlining CSS.
+//!
+//! Digests are rendered with `mail_context.json`, the context of a digest of feeds in
+//! `tests/feeds`.
+
+#![warn(clippy::pedantic)]
+#![allow(clippy::redundant_closure_for_method_calls)]
+
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/bidi.rs"]
+mod bidi;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/css.rs"]
+mod css;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/templates.rs"]
+mod templates;
+
+fn render_digest(c: &mut Criterion) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let context: serde_json::Value =
+        serde_json::from_str(include_str!("mail_context.json")).expect("invalid context");
+    let context = tera::Context::from_value(context).expect("context is not an object");
+
+    // The built-in templates as custom templates, which are compiled on each load.
+    let template_dir = manifest_dir.join("resources/templates");
+    c.bench_function("compile templates", |b| {
+        b.iter(|| templates::load(Some(&template_dir), None).expect("failed to load templates"));
+    });
+    c.bench_function("load built-in templates", |b| {
+        b.iter(|| templates::load(None, None).expect("failed to load templates"));
+    });
+
+    let tera = templates::load(None, None).expect("failed to load templates");
+    c.bench_function("render digest", |b| {
+        b.iter(|| {
+            tera.render(templates::MAIL, &context)
+                .expect("failed to render digest")
+        });
+    });
+
+    let html = tera
+        .render(templates::MAIL, &context)
+        .expect("failed to render digest");
+    let inliner = css::Inliner::default();
+    c.bench_function("inline CSS", |b| b.iter(|| inliner.inline(&html)));
+}
+
+criterion_group!(benches, render_digest);
+criterion_main!(benches);
@@ -0,0 +1,57 @@
+//! Benchmarks of starting the binary, which loads the config, opens the database, and compiles
+//! the templates before doing anything else. Also reports the size of the binary.
+
+#![warn(clippy::pedantic)]
+#![allow(clippy::redundant_closure_for_method_calls)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use criterion::{criterion_group, Criterion};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_squeakmail");
+
+/// Run the binary with the config and database in a directory, failing if it fails.
+fn run(dir: &Path, args: &[&str]) {
+    let status = Command::new(BINARY)
+        .arg("--config")
+        .arg(dir.join("squeakmail.toml"))
+        .arg("--database")
+        .arg(dir.join("squeakmail.db"))
+        .arg("--cache")
+        .arg(dir.join("cache.db"))
+        .args(args)
+        .output()
+        .expect("failed to run binary")
+        .status;
+    assert!(status.success(), "squeakmail {:?} failed: {}", args, status);
+}
+
+fn startup(c: &mut Criterion) {
+    let dir: PathBuf =
+        std::env::temp_dir().join(format!("squeakmail-bench-{}", std::process::id()));
+    // Create the example config and the database, so they're only read while benchmarking.
+    run(&dir, &["stats"]);
+    c.bench_function("startup stats", |b| {
+        b.iter(|| run(&dir, &["--read-only", "stats"]));
+    });
+    c.bench_function("startup render", |b| {
+        b.iter(|| run(&dir, &["--read-only", "render"]));
+    });
+    std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+}
+
+fn binary_size() {
+    let size = std::fs::metadata(BINARY)
+        .expect("failed to read binary metadata")
+        .len();
+    println!("binary size: {size} bytes ({BINARY})");
+}
+
+criterion_group!(benches, startup);
+
+fn main() {
+    binary_size();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
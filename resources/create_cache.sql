@@ -0,0 +1,22 @@
+PRAGMA cache.user_version = 1;
+
+CREATE TABLE cache.validator (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    etag TEXT CHECK(TYPEOF(etag) = 'text' OR TYPEOF(etag) = 'null'),
+    last_modified TEXT CHECK(TYPEOF(last_modified) = 'text' OR TYPEOF(last_modified) = 'null'),
+    PRIMARY KEY (feed_url)
+);
+
+CREATE TABLE cache.fetch (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    fetched_at DATETIME CHECK(DATETIME(fetched_at) IS NOT NULL),
+    status INTEGER CHECK(TYPEOF(status) = 'integer'),
+    bytes INTEGER CHECK(TYPEOF(bytes) = 'integer'),
+    is_conditional BOOLEAN NOT NULL DEFAULT 0 CHECK(is_conditional = 0 OR is_conditional = 1),
+    etag TEXT CHECK(TYPEOF(etag) = 'text' OR TYPEOF(etag) = 'null'),
+    last_modified TEXT CHECK(TYPEOF(last_modified) = 'text' OR TYPEOF(last_modified) = 'null'),
+    body_hash TEXT CHECK(TYPEOF(body_hash) = 'text' OR TYPEOF(body_hash) = 'null'),
+    duration_ms INTEGER CHECK(TYPEOF(duration_ms) = 'integer' OR TYPEOF(duration_ms) = 'null')
+);
+
+CREATE INDEX cache.fetch_feed_url ON fetch(feed_url);
@@ -0,0 +1,11 @@
+PRAGMA cache.user_version = 10;
+
+CREATE TABLE cache.article (
+    url TEXT PRIMARY KEY CHECK(TYPEOF(url) = 'text'),
+    body TEXT CHECK(TYPEOF(body) = 'text'),
+    etag TEXT CHECK(TYPEOF(etag) = 'text' OR TYPEOF(etag) = 'null'),
+    last_modified TEXT CHECK(TYPEOF(last_modified) = 'text' OR TYPEOF(last_modified) = 'null'),
+    fetched_at DATETIME CHECK(DATETIME(fetched_at) IS NOT NULL)
+);
+
+CREATE INDEX cache.article_fetched_at ON article(fetched_at);
@@ -0,0 +1,9 @@
+PRAGMA cache.user_version = 11;
+
+CREATE TABLE cache.item_summary (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    guid TEXT CHECK(TYPEOF(guid) = 'text'),
+    text_hash TEXT CHECK(TYPEOF(text_hash) = 'text'),
+    summary TEXT CHECK(TYPEOF(summary) = 'text'),
+    PRIMARY KEY (feed_url, guid)
+);
@@ -0,0 +1,8 @@
+PRAGMA cache.user_version = 2;
+
+CREATE TABLE cache.link_check (
+    link TEXT CHECK(TYPEOF(link) = 'text'),
+    checked_at DATETIME CHECK(DATETIME(checked_at) IS NOT NULL),
+    status INTEGER CHECK(TYPEOF(status) = 'integer'),
+    PRIMARY KEY (link)
+);
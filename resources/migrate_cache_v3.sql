@@ -0,0 +1,8 @@
+PRAGMA cache.user_version = 3;
+
+CREATE TABLE cache.resolved_link (
+    link TEXT CHECK(TYPEOF(link) = 'text'),
+    resolved_link TEXT CHECK(TYPEOF(resolved_link) = 'text'),
+    resolved_at DATETIME CHECK(DATETIME(resolved_at) IS NOT NULL),
+    PRIMARY KEY (link)
+);
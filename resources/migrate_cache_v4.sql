@@ -0,0 +1,7 @@
+PRAGMA cache.user_version = 4;
+
+CREATE TABLE cache.fetch_schedule (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    next_fetch_at DATETIME CHECK(DATETIME(next_fetch_at) IS NOT NULL),
+    PRIMARY KEY (feed_url)
+);
@@ -0,0 +1,9 @@
+PRAGMA cache.user_version = 5;
+
+CREATE TABLE cache.translation (
+    text TEXT CHECK(TYPEOF(text) = 'text'),
+    target_language TEXT CHECK(TYPEOF(target_language) = 'text'),
+    translation TEXT CHECK(TYPEOF(translation) = 'text'),
+    translated_at DATETIME CHECK(DATETIME(translated_at) IS NOT NULL),
+    PRIMARY KEY (text, target_language)
+);
@@ -0,0 +1,12 @@
+PRAGMA cache.user_version = 6;
+
+CREATE TABLE cache.fetch_run (
+    started_at DATETIME CHECK(DATETIME(started_at) IS NOT NULL),
+    duration_ms INTEGER CHECK(TYPEOF(duration_ms) = 'integer')
+);
+
+CREATE TABLE cache.fetch_error (
+    feed_url TEXT PRIMARY KEY CHECK(TYPEOF(feed_url) = 'text'),
+    failed_at DATETIME CHECK(DATETIME(failed_at) IS NOT NULL),
+    error TEXT CHECK(TYPEOF(error) = 'text')
+);
@@ -0,0 +1,8 @@
+PRAGMA cache.user_version = 7;
+
+CREATE TABLE cache.item_content (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    guid TEXT CHECK(TYPEOF(guid) = 'text'),
+    content TEXT CHECK(TYPEOF(content) = 'text'),
+    PRIMARY KEY (feed_url, guid)
+);
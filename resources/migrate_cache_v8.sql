@@ -0,0 +1,10 @@
+PRAGMA cache.user_version = 8;
+
+CREATE TABLE cache.access_token (
+    token_url TEXT CHECK(TYPEOF(token_url) = 'text'),
+    client_id TEXT CHECK(TYPEOF(client_id) = 'text'),
+    scope TEXT CHECK(TYPEOF(scope) = 'text'),
+    token TEXT CHECK(TYPEOF(token) = 'text'),
+    expires_at DATETIME CHECK(DATETIME(expires_at) IS NOT NULL),
+    PRIMARY KEY (token_url, client_id, scope)
+);
@@ -0,0 +1,8 @@
+PRAGMA cache.user_version = 9;
+
+CREATE TABLE cache.challenge (
+    feed_url TEXT PRIMARY KEY CHECK(TYPEOF(feed_url) = 'text'),
+    provider TEXT CHECK(TYPEOF(provider) = 'text'),
+    challenged_at DATETIME CHECK(DATETIME(challenged_at) IS NOT NULL),
+    count INTEGER CHECK(TYPEOF(count) = 'integer')
+);
@@ -0,0 +1,43 @@
+-- The feed table is rebuilt, which items refer to.
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+PRAGMA user_version = 10;
+
+-- Move validators and the fetch log to the cache, which can be deleted without losing state.
+INSERT OR REPLACE INTO cache.validator (feed_url, etag, last_modified)
+SELECT url, etag, last_modified FROM main.feed
+WHERE etag IS NOT NULL OR last_modified IS NOT NULL;
+
+INSERT INTO cache.fetch SELECT
+    feed_url,
+    fetched_at,
+    status,
+    bytes,
+    is_conditional,
+    etag,
+    last_modified,
+    body_hash,
+    duration_ms
+FROM main.fetch;
+
+DROP TABLE main.fetch;
+
+CREATE TABLE main.feed_v10 (
+    url TEXT CHECK(TYPEOF(url) = 'text'),
+    link TEXT CHECK(TYPEOF(link) = 'text'),
+    title TEXT CHECK(TYPEOF(title) = 'text'),
+    is_muted BOOLEAN NOT NULL DEFAULT 0 CHECK(is_muted = 0 OR is_muted = 1),
+    PRIMARY KEY (url)
+);
+
+INSERT INTO main.feed_v10 SELECT url, link, title, is_muted FROM main.feed;
+
+DROP TABLE main.feed;
+
+ALTER TABLE main.feed_v10 RENAME TO feed;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
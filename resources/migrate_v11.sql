@@ -0,0 +1,13 @@
+PRAGMA user_version = 11;
+
+CREATE TABLE feed_change (
+    id INTEGER PRIMARY KEY,
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    changed_at DATETIME CHECK(DATETIME(changed_at) IS NOT NULL),
+    old_title TEXT CHECK(TYPEOF(old_title) = 'text'),
+    new_title TEXT CHECK(TYPEOF(new_title) = 'text'),
+    old_link TEXT CHECK(TYPEOF(old_link) = 'text'),
+    new_link TEXT CHECK(TYPEOF(new_link) = 'text'),
+    is_read BOOLEAN NOT NULL DEFAULT 0 CHECK(is_read = 0 OR is_read = 1),
+    FOREIGN KEY (feed_url) REFERENCES feed(url)
+);
@@ -0,0 +1,3 @@
+PRAGMA user_version = 12;
+
+ALTER TABLE item ADD COLUMN snoozed_until DATETIME CHECK(snoozed_until IS NULL OR DATETIME(snoozed_until) IS NOT NULL);
@@ -0,0 +1,4 @@
+PRAGMA user_version = 13;
+
+ALTER TABLE feed ADD COLUMN hub TEXT CHECK(TYPEOF(hub) = 'text' OR TYPEOF(hub) = 'null');
+ALTER TABLE feed ADD COLUMN self_url TEXT CHECK(TYPEOF(self_url) = 'text' OR TYPEOF(self_url) = 'null');
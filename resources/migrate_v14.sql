@@ -0,0 +1,3 @@
+PRAGMA user_version = 14;
+
+ALTER TABLE item ADD COLUMN translated_title TEXT CHECK(TYPEOF(translated_title) = 'text' OR TYPEOF(translated_title) = 'null');
@@ -0,0 +1,3 @@
+PRAGMA user_version = 15;
+
+ALTER TABLE item ADD COLUMN is_bulk BOOLEAN NOT NULL DEFAULT 0 CHECK(is_bulk = 0 OR is_bulk = 1);
@@ -0,0 +1,31 @@
+PRAGMA user_version = 16;
+
+CREATE TABLE delivery (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    guid TEXT CHECK(TYPEOF(guid) = 'text'),
+    recipient TEXT CHECK(TYPEOF(recipient) = 'text'),
+    transport TEXT CHECK(transport IS NULL OR TYPEOF(transport) = 'text'),
+    attempted_at DATETIME CHECK(DATETIME(attempted_at) IS NOT NULL),
+    digest_id INTEGER CHECK(digest_id IS NULL OR TYPEOF(digest_id) = 'integer'),
+    delivered_at DATETIME CHECK(delivered_at IS NULL OR DATETIME(delivered_at) IS NOT NULL),
+    PRIMARY KEY (feed_url, guid, recipient),
+    FOREIGN KEY (digest_id) REFERENCES digest(id)
+);
+
+INSERT OR IGNORE INTO delivery (
+    feed_url,
+    guid,
+    recipient,
+    transport,
+    attempted_at,
+    digest_id,
+    delivered_at
+) SELECT
+    digest_item.feed_url,
+    digest_item.guid,
+    digest.recipient,
+    NULL,
+    digest.sent_at,
+    digest.id,
+    digest.sent_at
+FROM digest_item JOIN digest ON digest.id = digest_item.digest_id;
@@ -0,0 +1,3 @@
+PRAGMA user_version = 17;
+
+UPDATE item SET link = '' WHERE link = 'https://example.com';
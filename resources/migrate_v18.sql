@@ -0,0 +1,5 @@
+PRAGMA user_version = 18;
+
+ALTER TABLE item ADD COLUMN source_url TEXT;
+ALTER TABLE item ADD COLUMN fetched_at DATETIME;
+ALTER TABLE item ADD COLUMN fetch_ms INTEGER;
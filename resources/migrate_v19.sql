@@ -0,0 +1,4 @@
+PRAGMA user_version = 19;
+
+ALTER TABLE item ADD COLUMN is_saved BOOLEAN NOT NULL DEFAULT 0 CHECK(is_saved = 0 OR is_saved = 1);
+ALTER TABLE item ADD COLUMN resurfaced_count INTEGER NOT NULL DEFAULT 0;
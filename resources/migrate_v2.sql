@@ -0,0 +1,3 @@
+PRAGMA user_version = 2;
+
+ALTER TABLE feed ADD COLUMN is_muted BOOLEAN NOT NULL DEFAULT 0 CHECK(is_muted = 0 OR is_muted = 1);
@@ -0,0 +1,3 @@
+PRAGMA user_version = 20;
+
+ALTER TABLE feed ADD COLUMN identity TEXT NOT NULL DEFAULT 'guid' CHECK(TYPEOF(identity) = 'text');
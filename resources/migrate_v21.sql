@@ -0,0 +1,7 @@
+PRAGMA user_version = 21;
+
+CREATE TABLE pipeline_run (
+    name TEXT CHECK(TYPEOF(name) = 'text'),
+    ran_at DATETIME CHECK(DATETIME(ran_at) IS NOT NULL),
+    PRIMARY KEY (name)
+);
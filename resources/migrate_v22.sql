@@ -0,0 +1,21 @@
+PRAGMA user_version = 22;
+
+ALTER TABLE digest ADD COLUMN message_id TEXT CHECK(message_id IS NULL OR TYPEOF(message_id) = 'text');
+
+CREATE INDEX digest_message_id ON digest(message_id);
+
+CREATE TABLE bounce (
+    digest_id INTEGER CHECK(TYPEOF(digest_id) = 'integer'),
+    received_at DATETIME CHECK(DATETIME(received_at) IS NOT NULL),
+    status TEXT CHECK(status IS NULL OR TYPEOF(status) = 'text'),
+    diagnostic TEXT CHECK(diagnostic IS NULL OR TYPEOF(diagnostic) = 'text'),
+    PRIMARY KEY (digest_id),
+    FOREIGN KEY (digest_id) REFERENCES digest(id)
+);
+
+CREATE TABLE bounce_mailbox (
+    mailbox TEXT CHECK(TYPEOF(mailbox) = 'text'),
+    uid_validity INTEGER CHECK(TYPEOF(uid_validity) = 'integer'),
+    last_uid INTEGER CHECK(TYPEOF(last_uid) = 'integer'),
+    PRIMARY KEY (mailbox)
+);
@@ -0,0 +1,3 @@
+PRAGMA user_version = 23;
+
+ALTER TABLE bounce_mailbox RENAME TO mailbox_poll;
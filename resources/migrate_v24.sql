@@ -0,0 +1,4 @@
+PRAGMA user_version = 24;
+
+ALTER TABLE item ADD COLUMN content_hash TEXT CHECK(TYPEOF(content_hash) = 'text' OR TYPEOF(content_hash) = 'null');
+ALTER TABLE item ADD COLUMN is_updated BOOLEAN NOT NULL DEFAULT 0 CHECK(is_updated = 0 OR is_updated = 1);
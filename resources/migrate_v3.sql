@@ -0,0 +1,4 @@
+PRAGMA user_version = 3;
+
+ALTER TABLE item ADD COLUMN is_highlighted BOOLEAN NOT NULL DEFAULT 0 CHECK(is_highlighted = 0 OR is_highlighted = 1);
+ALTER TABLE item ADD COLUMN recipient TEXT CHECK(TYPEOF(recipient) = 'text' OR TYPEOF(recipient) = 'null');
@@ -0,0 +1,9 @@
+PRAGMA user_version = 4;
+
+CREATE TABLE item_tag (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    guid TEXT CHECK(TYPEOF(guid) = 'text'),
+    tag TEXT CHECK(TYPEOF(tag) = 'text'),
+    PRIMARY KEY (feed_url, guid, tag),
+    FOREIGN KEY (feed_url, guid) REFERENCES item(feed_url, guid)
+);
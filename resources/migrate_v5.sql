@@ -0,0 +1,18 @@
+PRAGMA user_version = 5;
+
+CREATE TABLE digest (
+    id INTEGER PRIMARY KEY,
+    recipient TEXT CHECK(TYPEOF(recipient) = 'text'),
+    sent_at DATETIME CHECK(DATETIME(sent_at) IS NOT NULL)
+);
+
+CREATE TABLE digest_item (
+    digest_id INTEGER CHECK(TYPEOF(digest_id) = 'integer'),
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    guid TEXT CHECK(TYPEOF(guid) = 'text'),
+    link TEXT CHECK(TYPEOF(link) = 'text'),
+    PRIMARY KEY (digest_id, feed_url, guid),
+    FOREIGN KEY (digest_id) REFERENCES digest(id)
+);
+
+CREATE INDEX digest_item_link ON digest_item(link);
@@ -0,0 +1,10 @@
+PRAGMA user_version = 6;
+
+CREATE TABLE fetch (
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    fetched_at DATETIME CHECK(DATETIME(fetched_at) IS NOT NULL),
+    status INTEGER CHECK(TYPEOF(status) = 'integer'),
+    bytes INTEGER CHECK(TYPEOF(bytes) = 'integer')
+);
+
+CREATE INDEX fetch_feed_url ON fetch(feed_url);
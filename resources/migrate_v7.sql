@@ -0,0 +1,6 @@
+PRAGMA user_version = 7;
+
+ALTER TABLE fetch ADD COLUMN is_conditional BOOLEAN NOT NULL DEFAULT 0 CHECK(is_conditional = 0 OR is_conditional = 1);
+ALTER TABLE fetch ADD COLUMN etag TEXT CHECK(TYPEOF(etag) = 'text' OR TYPEOF(etag) = 'null');
+ALTER TABLE fetch ADD COLUMN last_modified TEXT CHECK(TYPEOF(last_modified) = 'text' OR TYPEOF(last_modified) = 'null');
+ALTER TABLE fetch ADD COLUMN body_hash TEXT CHECK(TYPEOF(body_hash) = 'text' OR TYPEOF(body_hash) = 'null');
@@ -0,0 +1,22 @@
+PRAGMA user_version = 8;
+
+CREATE TABLE outbox (
+    id INTEGER PRIMARY KEY,
+    recipient TEXT CHECK(TYPEOF(recipient) = 'text'),
+    envelope TEXT CHECK(TYPEOF(envelope) = 'text'),
+    message_id TEXT CHECK(TYPEOF(message_id) = 'text'),
+    message BLOB CHECK(TYPEOF(message) = 'blob'),
+    queued_at DATETIME CHECK(DATETIME(queued_at) IS NOT NULL),
+    attempts INTEGER CHECK(TYPEOF(attempts) = 'integer'),
+    next_attempt_at DATETIME CHECK(DATETIME(next_attempt_at) IS NOT NULL),
+    last_error TEXT CHECK(TYPEOF(last_error) = 'text' OR TYPEOF(last_error) = 'null')
+);
+
+CREATE TABLE outbox_item (
+    outbox_id INTEGER CHECK(TYPEOF(outbox_id) = 'integer'),
+    feed_url TEXT CHECK(TYPEOF(feed_url) = 'text'),
+    guid TEXT CHECK(TYPEOF(guid) = 'text'),
+    link TEXT CHECK(TYPEOF(link) = 'text'),
+    PRIMARY KEY (outbox_id, feed_url, guid),
+    FOREIGN KEY (outbox_id) REFERENCES outbox(id)
+);
@@ -0,0 +1,3 @@
+PRAGMA user_version = 9;
+
+ALTER TABLE fetch ADD COLUMN duration_ms INTEGER CHECK(TYPEOF(duration_ms) = 'integer' OR TYPEOF(duration_ms) = 'null');
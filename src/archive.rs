@@ -0,0 +1,204 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::database;
+
+/// Section of digests that resurfaces a few old items that were highlighted or saved for later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Number of items to resurface in each digest.
+    #[serde(default = "default_count")]
+    pub count: usize,
+    /// Only resurface items published at least this many days ago.
+    #[serde(default = "default_min_age_days")]
+    pub min_age_days: u32,
+    /// Only resurface items published at most this many days ago.
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+}
+
+fn default_count() -> usize {
+    3
+}
+
+fn default_min_age_days() -> u32 {
+    30
+}
+
+/// Resurfaced item, which templates get in `archives`.
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub item: database::Item,
+    pub feed_title: String,
+    /// When the item was published, formatted for the locale.
+    pub published: String,
+}
+
+/// Pick items to resurface in a recipient's digest, ordered by when they were published.
+pub fn load(
+    config: &crate::Config,
+    archives: &Config,
+    database: &mut database::Database,
+    recipient: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Vec<Entry>, database::Error> {
+    let mut candidates = database.get_archive_candidates(
+        recipient,
+        archives
+            .max_age_days
+            .map(|days| now - Duration::days(i64::from(days))),
+        now - Duration::days(i64::from(archives.min_age_days)),
+    )?;
+    // With a fixed clock, draw the same items every time, by hashing each item instead.
+    if config.clock.is_fixed() {
+        for candidate in &mut candidates {
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(candidate.item.feed_url.as_bytes());
+            hasher.update(b"\n");
+            hasher.update(candidate.item.guid.as_bytes());
+            candidate.random = hasher.finalize();
+        }
+    }
+    let mut items = sample(candidates, archives.count);
+    items.sort_by_key(|item| item.pub_date);
+    let strings = config.locale.strings();
+    let mut entries = Vec::with_capacity(items.len());
+    for item in items {
+        let feed_title = database
+            .get_feed_by_url(&item.feed_url)?
+            .map(|feed| feed.title)
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| item.feed_url.clone());
+        entries.push(Entry {
+            published: strings.format_date(&config.clock.to_local(item.pub_date)),
+            feed_title,
+            item,
+        });
+    }
+    Ok(entries)
+}
+
+/// Draw items at random without replacement, weighted by how many of highlighted and saved they
+/// are, and less the more often they were resurfaced before.
+///
+/// Each item gets the key `u^(1/weight)` for its random `u` in (0, 1), and the items with the
+/// largest keys are drawn, following Efraimidis and Spirakis.
+fn sample(candidates: Vec<database::ArchiveCandidate>, count: usize) -> Vec<database::Item> {
+    let mut keyed: Vec<(f64, database::Item)> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.marks > 0)
+        .map(|candidate| {
+            let weight = f64::from(candidate.marks) / (1.0 + f64::from(candidate.resurfaced_count));
+            let u = (f64::from(candidate.random) + 1.0) / (f64::from(u32::MAX) + 2.0);
+            (u.powf(1.0 / weight), candidate.item)
+        })
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    keyed.truncate(count);
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn candidate(
+        guid: &str,
+        marks: u32,
+        resurfaced_count: u32,
+        random: u32,
+    ) -> database::ArchiveCandidate {
+        database::ArchiveCandidate {
+            item: database::Item {
+                feed_url: "https://example.org/feed.xml".to_string(),
+                guid: guid.to_string(),
+                title: guid.to_string(),
+                link: String::new(),
+                comments_link: None,
+                pub_date: Utc.ymd(2019, 11, 7).and_hms(0, 0, 0),
+                is_read: true,
+                is_highlighted: true,
+                recipient: None,
+                tags: Vec::new(),
+                is_dead: false,
+                translated_title: None,
+                is_bulk: false,
+                source: None,
+                parts: Vec::new(),
+                qr_code: None,
+                number: None,
+                content: Vec::new(),
+                excerpt: Vec::new(),
+                is_truncated: false,
+                images: Vec::new(),
+                summary: None,
+            },
+            marks,
+            resurfaced_count,
+            random,
+        }
+    }
+
+    fn guids(items: &[database::Item]) -> Vec<&str> {
+        items.iter().map(|item| item.guid.as_str()).collect()
+    }
+
+    #[test]
+    fn sample_draws_largest_keys() {
+        let half = u32::MAX / 2;
+        let items = sample(
+            vec![
+                candidate("a", 1, 0, half),
+                candidate("b", 1, 0, u32::MAX),
+                candidate("c", 1, 0, 0),
+            ],
+            2,
+        );
+        assert_eq!(guids(&items), ["b", "a"]);
+    }
+
+    #[test]
+    fn sample_weights_marked_and_fresh_items_higher() {
+        // With the same random number, a heavier item gets a larger key.
+        let half = u32::MAX / 2;
+        let items = sample(
+            vec![
+                candidate("resurfaced", 1, 3, half),
+                candidate("highlighted", 1, 0, half),
+                candidate("highlighted and saved", 2, 0, half),
+            ],
+            3,
+        );
+        assert_eq!(
+            guids(&items),
+            ["highlighted and saved", "highlighted", "resurfaced"]
+        );
+        assert!(sample(vec![candidate("unmarked", 0, 0, half)], 1).is_empty());
+    }
+
+    #[test]
+    fn sample_approximates_weights() {
+        // Draw one of two items many times, with random numbers spread evenly.
+        let steps = 1000;
+        let heavy_draws = (0..steps)
+            .filter(|i| {
+                let random = |j: u32| (u32::MAX / steps).wrapping_mul(j) ^ 0x5555_5555;
+                let items = sample(
+                    vec![
+                        candidate("heavy", 2, 0, random(*i)),
+                        candidate("light", 1, 0, random(*i * 7 + 3)),
+                    ],
+                    1,
+                );
+                guids(&items) == ["heavy"]
+            })
+            .count();
+        // The heavier item is drawn two thirds of the time.
+        assert!((600..733).contains(&heavy_draws), "{}", heavy_draws);
+    }
+}
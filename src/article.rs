@@ -0,0 +1,449 @@
+use std::fmt::Write;
+use std::io::Read;
+use std::time::Duration;
+
+use attohttpc::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT};
+use attohttpc::StatusCode;
+use derive_more::{Display, From};
+
+use crate::{css, excerpt, validators};
+
+/// Article pages larger than this aren't read.
+const MAX_PAGE_BYTES: u64 = 4 * 1024 * 1024;
+/// Elements whose contents are the text of the article, in order of preference.
+const CONTAINERS: [&str; 3] = ["article", "main", "body"];
+/// Elements that are never part of the text of an article.
+const BOILERPLATE: [&str; 12] = [
+    "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form",
+    "iframe", "svg", "button",
+];
+/// Elements of images, which are removed unless they're kept.
+const IMAGES: [&str; 3] = ["img", "picture", "figure"];
+/// Elements whose contents aren't markup, so tags in them are left alone.
+const RAW_TEXT: [&str; 2] = ["script", "style"];
+/// Elements that have no end tag.
+const VOID: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+#[derive(Debug, From, Display)]
+pub enum Error {
+    Http(attohttpc::Error),
+    Io(std::io::Error),
+    #[display(fmt = "unexpected status code: {_0}")]
+    UnexpectedStatusCode(u16),
+    #[display(fmt = "page is larger than {MAX_PAGE_BYTES} bytes")]
+    TooLarge,
+}
+
+/// Article page, with the validators to check whether it changed.
+pub struct Page {
+    pub body: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Download an article page, unless it's unchanged since it was downloaded with the validators,
+/// in which case there's no page.
+pub fn fetch(
+    url: &str,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> Result<Option<Page>, Error> {
+    let mut builder = attohttpc::get(url)
+        .header(USER_AGENT, env!("CARGO_PKG_NAME"))
+        .timeout(Duration::from_secs(30));
+    if let Some(etag) = etag {
+        builder = builder.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = last_modified {
+        builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let (status, headers, reader) = builder.send()?.split();
+    if status == StatusCode::NOT_MODIFIED && (etag.is_some() || last_modified.is_some()) {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(Error::UnexpectedStatusCode(status.as_u16()));
+    }
+    let mut body = Vec::new();
+    reader.take(MAX_PAGE_BYTES + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_PAGE_BYTES {
+        return Err(Error::TooLarge);
+    }
+    let (body, _, _) = crate::response_charset(&headers).decode(&body);
+    Ok(Some(Page {
+        body: body.into_owned(),
+        etag: crate::header_string(&headers, ETAG)
+            .as_deref()
+            .and_then(validators::normalize_etag),
+        last_modified: crate::header_string(&headers, LAST_MODIFIED)
+            .as_deref()
+            .and_then(validators::normalize_last_modified),
+    }))
+}
+
+/// What's removed from articles, besides scripts, navigation, and other boilerplate.
+#[derive(Default)]
+pub struct Cleanup<'a> {
+    /// Elements to remove, such as share buttons and lists of related posts.
+    pub remove: &'a [css::Selector],
+    /// Paragraphs with fewer characters of text than this are removed, such as bylines.
+    pub min_paragraph_chars: usize,
+    /// Keep images, with their URLs made absolute, instead of removing them.
+    pub keep_images: bool,
+}
+
+/// Article in an HTML page at a URL, as HTML: the contents of its `article` element, or else its
+/// `main` or `body` element, without comments, scripts, navigation, and other boilerplate.
+pub fn extract(html: &str, url: &str, cleanup: &Cleanup) -> String {
+    let tags = tags(html);
+    let contents = CONTAINERS
+        .iter()
+        .find_map(|&name| {
+            let index = tags
+                .iter()
+                .position(|tag| tag.name == name && !tag.is_end)?;
+            Some(Element::new(html, &tags, index).contents())
+                .filter(|contents| !contents.is_empty())
+        })
+        .unwrap_or(html);
+    let article = remove_elements(contents, |element| {
+        let name = element.name();
+        BOILERPLATE.contains(&name)
+            || (!cleanup.keep_images && IMAGES.contains(&name))
+            || (name == "p" && is_short_paragraph(element, cleanup))
+            || is_selected(element, cleanup.remove)
+    });
+    if cleanup.keep_images {
+        with_absolute_images(&article, url)
+    } else {
+        article
+    }
+}
+
+/// URLs of the images in HTML, in order.
+pub fn images(html: &str) -> Vec<String> {
+    tags(html)
+        .iter()
+        .filter(|tag| tag.name == "img" && !tag.is_end)
+        .filter_map(|tag| attribute(&html[tag.start..tag.end], "src"))
+        .map(|src| src.replace("&amp;", "&"))
+        .collect()
+}
+
+/// Whether a paragraph has less text than the cleanup keeps. Paragraphs of images are kept
+/// along with the images.
+fn is_short_paragraph(element: &Element, cleanup: &Cleanup) -> bool {
+    let contents = element.contents();
+    excerpt::words(contents).chars().count() < cleanup.min_paragraph_chars
+        && !(cleanup.keep_images && contents.to_ascii_lowercase().contains("<img"))
+}
+
+/// Whether any of the selectors selects an element.
+fn is_selected(element: &Element, selectors: &[css::Selector]) -> bool {
+    if selectors.is_empty() {
+        return false;
+    }
+    let start_tag = element.start_tag();
+    let id = attribute(start_tag, "id");
+    let classes: Vec<&str> = attribute(start_tag, "class")
+        .unwrap_or("")
+        .split_whitespace()
+        .collect();
+    selectors
+        .iter()
+        .any(|selector| selector.matches(element.name(), id, &classes))
+}
+
+/// HTML with its images given only an absolute `src`, from their `src` or lazily loaded
+/// `data-src` attributes, and images without either removed.
+fn with_absolute_images(html: &str, url: &str) -> String {
+    let base = url::Url::parse(url).ok();
+    let mut rewritten = String::new();
+    let mut copied = 0;
+    for tag in tags(html) {
+        if tag.name != "img" || tag.is_end {
+            continue;
+        }
+        rewritten.push_str(&html[copied..tag.start]);
+        copied = tag.end;
+        let start_tag = &html[tag.start..tag.end];
+        let src = attribute(start_tag, "src")
+            .filter(|src| !src.is_empty() && !src.starts_with("data:"))
+            .or_else(|| attribute(start_tag, "data-src"))
+            .map(|src| src.replace("&amp;", "&"));
+        let absolute = src.and_then(|src| match &base {
+            Some(base) => base.join(&src).ok().map(url::Url::into_string),
+            None => Some(src),
+        });
+        if let Some(absolute) = absolute {
+            write!(
+                rewritten,
+                "<img src=\"{}\">",
+                absolute.replace('&', "&amp;").replace('"', "&quot;")
+            )
+            .expect("writing to a string");
+        }
+    }
+    rewritten.push_str(&html[copied..]);
+    rewritten
+}
+
+/// Value of an attribute of a start tag, with its entities left as they are.
+fn attribute<'a>(start_tag: &'a str, name: &str) -> Option<&'a str> {
+    let inside = start_tag
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .trim_end_matches('/');
+    // What's after the tag name.
+    let mut rest = inside.trim_start_matches(|c: char| !c.is_whitespace());
+    loop {
+        rest = rest.trim_start();
+        let name_len = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        if name_len == 0 {
+            return None;
+        }
+        let (attribute, after) = rest.split_at(name_len);
+        let after = after.trim_start();
+        let (value, after) = match after.strip_prefix('=').map(str::trim_start) {
+            Some(quoted) if quoted.starts_with(['"', '\'']) => {
+                let quote = &quoted[..1];
+                let end = quoted[1..].find(quote).map_or(quoted.len(), |end| end + 1);
+                (&quoted[1..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            Some(unquoted) => {
+                let end = unquoted.find(char::is_whitespace).unwrap_or(unquoted.len());
+                unquoted.split_at(end)
+            }
+            None => ("", after),
+        };
+        if attribute.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = after;
+    }
+}
+
+/// Tag in HTML, or a comment, whose name is `!--`.
+struct Tag {
+    name: String,
+    is_end: bool,
+    /// Offset of the `<` that the tag starts with.
+    start: usize,
+    /// Offset after the `>` that the tag ends with.
+    end: usize,
+}
+
+/// Tags and comments of HTML, in order. The contents of raw text elements are skipped.
+fn tags(html: &str) -> Vec<Tag> {
+    // Lowercasing ASCII keeps byte offsets the same.
+    let lowercase = html.to_ascii_lowercase();
+    let mut tags = Vec::new();
+    let mut offset = 0;
+    while let Some(found) = html[offset..].find('<') {
+        let start = offset + found;
+        let rest = &lowercase[start..];
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(html.len(), |end| start + end + 3);
+            tags.push(Tag {
+                name: "!--".to_string(),
+                is_end: false,
+                start,
+                end,
+            });
+            offset = end;
+            continue;
+        }
+        let is_end = rest[1..].starts_with('/');
+        let name_start = start + 1 + usize::from(is_end);
+        let name: String = lowercase[name_start..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '!' || *c == '-')
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '!') {
+            // A `<` that isn't markup.
+            offset = start + 1;
+            continue;
+        }
+        let mut quote = None;
+        let mut end = html.len();
+        for (index, c) in html[name_start..].char_indices() {
+            match (quote, c) {
+                (Some(open), c) if c == open => quote = None,
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => {
+                    end = name_start + index + 1;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        offset = end;
+        if !is_end && RAW_TEXT.contains(&name.as_str()) {
+            let closing = format!("</{name}");
+            offset = lowercase[end..]
+                .find(&closing)
+                .map_or(html.len(), |close| end + close);
+        }
+        tags.push(Tag {
+            name,
+            is_end,
+            start,
+            end,
+        });
+    }
+    tags
+}
+
+/// Element of HTML that starts with one of its tags.
+struct Element<'a> {
+    html: &'a str,
+    tags: &'a [Tag],
+    index: usize,
+}
+impl<'a> Element<'a> {
+    fn new(html: &'a str, tags: &'a [Tag], index: usize) -> Self {
+        Self { html, tags, index }
+    }
+
+    fn name(&self) -> &str {
+        &self.tags[self.index].name
+    }
+
+    /// Index of the end tag of the element, if it has one.
+    fn end_tag(&self) -> Option<usize> {
+        let start_tag = &self.tags[self.index];
+        if VOID.contains(&start_tag.name.as_str()) || self.start_tag().ends_with("/>") {
+            return None;
+        }
+        let mut depth = 0;
+        for (index, tag) in self.tags.iter().enumerate().skip(self.index + 1) {
+            if tag.name != start_tag.name {
+                continue;
+            }
+            match (tag.is_end, depth) {
+                (true, 0) => return Some(index),
+                (true, _) => depth -= 1,
+                (false, _) => depth += 1,
+            }
+        }
+        None
+    }
+
+    fn start_tag(&self) -> &'a str {
+        let tag = &self.tags[self.index];
+        &self.html[tag.start..tag.end]
+    }
+
+    /// HTML between the start and end tags, which is empty if there's no end tag.
+    fn contents(&self) -> &'a str {
+        let start = self.tags[self.index].end;
+        self.end_tag()
+            .map_or("", |end_tag| &self.html[start..self.tags[end_tag].start])
+    }
+}
+
+/// HTML without comments, and without the elements that `is_removed` is true for, along with
+/// what's in them.
+fn remove_elements(html: &str, is_removed: impl Fn(&Element) -> bool) -> String {
+    let tags = tags(html);
+    let mut kept = String::new();
+    let mut copied = 0;
+    let mut index = 0;
+    while index < tags.len() {
+        let tag = &tags[index];
+        let element = Element::new(html, &tags, index);
+        let removed_until = if tag.name == "!--" {
+            Some(index)
+        } else if !tag.is_end && is_removed(&element) {
+            Some(element.end_tag().unwrap_or(index))
+        } else {
+            None
+        };
+        match removed_until {
+            Some(last) => {
+                kept.push_str(&html[copied..tag.start]);
+                copied = tags[last].end;
+                index = last + 1;
+            }
+            None => index += 1,
+        }
+    }
+    kept.push_str(&html[copied..]);
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.org/posts/1";
+
+    #[test]
+    fn extract_prefers_the_article_element() {
+        let html = "<html><head><title>Post</title><script>if (a<b) {}</script></head>\
+                    <body><nav><a href=\"/\">Home</a></nav>\
+                    <article><h1>Post</h1><!-- ad --><p>Text <b>here</b>.</p>\
+                    <aside><p>Related</p></aside><div><div>More</div></div></article>\
+                    <footer>Copyright</footer></body></html>";
+        assert_eq!(
+            extract(html, URL, &Cleanup::default()),
+            "<h1>Post</h1><p>Text <b>here</b>.</p><div><div>More</div></div>"
+        );
+    }
+
+    #[test]
+    fn extract_falls_back_to_the_body() {
+        let extract = |html| extract(html, URL, &Cleanup::default());
+        assert_eq!(
+            extract("<BODY><Header>Blog</Header><p title='a > b'>Text</p><br></BODY>"),
+            "<p title='a > b'>Text</p><br>"
+        );
+        assert_eq!(extract("Text <3"), "Text <3");
+    }
+
+    #[test]
+    fn extract_removes_selected_elements_and_short_paragraphs() {
+        let html = "<article><p class=\"byline\">By Ann</p><p>Long enough text.</p>\
+                    <div class='share big'><a>Share</a></div><div id=related>Related</div>\
+                    <figure><img src=\"a.png\"></figure></article>";
+        let remove = [
+            "div.share".parse().expect("failed to parse"),
+            "#related".parse().expect("failed to parse"),
+        ];
+        let cleanup = Cleanup {
+            remove: &remove,
+            min_paragraph_chars: 10,
+            keep_images: false,
+        };
+        assert_eq!(extract(html, URL, &cleanup), "<p>Long enough text.</p>");
+    }
+
+    #[test]
+    fn extract_keeps_images_with_absolute_urls() {
+        let html = "<article><p><img src=\"/a.png?x=1&amp;y=2\" alt=\"A\"></p>\
+                    <img src=\"data:image/gif;base64,R0\" data-src=\"b.png\"><img alt=\"\"></article>";
+        let cleanup = Cleanup {
+            min_paragraph_chars: 10,
+            keep_images: true,
+            ..Cleanup::default()
+        };
+        let article = extract(html, URL, &cleanup);
+        assert_eq!(
+            article,
+            "<p><img src=\"https://example.org/a.png?x=1&amp;y=2\"></p>\
+             <img src=\"https://example.org/posts/b.png\">"
+        );
+        assert_eq!(
+            images(&article),
+            [
+                "https://example.org/a.png?x=1&y=2",
+                "https://example.org/posts/b.png"
+            ]
+        );
+    }
+}
@@ -0,0 +1,20 @@
+//! Replacing files so that a crash never leaves them partly written.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Replace a file with new contents. The contents are written to a temporary file next to it
+/// first, which is renamed over the file, so readers see either the old or the new contents. The
+/// file keeps its permissions, since it may hold secrets.
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let mut file = File::create(&temp_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
@@ -0,0 +1,90 @@
+use crate::database::Fetch;
+use crate::validators;
+
+const OK: u16 = 200;
+const NOT_MODIFIED: u16 = 304;
+
+/// How a feed's server handles conditional requests, judged from its fetch history.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub fetches: usize,
+    pub conditional_fetches: usize,
+    pub not_modified: usize,
+    pub sends_etag: bool,
+    pub sends_last_modified: bool,
+    /// Conditional requests answered with an unchanged body instead of "not modified".
+    pub ignored_validators: usize,
+    /// Responses with an unchanged body but different validators.
+    pub unstable_validators: usize,
+    /// Responses with a changed body but the same validators.
+    pub stale_validators: usize,
+}
+impl Report {
+    pub fn new(fetches: &[Fetch]) -> Self {
+        let mut report = Self {
+            fetches: fetches.len(),
+            ..Self::default()
+        };
+        // Bodies are only compared with the previous downloaded body.
+        let mut previous: Option<&Fetch> = None;
+        for fetch in fetches {
+            if fetch.is_conditional {
+                report.conditional_fetches += 1;
+            }
+            if fetch.status == NOT_MODIFIED {
+                report.not_modified += 1;
+                continue;
+            }
+            if fetch.status != OK || fetch.body_hash.is_none() {
+                continue;
+            }
+            report.sends_etag |= fetch.etag.is_some();
+            report.sends_last_modified |= fetch.last_modified.is_some();
+            if let Some(previous) = previous {
+                let same_body = previous.body_hash == fetch.body_hash;
+                let same_etag = match (&previous.etag, &fetch.etag) {
+                    (Some(previous_etag), Some(etag)) => {
+                        validators::etags_match_weakly(previous_etag, etag)
+                    }
+                    (previous_etag, etag) => previous_etag == etag,
+                };
+                let same_validators = same_etag && previous.last_modified == fetch.last_modified;
+                let has_validators = fetch.etag.is_some() || fetch.last_modified.is_some();
+                if same_body && fetch.is_conditional {
+                    report.ignored_validators += 1;
+                }
+                if same_body && !same_validators && has_validators {
+                    report.unstable_validators += 1;
+                }
+                if !same_body && same_validators && has_validators {
+                    report.stale_validators += 1;
+                }
+            }
+            previous = Some(fetch);
+        }
+        report
+    }
+
+    /// One-line judgement of the feed.
+    pub fn verdict(&self) -> &'static str {
+        if self.fetches == 0 {
+            "never fetched"
+        } else if self.stale_validators > 0 {
+            "bogus validators: content changed without the validators changing"
+        } else if !self.sends_etag && !self.sends_last_modified {
+            if self.not_modified > 0 {
+                "supports conditional requests"
+            } else {
+                "no validators: every fetch downloads the full feed"
+            }
+        } else if self.unstable_validators > 0 {
+            "bogus validators: validators changed without the content changing"
+        } else if self.ignored_validators > 0 && self.not_modified == 0 {
+            "ignores validators: always responds with the full feed"
+        } else if self.conditional_fetches == 0 {
+            "not enough history"
+        } else {
+            "supports conditional requests"
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use tera::Value;
+
+/// Ranges of characters in right-to-left scripts, such as Hebrew and Arabic.
+const RTL_RANGES: [(char, char); 6] = [
+    ('\u{0590}', '\u{08FF}'),
+    ('\u{FB1D}', '\u{FDFF}'),
+    ('\u{FE70}', '\u{FEFF}'),
+    ('\u{10800}', '\u{10FFF}'),
+    ('\u{1E800}', '\u{1EFFF}'),
+    // Right-to-left mark.
+    ('\u{200F}', '\u{200F}'),
+];
+
+fn is_rtl_char(c: char) -> bool {
+    RTL_RANGES
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&c))
+}
+
+/// Direction of text, by its first letter, like browsers do for `dir="auto"`.
+///
+/// Text without letters is left-to-right.
+pub fn direction(text: &str) -> &'static str {
+    match text.chars().find(|c| c.is_alphabetic() || is_rtl_char(*c)) {
+        Some(c) if is_rtl_char(c) => "rtl",
+        _ => "ltr",
+    }
+}
+
+/// Tera filter for the direction of a string, to use as a `dir` attribute.
+///
+/// Many email clients don't support `dir="auto"`, so digests need the direction set explicitly.
+#[allow(clippy::implicit_hasher, clippy::unnecessary_wraps)]
+pub fn dir_filter(value: &Value, _args: &HashMap<String, Value>) -> tera::Result<Value> {
+    Ok(Value::from(direction(value.as_str().unwrap_or(""))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direction_uses_first_letter() {
+        assert_eq!(direction("Rust 1.40 released"), "ltr");
+        assert_eq!(direction("שלום עולם"), "rtl");
+        assert_eq!(direction("مرحبا بالعالم"), "rtl");
+        // Digits and punctuation don't have a direction.
+        assert_eq!(direction("2019: مرحبا Rust"), "rtl");
+        assert_eq!(direction("Rust: مرحبا"), "ltr");
+        assert_eq!(direction("2019"), "ltr");
+    }
+}
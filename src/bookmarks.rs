@@ -0,0 +1,173 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::database;
+
+/// Format of exported bookmarks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Netscape bookmark file, which browsers and bookmark managers import.
+    NetscapeHtml,
+    Json,
+}
+
+/// Items of a feed, exported as a folder named after the feed.
+pub struct Folder {
+    pub title: String,
+    pub items: Vec<database::Item>,
+}
+
+#[derive(Serialize)]
+struct Bookmark<'a> {
+    url: &'a str,
+    title: &'a str,
+    feed_url: &'a str,
+    feed_title: &'a str,
+    pub_date: DateTime<Utc>,
+    tags: &'a [String],
+    /// Fetch the item was first stored from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'a database::Source>,
+}
+
+/// Export items in a format, with a folder for each feed.
+pub fn export(format: Format, folders: &[Folder]) -> String {
+    match format {
+        Format::NetscapeHtml => to_netscape_html(folders),
+        Format::Json => to_json(folders),
+    }
+}
+
+fn to_netscape_html(folders: &[Folder]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>Bookmarks</TITLE>\n\
+         <H1>Bookmarks</H1>\n\
+         <DL><p>\n",
+    );
+    for folder in folders {
+        writeln!(html, "    <DT><H3>{}</H3>", escape_html(&folder.title))
+            .expect("writing to a string");
+        html.push_str("    <DL><p>\n");
+        for item in &folder.items {
+            write!(
+                html,
+                "        <DT><A HREF=\"{}\" ADD_DATE=\"{}\"",
+                escape_html(&item.link),
+                item.pub_date.timestamp()
+            )
+            .expect("writing to a string");
+            if !item.tags.is_empty() {
+                write!(html, " TAGS=\"{}\"", escape_html(&item.tags.join(",")))
+                    .expect("writing to a string");
+            }
+            writeln!(html, ">{}</A>", escape_html(&item.title)).expect("writing to a string");
+        }
+        html.push_str("    </DL><p>\n");
+    }
+    html.push_str("</DL><p>\n");
+    html
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn to_json(folders: &[Folder]) -> String {
+    let bookmarks: Vec<Bookmark> = folders
+        .iter()
+        .flat_map(|folder| {
+            folder.items.iter().map(move |item| Bookmark {
+                url: &item.link,
+                title: &item.title,
+                feed_url: &item.feed_url,
+                feed_title: &folder.title,
+                pub_date: item.pub_date,
+                tags: &item.tags,
+                source: item.source.as_ref(),
+            })
+        })
+        .collect();
+    let mut json =
+        serde_json::to_string_pretty(&bookmarks).expect("bookmarks cannot be serialized");
+    json.push('\n');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn folders() -> Vec<Folder> {
+        vec![Folder {
+            title: "Rust & friends".to_string(),
+            items: vec![database::Item {
+                feed_url: "https://example.org/feed.xml".to_string(),
+                guid: "1".to_string(),
+                title: "Async <await>".to_string(),
+                link: "https://example.org/1?a=1&b=2".to_string(),
+                comments_link: None,
+                pub_date: Utc.ymd(2019, 11, 7).and_hms(0, 0, 0),
+                is_read: true,
+                is_highlighted: true,
+                recipient: None,
+                tags: vec!["async".to_string(), "rust".to_string()],
+                is_dead: false,
+                translated_title: None,
+                is_bulk: false,
+                source: None,
+                parts: Vec::new(),
+                qr_code: None,
+                number: None,
+                content: Vec::new(),
+                excerpt: Vec::new(),
+                is_truncated: false,
+                images: Vec::new(),
+                summary: None,
+            }],
+        }]
+    }
+
+    #[test]
+    fn netscape_html_has_folder_per_feed() {
+        assert_eq!(
+            export(Format::NetscapeHtml, &folders()),
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+             <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+             <TITLE>Bookmarks</TITLE>\n\
+             <H1>Bookmarks</H1>\n\
+             <DL><p>\n    \
+             <DT><H3>Rust &amp; friends</H3>\n    \
+             <DL><p>\n        \
+             <DT><A HREF=\"https://example.org/1?a=1&amp;b=2\" ADD_DATE=\"1573084800\" \
+             TAGS=\"async,rust\">Async &lt;await&gt;</A>\n    \
+             </DL><p>\n\
+             </DL><p>\n"
+        );
+    }
+
+    #[test]
+    fn json_lists_bookmarks() {
+        let bookmarks: serde_json::Value =
+            serde_json::from_str(&export(Format::Json, &folders())).expect("invalid JSON");
+        assert_eq!(
+            bookmarks,
+            serde_json::json!([{
+                "url": "https://example.org/1?a=1&b=2",
+                "title": "Async <await>",
+                "feed_url": "https://example.org/feed.xml",
+                "feed_title": "Rust & friends",
+                "pub_date": "2019-11-07T00:00:00Z",
+                "tags": ["async", "rust"],
+            }])
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Noticing digests that bounced, by reading the bounce messages sent back to the from address.
+
+use chrono::{Local, Utc};
+
+use crate::database;
+
+/// What a bounce message says about a message that couldn't be delivered.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Bounce {
+    /// Message-IDs the bounce refers to, including the bounced message's.
+    pub message_ids: Vec<String>,
+    /// Status code of the failure, such as "5.1.1".
+    pub status: Option<String>,
+    /// Reply of the mail server that rejected the message.
+    pub diagnostic: Option<String>,
+}
+impl Bounce {
+    /// Read a bounce message, in the delivery status notification format (RFC 3464) or in a
+    /// format of its own that quotes the bounced message's header.
+    pub fn parse(message: &str) -> Self {
+        let message = message.replace("\r\n", "\n");
+        let mut bounce = Self::default();
+        let lower = message.to_ascii_lowercase();
+        for (start, _) in lower.match_indices("message-id:") {
+            let value = message[start + "message-id:".len()..].trim_start();
+            if let Some(end) = value.strip_prefix('<').and_then(|value| value.find('>')) {
+                let message_id = &value[..end + 2];
+                if !bounce.message_ids.iter().any(|id| id == message_id) {
+                    bounce.message_ids.push(message_id.to_string());
+                }
+            }
+        }
+        for line in message.lines() {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("status") && bounce.status.is_none() {
+                bounce.status = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("diagnostic-code") && bounce.diagnostic.is_none() {
+                // The type of the code, such as "smtp;", comes first.
+                let diagnostic = value.split_once(';').map_or(value, |(_, code)| code.trim());
+                bounce.diagnostic = Some(diagnostic.to_string());
+            }
+        }
+        bounce
+    }
+}
+
+/// Record a bounce of a digest, and log it. Bounces of other messages are ignored.
+pub fn record(database: &mut database::Database, bounce: &Bounce) -> Result<(), database::Error> {
+    for message_id in &bounce.message_ids {
+        let bounced = database.insert_bounce(
+            message_id,
+            Utc::now(),
+            bounce.status.as_deref(),
+            bounce.diagnostic.as_deref(),
+        )?;
+        if let Some(bounced) = bounced {
+            eprintln!(
+                "Digest to {} sent at {} bounced: {}",
+                bounced.recipient,
+                bounced.sent_at.with_timezone(&Local).format("%F %T"),
+                describe(&bounced)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Warn about recipients whose latest digests bounced, at least `after` in a row.
+pub fn warn(database: &mut database::Database, after: u32) -> Result<(), database::Error> {
+    for failure in database.get_delivery_failures()? {
+        if failure.bounced >= after {
+            eprintln!(
+                "warning: the last {} digests to {} bounced, most recently with: {}",
+                failure.bounced,
+                failure.recipient,
+                describe(&failure.last)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Status and diagnostic of a bounce, for logs.
+pub fn describe(bounce: &database::Bounce) -> String {
+    match (&bounce.status, &bounce.diagnostic) {
+        (Some(status), Some(diagnostic)) => format!("{status} {diagnostic}"),
+        (Some(status), None) => status.clone(),
+        (None, Some(diagnostic)) => diagnostic.clone(),
+        (None, None) => "no reason given".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_delivery_status_notification() {
+        let message = "From: MAILER-DAEMON@example.com\r\n\
+                       Message-ID: <bounce@example.com>\r\n\
+                       Content-Type: multipart/report; report-type=delivery-status; boundary=b\r\n\
+                       \r\n\
+                       --b\r\n\
+                       Content-Type: message/delivery-status\r\n\
+                       \r\n\
+                       Final-Recipient: rfc822; reader@example.org\r\n\
+                       Action: failed\r\n\
+                       Status: 5.7.1\r\n\
+                       Diagnostic-Code: smtp; 550 5.7.1 Message rejected as spam\r\n\
+                       \r\n\
+                       --b\r\n\
+                       Content-Type: text/rfc822-headers\r\n\
+                       \r\n\
+                       Subject: Digest\r\n\
+                       Message-ID:\r\n <0123abcd@example.com>\r\n\
+                       --b--\r\n";
+        assert_eq!(
+            Bounce::parse(message),
+            Bounce {
+                message_ids: vec![
+                    "<bounce@example.com>".to_string(),
+                    "<0123abcd@example.com>".to_string()
+                ],
+                status: Some("5.7.1".to_string()),
+                diagnostic: Some("550 5.7.1 Message rejected as spam".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_other_bounce() {
+        let message = "Subject: Undeliverable\n\nYour message could not be delivered.\n\n\
+                       message-id: <abc.lettre@localhost>\n";
+        assert_eq!(
+            Bounce::parse(message),
+            Bounce {
+                message_ids: vec!["<abc.lettre@localhost>".to_string()],
+                status: None,
+                diagnostic: None,
+            }
+        );
+    }
+}
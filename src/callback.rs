@@ -0,0 +1,377 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::database;
+
+// Request bodies are small form submissions.
+const MAX_BODY_LEN: usize = 64 * 1024;
+// Request lines and headers of links and form submissions are short.
+const MAX_LINE_LEN: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+/// How long a client may take to send a request or read the response. Connections are handled
+/// one at a time, so an idle or slow client would otherwise hold up every other one.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Base URL that footer links point at, as reachable from the mail client.
+    pub url: String,
+    /// Address the daemon listens on for footer link callbacks.
+    pub listen: SocketAddr,
+    /// Secret used to sign footer links so they can't be forged.
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Mute,
+    Unmute,
+    /// Remove the feed from the config file.
+    Unsubscribe,
+}
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Mute => "mute",
+            Self::Unmute => "unmute",
+            Self::Unsubscribe => "unsubscribe",
+        }
+    }
+    fn from_path(path: &str) -> Option<Self> {
+        match path {
+            "/mute" => Some(Self::Mute),
+            "/unmute" => Some(Self::Unmute),
+            "/unsubscribe" => Some(Self::Unsubscribe),
+            _ => None,
+        }
+    }
+}
+
+fn mac(secret: &str, action: Action, feed_url: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(action.as_str().as_bytes());
+    mac.update(b"\0");
+    mac.update(feed_url.as_bytes());
+    mac
+}
+
+fn token(secret: &str, action: Action, feed_url: &str) -> String {
+    let mut token = String::new();
+    for byte in mac(secret, action, feed_url).finalize().into_bytes() {
+        write!(token, "{byte:02x}").expect("writing to string cannot fail");
+    }
+    token
+}
+
+fn verify_token(secret: &str, action: Action, feed_url: &str, token: &str) -> bool {
+    let bytes: Option<Vec<u8>> = (0..token.len())
+        .step_by(2)
+        .map(|i| {
+            token
+                .get(i..i + 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        })
+        .collect();
+    bytes.is_some_and(|bytes| mac(secret, action, feed_url).verify_slice(&bytes).is_ok())
+}
+
+/// Return a signed link that performs action on the feed.
+pub fn link(config: &Config, action: Action, feed_url: &str) -> String {
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("feed", feed_url)
+        .append_pair("token", &token(&config.secret, action, feed_url))
+        .finish();
+    format!(
+        "{}/{}?{}",
+        config.url.trim_end_matches('/'),
+        action.as_str(),
+        query
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub params: Vec<(String, String)>,
+}
+impl Request {
+    /// Read a request, or `None` if it's malformed or its lines are too long.
+    pub fn read_from(stream: impl Read) -> std::io::Result<Option<Self>> {
+        let mut reader = BufReader::new(stream);
+        let Some(request_line) = read_line(&mut reader)? else {
+            return Ok(None);
+        };
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Ok(None);
+        };
+        let mut content_length = 0;
+        let mut headers = 0;
+        loop {
+            let Some(header) = read_line(&mut reader)? else {
+                return Ok(None);
+            };
+            if header.trim().is_empty() {
+                break;
+            }
+            headers += 1;
+            if headers > MAX_HEADERS {
+                return Ok(None);
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let Ok(url) = url::Url::parse(&format!("http://localhost{target}")) else {
+            return Ok(None);
+        };
+        let mut params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        if method == "POST" {
+            let mut body = vec![0; std::cmp::min(content_length, MAX_BODY_LEN)];
+            reader.read_exact(&mut body)?;
+            params.extend(url::form_urlencoded::parse(&body).into_owned());
+        }
+        Ok(Some(Self {
+            method: method.to_string(),
+            path: url.path().to_string(),
+            params,
+        }))
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    let page = format!(
+        "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\"><title>SqueakMail</title></head>\n\
+         <body>\n{body}\n</body>\n</html>\n"
+    );
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        page.len(),
+        page
+    )
+}
+
+fn handle(
+    config: &Config,
+    config_path: &Path,
+    stream: &TcpStream,
+    database: &Mutex<database::Database>,
+) -> std::io::Result<()> {
+    let Some(request) = Request::read_from(stream)? else {
+        return respond(stream, "400 Bad Request", "<p>Bad request.</p>");
+    };
+    let Some(action) = Action::from_path(&request.path) else {
+        return respond(stream, "404 Not Found", "<p>Not found.</p>");
+    };
+    let (Some(feed_url), Some(token)) = (request.param("feed"), request.param("token")) else {
+        return respond(stream, "400 Bad Request", "<p>Missing parameters.</p>");
+    };
+    if !verify_token(&config.secret, action, feed_url, token) {
+        return respond(stream, "403 Forbidden", "<p>Invalid link.</p>");
+    }
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    let feed = match database.get_feed_by_url(feed_url) {
+        Ok(Some(feed)) => feed,
+        Ok(None) => return respond(stream, "404 Not Found", "<p>Unknown feed.</p>"),
+        Err(e) => {
+            eprintln!("Failed to look up feed: {e}");
+            return respond(
+                stream,
+                "500 Internal Server Error",
+                "<p>Database error.</p>",
+            );
+        }
+    };
+    let title = escape_html(&feed.title);
+    // Link scanners in mail clients follow links, so only change state on POST.
+    if request.method != "POST" {
+        return respond(
+            stream,
+            "200 OK",
+            &format!(
+                "<form method=\"post\">\n<p>{} <strong>{}</strong>?</p>\n\
+                 <input type=\"hidden\" name=\"feed\" value=\"{}\">\n\
+                 <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+                 <button type=\"submit\">Confirm</button>\n</form>",
+                match action {
+                    Action::Mute => "Mute",
+                    Action::Unmute => "Unmute",
+                    Action::Unsubscribe => "Unsubscribe from",
+                },
+                title,
+                escape_html(feed_url),
+                escape_html(token)
+            ),
+        );
+    }
+    eprintln!("Received {} for {}", action.as_str(), feed_url);
+    if action == Action::Unsubscribe {
+        if let Err(e) = crate::remove_feed(config_path, feed_url) {
+            eprintln!("Failed to unsubscribe: {e}");
+            return respond(
+                stream,
+                "409 Conflict",
+                &format!(
+                    "<p>Failed to unsubscribe: {}</p>",
+                    escape_html(&e.to_string())
+                ),
+            );
+        }
+        return respond(
+            stream,
+            "200 OK",
+            &format!(
+                "<p><strong>{title}</strong> was removed from the config file. A running daemon \
+                 keeps fetching it until it's restarted.</p>"
+            ),
+        );
+    }
+    if let Err(e) = database.set_feed_muted(feed_url, action == Action::Mute) {
+        eprintln!("Failed to update feed: {e}");
+        return respond(
+            stream,
+            "500 Internal Server Error",
+            "<p>Database error.</p>",
+        );
+    }
+    let body = match action {
+        Action::Mute => format!(
+            "<p><strong>{}</strong> will no longer appear in digests.</p>\n\
+             <p><a href=\"{}\">Undo</a></p>",
+            title,
+            escape_html(&link(config, Action::Unmute, feed_url))
+        ),
+        Action::Unmute => format!("<p><strong>{title}</strong> will appear in digests again.</p>"),
+        Action::Unsubscribe => unreachable!("handled above"),
+    };
+    respond(stream, "200 OK", &body)
+}
+
+/// Read a line of at most `MAX_LINE_LEN` bytes, or `None` if it's longer.
+fn read_line(reader: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_line(&mut line)?;
+    Ok((line.len() <= MAX_LINE_LEN).then_some(line))
+}
+
+/// Limit how long reading a request from a connection and writing its response may take.
+pub fn set_timeouts(stream: &TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))
+}
+
+/// Serve footer link callbacks until the process is killed.
+pub fn serve(
+    config: &Config,
+    config_path: &Path,
+    database: &Mutex<database::Database>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(config.listen)?;
+    eprintln!("Listening on {}...", config.listen);
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| {
+            set_timeouts(&stream)?;
+            handle(config, config_path, &stream, database)
+        });
+        match result {
+            Ok(()) => {}
+            Err(e) => eprintln!("Failed to handle request: {e}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_request_with_query_and_form() {
+        let request = Request::read_from(
+            &b"POST /mute?feed=a HTTP/1.1\r\nContent-Length: 7\r\n\r\ntoken=t"[..],
+        )
+        .expect("failed to read")
+        .expect("no request");
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/mute");
+        assert_eq!(request.param("feed"), Some("a"));
+        assert_eq!(request.param("token"), Some("t"));
+    }
+
+    #[test]
+    fn read_request_rejects_long_lines_and_many_headers() {
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        assert!(Request::read_from(long_line.as_bytes())
+            .expect("failed to read")
+            .is_none());
+        let many_headers = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X: y\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert!(Request::read_from(many_headers.as_bytes())
+            .expect("failed to read")
+            .is_none());
+    }
+
+    #[test]
+    fn tokens_are_signed_per_action() {
+        let feed_url = "https://example.com/feed";
+        let unsubscribe = token("secret", Action::Unsubscribe, feed_url);
+        assert!(verify_token(
+            "secret",
+            Action::Unsubscribe,
+            feed_url,
+            &unsubscribe
+        ));
+        assert!(!verify_token(
+            "secret",
+            Action::Mute,
+            feed_url,
+            &unsubscribe
+        ));
+        assert!(!verify_token(
+            "other",
+            Action::Unsubscribe,
+            feed_url,
+            &unsubscribe
+        ));
+        assert_eq!(
+            Action::from_path(&format!("/{}", Action::Unsubscribe.as_str())),
+            Some(Action::Unsubscribe)
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Detection of challenge pages that bot protection services send instead of a feed, such as
+//! Cloudflare's "Just a moment..." page, so they aren't mistaken for broken feeds.
+
+use attohttpc::header::{HeaderMap, CONTENT_TYPE, SERVER};
+
+/// How much of a page is searched for markers.
+const MAX_SCAN_BYTES: usize = 64 * 1024;
+
+/// Markers in the HTML of challenge pages, lowercase, by the service that sends them.
+const PROVIDER_MARKERS: &[(&str, &[&str])] = &[
+    (
+        "Cloudflare",
+        &[
+            "/cdn-cgi/challenge-platform/",
+            "cf-browser-verification",
+            "cf_chl_opt",
+        ],
+    ),
+    ("DDoS-Guard", &["ddos-guard"]),
+    ("Sucuri", &["sucuri website firewall"]),
+    ("Imperva", &["_incapsula_resource", "incapsula incident id"]),
+    ("Akamai", &["errors.edgesuite.net"]),
+    ("AWS WAF", &["awswafintegration"]),
+];
+
+/// Markers of challenge pages from other services, which are only trusted on error responses,
+/// since ordinary web pages may contain them.
+const GENERIC_MARKERS: &[&str] = &[
+    "checking your browser",
+    "verify you are human",
+    "enable javascript and cookies to continue",
+    "captcha",
+];
+
+/// Name of the service that sent a challenge page instead of a feed, if the response is one.
+pub fn detect(status: u16, headers: &HeaderMap, body: &[u8]) -> Option<&'static str> {
+    if headers
+        .get("cf-mitigated")
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"challenge"))
+    {
+        return Some("Cloudflare");
+    }
+    if !is_html(headers, body) {
+        return None;
+    }
+    let html = String::from_utf8_lossy(&body[..body.len().min(MAX_SCAN_BYTES)]).to_lowercase();
+    let provider = PROVIDER_MARKERS
+        .iter()
+        .find(|(_, markers)| markers.iter().any(|marker| html.contains(marker)))
+        .map(|(provider, _)| *provider);
+    if provider.is_some() {
+        return provider;
+    }
+    let is_error = matches!(status, 403 | 429 | 503);
+    if is_error && GENERIC_MARKERS.iter().any(|marker| html.contains(marker)) {
+        let server = headers.get(SERVER).and_then(|server| server.to_str().ok());
+        return Some(match server {
+            Some(server) if server.eq_ignore_ascii_case("cloudflare") => "Cloudflare",
+            _ => "bot protection",
+        });
+    }
+    None
+}
+
+/// Whether a response is an HTML page, going by its content type, or by its start if it has none.
+pub fn is_html(headers: &HeaderMap, body: &[u8]) -> bool {
+    if let Some(content_type) = headers.get(CONTENT_TYPE) {
+        return content_type
+            .to_str()
+            .is_ok_and(|content_type| content_type.to_lowercase().contains("html"));
+    }
+    let start = String::from_utf8_lossy(&body[..body.len().min(256)]).to_lowercase();
+    let start = start.trim_start_matches('\u{feff}').trim_start();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// How long to wait before fetching a feed again after it got challenge pages `count` times in a
+/// row: an hour, doubling each time, up to a day.
+pub fn backoff(count: u32) -> chrono::Duration {
+    let hours = 1_i64 << count.saturating_sub(1).min(5);
+    chrono::Duration::hours(hours.min(24))
+}
+
+#[cfg(test)]
+mod tests {
+    use attohttpc::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn detect_cloudflare_header() {
+        let headers = headers(&[("cf-mitigated", "challenge")]);
+        assert_eq!(detect(403, &headers, b""), Some("Cloudflare"));
+    }
+
+    #[test]
+    fn detect_provider_markers() {
+        let body = b"<!DOCTYPE html><html><head><title>Just a moment...</title></head>\
+                     <body><script src=\"/cdn-cgi/challenge-platform/h/b/orchestrate\"></script>";
+        assert_eq!(detect(200, &HeaderMap::new(), body), Some("Cloudflare"));
+        let headers = headers(&[("content-type", "text/html; charset=utf-8")]);
+        let body = b"<p>DDoS-Guard is checking your browser</p>";
+        assert_eq!(detect(403, &headers, body), Some("DDoS-Guard"));
+    }
+
+    #[test]
+    fn detect_generic_markers_only_on_errors() {
+        let headers = headers(&[("content-type", "text/html")]);
+        let body = b"<html><body>Please verify you are human</body></html>";
+        assert_eq!(detect(403, &headers, body), Some("bot protection"));
+        assert_eq!(detect(200, &headers, body), None);
+    }
+
+    #[test]
+    fn detect_ignores_feeds_and_other_pages() {
+        let feed = b"<?xml version=\"1.0\"?><rss><channel><title>captcha</title></channel></rss>";
+        assert_eq!(detect(200, &HeaderMap::new(), feed), None);
+        let headers = headers(&[("content-type", "text/html")]);
+        assert_eq!(detect(404, &headers, b"<html>Not found</html>"), None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_day() {
+        assert_eq!(backoff(1), chrono::Duration::hours(1));
+        assert_eq!(backoff(3), chrono::Duration::hours(4));
+        assert_eq!(backoff(6), chrono::Duration::hours(24));
+        assert_eq!(backoff(100), chrono::Duration::hours(24));
+    }
+}
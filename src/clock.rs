@@ -0,0 +1,47 @@
+//! Source of the current time for rendering mail, which can be fixed so the same items render the
+//! same mail every time.
+
+use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
+
+/// When mail is rendered, for dates in subjects, headers, footers, and signatures.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Clock {
+    /// The system clock.
+    #[default]
+    System,
+    /// Always the given time, in UTC, regardless of the local time zone.
+    Fixed(DateTime<Utc>),
+}
+impl Clock {
+    /// Time `--deterministic` fixes the clock at, unless another is given with `--now`.
+    pub fn fixed_default() -> Self {
+        Self::Fixed(Utc.ymd(2000, 1, 1).and_hms(0, 0, 0))
+    }
+
+    pub fn is_fixed(self) -> bool {
+        matches!(self, Self::Fixed(_))
+    }
+
+    pub fn now(self) -> DateTime<Utc> {
+        match self {
+            Self::System => Utc::now(),
+            Self::Fixed(now) => now,
+        }
+    }
+
+    pub fn local_now(self) -> DateTime<FixedOffset> {
+        self.to_local(self.now())
+    }
+
+    /// A time in the local time zone, or in UTC if the clock is fixed, so output doesn't depend on
+    /// where it's rendered.
+    pub fn to_local(self, time: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Self::System => {
+                let local = time.with_timezone(&Local);
+                local.with_timezone(local.offset())
+            }
+            Self::Fixed(_) => time.with_timezone(&FixedOffset::east(0)),
+        }
+    }
+}
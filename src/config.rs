@@ -0,0 +1,822 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::num::{NonZeroU16, NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use lettre::EmailAddress;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    archive, callback, clock::Clock, css, daemon, dkim, feed, hooks, identity::Identity, inbox,
+    later, locale, lua, mx, oauth, pipeline, query, rules, schema, smime, summarize, translate,
+    transport, upgrade, web, window, Error, Result,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// How to show items that have no link.
+    #[serde(default)]
+    pub missing_link: MissingLink,
+    /// Order of feeds in digests, after feeds with `pinned` set.
+    #[serde(default)]
+    pub feed_order: FeedOrder,
+    /// End digests with the version, when feeds were last fetched, which feeds failed, and when
+    /// the next digest is expected.
+    #[serde(default)]
+    pub footer: bool,
+    /// Show a QR code of each item's link in digests, for opening items from printed digests.
+    #[serde(default)]
+    pub qr_codes: bool,
+    /// Show an excerpt of the text of each item's content in digests, with a link to read more,
+    /// for feeds of the secondary tier.
+    #[serde(default)]
+    pub include_content: bool,
+    /// List read items whose content changed since they were mailed, such as with a correction,
+    /// in a section of the next digest.
+    #[serde(default)]
+    pub updated_items: bool,
+    /// Directory of custom templates.
+    #[serde(default)]
+    pub template_dir: Option<PathBuf>,
+    /// Custom template of the digest, in place of `mail.html`.
+    #[serde(default)]
+    pub template_path: Option<PathBuf>,
+    /// Language of the fixed strings in digests.
+    #[serde(default)]
+    pub locale: locale::Locale,
+    /// Resurface a few old items that were highlighted or saved for later in each digest.
+    #[serde(default)]
+    pub archives: Option<archive::Config>,
+    /// Feeds and how to fetch them.
+    pub sources: Sources,
+    /// What to do with items before they're mailed.
+    #[serde(default)]
+    pub filters: Filters,
+    /// Who digests are mailed to, and how.
+    pub delivery: Delivery,
+    #[serde(default)]
+    pub callback: Option<callback::Config>,
+    #[serde(default)]
+    pub daemon: Option<daemon::Config>,
+    /// Web page of unread items for the daemon to serve, if built with the `web` feature.
+    #[serde(default)]
+    pub web: Option<web::Config>,
+    /// Shell commands to run around fetching feeds and sending mail.
+    #[serde(default)]
+    pub hooks: Option<hooks::Config>,
+    /// Lua script to filter and transform items with, if built with the `lua` feature.
+    #[serde(default)]
+    pub lua: Option<lua::Config>,
+    #[serde(default)]
+    pub accessibility: Option<Accessibility>,
+    /// Read-it-later service to save items to.
+    #[serde(default)]
+    pub read_later: Option<later::Config>,
+    /// Translation API for the titles of feeds with `translate` set.
+    #[serde(default)]
+    pub translation: Option<translate::Config>,
+    /// Command or API that summarizes the text of long items of feeds that show content.
+    #[serde(default)]
+    pub summarizer: Option<summarize::Config>,
+    /// IMAP mailbox to check for bounces of and replies to digests.
+    #[serde(default)]
+    pub inbox: Option<inbox::Config>,
+    /// Clock to render mail with, which is set by command line flags rather than the config file.
+    #[serde(skip)]
+    pub clock: Clock,
+}
+
+/// Feeds and how to fetch them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sources {
+    pub concurrency: NonZeroU16,
+    /// Check robots.txt before fetching, and wait for its crawl delay.
+    #[serde(default)]
+    pub respect_robots_txt: bool,
+    /// Directory to save the raw body of each fetched feed in, for debugging.
+    #[serde(default)]
+    pub raw_dir: Option<PathBuf>,
+    /// Number of raw bodies to keep per feed.
+    #[serde(default = "default_raw_keep")]
+    pub raw_keep: usize,
+    #[serde(
+        deserialize_with = "deserialize_feeds",
+        serialize_with = "serialize_feeds"
+    )]
+    pub feeds: Vec<FeedConfig>,
+}
+impl Default for Sources {
+    fn default() -> Self {
+        Self {
+            feeds: vec![FeedConfig::new("https://blog.rust-lang.org/feed.xml")],
+            concurrency: NonZeroU16::new(1).expect("invalid default"),
+            respect_robots_txt: false,
+            raw_dir: None,
+            raw_keep: default_raw_keep(),
+        }
+    }
+}
+
+/// What to do with items before they're mailed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filters {
+    /// Skip items with a link that was included in a digest sent within this many days.
+    #[serde(default)]
+    pub dedup_days: Option<u32>,
+    /// Collapse the new items of a feed into one line of the digest when a fetch finds more than
+    /// this many, such as when a feed is reset and republishes everything.
+    #[serde(default)]
+    pub bulk_threshold: Option<usize>,
+    /// Merge items titled as parts of a series, such as "Part 1" and "Part 2", that were
+    /// published within a day into one digest entry.
+    #[serde(default)]
+    pub merge_parts: bool,
+    /// Replace item links with the URL they redirect to, such as through a tracking service.
+    #[serde(default)]
+    pub resolve_links: bool,
+    /// Rules applied to items as they're stored, which refer to feeds by name or URL.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<rules::Rule>,
+    /// Tags whose items are left out of the regular digest, to only be mailed with `--tag`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclusive_tags: Vec<String>,
+}
+
+/// Who digests are mailed to, and how.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Delivery {
+    // TODO: EmailAddress should validate itself when deserializing.
+    pub from_email: EmailAddress,
+    pub to_email: EmailAddress,
+    /// Number of digests to send at the same time.
+    #[serde(default = "default_mail_concurrency")]
+    pub mail_concurrency: NonZeroU16,
+    /// Maximum number of digests to send per minute.
+    #[serde(default)]
+    pub mail_per_minute: Option<NonZeroU32>,
+    /// Send each item in its own email, threaded by feed, instead of in a digest.
+    #[serde(default)]
+    pub email_per_item: bool,
+    /// Send each feed's items in their own email, with the feed's title in the subject, instead
+    /// of in a digest.
+    #[serde(default)]
+    pub email_per_feed: bool,
+    /// Deliver mail directly to recipients' mail servers, instead of with sendmail.
+    #[serde(default)]
+    pub mx: Option<mx::Config>,
+    /// Send mail another way than with sendmail, unless `mx` is set.
+    #[serde(default)]
+    pub transport: Option<transport::Config>,
+    #[serde(default)]
+    pub smime: Option<smime::Config>,
+    #[serde(default)]
+    pub dkim: Option<dkim::Config>,
+    /// Hours of the day to send mail in. Mail outside them is held until they start.
+    #[serde(default)]
+    pub send_window: Option<window::Config>,
+    /// Named ways of mailing tagged items, each with its own options and schedule, which refer
+    /// to tags set by feeds or rules.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pipelines: Vec<pipeline::Config>,
+}
+impl Default for Delivery {
+    fn default() -> Self {
+        Self {
+            from_email: EmailAddress::new("squeakmail@example.com".to_string())
+                .expect("invalid default"),
+            to_email: EmailAddress::new("squeakmail@example.com".to_string())
+                .expect("invalid default"),
+            mail_concurrency: default_mail_concurrency(),
+            mail_per_minute: None,
+            email_per_item: false,
+            email_per_feed: false,
+            mx: None,
+            transport: None,
+            smime: None,
+            dkim: None,
+            send_window: None,
+            pipelines: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Read a config written for this or an older version. Keys that aren't in this version
+    /// are errors, unless `lenient` is set.
+    pub fn load(path: &Path, lenient: bool) -> Result<Loaded> {
+        let mut config_file = File::open(path)?;
+        let mut config_str = String::new();
+        config_file.read_to_string(&mut config_str)?;
+        Self::parse(&config_str, lenient)
+    }
+
+    /// Parse the text of a config, like [`Config::load`].
+    pub fn parse(config_str: &str, lenient: bool) -> Result<Loaded> {
+        let upgrade = upgrade::upgrade(toml::from_str(config_str)?);
+        let mut value = upgrade.value;
+        let unknown_keys = schema::strip_unknown_keys(&mut value);
+        if !lenient && !unknown_keys.is_empty() {
+            return Err(Error::UnknownConfigKeys(unknown_keys));
+        }
+        let mut config: Self = if upgrade.warnings.is_empty() && unknown_keys.is_empty() {
+            // Errors from parsing the text have line numbers.
+            toml::from_str(config_str)?
+        } else {
+            value.try_into()?
+        };
+        config.check()?;
+        config.resolve_references()?;
+        Ok(Loaded {
+            config,
+            upgraded: upgrade.warnings,
+            unknown_keys,
+        })
+    }
+
+    /// Check that options don't conflict.
+    fn check(&self) -> Result<()> {
+        // Items are stored by feed URL, so a feed listed twice would be fetched twice into the
+        // same items.
+        let mut urls = HashSet::new();
+        if let Some(feed) = self
+            .sources
+            .feeds
+            .iter()
+            .find(|feed| !urls.insert(feed.url.as_str()))
+        {
+            return Err(Error::DuplicateFeed(feed.url.clone()));
+        }
+        if has_conflicting_passwords(self.delivery.transport.as_ref()) {
+            return Err(Error::ConflictingSmtpPasswords(
+                "the SMTP transport".to_string(),
+            ));
+        }
+        // Mailing a pipeline marks its items read, so pipelines of the same items would take
+        // items from each other.
+        for (i, pipeline) in self.delivery.pipelines.iter().enumerate() {
+            if has_conflicting_passwords(pipeline.transport.as_ref()) {
+                return Err(Error::ConflictingSmtpPasswords(format!(
+                    "the SMTP transport of pipeline {:?}",
+                    pipeline.name
+                )));
+            }
+            if pipeline.interval_minutes.is_some() && pipeline.at.is_some() {
+                return Err(Error::ConflictingPipelineSchedules(pipeline.name.clone()));
+            }
+            if let Some(other) = self.delivery.pipelines[..i]
+                .iter()
+                .find(|other| other.name == pipeline.name || other.tag == pipeline.tag)
+            {
+                return Err(Error::ConflictingPipelines(
+                    other.name.clone(),
+                    pipeline.name.clone(),
+                ));
+            }
+        }
+        if let Some(summarizer) = &self.summarizer {
+            if summarizer.command.is_some() == summarizer.url.is_some() {
+                return Err(Error::SummarizerBackend);
+            }
+        }
+        Ok(())
+    }
+
+    /// Point rules at the feeds they name, checking that what sections refer to by name exists.
+    fn resolve_references(&mut self) -> Result<()> {
+        let mut names = HashSet::new();
+        if let Some(name) = self
+            .sources
+            .feeds
+            .iter()
+            .filter_map(|feed| feed.name.as_deref())
+            .find(|name| !names.insert(*name))
+        {
+            return Err(Error::DuplicateFeedName(name.to_string()));
+        }
+        for (i, rule) in self.filters.rules.iter_mut().enumerate() {
+            let Some(feed) = &rule.feed else {
+                continue;
+            };
+            let url = self
+                .sources
+                .feeds
+                .iter()
+                .find(|config| config.name.as_ref() == Some(feed) || config.url == *feed)
+                .map(|config| config.url.clone())
+                .ok_or_else(|| Error::UnknownFeed(format!("rule {}", i + 1), feed.clone()))?;
+            rule.feed_url = Some(url);
+        }
+        let tags: HashSet<&str> = self
+            .sources
+            .feeds
+            .iter()
+            .flat_map(|feed| &feed.tags)
+            .map(String::as_str)
+            .chain(
+                self.filters
+                    .rules
+                    .iter()
+                    .filter_map(|rule| match &rule.action {
+                        rules::Action::Tag(tag) => Some(tag.as_str()),
+                        _ => None,
+                    }),
+            )
+            .collect();
+        for pipeline in &self.delivery.pipelines {
+            if let Some(tag) = pipeline
+                .tag
+                .as_ref()
+                .filter(|tag| !tags.contains(tag.as_str()))
+            {
+                return Err(Error::UnknownTag(pipeline.name.clone(), tag.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Tags whose items are left out of the regular digest: the exclusive tags, and the tags
+    /// that pipelines mail, so their items aren't mailed twice.
+    pub fn exclusive_tags(&self) -> Vec<String> {
+        let mut tags = self.filters.exclusive_tags.clone();
+        tags.extend(
+            self.delivery
+                .pipelines
+                .iter()
+                .filter_map(|pipeline| pipeline.tag.clone()),
+        );
+        tags
+    }
+
+    /// Whether digests show any of the content of a feed's items, so it needs to be kept.
+    pub fn shows_content(&self, feed_config: &FeedConfig) -> bool {
+        match feed_config.tier {
+            Tier::Primary => true,
+            Tier::Secondary => self.include_content,
+            Tier::Firehose => false,
+        }
+    }
+
+    /// Domain of the from address.
+    pub fn sender_domain(&self) -> String {
+        let from_email = self.delivery.from_email.to_string();
+        from_email
+            .rsplit('@')
+            .next()
+            .unwrap_or("localhost")
+            .to_string()
+    }
+}
+/// Config read from a file, with what had to be changed to read it.
+pub struct Loaded {
+    pub config: Config,
+    /// Keys of older versions that were replaced.
+    pub upgraded: Vec<String>,
+    /// Keys that aren't in this version, which were ignored.
+    pub unknown_keys: Vec<String>,
+}
+
+impl std::default::Default for Config {
+    fn default() -> Self {
+        Self {
+            sources: Sources::default(),
+            filters: Filters::default(),
+            delivery: Delivery::default(),
+            missing_link: MissingLink::default(),
+            feed_order: FeedOrder::default(),
+            footer: false,
+            qr_codes: false,
+            include_content: false,
+            updated_items: false,
+            archives: None,
+            template_dir: None,
+            template_path: None,
+            locale: locale::Locale::default(),
+            callback: None,
+            daemon: None,
+            web: None,
+            hooks: None,
+            lua: None,
+            accessibility: None,
+            read_later: None,
+            translation: None,
+            summarizer: None,
+            inbox: None,
+            clock: Clock::System,
+        }
+    }
+}
+
+fn default_mail_concurrency() -> NonZeroU16 {
+    NonZeroU16::new(1).expect("invalid default")
+}
+
+fn default_raw_keep() -> usize {
+    10
+}
+
+/// How to show items that have no link in digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingLink {
+    /// Show the title as plain text.
+    #[default]
+    Text,
+    /// Link the title to the feed's site.
+    FeedLink,
+}
+
+/// Order of feeds in digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedOrder {
+    /// The order feeds are listed in the config file.
+    #[default]
+    Config,
+    /// Alphabetically by title.
+    Alphabetical,
+    /// Feeds with the most items first.
+    UnreadCount,
+}
+
+/// How much of a feed's items digests show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    /// Titles with the text of each item's content.
+    Primary,
+    /// Titles only.
+    #[default]
+    Secondary,
+    /// Titles in a compact list of links.
+    Firehose,
+}
+impl Tier {
+    // Serde passes fields by reference.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Options for reading digests with screen readers and screen magnifiers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Accessibility {
+    /// Base font size of HTML digests, in pixels.
+    #[serde(default)]
+    pub font_size: Option<NonZeroU16>,
+    /// Use black text on a white background, with underlined links.
+    #[serde(default)]
+    pub high_contrast: bool,
+    /// Start HTML digests with a heading, and put each feed in a section under it, so they can
+    /// be navigated by heading.
+    #[serde(default)]
+    pub headings: bool,
+    /// Include a plaintext version of digests, for mail clients that read it instead.
+    #[serde(default)]
+    pub plaintext: bool,
+}
+
+/// Feed to fetch, written either as a URL or as a table with per-feed options.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct FeedConfig {
+    pub url: String,
+    /// Name that rules can refer to the feed by, instead of its URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Tags attached to every item of the feed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Folder the feed is in for other feed readers, with nested folders separated by `/`, which
+    /// `import-opml` and `export-opml` keep. It doesn't change how the feed is mailed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    /// Attach a calendar of events detected in item titles to the digest.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub events: bool,
+    /// What to do with the items already in the feed when it's first fetched.
+    #[serde(default, skip_serializing_if = "OnAdd::is_default")]
+    pub on_add: OnAdd,
+    /// Shell command that each item is piped through as JSON, to rewrite or drop it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    /// Translate item titles with the configured translation API, and show both titles.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub translate: bool,
+    /// List the feed at the top of digests, before feeds without it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Send the feed's items in their own email, with the feed's title in the subject, instead of
+    /// in the digest.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub separate_email: bool,
+    /// How much of the feed's items digests show.
+    #[serde(default, skip_serializing_if = "Tier::is_default")]
+    pub tier: Tier,
+    /// Include at most this many of the feed's unread items in each digest, highlighted items
+    /// first and the others at random, and mark the rest read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<NonZeroUsize>,
+    /// Cut the text of the feed's items short after this many characters, with a link to read
+    /// the rest, instead of showing all of it, or the default length of excerpts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_content_chars: Option<NonZeroUsize>,
+    /// Cut the text of the feed's items short after this many paragraphs, with a link to read the
+    /// rest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_paragraphs: Option<NonZeroUsize>,
+    /// Show the text of the article that each item links to, from the page it's on, instead of
+    /// the item's content, if the feed's tier shows content.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub full_content: bool,
+    /// Elements to remove from articles of `full_content`, such as share buttons, by tag, ID, and
+    /// classes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove_selectors: Vec<css::Selector>,
+    /// Remove paragraphs with fewer characters of text than this from articles of
+    /// `full_content`, such as bylines and captions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_paragraph_chars: Option<NonZeroUsize>,
+    /// Show the images of the feed's content under its text, instead of leaving them out.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_images: bool,
+    /// Fetch the feed with an access token from an OAuth 2.0 token endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<oauth::Config>,
+    /// Query parameters added to the URL each time the feed is fetched, such as a timestamp
+    /// that an API requires.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub query: BTreeMap<String, query::Template>,
+    /// Repair item text that was decoded with the wrong encoding, such as "cafÃ©" for "café".
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub repair_encoding: bool,
+    /// If the feed fails to parse, repair malformed XML, such as unescaped ampersands and control
+    /// characters, and parse it again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lenient_xml: bool,
+    /// What the GUIDs of the feed's items are derived from, to tell new items from updated ones.
+    #[serde(default, skip_serializing_if = "Identity::is_default")]
+    pub identity: Identity,
+}
+impl FeedConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether any per-feed option is set.
+    fn has_options(&self) -> bool {
+        self.name.is_some()
+            || !self.tags.is_empty()
+            || self.folder.is_some()
+            || self.events
+            || !self.on_add.is_default()
+            || self.transform.is_some()
+            || self.translate
+            || self.pinned
+            || self.separate_email
+            || !self.tier.is_default()
+            || self.sample.is_some()
+            || self.max_content_chars.is_some()
+            || self.max_paragraphs.is_some()
+            || self.full_content
+            || !self.remove_selectors.is_empty()
+            || self.min_paragraph_chars.is_some()
+            || self.keep_images
+            || self.auth.is_some()
+            || !self.query.is_empty()
+            || self.repair_encoding
+            || self.lenient_xml
+            || !self.identity.is_default()
+    }
+}
+
+/// What to do with the items already in a feed when it's first fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnAdd {
+    /// Include every item in the next digest.
+    #[default]
+    Deliver,
+    /// Mark every item read.
+    MarkRead,
+    /// Include only this many of the most recent items, and mark the rest read.
+    Latest(usize),
+}
+impl OnAdd {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// GUIDs of items to mark read, if the items are from the first fetch of a feed.
+    pub fn read_guids(self, items: &[feed::Item]) -> HashSet<String> {
+        let mut items: Vec<&feed::Item> = items.iter().collect();
+        items.sort_by_key(|item| std::cmp::Reverse(item.pub_date));
+        let deliver = match self {
+            Self::Deliver => items.len(),
+            Self::MarkRead => 0,
+            Self::Latest(count) => count,
+        };
+        items
+            .into_iter()
+            .skip(deliver)
+            .map(|item| item.guid.clone())
+            .collect()
+    }
+}
+impl fmt::Display for OnAdd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Deliver => f.write_str("deliver"),
+            Self::MarkRead => f.write_str("mark_read"),
+            Self::Latest(count) => write!(f, "latest:{count}"),
+        }
+    }
+}
+impl FromStr for OnAdd {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "deliver" => Ok(Self::Deliver),
+            "mark_read" => Ok(Self::MarkRead),
+            _ => s
+                .strip_prefix("latest:")
+                .and_then(|count| count.parse().ok())
+                .map(Self::Latest)
+                .ok_or_else(|| {
+                    format!("invalid on_add {s:?}, expected \"deliver\", \"mark_read\", or \"latest:N\"")
+                }),
+        }
+    }
+}
+impl Serialize for OnAdd {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for OnAdd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Feed entry that may be written as a plain URL when it has no options.
+struct FeedEntry(FeedConfig);
+impl<'de> Deserialize<'de> for FeedEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct FeedEntryVisitor;
+        impl<'de> Visitor<'de> for FeedEntryVisitor {
+            type Value = FeedEntry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a feed URL or table")
+            }
+
+            fn visit_str<E: de::Error>(self, url: &str) -> std::result::Result<Self::Value, E> {
+                Ok(FeedEntry(FeedConfig::new(url)))
+            }
+
+            fn visit_map<M: MapAccess<'de>>(
+                self,
+                map: M,
+            ) -> std::result::Result<Self::Value, M::Error> {
+                FeedConfig::deserialize(de::value::MapAccessDeserializer::new(map)).map(FeedEntry)
+            }
+        }
+        deserializer.deserialize_any(FeedEntryVisitor)
+    }
+}
+
+fn deserialize_feeds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<FeedConfig>, D::Error> {
+    Ok(Vec::<FeedEntry>::deserialize(deserializer)?
+        .into_iter()
+        .map(|entry| entry.0)
+        .collect())
+}
+
+fn serialize_feeds<S: Serializer>(
+    feeds: &[FeedConfig],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    // TOML arrays can't mix strings and tables.
+    if feeds.iter().all(|feed| !feed.has_options()) {
+        serializer.collect_seq(feeds.iter().map(|feed| &feed.url))
+    } else {
+        serializer.collect_seq(feeds)
+    }
+}
+
+/// Whether an SMTP transport sets both its password and a file to read it from.
+fn has_conflicting_passwords(transport: Option<&transport::Config>) -> bool {
+    matches!(
+        transport,
+        Some(transport::Config::Smtp {
+            password: Some(_),
+            password_file: Some(_),
+            ..
+        })
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str) -> Config {
+        toml::from_str(&format!(
+            "[sources]\n\
+             concurrency = 1\n\
+             feeds = [{{ url = \"https://example.com/feed.xml\", name = \"example\", tags = [\"news\"] }}]\n\
+             [delivery]\n\
+             from_email = \"a@example.com\"\n\
+             to_email = \"b@example.com\"\n\
+             {toml}"
+        ))
+        .expect("invalid config")
+    }
+
+    #[test]
+    fn rules_refer_to_feeds_by_name_or_url() {
+        let mut config = load(
+            "[[filters.rules]]\nfeed = \"example\"\naction = \"drop\"\n\
+             [[filters.rules]]\nfeed = \"https://example.com/feed.xml\"\naction = \"drop\"\n\
+             [[delivery.pipelines]]\nname = \"news\"\ntag = \"news\"\n",
+        );
+        config.resolve_references().expect("failed to resolve");
+        for rule in &config.filters.rules {
+            assert_eq!(
+                rule.feed_url.as_deref(),
+                Some("https://example.com/feed.xml")
+            );
+            assert!(rule.applies_to_feed("https://example.com/feed.xml"));
+        }
+    }
+
+    #[test]
+    fn unknown_references_are_errors() {
+        let mut config = load("[[filters.rules]]\nfeed = \"exmaple\"\naction = \"drop\"\n");
+        assert_eq!(
+            config.resolve_references().map_err(|e| e.to_string()),
+            Err("rule 1 refers to feed \"exmaple\", which isn't in sources.feeds".to_string())
+        );
+
+        let mut config = load("[[delivery.pipelines]]\nname = \"alerts\"\ntag = \"alerts\"\n");
+        assert_eq!(
+            config.resolve_references().map_err(|e| e.to_string()),
+            Err("pipeline \"alerts\" mails tag \"alerts\", which no feed or rule sets".to_string())
+        );
+    }
+
+    #[test]
+    fn smtp_password_is_set_once() {
+        let config = load(
+            "[delivery.transport]\ntype = \"smtp\"\nhost = \"smtp.example.com\"\n\
+             password = \"secret\"\npassword_file = \"/run/secrets/smtp\"\n",
+        );
+        assert_eq!(
+            config.check().map_err(|e| e.to_string()),
+            Err("the SMTP transport sets both password and password_file".to_string())
+        );
+
+        let config = load(
+            "[[delivery.pipelines]]\nname = \"alerts\"\n\
+             [delivery.pipelines.transport]\ntype = \"smtp\"\nhost = \"smtp.example.com\"\n\
+             password = \"secret\"\npassword_file = \"/run/secrets/smtp\"\n",
+        );
+        assert_eq!(
+            config.check().map_err(|e| e.to_string()),
+            Err(
+                "the SMTP transport of pipeline \"alerts\" sets both password and password_file"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn summarizer_sets_one_backend() {
+        let error = Err("summarizer must set one of command and url".to_string());
+        let config = load("[summarizer]\nmin_chars = 1000\n");
+        assert_eq!(config.check().map_err(|e| e.to_string()), error);
+        let config = load("[summarizer]\ncommand = \"summarize\"\nurl = \"http://localhost/\"\n");
+        assert_eq!(config.check().map_err(|e| e.to_string()), error);
+        let config = load("[summarizer]\ncommand = \"summarize\"\n");
+        assert!(config.check().is_ok());
+    }
+}
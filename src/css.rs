@@ -0,0 +1,349 @@
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use regex::{Captures, Regex};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Selector of a single element by tag, ID, and classes, such as `p`, `.item`, or `a#top.link`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+impl Selector {
+    /// Parse a selector, if it only selects by tag, ID, and classes.
+    fn parse(selector: &str) -> Option<Self> {
+        let selector = selector.trim();
+        let tag_len = selector
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(selector.len());
+        let (tag, mut rest) = selector.split_at(tag_len);
+        let mut parsed = Self {
+            tag: Some(tag.to_lowercase()).filter(|tag| !tag.is_empty()),
+            id: None,
+            classes: Vec::new(),
+        };
+        while let Some(kind) = rest.chars().next() {
+            let name = &rest[kind.len_utf8()..];
+            let name_len = name
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or(name.len());
+            if name_len == 0 {
+                return None;
+            }
+            match kind {
+                '#' if parsed.id.is_none() => parsed.id = Some(name[..name_len].to_string()),
+                '.' => parsed.classes.push(name[..name_len].to_string()),
+                _ => return None,
+            }
+            rest = &name[name_len..];
+        }
+        if parsed.tag.is_none() && parsed.id.is_none() && parsed.classes.is_empty() {
+            return None;
+        }
+        Some(parsed)
+    }
+
+    fn specificity(&self) -> (usize, usize, usize) {
+        (
+            usize::from(self.id.is_some()),
+            self.classes.len(),
+            usize::from(self.tag.is_some()),
+        )
+    }
+
+    pub fn matches(&self, tag: &str, id: Option<&str>, classes: &[&str]) -> bool {
+        self.tag
+            .as_ref()
+            .is_none_or(|t| t.eq_ignore_ascii_case(tag))
+            && self.id.as_ref().is_none_or(|i| Some(i.as_str()) == id)
+            && self.classes.iter().all(|c| classes.contains(&c.as_str()))
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(tag) = &self.tag {
+            f.write_str(tag)?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, "#{id}")?;
+        }
+        for class in &self.classes {
+            write!(f, ".{class}")?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| {
+            format!("unsupported selector {s:?}, expected a tag, ID, and classes, such as \"div.share\"")
+        })
+    }
+}
+impl Serialize for Selector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for Selector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Rule of a stylesheet that can be inlined.
+struct Rule {
+    selector: Selector,
+    /// Declarations, each ending with a semicolon.
+    declarations: String,
+}
+
+/// Moves CSS rules from `<style>` elements into the `style` attributes of the elements they
+/// select, since many email clients ignore `<style>` elements.
+pub struct Inliner {
+    style: Regex,
+    comment: Regex,
+    start_tag: Regex,
+    attribute: Regex,
+}
+impl Default for Inliner {
+    fn default() -> Self {
+        Self {
+            style: Regex::new(r"(?is)<style[^>]*>(.*?)</style>").expect("invalid regex"),
+            comment: Regex::new(r"(?s)/\*.*?\*/").expect("invalid regex"),
+            start_tag: Regex::new(r"<([a-zA-Z][a-zA-Z0-9]*)(\s[^>]*)?>").expect("invalid regex"),
+            attribute: Regex::new(
+                r#"([a-zA-Z_:][-a-zA-Z0-9_:.]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+)))?"#,
+            )
+            .expect("invalid regex"),
+        }
+    }
+}
+impl Inliner {
+    /// Inline the rules of every `<style>` element.
+    ///
+    /// Only rules with selectors of a single element are inlined. Other rules, such as media
+    /// queries, are kept in a `<style>` element for the clients that support them.
+    pub fn inline(&self, html: &str) -> String {
+        let mut rules = Vec::new();
+        let html = self.style.replace_all(html, |captures: &Captures| {
+            let css = self.comment.replace_all(&captures[1], "");
+            let kept = parse_stylesheet(&css, &mut rules);
+            if kept.trim().is_empty() {
+                String::new()
+            } else {
+                format!("<style>{}</style>", kept.trim())
+            }
+        });
+        if rules.is_empty() {
+            return html.into_owned();
+        }
+        self.start_tag
+            .replace_all(&html, |captures: &Captures| {
+                self.inline_tag(captures, &rules)
+                    .unwrap_or_else(|| captures[0].to_string())
+            })
+            .into_owned()
+    }
+
+    /// Rewrite a start tag with the declarations of the rules that select it, if any do.
+    fn inline_tag(&self, captures: &Captures, rules: &[Rule]) -> Option<String> {
+        let tag = &captures[1];
+        let attributes = captures.get(2).map_or("", |attributes| attributes.as_str());
+        let mut id = None;
+        let mut classes = Vec::new();
+        let mut style = None;
+        for attribute in self.attribute.captures_iter(attributes) {
+            let value = attribute
+                .get(2)
+                .or_else(|| attribute.get(3))
+                .or_else(|| attribute.get(4))
+                .map_or("", |value| value.as_str());
+            match attribute[1].to_lowercase().as_str() {
+                "id" => id = Some(value),
+                "class" => classes = value.split_whitespace().collect(),
+                "style" => {
+                    let whole = attribute.get(0).expect("capture 0 always matches");
+                    style = Some((whole.start()..whole.end(), value));
+                }
+                _ => {}
+            }
+        }
+        let mut matching: Vec<(usize, &Rule)> = rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.selector.matches(tag, id, &classes))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // Later rules win ties of specificity, like in a stylesheet.
+        matching.sort_by_key(|(order, rule)| (rule.selector.specificity(), *order));
+        let mut declarations: String = matching
+            .iter()
+            .map(|(_, rule)| rule.declarations.replace('"', "'"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut attributes = attributes.to_string();
+        // Declarations in the style attribute win over the stylesheet.
+        if let Some((range, value)) = style {
+            declarations = format!("{declarations} {value}");
+            attributes.replace_range(range, "");
+        }
+        let attributes = attributes.trim_end();
+        let (attributes, self_closing) = match attributes.strip_suffix('/') {
+            Some(attributes) => (attributes.trim_end(), " /"),
+            None => (attributes, ""),
+        };
+        Some(format!(
+            "<{tag}{attributes} style=\"{}\"{self_closing}>",
+            declarations.trim()
+        ))
+    }
+}
+
+/// Add the rules of a stylesheet that can be inlined, and return the rest.
+fn parse_stylesheet(css: &str, rules: &mut Vec<Rule>) -> String {
+    let mut kept = String::new();
+    let mut rest = css.trim_start();
+    while !rest.is_empty() {
+        if rest.starts_with('@') {
+            // Keep at-rules, such as media queries, with any nested rules.
+            let end = at_rule_len(rest);
+            kept.push_str(&rest[..end]);
+            kept.push('\n');
+            rest = rest[end..].trim_start();
+            continue;
+        }
+        let Some(open) = rest.find('{') else {
+            break;
+        };
+        let close = rest[open..]
+            .find('}')
+            .map_or(rest.len(), |close| open + close);
+        let selectors = &rest[..open];
+        let declarations = normalize_declarations(&rest[open + 1..close]);
+        let mut unsupported = Vec::new();
+        for selector in selectors.split(',') {
+            match Selector::parse(selector) {
+                Some(selector) => rules.push(Rule {
+                    selector,
+                    declarations: declarations.clone(),
+                }),
+                None => unsupported.push(selector.trim()),
+            }
+        }
+        if !unsupported.is_empty() {
+            writeln!(kept, "{} {{ {declarations} }}", unsupported.join(", "))
+                .expect("writing to a string");
+        }
+        rest = rest[(close + 1).min(rest.len())..].trim_start();
+    }
+    kept
+}
+
+/// Length of the at-rule at the start of a stylesheet, up to its semicolon or closing brace.
+fn at_rule_len(css: &str) -> usize {
+    let mut depth = 0;
+    for (index, c) in css.char_indices() {
+        match c {
+            ';' if depth == 0 => return index + 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return index + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// Trim declarations and end each with a semicolon.
+fn normalize_declarations(declarations: &str) -> String {
+    declarations
+        .split(';')
+        .map(str::trim)
+        .filter(|declaration| !declaration.is_empty())
+        .map(|declaration| format!("{declaration};"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline(html: &str) -> String {
+        Inliner::default().inline(html)
+    }
+
+    #[test]
+    fn selector_parses_tags_ids_and_classes() {
+        assert_eq!(
+            Selector::parse(" a#top.link.big "),
+            Some(Selector {
+                tag: Some("a".to_string()),
+                id: Some("top".to_string()),
+                classes: vec!["link".to_string(), "big".to_string()],
+            })
+        );
+        assert!(Selector::parse("ol li").is_none());
+        assert!(Selector::parse("a:hover").is_none());
+        assert!(Selector::parse("*").is_none());
+        assert!(Selector::parse(".").is_none());
+        let selector: Selector = "DIV#top.share.big".parse().expect("failed to parse");
+        assert_eq!(selector.to_string(), "div#top.share.big");
+        assert!("div > p".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn inline_leaves_html_without_style_elements() {
+        let html = r#"<p style="color: red;">Hi</p>"#;
+        assert_eq!(inline(html), html);
+    }
+
+    #[test]
+    fn inline_applies_rules_by_specificity_and_order() {
+        assert_eq!(
+            inline(
+                "<style>\
+                 /* Links */ .item a { color: red } \
+                 .title { color: blue; font-weight: bold } \
+                 a { color: green; font-family: \"Helvetica\" } \
+                 h2, p { margin: 0 }\
+                 </style>\
+                 <h2>Feed</h2>\
+                 <a class=\"title\" href=\"/1\">One</a>\
+                 <a href='/2' style=\"color: black\">Two</a>\
+                 <br/>"
+            ),
+            "<style>.item a { color: red; }</style>\
+             <h2 style=\"margin: 0;\">Feed</h2>\
+             <a class=\"title\" href=\"/1\" style=\"color: green; font-family: 'Helvetica'; \
+             color: blue; font-weight: bold;\">One</a>\
+             <a href='/2' style=\"color: green; font-family: 'Helvetica'; color: black\">Two</a>\
+             <br/>"
+        );
+    }
+
+    #[test]
+    fn inline_keeps_at_rules() {
+        assert_eq!(
+            inline(
+                "<style>@media (max-width: 600px) { p { margin: 0 } } p { color: red }</style>\
+                 <p />"
+            ),
+            "<style>@media (max-width: 600px) { p { margin: 0 } }</style><p style=\"color: red;\" />"
+        );
+    }
+}
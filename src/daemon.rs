@@ -0,0 +1,298 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    callback, database, fetch_feeds, hooks, mail, metrics, pipeline, web, Error, Phase, Result,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Fetch feeds this often, instead of leaving fetching to a job scheduler.
+    pub fetch_interval_minutes: NonZeroU32,
+    /// Wait up to this long before the first fetch, so daemons started together don't fetch
+    /// together.
+    #[serde(default)]
+    pub startup_jitter_seconds: u32,
+    /// Wait up to this much longer than the fetch interval between fetches.
+    #[serde(default)]
+    pub jitter_seconds: u32,
+    /// Checkpoint and vacuum the database this often, between fetches.
+    #[serde(default = "default_maintenance_interval_minutes")]
+    pub maintenance_interval_minutes: NonZeroU32,
+    /// Free up to this many unused pages of each database file at a time.
+    #[serde(default = "default_vacuum_pages")]
+    pub vacuum_pages: NonZeroU32,
+    /// Write metrics of each round of fetching and mailing to this file, for the node exporter's
+    /// textfile collector.
+    #[serde(default)]
+    pub metrics_textfile: Option<PathBuf>,
+}
+
+fn default_maintenance_interval_minutes() -> NonZeroU32 {
+    NonZeroU32::new(60).expect("invalid default")
+}
+
+fn default_vacuum_pages() -> NonZeroU32 {
+    NonZeroU32::new(1000).expect("invalid default")
+}
+
+/// Serve callbacks and fetch feeds periodically, depending on what's configured.
+pub fn run(config: crate::Config, config_path: &Path, database: database::Database) -> Result<()> {
+    let config = Arc::new(config);
+    let database = Arc::new(Mutex::new(database));
+    if config.callback.is_none() && config.web.is_none() && config.daemon.is_none() {
+        return Err(Error::DaemonNotConfigured);
+    }
+    let web_server = config
+        .web
+        .as_ref()
+        .map(web::Server::bind)
+        .transpose()
+        .map_err(Error::WebServer)?
+        .map(|web_server| {
+            let config = config.clone();
+            let database = database.clone();
+            thread::spawn(move || web_server.serve(&config, &database))
+        });
+    let server = config.callback.is_some().then(|| {
+        let config = config.clone();
+        let config_path = config_path.to_path_buf();
+        let database = database.clone();
+        thread::spawn(move || {
+            let callback = config.callback.as_ref().expect("callback is configured");
+            callback::serve(callback, &config_path, &database)
+        })
+    });
+    if let Some(daemon) = &config.daemon {
+        let fetch_interval =
+            Duration::from_secs(u64::from(daemon.fetch_interval_minutes.get()) * 60);
+        let maintenance_interval =
+            Duration::from_secs(u64::from(daemon.maintenance_interval_minutes.get()) * 60);
+        database
+            .lock()
+            .expect("thread panicked while holding database mutex")
+            .enable_maintenance()?;
+        thread::sleep(random_delay(daemon.startup_jitter_seconds));
+        let mut last_maintenance = Instant::now();
+        let mut errors = 0;
+        loop {
+            let errors_before = errors;
+            let summary = match fetch_due_feeds(&config, &database) {
+                Ok(summary) => summary,
+                Err(e) => {
+                    keep_running(e.context(Phase::Fetch, None), &mut errors)?;
+                    None
+                }
+            };
+            crate::poll_inbox(
+                &config,
+                &mut database
+                    .lock()
+                    .expect("thread panicked while holding database mutex"),
+            );
+            if let Err(e) = mail_due_pipelines(&config, &database) {
+                keep_running(e.context(Phase::Mail, None), &mut errors)?;
+            }
+            // Fetches have finished, and callbacks wait for the database while it runs.
+            if last_maintenance.elapsed() >= maintenance_interval {
+                let result = database
+                    .lock()
+                    .expect("thread panicked while holding database mutex")
+                    .run_maintenance(daemon.vacuum_pages.get());
+                if let Err(e) = result {
+                    eprintln!("Database maintenance failed: {e}");
+                }
+                last_maintenance = Instant::now();
+            }
+            if let Some(path) = &daemon.metrics_textfile {
+                write_metrics(path, summary.as_ref(), errors == errors_before, errors);
+            }
+            thread::sleep(
+                until_next_tick(&config, &database, fetch_interval).unwrap_or_else(|e| {
+                    eprintln!("Failed to schedule the next fetch: {e}");
+                    errors += 1;
+                    fetch_interval
+                }) + random_delay(daemon.jitter_seconds),
+            );
+        }
+    }
+    if let Some(server) = server {
+        server
+            .join()
+            .expect("thread panicked")
+            .map_err(Error::CallbackServer)?;
+    }
+    if let Some(web_server) = web_server {
+        web_server.join().expect("thread panicked");
+    }
+    Ok(())
+}
+
+/// Fetch the feeds that are due, returning the summary of the fetch if any were.
+fn fetch_due_feeds(
+    config: &Arc<crate::Config>,
+    database: &Arc<Mutex<database::Database>>,
+) -> Result<Option<hooks::PostFetch>> {
+    let next_fetches = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_next_fetches()?;
+    let now = Utc::now();
+    // Feeds fetched before the daemon last stopped wait until they're due.
+    let due = config
+        .sources
+        .feeds
+        .iter()
+        .filter(|feed| next_fetches.get(&feed.url).is_none_or(|at| *at <= now))
+        .cloned()
+        .collect::<Vec<_>>();
+    if due.is_empty() {
+        return Ok(None);
+    }
+    fetch_feeds(config, due, database, None).map(Some)
+}
+
+/// Log an error of a round of fetching and mailing, so the daemon tries again in the next one,
+/// unless it was interrupted or the config is invalid, which trying again won't fix.
+fn keep_running(e: Error, errors: &mut usize) -> Result<()> {
+    // Exit statuses of config errors and interruptions.
+    if matches!(e.exit_code(), 2 | 130) {
+        return Err(e);
+    }
+    eprintln!("error: {e}");
+    *errors += 1;
+    Ok(())
+}
+
+/// Write the metrics of a round of fetching and mailing, logging failures so the daemon keeps
+/// running.
+fn write_metrics(path: &Path, summary: Option<&hooks::PostFetch>, succeeded: bool, errors: usize) {
+    let run = metrics::Run::daemon(Utc::now(), summary, succeeded, errors);
+    if let Err(e) = metrics::write(path, &run) {
+        eprintln!("Failed to write metrics: {e}");
+    }
+}
+
+/// Time until the next feed or scheduled pipeline is due, or the fetch interval if no feed has
+/// been scheduled.
+fn until_next_tick(
+    config: &crate::Config,
+    database: &Mutex<database::Database>,
+    fetch_interval: Duration,
+) -> Result<Duration> {
+    let until_next_fetch = until_next_fetch(config, database)?.unwrap_or(fetch_interval);
+    Ok(match until_next_pipeline(config, database)? {
+        Some(until_next_pipeline) => until_next_fetch.min(until_next_pipeline),
+        None => until_next_fetch,
+    })
+}
+
+/// Mail the scheduled pipelines that are due, logging failures so the daemon keeps running.
+fn mail_due_pipelines(config: &crate::Config, database: &Mutex<database::Database>) -> Result<()> {
+    if !config
+        .delivery
+        .pipelines
+        .iter()
+        .any(pipeline::Config::is_scheduled)
+    {
+        return Ok(());
+    }
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    let runs = database.get_pipeline_runs()?;
+    let now = Local::now();
+    for pipeline in &config.delivery.pipelines {
+        if !pipeline.is_due(runs.get(&pipeline.name).copied(), &now) {
+            continue;
+        }
+        eprintln!("Mailing pipeline {}...", pipeline.name);
+        let result = mail(
+            &pipeline.apply(config),
+            &mut database,
+            false,
+            pipeline.tag.as_deref(),
+        );
+        if let Err(e) = result {
+            eprintln!("Failed to mail pipeline {}: {e}", pipeline.name);
+        }
+        database.set_pipeline_run(&pipeline.name, now.with_timezone(&Utc))?;
+    }
+    Ok(())
+}
+
+/// Record when a fetched feed is next due, so a restarted daemon doesn't fetch it early.
+pub fn schedule_next_fetch(
+    daemon: &Config,
+    feed_url: &str,
+    database: &Mutex<database::Database>,
+) -> Result<()> {
+    let fetch_interval = chrono::Duration::minutes(i64::from(daemon.fetch_interval_minutes.get()));
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .set_next_fetch(feed_url, Utc::now() + fetch_interval)?;
+    Ok(())
+}
+
+/// Time until the first configured feed is due, if any feed has been scheduled.
+fn until_next_fetch(
+    config: &crate::Config,
+    database: &Mutex<database::Database>,
+) -> Result<Option<Duration>> {
+    let next_fetches = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_next_fetches()?;
+    Ok(config
+        .sources
+        .feeds
+        .iter()
+        .filter_map(|feed| next_fetches.get(&feed.url))
+        .min()
+        .map(|at| (*at - Utc::now()).to_std().unwrap_or_default()))
+}
+
+/// Time until the first scheduled pipeline is due, if any pipeline is scheduled.
+fn until_next_pipeline(
+    config: &crate::Config,
+    database: &Mutex<database::Database>,
+) -> Result<Option<Duration>> {
+    if !config
+        .delivery
+        .pipelines
+        .iter()
+        .any(pipeline::Config::is_scheduled)
+    {
+        return Ok(None);
+    }
+    let runs = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_pipeline_runs()?;
+    let now = Local::now();
+    Ok(config
+        .delivery
+        .pipelines
+        .iter()
+        .filter_map(|pipeline| pipeline.next_run(runs.get(&pipeline.name).copied(), &now))
+        .min()
+        .map(|at| (at - now.with_timezone(&Utc)).to_std().unwrap_or_default()))
+}
+
+/// Random delay of up to `max_seconds`.
+fn random_delay(max_seconds: u32) -> Duration {
+    // The standard library has no random number generator, but hashers are randomly seeded.
+    let random = RandomState::new().build_hasher().finish();
+    let max_millis = u64::from(max_seconds) * 1000;
+    Duration::from_millis(random % (max_millis + 1))
+}
@@ -1,84 +1,465 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::path::Path;
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use derive_more::{Display, From};
 use rusqlite::OptionalExtension;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, From, Display)]
 pub enum Error {
     Sql(rusqlite::Error),
-    #[display(fmt = "unknown database version: {}", _0)]
+    #[display(fmt = "unknown database version: {_0}")]
     UnknownVersion(u32),
+    #[from(ignore)]
+    #[display(fmt = "unknown cache version: {_0}")]
+    UnknownCacheVersion(u32),
+    #[display(fmt = "database needs migrating, which can't be done read-only")]
+    NotMigrated,
 }
 
 type Result<T = ()> = std::result::Result<T, Error>;
 
-#[derive(Debug, Serialize)]
+/// Value of the `auto_vacuum` pragma when incremental vacuuming is enabled.
+const AUTO_VACUUM_INCREMENTAL: u32 = 2;
+
+/// Version of the state database that the migrations lead to.
+const VERSION: u32 = 24;
+/// Version of the cache that the cache migrations lead to.
+const CACHE_VERSION: u32 = 11;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Feed {
     pub url: String,
     pub link: String,
     pub title: String,
     pub etag: Option<String>,
     pub last_modified: Option<String>,
+    pub is_muted: bool,
+    /// URL of the hub that pushes updates of the feed to subscribers.
+    pub hub: Option<String>,
+    /// Canonical URL the feed gives for itself, which may differ from the URL it's fetched from.
+    pub self_url: Option<String>,
+    /// Identity strategy the GUIDs of the feed's items were derived with, as it's configured.
+    pub identity: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Item {
     pub feed_url: String,
     pub guid: String,
     pub title: String,
+    /// Empty if the item has no link.
     pub link: String,
     pub comments_link: Option<String>,
     pub pub_date: DateTime<Utc>,
     pub is_read: bool,
+    pub is_highlighted: bool,
+    pub recipient: Option<String>,
+    pub tags: Vec<String>,
+    /// Whether the link was found to be gone when it was last checked.
+    #[serde(default)]
+    pub is_dead: bool,
+    /// Whether the item came in a fetch with so many new items that the digest collapses them.
+    #[serde(default)]
+    pub is_bulk: bool,
+    /// Title translated to the configured language, if the feed is translated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translated_title: Option<String>,
+    /// Fetch the item was first stored from, unless it was stored by an older version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    /// Later parts of a multi-part post that were merged into this item for the digest.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<Item>,
+    /// Image of the QR code of the link for the digest, as a data URI, if enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qr_code: Option<String>,
+    /// Number the item is listed with in the digest, for replies to refer to, if enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<usize>,
+    /// Paragraphs of the text of the item's content, if its feed's tier shows it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content: Vec<String>,
+    /// Paragraphs of the start of the text of the item's content, if `include_content` is set
+    /// and its feed's tier shows titles only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excerpt: Vec<String>,
+    /// Whether text was left out of the item's content or excerpt, so digests link to the rest.
+    #[serde(default)]
+    pub is_truncated: bool,
+    /// URLs of the images of the item's content, if its feed keeps them and its tier shows it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// Summary of the text of the item's content, if it's long and a summarizer is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+/// Content of an item, to show in digests.
+#[derive(Debug)]
+pub struct ItemContent {
+    pub content: String,
+    /// Summary of the text of the content, if it was summarized.
+    pub summary: Option<String>,
+}
+
+/// Article page that an item links to, downloaded to show its text in digests.
+#[derive(Debug)]
+pub struct Article {
+    pub body: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When the page was last downloaded, or found to be unchanged.
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Fetch an item was first stored from, for finding where a surprising item came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    /// URL the feed was fetched from, after query parameters and redirects.
+    pub url: String,
+    pub fetched_at: DateTime<Utc>,
+    /// Time the fetch took, in milliseconds.
+    pub fetch_ms: i64,
+}
+
+/// Read item that may be resurfaced in a digest.
+#[derive(Debug)]
+pub struct ArchiveCandidate {
+    pub item: Item,
+    /// How many of highlighted and saved for later the item is.
+    pub marks: u32,
+    /// Number of digests the item was resurfaced in.
+    pub resurfaced_count: u32,
+    /// Random number for sampling, drawn by the database.
+    pub random: u32,
+}
+
+/// What was done with an item, which can't be fetched again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ItemState {
+    pub feed_url: String,
+    pub guid: String,
+    pub is_read: bool,
+    #[serde(default)]
+    pub is_highlighted: bool,
+    /// Whether the item was saved to the read-it-later service.
+    #[serde(default)]
+    pub is_saved: bool,
+}
+
+/// Change of a feed's title or link, which may mean someone else took it over.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedChange {
+    pub feed_url: String,
+    pub changed_at: DateTime<Utc>,
+    pub old_title: String,
+    pub new_title: String,
+    pub old_link: String,
+    pub new_link: String,
+}
+
+/// Item included in a sent digest.
+#[derive(Debug, Clone)]
+pub struct DigestItem {
+    pub feed_url: String,
+    pub guid: String,
+    pub link: String,
+}
+
+/// Run of fetching feeds.
+pub struct FetchRun {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+}
+
+/// Digest that was sent, with the feed URLs and GUIDs of its items.
+pub struct SentDigest {
+    pub sent_at: DateTime<Utc>,
+    pub items: HashSet<(String, String)>,
+}
+
+/// Bounce of a digest that was sent.
+#[derive(Debug)]
+pub struct Bounce {
+    pub recipient: String,
+    pub sent_at: DateTime<Utc>,
+    pub status: Option<String>,
+    pub diagnostic: Option<String>,
 }
 
+/// Recipient whose latest digests bounced.
+#[derive(Debug)]
+pub struct DeliveryFailure {
+    pub recipient: String,
+    /// Number of the latest digests in a row that bounced.
+    pub bounced: u32,
+    /// Bounce of the latest digest.
+    pub last: Bounce,
+}
+
+/// Rendered mail waiting to be delivered.
+#[derive(Debug)]
+pub struct OutboxMail {
+    pub id: i64,
+    pub recipient: String,
+    /// Serialized envelope.
+    pub envelope: String,
+    pub message_id: String,
+    pub message: Vec<u8>,
+    pub items: Vec<DigestItem>,
+    pub queued_at: DateTime<Utc>,
+    /// Number of failed delivery attempts.
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// OAuth 2.0 access token for fetching feeds, cached until it expires.
+#[derive(Debug)]
+pub struct AccessToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Challenge pages that a feed got instead of the feed, in a row.
+#[derive(Debug)]
+pub struct Challenge {
+    /// Service that sent the last challenge page.
+    pub provider: String,
+    pub challenged_at: DateTime<Utc>,
+    pub count: u32,
+}
+
+/// HTTP response to a feed request.
+#[derive(Debug)]
+pub struct Fetch {
+    pub feed_url: String,
+    pub fetched_at: DateTime<Utc>,
+    pub status: u16,
+    /// Size of the response body.
+    pub bytes: i64,
+    /// Whether the request included validators.
+    pub is_conditional: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// SHA-256 of the response body, if it was downloaded.
+    pub body_hash: Option<String>,
+    /// Time taken to send the request and download the response, in milliseconds, if it was
+    /// recorded.
+    pub duration_ms: Option<i64>,
+}
+
+/// Totals of the recorded fetches of a feed.
+#[derive(Debug)]
+pub struct FetchStats {
+    pub feed_url: String,
+    pub fetches: u32,
+    pub not_modified: u32,
+    pub bytes: i64,
+    pub last_fetched_at: DateTime<Utc>,
+}
+
+/// Database of state, such as which items have been read, with an attached cache of data that
+/// can be fetched again, such as validators and the fetch log.
+///
+/// Deleting the cache loses no state.
 pub struct Database {
     connection: rusqlite::Connection,
 }
 impl Database {
-    pub fn open(path: &Path) -> Result<Self> {
+    pub fn open(path: &Path, cache_path: &Path) -> Result<Self> {
         let connection = rusqlite::Connection::open(path)?;
+        connection.execute(
+            "ATTACH DATABASE ? AS cache",
+            rusqlite::params![cache_path.to_string_lossy()],
+        )?;
         let mut database = Self { connection };
+        // Later state migrations move data into the cache.
+        database.run_cache_migrations()?;
         database.run_migrations()?;
         Ok(database)
     }
 
+    /// Open the databases without writing to them, such as while a daemon writes to them. Fails
+    /// if they need migrating.
+    pub fn open_read_only(path: &Path, cache_path: &Path) -> Result<Self> {
+        let connection = rusqlite::Connection::open_with_flags(
+            read_only_uri(path),
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_URI,
+        )?;
+        connection.execute(
+            "ATTACH DATABASE ? AS cache",
+            rusqlite::params![read_only_uri(cache_path)],
+        )?;
+        let user_version = |schema: &str| -> Result<u32> {
+            Ok(connection.query_row_and_then(
+                &format!("PRAGMA {schema}.user_version"),
+                rusqlite::NO_PARAMS,
+                |row| row.get(0),
+            )?)
+        };
+        let (version, cache_version) = (user_version("main")?, user_version("cache")?);
+        if version > VERSION {
+            return Err(Error::UnknownVersion(version));
+        }
+        if cache_version > CACHE_VERSION {
+            return Err(Error::UnknownCacheVersion(cache_version));
+        }
+        if version < VERSION || cache_version < CACHE_VERSION {
+            return Err(Error::NotMigrated);
+        }
+        Ok(Self { connection })
+    }
+
+    fn run_cache_migrations(&mut self) -> Result<()> {
+        loop {
+            let user_version: u32 = self.connection.query_row_and_then(
+                "PRAGMA cache.user_version",
+                rusqlite::NO_PARAMS,
+                |row| row.get(0),
+            )?;
+            let migration = match user_version {
+                0 => include_str!("../resources/create_cache.sql"),
+                1 => include_str!("../resources/migrate_cache_v2.sql"),
+                2 => include_str!("../resources/migrate_cache_v3.sql"),
+                3 => include_str!("../resources/migrate_cache_v4.sql"),
+                4 => include_str!("../resources/migrate_cache_v5.sql"),
+                5 => include_str!("../resources/migrate_cache_v6.sql"),
+                6 => include_str!("../resources/migrate_cache_v7.sql"),
+                7 => include_str!("../resources/migrate_cache_v8.sql"),
+                8 => include_str!("../resources/migrate_cache_v9.sql"),
+                9 => include_str!("../resources/migrate_cache_v10.sql"),
+                10 => include_str!("../resources/migrate_cache_v11.sql"),
+                CACHE_VERSION => return Ok(()),
+                version => return Err(Error::UnknownCacheVersion(version)),
+            };
+            self.connection.execute_batch(migration)?;
+        }
+    }
+
     fn run_migrations(&mut self) -> Result<()> {
-        let user_version: u32 = self.connection.query_row_and_then(
-            "PRAGMA user_version",
-            rusqlite::NO_PARAMS,
-            |row| row.get(0),
-        )?;
-        match user_version {
-            0 => {
-                self.connection
-                    .execute_batch(include_str!("../resources/create_db.sql"))?;
-                Ok(())
+        loop {
+            let user_version: u32 = self.connection.query_row_and_then(
+                "PRAGMA user_version",
+                rusqlite::NO_PARAMS,
+                |row| row.get(0),
+            )?;
+            let migration = match user_version {
+                0 => include_str!("../resources/create_db.sql"),
+                1 => include_str!("../resources/migrate_v2.sql"),
+                2 => include_str!("../resources/migrate_v3.sql"),
+                3 => include_str!("../resources/migrate_v4.sql"),
+                4 => include_str!("../resources/migrate_v5.sql"),
+                5 => include_str!("../resources/migrate_v6.sql"),
+                6 => include_str!("../resources/migrate_v7.sql"),
+                7 => include_str!("../resources/migrate_v8.sql"),
+                8 => include_str!("../resources/migrate_v9.sql"),
+                9 => include_str!("../resources/migrate_v10.sql"),
+                10 => include_str!("../resources/migrate_v11.sql"),
+                11 => include_str!("../resources/migrate_v12.sql"),
+                12 => include_str!("../resources/migrate_v13.sql"),
+                13 => include_str!("../resources/migrate_v14.sql"),
+                14 => include_str!("../resources/migrate_v15.sql"),
+                15 => include_str!("../resources/migrate_v16.sql"),
+                16 => include_str!("../resources/migrate_v17.sql"),
+                17 => include_str!("../resources/migrate_v18.sql"),
+                18 => include_str!("../resources/migrate_v19.sql"),
+                19 => include_str!("../resources/migrate_v20.sql"),
+                20 => include_str!("../resources/migrate_v21.sql"),
+                21 => include_str!("../resources/migrate_v22.sql"),
+                22 => include_str!("../resources/migrate_v23.sql"),
+                23 => include_str!("../resources/migrate_v24.sql"),
+                VERSION => return Ok(()),
+                version => return Err(Error::UnknownVersion(version)),
+            };
+            self.connection.execute_batch(migration)?;
+        }
+    }
+
+    /// Prepare the state database and cache for running for a long time, by switching them to
+    /// write-ahead logging and incremental vacuuming.
+    ///
+    /// Enabling incremental vacuuming takes a full vacuum the first time.
+    pub fn enable_maintenance(&mut self) -> Result<()> {
+        for schema in &["main", "cache"] {
+            self.connection.query_row(
+                &format!("PRAGMA {schema}.journal_mode = WAL"),
+                rusqlite::NO_PARAMS,
+                |_| Ok(()),
+            )?;
+            let auto_vacuum: u32 = self.connection.query_row(
+                &format!("PRAGMA {schema}.auto_vacuum"),
+                rusqlite::NO_PARAMS,
+                |row| row.get(0),
+            )?;
+            if auto_vacuum != AUTO_VACUUM_INCREMENTAL {
+                eprintln!("Enabling incremental vacuum of the {schema} database...");
+                self.connection.execute_batch(&format!(
+                    "PRAGMA {schema}.auto_vacuum = INCREMENTAL; VACUUM {schema};"
+                ))?;
             }
-            1 => Ok(()),
-            version => Err(Error::UnknownVersion(version)),
         }
+        Ok(())
+    }
+
+    /// Move the write-ahead logs into the database files and truncate them, and free up to
+    /// `pages` unused pages from the end of each database file.
+    pub fn run_maintenance(&mut self, pages: u32) -> Result<()> {
+        for schema in &["main", "cache"] {
+            self.connection.query_row(
+                &format!("PRAGMA {schema}.wal_checkpoint(TRUNCATE)"),
+                rusqlite::NO_PARAMS,
+                |_| Ok(()),
+            )?;
+            self.connection
+                .execute_batch(&format!("PRAGMA {schema}.incremental_vacuum({pages})"))?;
+        }
+        Ok(())
     }
 
     pub fn insert_update_feed(&mut self, feed: &Feed) -> Result<()> {
-        self.connection.execute(
-            "REPLACE INTO feed ( \
+        let transaction = self.connection.transaction()?;
+        // is_muted is not set if the feed already exists.
+        transaction.execute(
+            "INSERT INTO feed ( \
              url, \
              link, \
              title, \
-             etag, \
-             last_modified \
-             ) VALUES (?, ?, ?, ?, ?)",
+             is_muted, \
+             hub, \
+             self_url, \
+             identity \
+             ) VALUES (?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (url) DO UPDATE SET \
+             link = excluded.link, \
+             title = excluded.title, \
+             hub = excluded.hub, \
+             self_url = excluded.self_url, \
+             identity = excluded.identity",
             rusqlite::params![
                 feed.url,
                 feed.link,
                 feed.title,
-                feed.etag,
-                feed.last_modified
+                feed.is_muted,
+                feed.hub,
+                feed.self_url,
+                feed.identity
             ],
         )?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO cache.validator ( \
+             feed_url, \
+             etag, \
+             last_modified \
+             ) VALUES (?, ?, ?)",
+            rusqlite::params![feed.url, feed.etag, feed.last_modified],
+        )?;
+        transaction.commit()?;
         Ok(())
     }
 
@@ -87,11 +468,17 @@ impl Database {
             .connection
             .query_row(
                 "SELECT \
-                 link, \
-                 title, \
-                 etag, \
-                 last_modified \
-                 FROM feed WHERE url = ?",
+                 feed.link, \
+                 feed.title, \
+                 validator.etag, \
+                 validator.last_modified, \
+                 feed.is_muted, \
+                 feed.hub, \
+                 feed.self_url, \
+                 feed.identity \
+                 FROM feed \
+                 LEFT JOIN cache.validator AS validator ON validator.feed_url = feed.url \
+                 WHERE feed.url = ?",
                 rusqlite::params![url],
                 |row| {
                     Ok(Feed {
@@ -100,15 +487,49 @@ impl Database {
                         title: row.get(1)?,
                         etag: row.get(2)?,
                         last_modified: row.get(3)?,
+                        is_muted: row.get(4)?,
+                        hub: row.get(5)?,
+                        self_url: row.get(6)?,
+                        identity: row.get(7)?,
                     })
                 },
             )
             .optional()?)
     }
 
-    pub fn insert_update_item(&mut self, item: &Item) -> Result<()> {
-        // is_read is not set if the item already exists.
+    /// Get the URLs of other feeds with the canonical URL, which are the same feed.
+    pub fn get_feeds_with_self_url(&mut self, url: &str, self_url: &str) -> Result<Vec<String>> {
+        self.connection
+            .prepare("SELECT url FROM feed WHERE self_url = ? AND url != ? ORDER BY url")?
+            .query_map(rusqlite::params![self_url, url], |row| row.get(0))?
+            .map(|url| url.map_err(Error::from))
+            .collect()
+    }
+
+    /// Get the URLs of muted feeds.
+    pub fn get_muted_feeds(&mut self) -> Result<Vec<String>> {
+        self.connection
+            .prepare("SELECT url FROM feed WHERE is_muted = 1 ORDER BY url")?
+            .query_map(rusqlite::NO_PARAMS, |row| row.get(0))?
+            .map(|url| url.map_err(Error::from))
+            .collect()
+    }
+
+    pub fn set_feed_muted(&mut self, url: &str, is_muted: bool) -> Result<()> {
         self.connection.execute(
+            "UPDATE feed SET is_muted = ? WHERE url = ?",
+            rusqlite::params![is_muted, url],
+        )?;
+        Ok(())
+    }
+
+    /// Insert an item, or update it if it exists. The hash of its content is only recorded for
+    /// new items, since existing items' hashes are updated with `update_content_hash`.
+    pub fn insert_update_item(&mut self, item: &Item, content_hash: Option<&str>) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        // is_read, is_bulk, the source, and the content hash are not set if the item already
+        // exists.
+        transaction.execute(
             "INSERT INTO item ( \
              feed_url, \
              guid, \
@@ -116,12 +537,23 @@ impl Database {
              comments_link, \
              title, \
              pub_date, \
-             is_read \
-             ) VALUES (?, ?, ?, ?, ?, ?, ?) \
+             is_read, \
+             is_highlighted, \
+             recipient, \
+             translated_title, \
+             is_bulk, \
+             source_url, \
+             fetched_at, \
+             fetch_ms, \
+             content_hash \
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
              ON CONFLICT (feed_url, guid) DO UPDATE SET \
              link = excluded.link, \
              title = excluded.title, \
-             pub_date = excluded.pub_date",
+             pub_date = excluded.pub_date, \
+             is_highlighted = excluded.is_highlighted, \
+             recipient = excluded.recipient, \
+             translated_title = excluded.translated_title",
             rusqlite::params![
                 item.feed_url,
                 item.guid,
@@ -130,13 +562,41 @@ impl Database {
                 item.title,
                 item.pub_date,
                 item.is_read,
+                item.is_highlighted,
+                item.recipient,
+                item.translated_title,
+                item.is_bulk,
+                item.source.as_ref().map(|source| &source.url),
+                item.source.as_ref().map(|source| source.fetched_at),
+                item.source.as_ref().map(|source| source.fetch_ms),
+                content_hash,
             ],
         )?;
+        transaction.execute(
+            "DELETE FROM item_tag WHERE feed_url = ? AND guid = ?",
+            rusqlite::params![item.feed_url, item.guid],
+        )?;
+        for tag in &item.tags {
+            transaction.execute(
+                "INSERT OR IGNORE INTO item_tag (feed_url, guid, tag) VALUES (?, ?, ?)",
+                rusqlite::params![item.feed_url, item.guid, tag],
+            )?;
+        }
+        transaction.commit()?;
         Ok(())
     }
 
-    pub fn get_unread_items(&mut self, feed_url: &str) -> Result<Vec<Item>> {
-        self.connection
+    /// Get unread items of a feed that have the tag, or that have none of the exclusive tags if
+    /// tag is `None`, without the items that are snoozed at `now`.
+    pub fn get_unread_items(
+        &mut self,
+        feed_url: &str,
+        tag: Option<&str>,
+        exclusive_tags: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Item>> {
+        let mut items = self
+            .connection
             .prepare(
                 "SELECT \
                  feed_url, \
@@ -145,31 +605,1383 @@ impl Database {
                  comments_link, \
                  title, \
                  pub_date, \
-                 is_read \
+                 is_read, \
+                 is_highlighted, \
+                 recipient, \
+                 EXISTS ( \
+                 SELECT 1 FROM cache.link_check WHERE \
+                 link_check.link = item.link AND link_check.status IN (404, 410) \
+                 ), \
+                 translated_title, \
+                 is_bulk, \
+                 source_url, \
+                 fetched_at, \
+                 fetch_ms \
                  FROM item WHERE \
-                 feed_url = ? AND \
-                 is_read = 0 \
-                 ORDER BY pub_date asc",
+                 feed_url = ?1 AND \
+                 is_read = 0 AND \
+                 (snoozed_until IS NULL OR snoozed_until <= ?3) AND \
+                 ((?2 IS NULL AND NOT EXISTS ( \
+                 SELECT 1 FROM item_tag WHERE \
+                 item_tag.feed_url = item.feed_url AND item_tag.guid = item.guid AND \
+                 item_tag.tag IN (SELECT value FROM json_each(?4)) \
+                 )) OR EXISTS ( \
+                 SELECT 1 FROM item_tag WHERE \
+                 item_tag.feed_url = item.feed_url AND item_tag.guid = item.guid AND item_tag.tag = ?2 \
+                 )) \
+                 ORDER BY pub_date asc, guid asc",
             )?
-            .query_map(rusqlite::params![feed_url], |row| {
-                Ok(Item {
+            .query_map(
+                rusqlite::params![feed_url, tag, now, json_list(exclusive_tags)],
+                item_from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<Item>>>()?;
+        for item in &mut items {
+            item.tags = self.get_item_tags(&item.feed_url, &item.guid)?;
+        }
+        Ok(items)
+    }
+
+    /// Hide unread items with the GUID, of the feed if given, from digests until the given time.
+    ///
+    /// Returns the number of items snoozed.
+    pub fn snooze_item(
+        &mut self,
+        guid: &str,
+        feed_url: Option<&str>,
+        until: DateTime<Utc>,
+    ) -> Result<usize> {
+        Ok(self.connection.execute(
+            "UPDATE item SET snoozed_until = ?1 WHERE \
+             guid = ?2 AND (?3 IS NULL OR feed_url = ?3) AND is_read = 0",
+            rusqlite::params![until, guid, feed_url],
+        )?)
+    }
+
+    /// Get every stored item of a feed, without tags.
+    pub fn get_items(&mut self, feed_url: &str) -> Result<Vec<Item>> {
+        Ok(self
+            .connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 guid, \
+                 link, \
+                 comments_link, \
+                 title, \
+                 pub_date, \
+                 is_read, \
+                 is_highlighted, \
+                 recipient, \
+                 EXISTS ( \
+                 SELECT 1 FROM cache.link_check WHERE \
+                 link_check.link = item.link AND link_check.status IN (404, 410) \
+                 ), \
+                 translated_title, \
+                 is_bulk, \
+                 source_url, \
+                 fetched_at, \
+                 fetch_ms \
+                 FROM item WHERE feed_url = ?1 \
+                 ORDER BY pub_date asc, guid asc",
+            )?
+            .query_map(rusqlite::params![feed_url], item_from_row)?
+            .collect::<rusqlite::Result<Vec<Item>>>()?)
+    }
+
+    /// Get items with the GUID, of the feed if given.
+    pub fn get_items_by_guid(&mut self, guid: &str, feed_url: Option<&str>) -> Result<Vec<Item>> {
+        Ok(self
+            .connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 guid, \
+                 link, \
+                 comments_link, \
+                 title, \
+                 pub_date, \
+                 is_read, \
+                 is_highlighted, \
+                 recipient, \
+                 EXISTS ( \
+                 SELECT 1 FROM cache.link_check WHERE \
+                 link_check.link = item.link AND link_check.status IN (404, 410) \
+                 ), \
+                 translated_title, \
+                 is_bulk, \
+                 source_url, \
+                 fetched_at, \
+                 fetch_ms \
+                 FROM item WHERE guid = ?1 AND (?2 IS NULL OR feed_url = ?2) \
+                 ORDER BY pub_date asc, guid asc",
+            )?
+            .query_map(rusqlite::params![guid, feed_url], item_from_row)?
+            .collect::<rusqlite::Result<Vec<Item>>>()?)
+    }
+
+    /// Get every highlighted item, with tags, ordered by feed.
+    pub fn get_highlighted_items(&mut self) -> Result<Vec<Item>> {
+        let mut items = self
+            .connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 guid, \
+                 link, \
+                 comments_link, \
+                 title, \
+                 pub_date, \
+                 is_read, \
+                 is_highlighted, \
+                 recipient, \
+                 EXISTS ( \
+                 SELECT 1 FROM cache.link_check WHERE \
+                 link_check.link = item.link AND link_check.status IN (404, 410) \
+                 ), \
+                 translated_title, \
+                 is_bulk, \
+                 source_url, \
+                 fetched_at, \
+                 fetch_ms \
+                 FROM item WHERE is_highlighted = 1 \
+                 ORDER BY feed_url asc, pub_date asc, guid asc",
+            )?
+            .query_map(rusqlite::NO_PARAMS, item_from_row)?
+            .collect::<rusqlite::Result<Vec<Item>>>()?;
+        for item in &mut items {
+            item.tags = self.get_item_tags(&item.feed_url, &item.guid)?;
+        }
+        Ok(items)
+    }
+
+    /// Get read items of a recipient's digest that were highlighted or saved for later, published
+    /// in a range, as candidates for resurfacing, ordered by feed.
+    pub fn get_archive_candidates(
+        &mut self,
+        recipient: Option<&str>,
+        published_after: Option<DateTime<Utc>>,
+        published_before: DateTime<Utc>,
+    ) -> Result<Vec<ArchiveCandidate>> {
+        Ok(self
+            .connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 guid, \
+                 link, \
+                 comments_link, \
+                 title, \
+                 pub_date, \
+                 is_read, \
+                 is_highlighted, \
+                 recipient, \
+                 EXISTS ( \
+                 SELECT 1 FROM cache.link_check WHERE \
+                 link_check.link = item.link AND link_check.status IN (404, 410) \
+                 ), \
+                 translated_title, \
+                 is_bulk, \
+                 source_url, \
+                 fetched_at, \
+                 fetch_ms, \
+                 is_highlighted + is_saved, \
+                 resurfaced_count, \
+                 random() & 4294967295 \
+                 FROM item WHERE is_read = 1 AND (is_highlighted = 1 OR is_saved = 1) \
+                 AND recipient IS ? AND (? IS NULL OR pub_date >= ?) AND pub_date < ? \
+                 ORDER BY feed_url asc, pub_date asc, guid asc",
+            )?
+            .query_map(
+                rusqlite::params![
+                    recipient,
+                    published_after,
+                    published_after,
+                    published_before
+                ],
+                |row| {
+                    Ok(ArchiveCandidate {
+                        item: item_from_row(row)?,
+                        marks: row.get(15)?,
+                        resurfaced_count: row.get(16)?,
+                        random: row.get(17)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<ArchiveCandidate>>>()?)
+    }
+
+    /// Record that items were saved to the read-it-later service.
+    pub fn mark_items_saved(&mut self, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "UPDATE item SET is_saved = 1 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![item.feed_url, item.guid],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Highlight items, as if a rule had.
+    pub fn mark_items_highlighted(&mut self, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "UPDATE item SET is_highlighted = 1 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![item.feed_url, item.guid],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Record that items were resurfaced in a digest, so they're less likely to be again.
+    pub fn mark_items_resurfaced(&mut self, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "UPDATE item SET resurfaced_count = resurfaced_count + 1 \
+                 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![item.feed_url, item.guid],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Record the hash of an item's content, keeping the last one if it has none now. Returns
+    /// whether it changed since the item was delivered and read.
+    pub fn update_content_hash(
+        &mut self,
+        feed_url: &str,
+        guid: &str,
+        content_hash: Option<&str>,
+    ) -> Result<bool> {
+        let transaction = self.connection.transaction()?;
+        let is_changed = transaction
+            .query_row(
+                "SELECT is_read AND content_hash IS NOT NULL AND content_hash != ? \
+                 AND EXISTS ( \
+                 SELECT 1 FROM delivery WHERE \
+                 delivery.feed_url = item.feed_url AND delivery.guid = item.guid \
+                 ) \
+                 FROM item WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![content_hash, feed_url, guid],
+                |row| row.get::<_, Option<bool>>(0),
+            )
+            .optional()?
+            .flatten()
+            .unwrap_or(false);
+        transaction.execute(
+            "UPDATE item SET content_hash = COALESCE(?, content_hash) \
+             WHERE feed_url = ? AND guid = ?",
+            rusqlite::params![content_hash, feed_url, guid],
+        )?;
+        transaction.commit()?;
+        Ok(is_changed)
+    }
+
+    /// Record that the content of read items changed, to list them in the next digest.
+    pub fn mark_items_updated(&mut self, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "UPDATE item SET is_updated = 1 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![item.feed_url, item.guid],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Record that updated items were listed in a digest, so they aren't again until they change.
+    pub fn clear_items_updated(&mut self, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "UPDATE item SET is_updated = 0 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![item.feed_url, item.guid],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Get the read items of a recipient whose content changed since they were delivered,
+    /// ordered by feed.
+    pub fn get_updated_items(&mut self, recipient: Option<&str>) -> Result<Vec<Item>> {
+        Ok(self
+            .connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 guid, \
+                 link, \
+                 comments_link, \
+                 title, \
+                 pub_date, \
+                 is_read, \
+                 is_highlighted, \
+                 recipient, \
+                 EXISTS ( \
+                 SELECT 1 FROM cache.link_check WHERE \
+                 link_check.link = item.link AND link_check.status IN (404, 410) \
+                 ), \
+                 translated_title, \
+                 is_bulk, \
+                 source_url, \
+                 fetched_at, \
+                 fetch_ms \
+                 FROM item WHERE is_updated = 1 AND recipient IS ? \
+                 ORDER BY feed_url asc, pub_date asc, guid asc",
+            )?
+            .query_map(rusqlite::params![recipient], item_from_row)?
+            .collect::<rusqlite::Result<Vec<Item>>>()?)
+    }
+
+    /// Get the state of every item, ordered by feed.
+    pub fn get_item_states(&mut self) -> Result<Vec<ItemState>> {
+        Ok(self
+            .connection
+            .prepare(
+                "SELECT feed_url, guid, is_read, is_highlighted, is_saved FROM item \
+                 ORDER BY feed_url asc, pub_date asc, guid asc",
+            )?
+            .query_map(rusqlite::NO_PARAMS, |row| {
+                Ok(ItemState {
                     feed_url: row.get(0)?,
                     guid: row.get(1)?,
-                    link: row.get(2)?,
-                    comments_link: row.get(3)?,
-                    title: row.get(4)?,
-                    pub_date: row.get(5)?,
-                    is_read: row.get(6)?,
+                    is_read: row.get(2)?,
+                    is_highlighted: row.get(3)?,
+                    is_saved: row.get(4)?,
                 })
             })?
-            .map(|item| item.map_err(Error::from))
+            .collect::<rusqlite::Result<Vec<ItemState>>>()?)
+    }
+
+    /// Set the state of the items that are stored, returning how many of them there were.
+    pub fn set_item_states(&mut self, states: &[ItemState]) -> Result<usize> {
+        let transaction = self.connection.transaction()?;
+        let mut updated = 0;
+        for state in states {
+            updated += transaction.execute(
+                "UPDATE item SET is_read = ?, is_highlighted = ?, is_saved = ? \
+                 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![
+                    state.is_read,
+                    state.is_highlighted,
+                    state.is_saved,
+                    state.feed_url,
+                    state.guid
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(updated)
+    }
+
+    fn get_item_tags(&mut self, feed_url: &str, guid: &str) -> Result<Vec<String>> {
+        self.connection
+            .prepare("SELECT tag FROM item_tag WHERE feed_url = ? AND guid = ? ORDER BY tag")?
+            .query_map(rusqlite::params![feed_url, guid], |row| row.get(0))?
+            .map(|tag| tag.map_err(Error::from))
             .collect()
     }
 
-    pub fn mark_all_items_read(&mut self) -> Result<()> {
+    /// Mark items with the tag as read, or items with none of the exclusive tags if tag is `None`,
+    /// unless they're snoozed at `now`.
+    /// Mark the items of the regular digest read, which are those without an exclusive tag that
+    /// aren't snoozed.
+    pub fn mark_items_read(&mut self, exclusive_tags: &[String], now: DateTime<Utc>) -> Result<()> {
         // TODO: Avoid marking items as read if they're not currently in the config?
+        self.connection.execute(
+            "UPDATE item SET is_read = 1 WHERE \
+             (snoozed_until IS NULL OR snoozed_until <= ?1) AND NOT EXISTS ( \
+             SELECT 1 FROM item_tag WHERE \
+             item_tag.feed_url = item.feed_url AND item_tag.guid = item.guid AND \
+             item_tag.tag IN (SELECT value FROM json_each(?2)) \
+             )",
+            rusqlite::params![now, json_list(exclusive_tags)],
+        )?;
+        Ok(())
+    }
+
+    pub fn mark_digest_items_read(&mut self, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "UPDATE item SET is_read = 1 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![item.feed_url, item.guid],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Record a sent digest, and complete the deliveries of its items.
+    pub fn insert_digest(
+        &mut self,
+        recipient: &str,
+        sent_at: DateTime<Utc>,
+        message_id: &str,
+        items: &[DigestItem],
+    ) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO digest (recipient, sent_at, message_id) VALUES (?, ?, ?)",
+            rusqlite::params![recipient, sent_at, message_id],
+        )?;
+        let digest_id = transaction.last_insert_rowid();
+        for item in items {
+            transaction.execute(
+                "INSERT INTO digest_item (digest_id, feed_url, guid, link) VALUES (?, ?, ?, ?)",
+                rusqlite::params![digest_id, item.feed_url, item.guid, item.link],
+            )?;
+            transaction.execute(
+                "UPDATE delivery SET digest_id = ?, delivered_at = ? \
+                 WHERE feed_url = ? AND guid = ? AND recipient = ?",
+                rusqlite::params![digest_id, sent_at, item.feed_url, item.guid, recipient],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Get the recipient of the digest with the Message-ID and its items, in the order they were
+    /// listed, if there is one.
+    pub fn get_digest_by_message_id(
+        &mut self,
+        message_id: &str,
+    ) -> Result<Option<(String, Vec<DigestItem>)>> {
+        let digest: Option<(i64, String)> = self
+            .connection
+            .query_row(
+                "SELECT id, recipient FROM digest WHERE message_id = ?",
+                rusqlite::params![message_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((digest_id, recipient)) = digest else {
+            return Ok(None);
+        };
+        let items = self
+            .connection
+            .prepare(
+                "SELECT feed_url, guid, link FROM digest_item WHERE digest_id = ? ORDER BY rowid",
+            )?
+            .query_map(rusqlite::params![digest_id], |row| {
+                Ok(DigestItem {
+                    feed_url: row.get(0)?,
+                    guid: row.get(1)?,
+                    link: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<DigestItem>>>()?;
+        Ok(Some((recipient, items)))
+    }
+
+    /// Get the last digest sent to a recipient.
+    pub fn get_last_digest(&mut self, recipient: &str) -> Result<Option<SentDigest>> {
+        let digest: Option<(i64, DateTime<Utc>)> = self
+            .connection
+            .query_row(
+                "SELECT id, sent_at FROM digest WHERE recipient = ? \
+                 ORDER BY sent_at DESC, id DESC LIMIT 1",
+                rusqlite::params![recipient],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((id, sent_at)) = digest else {
+            return Ok(None);
+        };
+        let items = self
+            .connection
+            .prepare("SELECT feed_url, guid FROM digest_item WHERE digest_id = ?")?
+            .query_map(rusqlite::params![id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<HashSet<(String, String)>>>()?;
+        Ok(Some(SentDigest { sent_at, items }))
+    }
+
+    /// Record that items are about to be sent to a recipient, before sending them, so they aren't
+    /// sent again if sending is interrupted.
+    pub fn start_deliveries(
+        &mut self,
+        recipient: &str,
+        transport: &str,
+        attempted_at: DateTime<Utc>,
+        items: &[DigestItem],
+    ) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "INSERT INTO delivery (feed_url, guid, recipient, transport, attempted_at) \
+                 VALUES (?, ?, ?, ?, ?)",
+                rusqlite::params![item.feed_url, item.guid, recipient, transport, attempted_at],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Forget deliveries of items to a recipient that failed, so they can be sent again.
+    pub fn cancel_deliveries(&mut self, recipient: &str, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "DELETE FROM delivery \
+                 WHERE feed_url = ? AND guid = ? AND recipient = ? AND delivered_at IS NULL",
+                rusqlite::params![item.feed_url, item.guid, recipient],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Get the GUIDs and recipients of items of a feed that were delivered, or whose delivery was
+    /// started and never completed.
+    pub fn get_deliveries(&mut self, feed_url: &str) -> Result<HashSet<(String, String)>> {
         self.connection
-            .execute("UPDATE item SET is_read = 1", rusqlite::params![])?;
+            .prepare("SELECT guid, recipient FROM delivery WHERE feed_url = ?")?
+            .query_map(rusqlite::params![feed_url], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .map(|delivery| delivery.map_err(Error::from))
+            .collect()
+    }
+
+    pub fn mark_digest_items_unread(&mut self, items: &[DigestItem]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for item in items {
+            transaction.execute(
+                "UPDATE item SET is_read = 0 WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![item.feed_url, item.guid],
+            )?;
+        }
+        transaction.commit()?;
         Ok(())
     }
+
+    /// Add mail to the outbox, ignoring its ID.
+    pub fn insert_outbox_mail(&mut self, mail: &OutboxMail) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO outbox ( \
+             recipient, \
+             envelope, \
+             message_id, \
+             message, \
+             queued_at, \
+             attempts, \
+             next_attempt_at, \
+             last_error \
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                mail.recipient,
+                mail.envelope,
+                mail.message_id,
+                mail.message,
+                mail.queued_at,
+                mail.attempts,
+                mail.next_attempt_at,
+                mail.last_error,
+            ],
+        )?;
+        let outbox_id = transaction.last_insert_rowid();
+        for item in &mail.items {
+            transaction.execute(
+                "INSERT INTO outbox_item (outbox_id, feed_url, guid, link) VALUES (?, ?, ?, ?)",
+                rusqlite::params![outbox_id, item.feed_url, item.guid, item.link],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Get mail in the outbox that is due to be delivered at the given time, oldest first.
+    pub fn get_due_outbox_mails(&mut self, now: DateTime<Utc>) -> Result<Vec<OutboxMail>> {
+        let mut mails = self
+            .connection
+            .prepare(
+                "SELECT \
+                 id, \
+                 recipient, \
+                 envelope, \
+                 message_id, \
+                 message, \
+                 queued_at, \
+                 attempts, \
+                 next_attempt_at, \
+                 last_error \
+                 FROM outbox WHERE next_attempt_at <= ? \
+                 ORDER BY queued_at ASC",
+            )?
+            .query_map(rusqlite::params![now], |row| {
+                Ok(OutboxMail {
+                    id: row.get(0)?,
+                    recipient: row.get(1)?,
+                    envelope: row.get(2)?,
+                    message_id: row.get(3)?,
+                    message: row.get(4)?,
+                    items: Vec::new(),
+                    queued_at: row.get(5)?,
+                    attempts: row.get(6)?,
+                    next_attempt_at: row.get(7)?,
+                    last_error: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<OutboxMail>>>()?;
+        for mail in &mut mails {
+            mail.items = self
+                .connection
+                .prepare(
+                    "SELECT feed_url, guid, link FROM outbox_item WHERE outbox_id = ? \
+                     ORDER BY rowid",
+                )?
+                .query_map(rusqlite::params![mail.id], |row| {
+                    Ok(DigestItem {
+                        feed_url: row.get(0)?,
+                        guid: row.get(1)?,
+                        link: row.get(2)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<DigestItem>>>()?;
+        }
+        Ok(mails)
+    }
+
+    /// Record a failed delivery attempt of mail in the outbox.
+    pub fn reschedule_outbox_mail(
+        &mut self,
+        id: i64,
+        next_attempt_at: DateTime<Utc>,
+        error: &str,
+    ) -> Result<()> {
+        self.connection.execute(
+            "UPDATE outbox SET \
+             attempts = attempts + 1, \
+             next_attempt_at = ?, \
+             last_error = ? \
+             WHERE id = ?",
+            rusqlite::params![next_attempt_at, error, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_outbox_mail(&mut self, id: i64) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM outbox_item WHERE outbox_id = ?",
+            rusqlite::params![id],
+        )?;
+        transaction.execute("DELETE FROM outbox WHERE id = ?", rusqlite::params![id])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn insert_feed_change(&mut self, change: &FeedChange) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO feed_change ( \
+             feed_url, \
+             changed_at, \
+             old_title, \
+             new_title, \
+             old_link, \
+             new_link \
+             ) VALUES (?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                change.feed_url,
+                change.changed_at,
+                change.old_title,
+                change.new_title,
+                change.old_link,
+                change.new_link,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Get the changes of a feed that haven't been included in a digest, oldest first.
+    pub fn get_unread_feed_changes(&mut self, feed_url: &str) -> Result<Vec<FeedChange>> {
+        self.connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 changed_at, \
+                 old_title, \
+                 new_title, \
+                 old_link, \
+                 new_link \
+                 FROM feed_change WHERE feed_url = ? AND is_read = 0 \
+                 ORDER BY changed_at ASC",
+            )?
+            .query_map(rusqlite::params![feed_url], |row| {
+                Ok(FeedChange {
+                    feed_url: row.get(0)?,
+                    changed_at: row.get(1)?,
+                    old_title: row.get(2)?,
+                    new_title: row.get(3)?,
+                    old_link: row.get(4)?,
+                    new_link: row.get(5)?,
+                })
+            })?
+            .map(|change| change.map_err(Error::from))
+            .collect()
+    }
+
+    pub fn mark_feed_changes_read(&mut self) -> Result<()> {
+        self.connection
+            .execute("UPDATE feed_change SET is_read = 1", rusqlite::NO_PARAMS)?;
+        Ok(())
+    }
+
+    /// Get the distinct links of unread items published since the given time.
+    pub fn get_unread_links(&mut self, since: DateTime<Utc>) -> Result<Vec<String>> {
+        self.connection
+            .prepare(
+                "SELECT DISTINCT link FROM item WHERE is_read = 0 AND pub_date >= ? AND link != ''",
+            )?
+            .query_map(rusqlite::params![since], |row| row.get(0))?
+            .map(|link| link.map_err(Error::from))
+            .collect()
+    }
+
+    /// Record the status a link responded with, replacing the previous check.
+    pub fn insert_link_check(
+        &mut self,
+        link: &str,
+        checked_at: DateTime<Utc>,
+        status: u16,
+    ) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO cache.link_check (link, checked_at, status) VALUES (?, ?, ?)",
+            rusqlite::params![link, checked_at, status],
+        )?;
+        Ok(())
+    }
+
+    /// Get the URL a link was found to redirect to, if it has been resolved.
+    pub fn get_resolved_link(&mut self, link: &str) -> Result<Option<String>> {
+        self.connection
+            .query_row(
+                "SELECT resolved_link FROM cache.resolved_link WHERE link = ?",
+                rusqlite::params![link],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Error::from)
+    }
+
+    /// Record the URL a link redirects to, so it isn't resolved again.
+    pub fn insert_resolved_link(
+        &mut self,
+        link: &str,
+        resolved_link: &str,
+        resolved_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO cache.resolved_link (link, resolved_link, resolved_at) \
+             VALUES (?, ?, ?)",
+            rusqlite::params![link, resolved_link, resolved_at],
+        )?;
+        Ok(())
+    }
+
+    /// Get when each feed is next due to be fetched by the daemon, by feed URL.
+    pub fn get_next_fetches(&mut self) -> Result<HashMap<String, DateTime<Utc>>> {
+        self.connection
+            .prepare("SELECT feed_url, next_fetch_at FROM cache.fetch_schedule")?
+            .query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .map(|next_fetch| next_fetch.map_err(Error::from))
+            .collect()
+    }
+
+    /// Record when a feed is next due to be fetched by the daemon.
+    pub fn set_next_fetch(&mut self, feed_url: &str, next_fetch_at: DateTime<Utc>) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO cache.fetch_schedule (feed_url, next_fetch_at) VALUES (?, ?)",
+            rusqlite::params![feed_url, next_fetch_at],
+        )?;
+        Ok(())
+    }
+
+    /// Record that the digest with the Message-ID bounced, returning the bounce, or `None` if no
+    /// digest has the Message-ID. A digest that bounced before keeps its first bounce.
+    pub fn insert_bounce(
+        &mut self,
+        message_id: &str,
+        received_at: DateTime<Utc>,
+        status: Option<&str>,
+        diagnostic: Option<&str>,
+    ) -> Result<Option<Bounce>> {
+        let digest: Option<(i64, String, DateTime<Utc>)> = self
+            .connection
+            .query_row(
+                "SELECT id, recipient, sent_at FROM digest WHERE message_id = ?",
+                rusqlite::params![message_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let Some((digest_id, recipient, sent_at)) = digest else {
+            return Ok(None);
+        };
+        self.connection.execute(
+            "INSERT OR IGNORE INTO bounce (digest_id, received_at, status, diagnostic) \
+             VALUES (?, ?, ?, ?)",
+            rusqlite::params![digest_id, received_at, status, diagnostic],
+        )?;
+        Ok(Some(Bounce {
+            recipient,
+            sent_at,
+            status: status.map(ToString::to_string),
+            diagnostic: diagnostic.map(ToString::to_string),
+        }))
+    }
+
+    /// Get the recipients whose latest digest bounced, with how many in a row did.
+    pub fn get_delivery_failures(&mut self) -> Result<Vec<DeliveryFailure>> {
+        let digests = self
+            .connection
+            .prepare(
+                "SELECT recipient, sent_at, bounce.digest_id IS NOT NULL, status, diagnostic \
+                 FROM digest LEFT JOIN bounce ON bounce.digest_id = digest.id \
+                 ORDER BY recipient, sent_at DESC, id DESC",
+            )?
+            .query_map(rusqlite::NO_PARAMS, |row| {
+                Ok((
+                    row.get::<_, bool>(2)?,
+                    Bounce {
+                        recipient: row.get(0)?,
+                        sent_at: row.get(1)?,
+                        status: row.get(3)?,
+                        diagnostic: row.get(4)?,
+                    },
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut failures: Vec<DeliveryFailure> = Vec::new();
+        let mut recipient: Option<String> = None;
+        // Whether the digests of the recipient have bounced so far, from the latest.
+        let mut bouncing = false;
+        for (bounced, bounce) in digests {
+            if recipient.as_ref() != Some(&bounce.recipient) {
+                recipient = Some(bounce.recipient.clone());
+                bouncing = bounced;
+                if bounced {
+                    failures.push(DeliveryFailure {
+                        recipient: bounce.recipient.clone(),
+                        bounced: 1,
+                        last: bounce,
+                    });
+                }
+            } else if bouncing && bounced {
+                failures.last_mut().expect("failure was pushed").bounced += 1;
+            } else {
+                bouncing = false;
+            }
+        }
+        Ok(failures)
+    }
+
+    /// Get the UIDVALIDITY of a mailbox and the last UID read from it, if it was polled.
+    pub fn get_last_uid(&mut self, mailbox: &str) -> Result<Option<(u32, u32)>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT uid_validity, last_uid FROM mailbox_poll WHERE mailbox = ?",
+                rusqlite::params![mailbox],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?)
+    }
+
+    /// Record the last UID read from a mailbox.
+    pub fn set_last_uid(&mut self, mailbox: &str, uid_validity: u32, last_uid: u32) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO mailbox_poll (mailbox, uid_validity, last_uid) \
+             VALUES (?, ?, ?)",
+            rusqlite::params![mailbox, uid_validity, last_uid],
+        )?;
+        Ok(())
+    }
+
+    /// Get when each pipeline was last mailed by the daemon.
+    pub fn get_pipeline_runs(&mut self) -> Result<HashMap<String, DateTime<Utc>>> {
+        self.connection
+            .prepare("SELECT name, ran_at FROM pipeline_run")?
+            .query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .map(|run| run.map_err(Error::from))
+            .collect()
+    }
+
+    /// Record when a pipeline was mailed by the daemon.
+    pub fn set_pipeline_run(&mut self, name: &str, ran_at: DateTime<Utc>) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO pipeline_run (name, ran_at) VALUES (?, ?)",
+            rusqlite::params![name, ran_at],
+        )?;
+        Ok(())
+    }
+
+    /// Record a run of fetching feeds.
+    pub fn insert_fetch_run(&mut self, run: &FetchRun) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO cache.fetch_run (started_at, duration_ms) VALUES (?, ?)",
+            rusqlite::params![run.started_at, run.duration_ms],
+        )?;
+        Ok(())
+    }
+
+    /// Get the latest run of fetching feeds.
+    pub fn get_last_fetch_run(&mut self) -> Result<Option<FetchRun>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT started_at, duration_ms FROM cache.fetch_run \
+                 ORDER BY started_at DESC LIMIT 1",
+                rusqlite::NO_PARAMS,
+                |row| {
+                    Ok(FetchRun {
+                        started_at: row.get(0)?,
+                        duration_ms: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Record the error of a feed's last fetch, or clear it if `error` is `None`.
+    pub fn set_fetch_error(
+        &mut self,
+        feed_url: &str,
+        failed_at: DateTime<Utc>,
+        error: Option<&str>,
+    ) -> Result<()> {
+        match error {
+            Some(error) => self.connection.execute(
+                "INSERT OR REPLACE INTO cache.fetch_error (feed_url, failed_at, error) \
+                 VALUES (?, ?, ?)",
+                rusqlite::params![feed_url, failed_at, error],
+            )?,
+            None => self.connection.execute(
+                "DELETE FROM cache.fetch_error WHERE feed_url = ?",
+                rusqlite::params![feed_url],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Get the challenge pages that a feed got in a row, if its last response was one.
+    pub fn get_challenge(&mut self, feed_url: &str) -> Result<Option<Challenge>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT provider, challenged_at, count FROM cache.challenge WHERE feed_url = ?",
+                rusqlite::params![feed_url],
+                |row| {
+                    Ok(Challenge {
+                        provider: row.get(0)?,
+                        challenged_at: row.get(1)?,
+                        count: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Record that a feed got a challenge page, or clear its challenges if `provider` is `None`,
+    /// returning the challenges in a row.
+    pub fn set_challenge(
+        &mut self,
+        feed_url: &str,
+        challenged_at: DateTime<Utc>,
+        provider: Option<&str>,
+    ) -> Result<Option<Challenge>> {
+        match provider {
+            Some(provider) => self.connection.execute(
+                "INSERT OR REPLACE INTO cache.challenge (feed_url, provider, challenged_at, count) \
+                 VALUES (?, ?, ?, \
+                 COALESCE((SELECT count FROM cache.challenge WHERE feed_url = ?), 0) + 1)",
+                rusqlite::params![feed_url, provider, challenged_at, feed_url],
+            )?,
+            None => self.connection.execute(
+                "DELETE FROM cache.challenge WHERE feed_url = ?",
+                rusqlite::params![feed_url],
+            )?,
+        };
+        self.get_challenge(feed_url)
+    }
+
+    /// Get the errors of feeds whose last fetch failed, by feed URL.
+    pub fn get_fetch_errors(&mut self) -> Result<HashMap<String, String>> {
+        self.connection
+            .prepare("SELECT feed_url, error FROM cache.fetch_error")?
+            .query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .map(|error| error.map_err(Error::from))
+            .collect()
+    }
+
+    /// Get a translation of text to the language, if it has been translated.
+    pub fn get_translation(&mut self, text: &str, target_language: &str) -> Result<Option<String>> {
+        self.connection
+            .query_row(
+                "SELECT translation FROM cache.translation WHERE text = ? AND target_language = ?",
+                rusqlite::params![text, target_language],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Error::from)
+    }
+
+    /// Record a translation of text, so it isn't translated again.
+    pub fn insert_translation(
+        &mut self,
+        text: &str,
+        target_language: &str,
+        translation: &str,
+        translated_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO cache.translation \
+             (text, target_language, translation, translated_at) VALUES (?, ?, ?, ?)",
+            rusqlite::params![text, target_language, translation, translated_at],
+        )?;
+        Ok(())
+    }
+
+    /// Get the stored content of each item of a feed, with its summary, by GUID.
+    pub fn get_item_contents(&mut self, feed_url: &str) -> Result<HashMap<String, ItemContent>> {
+        self.connection
+            .prepare(
+                "SELECT guid, content, summary FROM cache.item_content \
+                 LEFT JOIN cache.item_summary USING (feed_url, guid) \
+                 WHERE feed_url = ?",
+            )?
+            .query_map(rusqlite::params![feed_url], |row| {
+                Ok((
+                    row.get(0)?,
+                    ItemContent {
+                        content: row.get(1)?,
+                        summary: row.get(2)?,
+                    },
+                ))
+            })?
+            .map(|content| content.map_err(Error::from))
+            .collect()
+    }
+
+    /// Record the content of an item, to show in digests, replacing what was recorded before.
+    pub fn set_item_content(&mut self, feed_url: &str, guid: &str, content: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO cache.item_content (feed_url, guid, content) VALUES (?, ?, ?)",
+            rusqlite::params![feed_url, guid, content],
+        )?;
+        Ok(())
+    }
+
+    /// Get the hash of the text that an item's summary was made from, if it was summarized.
+    pub fn get_summary_text_hash(&mut self, feed_url: &str, guid: &str) -> Result<Option<String>> {
+        self.connection
+            .query_row(
+                "SELECT text_hash FROM cache.item_summary WHERE feed_url = ? AND guid = ?",
+                rusqlite::params![feed_url, guid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Error::from)
+    }
+
+    /// Record the summary of an item, with the hash of the text it was made from, so the same
+    /// text isn't summarized again.
+    pub fn set_item_summary(
+        &mut self,
+        feed_url: &str,
+        guid: &str,
+        text_hash: &str,
+        summary: &str,
+    ) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO cache.item_summary (feed_url, guid, text_hash, summary) \
+             VALUES (?, ?, ?, ?)",
+            rusqlite::params![feed_url, guid, text_hash, summary],
+        )?;
+        Ok(())
+    }
+
+    /// Get the article page at a URL, if it was downloaded before and hasn't been evicted.
+    pub fn get_article(&mut self, url: &str) -> Result<Option<Article>> {
+        self.connection
+            .query_row(
+                "SELECT body, etag, last_modified, fetched_at FROM cache.article WHERE url = ?",
+                rusqlite::params![url],
+                |row| {
+                    Ok(Article {
+                        body: row.get(0)?,
+                        etag: row.get(1)?,
+                        last_modified: row.get(2)?,
+                        fetched_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(Error::from)
+    }
+
+    /// Record the article page at a URL, and evict the least recently fetched pages while the
+    /// bodies of all of them take more than `max_bytes`.
+    pub fn insert_article(&mut self, url: &str, article: &Article, max_bytes: u64) -> Result<()> {
+        let max_bytes = i64::try_from(max_bytes).unwrap_or(i64::MAX);
+        self.connection.execute(
+            "INSERT OR REPLACE INTO cache.article (url, body, etag, last_modified, fetched_at) \
+             VALUES (?, ?, ?, ?, ?)",
+            rusqlite::params![
+                url,
+                article.body,
+                article.etag,
+                article.last_modified,
+                article.fetched_at
+            ],
+        )?;
+        self.connection.execute(
+            "DELETE FROM cache.article WHERE url IN ( \
+                 SELECT url FROM ( \
+                     SELECT url, SUM(LENGTH(CAST(body AS BLOB))) \
+                         OVER (ORDER BY fetched_at DESC, url) AS total \
+                     FROM cache.article \
+                 ) WHERE total > ? \
+             )",
+            rusqlite::params![max_bytes],
+        )?;
+        Ok(())
+    }
+
+    /// Get the cached access token from a token endpoint for a client and scope, which may have
+    /// expired.
+    pub fn get_access_token(
+        &mut self,
+        token_url: &str,
+        client_id: &str,
+        scope: &str,
+    ) -> Result<Option<AccessToken>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT token, expires_at FROM cache.access_token \
+                 WHERE token_url = ? AND client_id = ? AND scope = ?",
+                rusqlite::params![token_url, client_id, scope],
+                |row| {
+                    Ok(AccessToken {
+                        token: row.get(0)?,
+                        expires_at: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Cache an access token from a token endpoint for a client and scope, or forget the cached
+    /// token if `token` is `None`.
+    pub fn set_access_token(
+        &mut self,
+        token_url: &str,
+        client_id: &str,
+        scope: &str,
+        token: Option<&AccessToken>,
+    ) -> Result<()> {
+        match token {
+            Some(token) => self.connection.execute(
+                "INSERT OR REPLACE INTO cache.access_token \
+                 (token_url, client_id, scope, token, expires_at) VALUES (?, ?, ?, ?, ?)",
+                rusqlite::params![token_url, client_id, scope, token.token, token.expires_at],
+            )?,
+            None => self.connection.execute(
+                "DELETE FROM cache.access_token \
+                 WHERE token_url = ? AND client_id = ? AND scope = ?",
+                rusqlite::params![token_url, client_id, scope],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Get links of items included in digests sent since the given time.
+    pub fn get_sent_links(&mut self, since: DateTime<Utc>) -> Result<HashSet<String>> {
+        self.connection
+            .prepare(
+                "SELECT DISTINCT digest_item.link \
+                 FROM digest_item JOIN digest ON digest.id = digest_item.digest_id \
+                 WHERE digest.sent_at >= ? AND digest_item.link != ''",
+            )?
+            .query_map(rusqlite::params![since], |row| row.get(0))?
+            .map(|link| link.map_err(Error::from))
+            .collect()
+    }
+
+    pub fn insert_fetch(&mut self, fetch: &Fetch) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO cache.fetch ( \
+             feed_url, \
+             fetched_at, \
+             status, \
+             bytes, \
+             is_conditional, \
+             etag, \
+             last_modified, \
+             body_hash, \
+             duration_ms \
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                fetch.feed_url,
+                fetch.fetched_at,
+                fetch.status,
+                fetch.bytes,
+                fetch.is_conditional,
+                fetch.etag,
+                fetch.last_modified,
+                fetch.body_hash,
+                fetch.duration_ms,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Get the recorded fetches of a feed, oldest first.
+    pub fn get_fetches(&mut self, feed_url: &str) -> Result<Vec<Fetch>> {
+        self.connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 fetched_at, \
+                 status, \
+                 bytes, \
+                 is_conditional, \
+                 etag, \
+                 last_modified, \
+                 body_hash, \
+                 duration_ms \
+                 FROM cache.fetch WHERE feed_url = ? \
+                 ORDER BY fetched_at ASC",
+            )?
+            .query_map(rusqlite::params![feed_url], |row| {
+                Ok(Fetch {
+                    feed_url: row.get(0)?,
+                    fetched_at: row.get(1)?,
+                    status: row.get(2)?,
+                    bytes: row.get(3)?,
+                    is_conditional: row.get(4)?,
+                    etag: row.get(5)?,
+                    last_modified: row.get(6)?,
+                    body_hash: row.get(7)?,
+                    duration_ms: row.get(8)?,
+                })
+            })?
+            .map(|fetch| fetch.map_err(Error::from))
+            .collect()
+    }
+
+    /// Get fetch totals for each feed, ordered by most bytes downloaded.
+    pub fn get_fetch_stats(&mut self) -> Result<Vec<FetchStats>> {
+        self.connection
+            .prepare(
+                "SELECT \
+                 feed_url, \
+                 COUNT(*), \
+                 SUM(status = 304), \
+                 SUM(bytes), \
+                 MAX(fetched_at) \
+                 FROM cache.fetch \
+                 GROUP BY feed_url \
+                 ORDER BY SUM(bytes) DESC",
+            )?
+            .query_map(rusqlite::NO_PARAMS, |row| {
+                Ok(FetchStats {
+                    feed_url: row.get(0)?,
+                    fetches: row.get(1)?,
+                    not_modified: row.get(2)?,
+                    bytes: row.get(3)?,
+                    last_fetched_at: row.get(4)?,
+                })
+            })?
+            .map(|stats| stats.map_err(Error::from))
+            .collect()
+    }
+
+    /// Get when each feed was last fetched, by feed URL.
+    pub fn get_last_fetches(&mut self) -> Result<HashMap<String, DateTime<Utc>>> {
+        self.connection
+            .prepare("SELECT feed_url, MAX(fetched_at) FROM cache.fetch GROUP BY feed_url")?
+            .query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .map(|fetch| fetch.map_err(Error::from))
+            .collect()
+    }
+
+    /// Get the average duration of each feed's recorded fetches.
+    pub fn get_fetch_durations(&mut self) -> Result<HashMap<String, Duration>> {
+        self.connection
+            .prepare(
+                "SELECT feed_url, CAST(AVG(duration_ms) AS INTEGER) \
+                 FROM cache.fetch \
+                 WHERE duration_ms IS NOT NULL \
+                 GROUP BY feed_url",
+            )?
+            .query_map(rusqlite::NO_PARAMS, |row| {
+                let duration_ms: i64 = row.get(1)?;
+                Ok((
+                    row.get(0)?,
+                    Duration::from_millis(u64::try_from(duration_ms).unwrap_or(0)),
+                ))
+            })?
+            .map(|duration| duration.map_err(Error::from))
+            .collect()
+    }
+}
+
+/// URI that opens a database file read-only.
+fn read_only_uri(path: &Path) -> String {
+    let path = path
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    format!("file:{path}?mode=ro")
+}
+
+/// JSON array of strings, for queries to read with `json_each`.
+fn json_list(strings: &[String]) -> String {
+    serde_json::to_string(strings).expect("strings always serialize")
+}
+
+/// Read an item selected with the columns of `get_unread_items`, without tags.
+fn item_from_row(row: &rusqlite::Row) -> rusqlite::Result<Item> {
+    Ok(Item {
+        feed_url: row.get(0)?,
+        guid: row.get(1)?,
+        link: row.get(2)?,
+        comments_link: row.get(3)?,
+        title: row.get(4)?,
+        pub_date: row.get(5)?,
+        is_read: row.get(6)?,
+        is_highlighted: row.get(7)?,
+        recipient: row.get(8)?,
+        tags: Vec::new(),
+        is_dead: row.get(9)?,
+        translated_title: row.get(10)?,
+        is_bulk: row.get(11)?,
+        source: match (row.get(12)?, row.get(13)?, row.get(14)?) {
+            (Some(url), Some(fetched_at), Some(fetch_ms)) => Some(Source {
+                url,
+                fetched_at,
+                fetch_ms,
+            }),
+            _ => None,
+        },
+        parts: Vec::new(),
+        qr_code: None,
+        number: None,
+        content: Vec::new(),
+        excerpt: Vec::new(),
+        is_truncated: false,
+        images: Vec::new(),
+        summary: None,
+    })
 }
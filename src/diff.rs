@@ -0,0 +1,96 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{database, feed};
+
+/// Differences between the stored items of a feed and the items it currently has.
+#[derive(Default)]
+pub struct FeedDiff<'a> {
+    pub new: Vec<&'a feed::Item>,
+    /// Current items with the names of fields that differ from the stored item.
+    pub updated: Vec<(&'a feed::Item, Vec<&'static str>)>,
+    /// Stored items missing from the feed.
+    pub disappeared: Vec<&'a database::Item>,
+}
+impl<'a> FeedDiff<'a> {
+    /// Compare items by GUID.
+    ///
+    /// The database keeps items after they fall off the end of the feed, so only stored items at
+    /// least as new as the oldest current item are reported as disappeared.
+    pub fn new(stored: &'a [database::Item], current: &'a [feed::Item]) -> Self {
+        let stored_by_guid: HashMap<&str, &database::Item> = stored
+            .iter()
+            .map(|item| (item.guid.as_str(), item))
+            .collect();
+        let mut diff = Self::default();
+        for item in current {
+            match stored_by_guid.get(item.guid.as_str()) {
+                None => diff.new.push(item),
+                Some(stored_item) => {
+                    let fields = changed_fields(stored_item, item);
+                    if !fields.is_empty() {
+                        diff.updated.push((item, fields));
+                    }
+                }
+            }
+        }
+        let current_guids: HashSet<&str> = current.iter().map(|item| item.guid.as_str()).collect();
+        if let Some(oldest) = current.iter().map(|item| item.pub_date).min() {
+            diff.disappeared = stored
+                .iter()
+                .filter(|item| {
+                    item.pub_date >= oldest && !current_guids.contains(item.guid.as_str())
+                })
+                .collect();
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty() && self.updated.is_empty() && self.disappeared.is_empty()
+    }
+}
+
+fn changed_fields(stored: &database::Item, current: &feed::Item) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if stored.title != current.title {
+        fields.push("title");
+    }
+    if stored.link != current.link {
+        fields.push("link");
+    }
+    if stored.comments_link != current.comments_link {
+        fields.push("comments link");
+    }
+    if stored.pub_date != current.pub_date {
+        fields.push("date");
+    }
+    fields
+}
+
+/// Items of a digest compared with the last digest sent to the same recipient.
+#[derive(Default)]
+pub struct DigestDiff<'a> {
+    pub new: Vec<&'a database::Item>,
+    /// Items that were also in the last digest.
+    pub carried_over: Vec<&'a database::Item>,
+    /// Feed URLs and GUIDs of items of the last digest that aren't in this one.
+    pub dropped: Vec<&'a (String, String)>,
+}
+impl<'a> DigestDiff<'a> {
+    pub fn new(items: &[&'a database::Item], last: &'a HashSet<(String, String)>) -> Self {
+        let mut diff = Self::default();
+        let mut keys = HashSet::new();
+        for &item in items {
+            let key = (item.feed_url.clone(), item.guid.clone());
+            if last.contains(&key) {
+                diff.carried_over.push(item);
+            } else {
+                diff.new.push(item);
+            }
+            keys.insert(key);
+        }
+        diff.dropped = last.iter().filter(|key| !keys.contains(*key)).collect();
+        diff.dropped.sort();
+        diff
+    }
+}
@@ -0,0 +1,148 @@
+use std::ffi::OsStr;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use lettre::SendableEmail;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{message, openssl};
+
+// Header fields to sign, if present.
+const SIGNED_FIELDS: [&str; 9] = [
+    "from",
+    "to",
+    "subject",
+    "date",
+    "message-id",
+    "in-reply-to",
+    "references",
+    "mime-version",
+    "content-type",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Domain to sign for, instead of the domain of the from address.
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Selector of the DNS record publishing the public key.
+    pub selector: String,
+    /// PEM RSA private key.
+    pub key: PathBuf,
+}
+
+/// Add a DKIM signature to an email (RFC 6376), using the `openssl` command.
+///
+/// Uses relaxed canonicalization, which survives relays that rewrap headers or whitespace. The
+/// signature is timestamped with `now`.
+pub fn sign(
+    config: &Config,
+    domain: &str,
+    mail: SendableEmail,
+    now: DateTime<Utc>,
+) -> io::Result<SendableEmail> {
+    let envelope = mail.envelope().clone();
+    let message_id = mail.message_id().to_string();
+    let text = message::crlf_line_endings(&mail.message_to_string()?);
+    let (header, body) = message::split(&text);
+    let fields = message::header_fields(header);
+    let mut signed_names = Vec::new();
+    let mut data = String::new();
+    for name in &SIGNED_FIELDS {
+        // Verifiers use the last instance of each field.
+        let field = fields
+            .iter()
+            .rev()
+            .find(|field| message::field_name(field).eq_ignore_ascii_case(name));
+        if let Some(field) = field {
+            signed_names.push(*name);
+            data.push_str(&relaxed_field(field));
+        }
+    }
+    let body_hash = base64::encode(&Sha256::digest(relaxed_body(body).as_bytes()));
+    let signature_field = format!(
+        "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d={domain}; s={}; t={}; h={}; \
+         bh={body_hash}; b=",
+        config.selector,
+        now.timestamp(),
+        signed_names.join(":"),
+    );
+    // The signature covers its own field with an empty signature, without the trailing CRLF.
+    data.push_str(relaxed_field(&signature_field).trim_end_matches("\r\n"));
+    let args: [&OsStr; 4] = [
+        "dgst".as_ref(),
+        "-sha256".as_ref(),
+        "-sign".as_ref(),
+        config.key.as_ref(),
+    ];
+    let signature = openssl::run(args, data.into_bytes())?;
+    let text = format!("{signature_field}{}\r\n{text}", base64::encode(&signature));
+    Ok(SendableEmail::new(envelope, message_id, text.into_bytes()))
+}
+
+/// Canonicalize a header field with the "relaxed" algorithm (RFC 6376 section 3.4.2).
+fn relaxed_field(field: &str) -> String {
+    let (name, value) = field.split_once(':').unwrap_or((field, ""));
+    let value = collapse_whitespace(&value.replace("\r\n", ""));
+    format!("{}:{}\r\n", name.trim().to_lowercase(), value.trim())
+}
+
+/// Canonicalize a body with the "relaxed" algorithm (RFC 6376 section 3.4.4).
+fn relaxed_body(body: &str) -> String {
+    let mut lines: Vec<String> = body
+        .split("\r\n")
+        .map(|line| collapse_whitespace(line).trim_end().to_string())
+        .collect();
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    let mut canonical = lines.join("\r\n");
+    if !lines.is_empty() {
+        canonical.push_str("\r\n");
+    }
+    canonical
+}
+
+/// Replace each run of spaces and tabs with a single space.
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut after_whitespace = false;
+    for c in text.chars() {
+        if c == ' ' || c == '\t' {
+            if !after_whitespace {
+                collapsed.push(' ');
+            }
+            after_whitespace = true;
+        } else {
+            collapsed.push(c);
+            after_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example from RFC 6376 section 3.4.5.
+    #[test]
+    fn relaxed_field_matches_rfc_example() {
+        assert_eq!(relaxed_field("A: X"), "a:X\r\n");
+        assert_eq!(relaxed_field("B : Y\t\r\n\tZ  "), "b:Y Z\r\n");
+    }
+
+    #[test]
+    fn relaxed_body_matches_rfc_example() {
+        assert_eq!(relaxed_body(" C \r\nD \t E\r\n\r\n\r\n"), " C\r\nD E\r\n");
+    }
+
+    #[test]
+    fn relaxed_body_of_empty_body_is_empty() {
+        assert_eq!(relaxed_body(""), "");
+        assert_eq!(relaxed_body("\r\n\r\n"), "");
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use dns_parser::{Builder, Packet, QueryClass, QueryType, RData, ResponseCode};
+
+const DNS_PORT: u16 = 53;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const ATTEMPTS: usize = 3;
+
+/// Look up the MX records of a domain, returning `None` if the domain doesn't exist.
+///
+/// Queries the given nameserver, or the first one in /etc/resolv.conf.
+pub fn lookup_mx(
+    domain: &str,
+    nameserver: Option<SocketAddr>,
+) -> io::Result<Option<Vec<(u16, String)>>> {
+    let nameserver = match nameserver {
+        Some(nameserver) => nameserver,
+        None => system_nameserver()?,
+    };
+    // The standard library has no random number generator, but hashers are randomly seeded.
+    let random = RandomState::new().build_hasher().finish().to_le_bytes();
+    let id = u16::from_le_bytes([random[0], random[1]]);
+    let mut builder = Builder::new_query(id, true);
+    builder.add_question(domain, false, QueryType::MX, QueryClass::IN);
+    let query = builder
+        .build()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS query is too long"))?;
+    let response = exchange(nameserver, id, &query)?;
+    let packet = Packet::parse(&response)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    match packet.header.response_code {
+        ResponseCode::NoError => {}
+        ResponseCode::NameError => return Ok(None),
+        code => return Err(io::Error::other(format!("DNS error: {code:?}"))),
+    }
+    Ok(Some(
+        packet
+            .answers
+            .iter()
+            .filter_map(|answer| match &answer.data {
+                RData::MX(mx) => Some((mx.preference, mx.exchange.to_string())),
+                _ => None,
+            })
+            .collect(),
+    ))
+}
+
+/// Send a query over UDP, retrying if no response arrives.
+fn exchange(nameserver: SocketAddr, id: u16, query: &[u8]) -> io::Result<Vec<u8>> {
+    let local: IpAddr = if nameserver.is_ipv4() {
+        Ipv4Addr::UNSPECIFIED.into()
+    } else {
+        Ipv6Addr::UNSPECIFIED.into()
+    };
+    let socket = UdpSocket::bind((local, 0))?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect(nameserver)?;
+    let mut buffer = [0; 4096];
+    let mut last_error = io::Error::new(io::ErrorKind::TimedOut, "DNS query timed out");
+    for _ in 0..ATTEMPTS {
+        socket.send(query)?;
+        match socket.recv(&mut buffer) {
+            // Ignore late responses to earlier queries.
+            Ok(len) if len >= 2 && buffer[..2] == id.to_be_bytes() => {
+                return Ok(buffer[..len].to_vec())
+            }
+            Ok(_) => {}
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// First nameserver in /etc/resolv.conf, or the local host if there are none.
+fn system_nameserver() -> io::Result<SocketAddr> {
+    let resolv_conf = fs::read_to_string("/etc/resolv.conf")?;
+    let address = resolv_conf
+        .lines()
+        .filter_map(|line| line.strip_prefix("nameserver"))
+        .find_map(|address| address.trim().parse::<IpAddr>().ok())
+        .unwrap_or_else(|| Ipv4Addr::LOCALHOST.into());
+    Ok(SocketAddr::new(address, DNS_PORT))
+}
@@ -0,0 +1,168 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use url::Url;
+
+/// URL with the differences that don't change what's fetched removed, such as the case of the
+/// host, a default port, or a fragment.
+pub fn canonical_url(url: &str) -> String {
+    match Url::parse(url.trim()) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => url.trim().to_string(),
+    }
+}
+
+/// Keep the first of each set of feeds with the same canonical URL. Also returns the URL of each
+/// feed that was dropped, with the URL of the feed it's the same as.
+pub fn first_by_canonical_url<T>(
+    feeds: Vec<T>,
+    url: impl Fn(&T) -> &str,
+) -> (Vec<T>, Vec<(String, String)>) {
+    let mut first_urls: HashMap<String, String> = HashMap::new();
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    for feed in feeds {
+        match first_urls.entry(canonical_url(url(&feed))) {
+            Entry::Occupied(first) => dropped.push((url(&feed).to_string(), first.get().clone())),
+            Entry::Vacant(entry) => {
+                entry.insert(url(&feed).to_string());
+                kept.push(feed);
+            }
+        }
+    }
+    (kept, dropped)
+}
+
+/// Group feeds that are the same feed, because they share a canonical URL, such as a self link
+/// or redirect target, or one's canonical URL is the other's URL.
+///
+/// Each feed is given with its canonical URLs. Only groups of more than one feed are returned,
+/// with feeds in the order given.
+pub fn group<'a>(feeds: &[(&'a str, Vec<String>)]) -> Vec<Vec<&'a str>> {
+    // URLs known for each group, and the indexes of its feeds.
+    let mut groups: Vec<(HashSet<&str>, Vec<usize>)> = Vec::new();
+    for (index, (url, canonical_urls)) in feeds.iter().enumerate() {
+        let mut urls: HashSet<&str> = canonical_urls.iter().map(String::as_str).collect();
+        urls.insert(url);
+        let mut members = vec![index];
+        // Merge every group that shares a URL, since this feed joins them.
+        let (shared, rest): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|(group_urls, _)| !group_urls.is_disjoint(&urls));
+        for (group_urls, group_members) in shared {
+            urls.extend(group_urls);
+            members.extend(group_members);
+        }
+        groups = rest;
+        groups.push((urls, members));
+    }
+    let mut duplicates: Vec<Vec<usize>> = groups
+        .into_iter()
+        .map(|(_, mut members)| {
+            members.sort_unstable();
+            members
+        })
+        .filter(|members| members.len() > 1)
+        .collect();
+    duplicates.sort();
+    duplicates
+        .into_iter()
+        .map(|members| members.into_iter().map(|index| feeds[index].0).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_by_canonical_url_drops_other_spellings() {
+        let feeds = vec![
+            "https://a.example/feed",
+            "https://b.example/feed",
+            "HTTPS://A.example:443/feed#top",
+            "not a url",
+            " not a url",
+        ];
+        assert_eq!(
+            first_by_canonical_url(feeds, |url| url),
+            (
+                vec![
+                    "https://a.example/feed",
+                    "https://b.example/feed",
+                    "not a url"
+                ],
+                vec![
+                    (
+                        "HTTPS://A.example:443/feed#top".to_string(),
+                        "https://a.example/feed".to_string()
+                    ),
+                    (" not a url".to_string(), "not a url".to_string()),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn group_finds_feeds_with_shared_canonical_urls() {
+        let feeds = [
+            (
+                "http://a.example/feed",
+                vec!["https://a.example/feed".to_string()],
+            ),
+            ("http://b.example/rss", vec![]),
+            ("https://a.example/feed", vec![]),
+            (
+                "https://c.example/mirror",
+                vec!["https://c.example/feed".to_string()],
+            ),
+            (
+                "https://b.example/rss",
+                vec!["https://b.example/feed".to_string()],
+            ),
+            (
+                "https://c.example/feed.xml",
+                vec!["https://c.example/feed".to_string()],
+            ),
+        ];
+        assert_eq!(
+            group(&feeds),
+            vec![
+                vec!["http://a.example/feed", "https://a.example/feed"],
+                vec!["https://c.example/mirror", "https://c.example/feed.xml"],
+            ]
+        );
+    }
+
+    #[test]
+    fn group_merges_groups_joined_by_a_later_feed() {
+        let feeds = [
+            (
+                "https://one.example/",
+                vec!["https://x.example/".to_string()],
+            ),
+            (
+                "https://two.example/",
+                vec!["https://y.example/".to_string()],
+            ),
+            (
+                "https://three.example/",
+                vec![
+                    "https://x.example/".to_string(),
+                    "https://y.example/".to_string(),
+                ],
+            ),
+        ];
+        assert_eq!(
+            group(&feeds),
+            vec![vec![
+                "https://one.example/",
+                "https://two.example/",
+                "https://three.example/"
+            ]]
+        );
+    }
+}
@@ -0,0 +1,461 @@
+//! Errors of commands, with the context they happened in and stable codes for scripts.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use derive_more::{Display, From};
+use serde::Serialize;
+
+use crate::{database, feed, later, lua, mx, oauth, transport};
+
+/// Part of a run an error happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    #[display(fmt = "fetching")]
+    Fetch,
+    #[display(fmt = "mailing")]
+    Mail,
+}
+
+/// Where an error happened, such as the feed being fetched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Context {
+    pub phase: Phase,
+    pub feed: Option<String>,
+}
+impl std::fmt::Display for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.feed {
+            Some(feed) => write!(f, "{} {feed}", self.phase),
+            None => write!(f, "{}", self.phase),
+        }
+    }
+}
+
+#[derive(Debug, From, Display)]
+pub enum Error {
+    #[display(fmt = "failed to parse config: {_0}")]
+    ParseConfig(toml::de::Error),
+    #[display(fmt = "failed to read config: {_0}")]
+    ReadConfig(std::io::Error),
+    #[display(fmt = "feed not modified")]
+    FeedNotModified,
+    #[display(fmt = "disallowed by robots.txt")]
+    RobotsDisallowed,
+    #[display(fmt = "invalid feed URL: {_0}")]
+    InvalidUrl(url::ParseError),
+    #[display(fmt = "unexpected status code: {_0}")]
+    UnexpectedStatusCode(u16),
+    #[from(ignore)]
+    #[display(
+        fmt = "got a {_0} challenge page instead of the feed, the host may be blocking automated \
+               clients"
+    )]
+    Challenge(String),
+    #[from(ignore)]
+    #[display(fmt = "backing off until {_1} after {_0} challenge pages")]
+    ChallengeBackoff(String, DateTime<Utc>),
+    Http(attohttpc::Error),
+    Parse(feed::Error),
+    #[display(fmt = "database error: {_0}")]
+    Database(database::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to create config directory: {_0}")]
+    CreateConfigDir(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to create config file: {_0}")]
+    CreateConfigFile(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to create database directory: {_0}")]
+    CreateDatabaseDir(std::io::Error),
+    #[display(fmt = "sendmail error: {_0}")]
+    Sendmail(lettre::sendmail::error::Error),
+    #[display(fmt = "delivery error: {_0}")]
+    Mx(mx::Error),
+    #[display(fmt = "template error: {}", "error_chain(_0)")]
+    Template(tera::Error),
+    #[from(ignore)]
+    #[display(
+        fmt = "template error in {}: {}; fix the template, or remove it to use the built-in one",
+        "_0.display()",
+        "error_chain(_1)"
+    )]
+    CustomTemplate(PathBuf, tera::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to send {_0} of {_1} mails")]
+    SendFailed(usize, usize),
+    #[display(fmt = "failed to handle Ctrl-C: {_0}")]
+    Signal(ctrlc::Error),
+    #[display(fmt = "interrupted")]
+    Interrupted,
+    #[display(fmt = "this command writes to the database, so it can't be run with --read-only")]
+    ReadOnlyCommand,
+    #[display(fmt = "daemon needs a callback, a web page, or a fetch interval configured")]
+    DaemonNotConfigured,
+    #[from(ignore)]
+    #[display(fmt = "callback server error: {_0}")]
+    CallbackServer(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "web server error: {_0}")]
+    WebServer(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "preview server error: {_0}")]
+    PreviewServer(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to sign mail: {_0}")]
+    Sign(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "hook failed: {_0}")]
+    Hook(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to transform items: {_0}")]
+    Transform(std::io::Error),
+    #[display(fmt = "Lua script error: {_0}")]
+    Lua(lua::Error),
+    Transport(transport::Error),
+    #[from(ignore)]
+    #[display(
+        fmt = "found a database from an older version at {}, run `squeakmail migrate-state` to \
+               move it",
+        "_0.display()"
+    )]
+    StateNotMigrated(PathBuf),
+    #[from(ignore)]
+    #[display(fmt = "{} already exists", "_0.display()")]
+    StateExists(PathBuf),
+    #[from(ignore)]
+    #[display(fmt = "failed to move database: {_0}")]
+    MigrateState(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to move database aside: {_0}")]
+    Rebuild(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to write metrics: {_0}")]
+    WriteMetrics(std::io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to read state export: {_0}")]
+    ReadState(std::io::Error),
+    #[display(fmt = "invalid state export: {_0}")]
+    ParseState(serde_json::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to write config: {_0}")]
+    WriteConfig(std::io::Error),
+    #[from(ignore)]
+    #[display(
+        fmt = "{} changed while it was being rewritten, so it was left as it is",
+        "_0.display()"
+    )]
+    ConfigChanged(PathBuf),
+    #[from(ignore)]
+    #[display(
+        fmt = "{} has keys of an older version, run `squeakmail config upgrade` first",
+        "_0.display()"
+    )]
+    ConfigNeedsUpgrade(PathBuf),
+    #[display(fmt = "feeds of the config are written in a way that can't be edited in place")]
+    FeedsNotEditable,
+    #[display(fmt = "failed to serialize config: {_0}")]
+    SerializeConfig(toml::ser::Error),
+    #[from(ignore)]
+    #[display(fmt = "no unread item with GUID {_0:?}")]
+    NoUnreadItem(String),
+    #[from(ignore)]
+    #[display(fmt = "no item with GUID {_0:?}")]
+    NoItem(String),
+    #[from(ignore)]
+    #[display(fmt = "item with GUID {_0:?} has no link")]
+    NoLink(String),
+    #[from(ignore)]
+    #[display(fmt = "feed is larger than {_0} bytes")]
+    FeedTooLarge(u64),
+    #[display(fmt = "failed to get access token: {_0}")]
+    OAuth(oauth::Error),
+    #[display(fmt = "read-it-later error: {_0}")]
+    ReadLater(later::Error),
+    #[display(fmt = "no read-it-later service is configured")]
+    ReadLaterNotConfigured,
+    #[from(ignore)]
+    #[display(fmt = "feed {_0} is listed more than once")]
+    DuplicateFeed(String),
+    #[display(fmt = "some feeds are subscribed to more than once")]
+    DuplicateFeeds,
+    #[from(ignore)]
+    #[display(fmt = "failed to read OPML file: {_0}")]
+    ReadOpml(std::io::Error),
+    #[display(fmt = "invalid OPML file: {_0}")]
+    Opml(quick_xml::Error),
+    #[from(ignore)]
+    #[display(fmt = "already subscribed to {_0}")]
+    AlreadySubscribed(String),
+    #[from(ignore)]
+    #[display(fmt = "not subscribed to {_0}")]
+    NotSubscribed(String),
+    #[from(ignore)]
+    #[display(fmt = "pipelines {_0:?} and {_1:?} have the same name or tag")]
+    ConflictingPipelines(String, String),
+    #[from(ignore)]
+    #[display(fmt = "pipeline {_0:?} sets both interval_minutes and at")]
+    ConflictingPipelineSchedules(String),
+    /// The SMTP transport, such as "the SMTP transport of pipeline \"alerts\"".
+    #[from(ignore)]
+    #[display(fmt = "{_0} sets both password and password_file")]
+    ConflictingSmtpPasswords(String),
+    #[display(fmt = "summarizer must set one of command and url")]
+    SummarizerBackend,
+    #[from(ignore)]
+    #[display(fmt = "more than one feed is named {_0:?}")]
+    DuplicateFeedName(String),
+    #[from(ignore)]
+    #[display(fmt = "{_0} refers to feed {_1:?}, which isn't in sources.feeds")]
+    UnknownFeed(String, String),
+    #[from(ignore)]
+    #[display(fmt = "pipeline {_0:?} mails tag {_1:?}, which no feed or rule sets")]
+    UnknownTag(String, String),
+    #[from(ignore)]
+    #[display(fmt = "no pipeline is named {_0:?}")]
+    UnknownPipeline(String),
+    #[display(
+        fmt = "unknown config keys {}, pass --lenient-config to ignore them",
+        "quoted_keys(_0)"
+    )]
+    UnknownConfigKeys(Vec<String>),
+    #[from(ignore)]
+    #[display(fmt = "{_0}: {_1}")]
+    Context(Context, Box<Error>),
+}
+
+pub type Result<T = ()> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Add where the error happened.
+    pub fn context(self, phase: Phase, feed: Option<&str>) -> Self {
+        let context = Context {
+            phase,
+            feed: feed.map(str::to_string),
+        };
+        Self::Context(context, Box::new(self))
+    }
+
+    /// The error without the context it happened in.
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::Context(_, source) => source.root(),
+            error => error,
+        }
+    }
+
+    /// Code identifying the kind of error, which stays the same between versions, for scripts.
+    pub fn code(&self) -> &'static str {
+        match self.root() {
+            Self::ParseConfig(_) => "parse_config",
+            Self::ReadConfig(_) => "read_config",
+            Self::FeedNotModified => "feed_not_modified",
+            Self::RobotsDisallowed => "robots_disallowed",
+            Self::InvalidUrl(_) => "invalid_url",
+            Self::UnexpectedStatusCode(_) => "unexpected_status_code",
+            Self::Challenge(_) => "challenge",
+            Self::ChallengeBackoff(..) => "challenge_backoff",
+            Self::Http(_) => "http",
+            Self::Parse(_) => "parse_feed",
+            Self::Database(_) => "database",
+            Self::CreateConfigDir(_) => "create_config_dir",
+            Self::CreateConfigFile(_) => "create_config_file",
+            Self::CreateDatabaseDir(_) => "create_database_dir",
+            Self::Sendmail(_) => "sendmail",
+            Self::Mx(_) => "mx",
+            Self::Template(_) => "template",
+            Self::CustomTemplate(..) => "custom_template",
+            Self::SendFailed(..) => "send_failed",
+            Self::Signal(_) => "signal",
+            Self::Interrupted => "interrupted",
+            Self::ReadOnlyCommand => "read_only_command",
+            Self::DaemonNotConfigured => "daemon_not_configured",
+            Self::CallbackServer(_) => "callback_server",
+            Self::WebServer(_) => "web_server",
+            Self::PreviewServer(_) => "preview_server",
+            Self::Sign(_) => "sign",
+            Self::Hook(_) => "hook",
+            Self::Transform(_) => "transform",
+            Self::Lua(_) => "lua",
+            Self::Transport(_) => "transport",
+            Self::StateNotMigrated(_) => "state_not_migrated",
+            Self::StateExists(_) => "state_exists",
+            Self::MigrateState(_) => "migrate_state",
+            Self::WriteMetrics(_) => "write_metrics",
+            Self::Rebuild(_) => "rebuild",
+            Self::ReadState(_) => "read_state",
+            Self::ParseState(_) => "parse_state",
+            Self::WriteConfig(_) => "write_config",
+            Self::ConfigChanged(_) => "config_changed",
+            Self::ConfigNeedsUpgrade(_) => "config_needs_upgrade",
+            Self::FeedTooLarge(_) => "feed_too_large",
+            Self::FeedsNotEditable => "feeds_not_editable",
+            Self::SerializeConfig(_) => "serialize_config",
+            Self::NoUnreadItem(_) => "no_unread_item",
+            Self::NoItem(_) => "no_item",
+            Self::NoLink(_) => "no_link",
+            Self::OAuth(_) => "oauth",
+            Self::ReadLater(_) => "read_later",
+            Self::ReadLaterNotConfigured => "read_later_not_configured",
+            Self::DuplicateFeed(_) => "duplicate_feed",
+            Self::DuplicateFeeds => "duplicate_feeds",
+            Self::ReadOpml(_) => "read_opml",
+            Self::Opml(_) => "opml",
+            Self::AlreadySubscribed(_) => "already_subscribed",
+            Self::NotSubscribed(_) => "not_subscribed",
+            Self::ConflictingPipelines(..) => "conflicting_pipelines",
+            Self::ConflictingPipelineSchedules(_) => "conflicting_pipeline_schedules",
+            Self::ConflictingSmtpPasswords(_) => "conflicting_smtp_passwords",
+            Self::SummarizerBackend => "summarizer_backend",
+            Self::DuplicateFeedName(_) => "duplicate_feed_name",
+            Self::UnknownFeed(..) => "unknown_feed",
+            Self::UnknownTag(..) => "unknown_tag",
+            Self::UnknownPipeline(_) => "unknown_pipeline",
+            Self::UnknownConfigKeys(_) => "unknown_config_keys",
+            Self::Context(..) => unreachable!("root error has no context"),
+        }
+    }
+
+    /// Status to exit with: 2 for config errors, 3 for database errors, 4 for fetch errors, 5
+    /// for mail errors, 6 for hook errors, 130 when interrupted, and 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self.root() {
+            Self::ParseConfig(_)
+            | Self::ReadConfig(_)
+            | Self::CreateConfigDir(_)
+            | Self::CreateConfigFile(_)
+            | Self::WriteConfig(_)
+            | Self::ConfigChanged(_)
+            | Self::ConfigNeedsUpgrade(_)
+            | Self::FeedsNotEditable
+            | Self::SerializeConfig(_)
+            | Self::DaemonNotConfigured
+            | Self::ReadLaterNotConfigured
+            | Self::DuplicateFeed(_)
+            | Self::DuplicateFeeds
+            | Self::AlreadySubscribed(_)
+            | Self::NotSubscribed(_)
+            | Self::ConflictingPipelines(..)
+            | Self::ConflictingPipelineSchedules(_)
+            | Self::ConflictingSmtpPasswords(_)
+            | Self::SummarizerBackend
+            | Self::DuplicateFeedName(_)
+            | Self::UnknownFeed(..)
+            | Self::UnknownTag(..)
+            | Self::UnknownPipeline(_)
+            | Self::UnknownConfigKeys(_) => 2,
+            Self::Database(_)
+            | Self::CreateDatabaseDir(_)
+            | Self::StateNotMigrated(_)
+            | Self::StateExists(_)
+            | Self::MigrateState(_)
+            | Self::Rebuild(_)
+            | Self::ReadState(_)
+            | Self::ParseState(_) => 3,
+            Self::FeedNotModified
+            | Self::RobotsDisallowed
+            | Self::InvalidUrl(_)
+            | Self::UnexpectedStatusCode(_)
+            | Self::Challenge(_)
+            | Self::ChallengeBackoff(..)
+            | Self::FeedTooLarge(_)
+            | Self::Http(_)
+            | Self::OAuth(_)
+            | Self::Parse(_) => 4,
+            Self::Sendmail(_)
+            | Self::Mx(_)
+            | Self::Template(_)
+            | Self::CustomTemplate(..)
+            | Self::SendFailed(..)
+            | Self::Sign(_)
+            | Self::Transport(_) => 5,
+            Self::Hook(_) => 6,
+            Self::Interrupted => 130,
+            _ => 1,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Context(_, source) => Some(source.as_ref()),
+            Self::ParseConfig(e) => Some(e),
+            Self::InvalidUrl(e) => Some(e),
+            Self::Http(e) => Some(e),
+            Self::Opml(e) => Some(e),
+            Self::Template(e) | Self::CustomTemplate(_, e) => Some(e),
+            Self::ParseState(e) => Some(e),
+            Self::SerializeConfig(e) => Some(e),
+            Self::ReadConfig(e)
+            | Self::CreateConfigDir(e)
+            | Self::CreateConfigFile(e)
+            | Self::CreateDatabaseDir(e)
+            | Self::CallbackServer(e)
+            | Self::WebServer(e)
+            | Self::PreviewServer(e)
+            | Self::Sign(e)
+            | Self::Hook(e)
+            | Self::Transform(e)
+            | Self::MigrateState(e)
+            | Self::WriteMetrics(e)
+            | Self::Rebuild(e)
+            | Self::ReadState(e)
+            | Self::WriteConfig(e)
+            | Self::ReadOpml(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Format config keys as a list, such as "`a`, `b`".
+fn quoted_keys(keys: &[String]) -> String {
+    keys.iter()
+        .map(|key| format!("`{key}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format an error with its sources, for errors that hide details in their sources.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push_str(": ");
+        chain.push_str(&error.to_string());
+        source = error.source();
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_is_shown_but_not_coded() {
+        let error = Error::UnexpectedStatusCode(404)
+            .context(Phase::Fetch, Some("https://example.com/feed.xml"))
+            .context(Phase::Fetch, None);
+        assert_eq!(
+            error.to_string(),
+            "fetching: fetching https://example.com/feed.xml: unexpected status code: 404"
+        );
+        assert_eq!(error.root().to_string(), "unexpected status code: 404");
+        assert_eq!(error.code(), "unexpected_status_code");
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn source_chain_includes_context() {
+        let error = Error::Hook(std::io::Error::other("exited with 1")).context(Phase::Mail, None);
+        let source = std::error::Error::source(&error).expect("no source");
+        assert_eq!(source.to_string(), "hook failed: exited with 1");
+        assert_eq!(
+            source.source().map(ToString::to_string).as_deref(),
+            Some("exited with 1")
+        );
+        assert_eq!(error.exit_code(), 6);
+    }
+}
@@ -0,0 +1,188 @@
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use regex::{Captures, Regex};
+use sha2::{Digest as _, Sha256};
+
+use crate::database;
+
+// iCalendar lines longer than this many octets must be folded.
+const MAX_LINE_LEN: usize = 75;
+const MONTHS: &str = "january|february|march|april|may|june|july|august|september|october|\
+                      november|december|jan|feb|mar|apr|jun|jul|aug|sept|sep|oct|nov|dec";
+// Every month name and abbreviation starts with one of these.
+const MONTH_PREFIXES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Start of an event, which is a whole day unless a time was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Start {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+/// Event detected in an item.
+#[derive(Debug)]
+pub struct Event {
+    pub uid: String,
+    pub start: Start,
+    pub summary: String,
+    pub url: String,
+}
+
+/// Patterns for dates written in item titles.
+pub struct Detector {
+    iso: Regex,
+    month_day: Regex,
+    day_month: Regex,
+    time: Regex,
+}
+impl Default for Detector {
+    fn default() -> Self {
+        Self {
+            iso: Regex::new(r"\b(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})(?:T|\b)")
+                .expect("invalid regex"),
+            month_day: Regex::new(&format!(
+                r"(?i)\b(?P<month>{MONTHS})\.?\s+(?P<day>\d{{1,2}})(?:st|nd|rd|th)?\b(?:,?\s+(?P<year>\d{{4}})\b)?"
+            ))
+            .expect("invalid regex"),
+            day_month: Regex::new(&format!(
+                r"(?i)\b(?P<day>\d{{1,2}})(?:st|nd|rd|th)?\s+(?P<month>{MONTHS})\b\.?(?:,?\s+(?P<year>\d{{4}})\b)?"
+            ))
+            .expect("invalid regex"),
+            time: Regex::new(
+                r"(?i)^,?\s*(?:at\s+|@\s*)?(?P<hour>\d{1,2})(?::(?P<minute>\d{2}))?\s*(?P<meridiem>[ap]\.?m\b\.?)?",
+            )
+            .expect("invalid regex"),
+        }
+    }
+}
+impl Detector {
+    /// Find the first date in the item title, and the time following it if there is one.
+    pub fn detect(&self, item: &database::Item) -> Option<Event> {
+        let (date, end) = self.detect_date(&item.title, item.pub_date)?;
+        let start = match self.detect_time(&item.title[end..]) {
+            Some(time) => Start::DateTime(date.and_time(time)),
+            None => Start::Date(date),
+        };
+        let uid = Sha256::digest(format!("{}\n{}", item.feed_url, item.guid));
+        Some(Event {
+            uid: format!("{uid:x}@squeakmail"),
+            start,
+            summary: item.title.clone(),
+            url: item.link.clone(),
+        })
+    }
+
+    /// Returns the date and the index after it.
+    fn detect_date(&self, title: &str, pub_date: DateTime<Utc>) -> Option<(NaiveDate, usize)> {
+        [&self.iso, &self.month_day, &self.day_month]
+            .iter()
+            .filter_map(|regex| regex.captures(title))
+            .filter_map(|captures| {
+                let whole = captures.get(0)?;
+                Some((parse_date(&captures, pub_date)?, whole.start(), whole.end()))
+            })
+            .min_by_key(|(_, start, _)| *start)
+            .map(|(date, _, end)| (date, end))
+    }
+
+    fn detect_time(&self, text: &str) -> Option<NaiveTime> {
+        let captures = self.time.captures(text)?;
+        let mut hour: u32 = captures["hour"].parse().ok()?;
+        let minute = captures.name("minute");
+        let meridiem = captures.name("meridiem");
+        // A bare number after a date is more likely a count than a time.
+        if minute.is_none() && meridiem.is_none() {
+            return None;
+        }
+        if let Some(meridiem) = meridiem {
+            if !(1..=12).contains(&hour) {
+                return None;
+            }
+            let is_pm = meridiem.as_str().to_lowercase().starts_with('p');
+            hour = hour % 12 + if is_pm { 12 } else { 0 };
+        }
+        let minute = minute.map_or(Some(0), |minute| minute.as_str().parse().ok())?;
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+}
+
+/// Build a date from captures, assuming the next occurrence after publication if the year is
+/// missing.
+fn parse_date(captures: &Captures, pub_date: DateTime<Utc>) -> Option<NaiveDate> {
+    let day = captures["day"].parse().ok()?;
+    let month = &captures["month"];
+    let month = month.parse().ok().or_else(|| {
+        let month = month.to_lowercase();
+        let index = MONTH_PREFIXES
+            .iter()
+            .position(|prefix| month.starts_with(prefix))?;
+        u32::try_from(index + 1).ok()
+    })?;
+    if let Some(year) = captures.name("year") {
+        return NaiveDate::from_ymd_opt(year.as_str().parse().ok()?, month, day);
+    }
+    let published = pub_date.naive_utc().date();
+    let date = NaiveDate::from_ymd_opt(published.year(), month, day)?;
+    if date < published {
+        NaiveDate::from_ymd_opt(published.year() + 1, month, day)
+    } else {
+        Some(date)
+    }
+}
+
+/// Write events as an iCalendar file.
+pub fn to_ics(events: &[Event], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//SqueakMail//SqueakMail//EN".to_string(),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")));
+        lines.push(match event.start {
+            Start::Date(date) => format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+            // Floating time, since the feed doesn't say which time zone it's in.
+            Start::DateTime(date_time) => {
+                format!("DTSTART:{}", date_time.format("%Y%m%dT%H%M%S"))
+            }
+        });
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if !event.url.is_empty() {
+            lines.push(format!("URL:{}", event.url));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    let mut ics = String::new();
+    for line in lines {
+        fold_line(&mut ics, &line);
+    }
+    ics
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+/// Append a line, folding it into continuation lines that start with a space.
+fn fold_line(ics: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE_LEN {
+            ics.push_str("\r\n ");
+            len = 1;
+        }
+        ics.push(c);
+        len += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
@@ -0,0 +1,320 @@
+// Longest title derived from content, in characters, before it's shortened at a word.
+const MAX_TITLE_CHARS: usize = 80;
+/// Longest excerpt of content, in characters, unless a feed sets its own.
+pub const MAX_EXCERPT_CHARS: usize = 300;
+
+/// Title for an item that doesn't have one, from the first sentence of its content, or else the
+/// last segment of its link's path, such as "Hello world" for ".../hello-world.html".
+pub fn title(content: Option<&str>, link: &str) -> Option<String> {
+    content
+        .and_then(first_sentence)
+        .or_else(|| slug_title(link))
+}
+
+/// Words of HTML or plain text content, without its markup, so changes to only its markup or
+/// spacing leave them the same.
+pub fn words(content: &str) -> String {
+    strip_tags(content)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Paragraphs of the text of HTML or plain text content, with their spacing collapsed.
+pub fn paragraphs(content: &str) -> Vec<String> {
+    strip_tags(content)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect()
+}
+
+/// Paragraphs of HTML or plain text content, cut short after `max_paragraphs` paragraphs or
+/// `max_chars` characters, and whether the rest was left out.
+pub fn excerpt(content: &str, max_chars: usize, max_paragraphs: usize) -> (Vec<String>, bool) {
+    let mut paragraphs = paragraphs(content);
+    let has_more_paragraphs = paragraphs.len() > max_paragraphs;
+    paragraphs.truncate(max_paragraphs);
+    let (paragraphs, is_shortened) = truncate(paragraphs, max_chars);
+    (paragraphs, has_more_paragraphs || is_shortened)
+}
+
+/// Paragraphs shortened to at most `max_chars` characters in all, at a word, and whether any
+/// text was left out.
+pub fn truncate(paragraphs: Vec<String>, max_chars: usize) -> (Vec<String>, bool) {
+    let mut budget = max_chars;
+    let mut kept = Vec::new();
+    for paragraph in paragraphs {
+        let len = paragraph.chars().count();
+        if len > budget {
+            if budget > 0 {
+                kept.push(shorten(&paragraph, budget));
+            }
+            return (kept, true);
+        }
+        budget -= len;
+        kept.push(paragraph);
+    }
+    (kept, false)
+}
+
+/// First line or sentence of HTML or plain text content, shortened if it's long.
+fn first_sentence(content: &str) -> Option<String> {
+    let text = strip_tags(content);
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let sentence = line
+        .match_indices(['.', '!', '?'])
+        .map(|(index, _)| index + 1)
+        .find(|&end| line[end..].starts_with(char::is_whitespace))
+        .map_or(line, |end| &line[..end]);
+    let sentence = sentence.split_whitespace().collect::<Vec<_>>().join(" ");
+    if sentence.chars().count() <= MAX_TITLE_CHARS {
+        return Some(sentence);
+    }
+    Some(shorten(&sentence, MAX_TITLE_CHARS))
+}
+
+/// Text shortened at a word to at most `max_chars` characters, with an ellipsis after it.
+fn shorten(text: &str, max_chars: usize) -> String {
+    let mut shortened = String::new();
+    for word in text.split(' ') {
+        if shortened.chars().count() + word.chars().count() + 1 > max_chars {
+            break;
+        }
+        if !shortened.is_empty() {
+            shortened.push(' ');
+        }
+        shortened.push_str(word);
+    }
+    if shortened.is_empty() {
+        shortened = text.chars().take(max_chars).collect();
+    }
+    shortened.push('…');
+    shortened
+}
+
+/// Text of HTML, with line breaks where blocks end and common entities decoded. The contents of
+/// scripts, style sheets, and `noscript` elements aren't text, and are left out.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end].to_ascii_lowercase();
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("");
+        if matches!(
+            name,
+            "br" | "p" | "div" | "li" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote"
+        ) {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+        if matches!(name, "script" | "style" | "noscript") && !tag.starts_with('/') {
+            // Lowercasing ASCII keeps byte offsets the same.
+            let closing = format!("</{name}");
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(close) => &rest[close..],
+                None => "",
+            };
+        }
+    }
+    text.push_str(rest);
+    decode_entities(&text)
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..=end])?, end + 2)));
+        if let Some((c, len)) = entity {
+            decoded.push(c);
+            rest = &rest[len..];
+        } else {
+            decoded.push('&');
+            rest = &rest[1..];
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Title from the last segment of a link's path, unless it's only a number or ID.
+fn slug_title(link: &str) -> Option<String> {
+    let url = url::Url::parse(link).ok()?;
+    let segment = url
+        .path_segments()?
+        .rev()
+        .find(|segment| !segment.is_empty())?;
+    let slug = segment.split('.').next().unwrap_or(segment);
+    let slug = percent_decode(slug);
+    let words: Vec<&str> = slug
+        .split(['-', '_', '+', ' '])
+        .filter(|word| !word.is_empty())
+        .collect();
+    if !words
+        .iter()
+        .any(|word| word.chars().any(char::is_alphabetic))
+    {
+        return None;
+    }
+    let title = words.join(" ");
+    let mut chars = title.chars();
+    let first = chars.next()?;
+    Some(first.to_uppercase().chain(chars).collect())
+}
+
+fn percent_decode(s: &str) -> String {
+    url::form_urlencoded::parse(format!("s={s}").as_bytes())
+        .next()
+        .map_or_else(|| s.to_string(), |(_, value)| value.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_from_first_sentence_of_content() {
+        assert_eq!(
+            title(
+                Some("<p>Shipped the new release! Details below.</p><p>More</p>"),
+                "https://example.org/123"
+            ),
+            Some("Shipped the new release!".to_string())
+        );
+        assert_eq!(
+            title(Some("Coffee &amp; code<br>second line"), ""),
+            Some("Coffee & code".to_string())
+        );
+        assert_eq!(
+            title(Some("Version 1.2 is out, see v1.2.3."), ""),
+            Some("Version 1.2 is out, see v1.2.3.".to_string())
+        );
+    }
+
+    #[test]
+    fn title_shortens_long_sentences_at_a_word() {
+        let content = "word ".repeat(30);
+        let title = title(Some(&content), "").expect("no title");
+        assert!(title.ends_with("word…"));
+        assert!(title.chars().count() <= MAX_TITLE_CHARS + 1);
+    }
+
+    #[test]
+    fn paragraphs_of_html_and_plain_text() {
+        assert_eq!(
+            paragraphs("<p>First  paragraph,\n wrapped.</p><p>Second &amp; last</p>"),
+            ["First paragraph,", "wrapped.", "Second & last"]
+        );
+        assert_eq!(
+            paragraphs("<h2>Title</h2>Text<br/>more<div><p></p></div>"),
+            ["Title", "Text", "more"]
+        );
+        assert_eq!(paragraphs("  plain\n\n text  "), ["plain", "text"]);
+        assert!(paragraphs("<img src=\"a.png\">").is_empty());
+    }
+
+    #[test]
+    fn paragraphs_leave_out_scripts_and_styles() {
+        assert_eq!(
+            paragraphs(
+                "<style>p { color: red; }</style><p>Text</p>\
+                 <SCRIPT type=\"text/javascript\">if (a < b) track();</SCRIPT>\
+                 <noscript><img src=\"pixel.gif\">Enable JavaScript</noscript><p>More</p>"
+            ),
+            ["Text", "More"]
+        );
+        assert_eq!(paragraphs("<p>Text</p><script>never closed"), ["Text"]);
+    }
+
+    #[test]
+    fn truncate_shortens_paragraphs_at_a_word() {
+        let paragraphs = || vec!["one two three".to_string(), "four five".to_string()];
+        assert_eq!(
+            truncate(paragraphs(), 22),
+            (
+                vec!["one two three".to_string(), "four five".to_string()],
+                false
+            )
+        );
+        assert_eq!(
+            truncate(paragraphs(), 18),
+            (vec!["one two three".to_string(), "four…".to_string()], true)
+        );
+        assert_eq!(
+            truncate(paragraphs(), 13),
+            (vec!["one two three".to_string()], true)
+        );
+        assert_eq!(
+            truncate(paragraphs(), 9),
+            (vec!["one two…".to_string()], true)
+        );
+    }
+
+    #[test]
+    fn excerpt_keeps_to_the_budget() {
+        let content = "<p>one two</p><p>three</p><p>four</p>";
+        let excerpt = |max_chars, max_paragraphs| excerpt(content, max_chars, max_paragraphs);
+        let paragraphs = |paragraphs: &[&str]| paragraphs.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            excerpt(usize::MAX, usize::MAX),
+            (paragraphs(&["one two", "three", "four"]), false)
+        );
+        assert_eq!(
+            excerpt(usize::MAX, 2),
+            (paragraphs(&["one two", "three"]), true)
+        );
+        assert_eq!(excerpt(5, usize::MAX), (paragraphs(&["one…"]), true));
+        assert_eq!(excerpt(12, 3), (paragraphs(&["one two", "three"]), true));
+    }
+
+    #[test]
+    fn title_from_link_slug() {
+        assert_eq!(
+            title(
+                Some("<img src=\"a.png\">"),
+                "https://example.org/2019/11/hello-world.html"
+            ),
+            Some("Hello world".to_string())
+        );
+        assert_eq!(
+            title(None, "https://example.org/notes/caf%C3%A9_au_lait/"),
+            Some("Café au lait".to_string())
+        );
+        assert_eq!(title(None, "https://example.org/statuses/10345"), None);
+        assert_eq!(title(None, ""), None);
+    }
+}
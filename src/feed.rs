@@ -1,51 +1,133 @@
-use std::io::Read;
+use std::io::{BufReader, Cursor, Read};
 use std::slice::Iter;
-use std::str::FromStr;
 
 use atom_syndication as atom;
 use chrono::{DateTime, FixedOffset, Utc};
 use derive_more::{Display, From};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
 
+use crate::excerpt;
+
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Item {
     pub guid: String,
     pub title: String,
+    /// Empty if the item has no link.
     pub link: String,
     pub comments_link: Option<String>,
     pub pub_date: DateTime<Utc>,
+    pub author: Option<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Hash of the words of the item's content, to notice when it changes, if it has any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// HTML or plain text content of the item, or its summary if it has no content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
 }
 impl From<&rss::Item> for Item {
     fn from(item: &rss::Item) -> Self {
+        let link = item.link().map(clean).unwrap_or_default();
+        let content = item.content().or_else(|| item.description());
         Self {
             guid: item.guid().map_or("", |guid| guid.value()).to_string(),
-            title: item.title().unwrap_or("Untitled").to_string(),
-            link: item.link().unwrap_or("https://example.com").to_string(),
-            comments_link: item.comments().map(|s| s.to_string()),
+            title: title(
+                item.title(),
+                item.description().or_else(|| item.content()),
+                &link,
+            ),
+            link,
+            comments_link: item.comments().map(clean),
             pub_date: item.pub_date().map_or_else(Utc::now, |date_str| {
                 DateTime::parse_from_rfc2822(date_str)
                     .unwrap_or_else(|_| Utc::now().with_timezone(&FixedOffset::east(0)))
                     .with_timezone(&Utc)
             }),
+            author: item.author().map(|s| s.to_string()),
+            categories: item
+                .categories()
+                .iter()
+                .map(|category| category.name().to_string())
+                .collect(),
+            content_hash: content_hash(content),
+            content: content.map(str::to_string),
         }
     }
 }
 impl From<&atom::Entry> for Item {
     fn from(entry: &atom::Entry) -> Self {
+        let link = entry
+            .links()
+            .first()
+            .map(|link| clean(link.href()))
+            .unwrap_or_default();
+        let content = entry
+            .content()
+            .and_then(atom::Content::value)
+            .or_else(|| entry.summary());
         Self {
             guid: entry.id().to_string(),
-            title: entry.title().to_string(),
-            link: entry
-                .links()
-                .first()
-                .map_or("https://example.com", |link| link.href())
-                .to_string(),
+            title: title(
+                Some(entry.title()),
+                entry
+                    .summary()
+                    .or_else(|| entry.content().and_then(atom::Content::value)),
+                &link,
+            ),
+            link,
             comments_link: None,
             pub_date: entry
                 .published()
                 .map_or_else(Utc::now, |dt| dt.with_timezone(&Utc)),
+            author: entry
+                .authors()
+                .first()
+                .map(|person| person.name().to_string()),
+            categories: entry
+                .categories()
+                .iter()
+                .map(|category| category.term().to_string())
+                .collect(),
+            content_hash: content_hash(content),
+            content: content.map(str::to_string),
         }
     }
 }
 
+/// Title of an item, derived from its content or link if it has none, such as in microblogs.
+fn title(title: Option<&str>, content: Option<&str>, link: &str) -> String {
+    let title = title.map(clean).filter(|title| !title.trim().is_empty());
+    title
+        .or_else(|| excerpt::title(content, link).map(|title| clean(&title)))
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+fn content_hash(content: Option<&str>) -> Option<String> {
+    let words = excerpt::words(content?);
+    if words.is_empty() {
+        return None;
+    }
+    Some(format!("{:x}", Sha256::digest(words.as_bytes())))
+}
+
+/// Normalize text to NFC, replace control characters that are whitespace with spaces, and remove
+/// other control characters and bidirectional overrides, which can make text look like something
+/// it isn't.
+fn clean(text: &str) -> String {
+    text.nfc()
+        .filter_map(|c| match c {
+            '\t' | '\n' | '\r' => Some(' '),
+            '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' => None,
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
 #[derive(Debug, Display, From)]
 pub enum Error {
     Io(std::io::Error),
@@ -55,35 +137,128 @@ pub enum Error {
 
 type Result<T = ()> = std::result::Result<T, Error>;
 
+// How much of a body to read looking for its root element, which only comments or a long
+// doctype should push this far.
+const MAX_SNIFF_BYTES: usize = 64 * 1024;
+
 pub enum Feed {
     Rss(Box<rss::Channel>),
     Atom(Box<atom::Feed>),
 }
+/// Name of the root element of an XML document, if the start of the document includes it.
+///
+/// Skips the byte order mark, XML declaration, processing instructions, comments, and doctype.
+fn root_element(start: &[u8]) -> Option<&[u8]> {
+    let mut rest = start.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(start);
+    loop {
+        rest = &rest[rest.iter().position(|byte| !byte.is_ascii_whitespace())?..];
+        rest = if let Some(after) = rest.strip_prefix(b"<?") {
+            skip_past(after, b"?>")?
+        } else if let Some(after) = rest.strip_prefix(b"<!--") {
+            skip_past(after, b"-->")?
+        } else if let Some(after) = rest.strip_prefix(b"<!") {
+            // Doctypes may have an internal subset in brackets, which contains '>'.
+            let end = after
+                .iter()
+                .position(|&byte| byte == b'[' || byte == b'>')?;
+            let after = if after[end] == b'[' {
+                skip_past(&after[end..], b"]")?
+            } else {
+                after
+            };
+            skip_past(after, b">")?
+        } else {
+            let after = rest.strip_prefix(b"<")?;
+            let end = after
+                .iter()
+                .position(|&byte| byte.is_ascii_whitespace() || byte == b'>' || byte == b'/')?;
+            return Some(&after[..end]);
+        };
+    }
+}
+
+/// Bytes after the first occurrence of a pattern.
+fn skip_past<'a>(bytes: &'a [u8], pattern: &[u8]) -> Option<&'a [u8]> {
+    bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+        .map(|index| &bytes[index + pattern.len()..])
+}
+
 impl Feed {
+    /// Parse a feed as it's read, choosing the format by its root element.
     pub fn read_from<B: Read>(mut reader: B) -> Result<Self> {
-        let mut body = String::new();
-        reader.read_to_string(&mut body)?;
-        match rss::Channel::from_str(&body) {
-            Ok(channel) => Ok(Self::Rss(Box::new(channel))),
-            Err(_) => match atom::Feed::from_str(&body) {
-                Ok(feed) => Ok(Self::Atom(Box::new(feed))),
-                Err(_) => Err(Error::Parse),
-            },
+        let mut start = Vec::new();
+        let root = loop {
+            if let Some(root) = root_element(&start) {
+                break root.to_vec();
+            }
+            if start.len() >= MAX_SNIFF_BYTES
+                || (&mut reader).take(8192).read_to_end(&mut start)? == 0
+            {
+                return Err(Error::Parse);
+            }
+        };
+        // Root elements may have a namespace prefix, such as "rdf:RDF" for RSS 1.0.
+        let name = root.rsplit(|&byte| byte == b':').next().unwrap_or(&root);
+        let reader = BufReader::new(Cursor::new(start).chain(reader));
+        match name {
+            b"rss" | b"RDF" => rss::Channel::read_from(reader)
+                .map(|channel| Self::Rss(Box::new(channel)))
+                .map_err(|_| Error::Parse),
+            b"feed" => atom::Feed::read_from(reader)
+                .map(|feed| Self::Atom(Box::new(feed)))
+                .map_err(|_| Error::Parse),
+            _ => Err(Error::Parse),
         }
     }
-    pub fn title(&self) -> &str {
-        match self {
+    pub fn title(&self) -> String {
+        clean(match self {
             Self::Rss(channel) => channel.title(),
             Self::Atom(feed) => feed.title(),
-        }
+        })
     }
-    pub fn link(&self) -> &str {
-        match self {
+    pub fn link(&self) -> String {
+        clean(match self {
             Self::Rss(channel) => channel.link(),
             Self::Atom(feed) => feed.links().first().map_or("Untitled", |link| link.href()),
+        })
+    }
+    /// URL of the hub that pushes updates of the feed to subscribers.
+    pub fn hub(&self) -> Option<String> {
+        self.link_with_rel("hub")
+    }
+    /// URL the feed gives for itself, which is its canonical URL.
+    pub fn self_link(&self) -> Option<String> {
+        self.link_with_rel("self")
+    }
+    /// URL of the first link with the relation, from Atom links in RSS channels.
+    fn link_with_rel(&self, rel: &str) -> Option<String> {
+        match self {
+            Self::Rss(channel) => {
+                // Prefixes are declared on the rss element, but some feeds declare "atom" on the
+                // link instead.
+                let prefixes = channel
+                    .namespaces()
+                    .iter()
+                    .filter(|(_, namespace)| *namespace == ATOM_NAMESPACE)
+                    .map(|(prefix, _)| prefix.as_str())
+                    .chain(std::iter::once("atom"));
+                prefixes
+                    .filter_map(|prefix| channel.extensions().get(prefix)?.get("link"))
+                    .flatten()
+                    .find(|link| link.attrs().get("rel").map(String::as_str) == Some(rel))
+                    .and_then(|link| link.attrs().get("href"))
+                    .map(|href| clean(href))
+            }
+            Self::Atom(feed) => feed
+                .links()
+                .iter()
+                .find(|link| link.rel() == rel)
+                .map(|link| clean(link.href())),
         }
     }
-    pub fn items(&self) -> Items {
+    pub fn items(&self) -> Items<'_> {
         match self {
             Self::Rss(channel) => Items::Rss(channel.items().iter()),
             Self::Atom(feed) => Items::Atom(feed.entries().iter()),
@@ -104,3 +279,101 @@ impl Iterator for Items<'_> {
         }
     }
 }
+
+/// Golden-file tests against the real-world feeds in `tests/feeds`.
+///
+/// Each `<name>.xml` is parsed and compared with the feed and items in `<name>.json`. Feeds must
+/// date their items, since missing dates are replaced with the current time. After checking that
+/// a change in parsing is intended, rewrite the expected files with `UPDATE_GOLDEN=1 cargo test`.
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Parsed {
+        title: String,
+        link: String,
+        hub: Option<String>,
+        self_link: Option<String>,
+        items: Vec<Item>,
+    }
+
+    fn parse(path: &Path) -> serde_json::Value {
+        let feed = Feed::read_from(File::open(path).expect("failed to open feed"))
+            .unwrap_or_else(|e| panic!("failed to parse {}: {e}", path.display()));
+        serde_json::to_value(Parsed {
+            title: feed.title(),
+            link: feed.link(),
+            hub: feed.hub(),
+            self_link: feed.self_link(),
+            items: feed.items().collect(),
+        })
+        .expect("failed to serialize feed")
+    }
+
+    #[test]
+    fn root_element_skips_prolog() {
+        assert_eq!(root_element(b"<rss version=\"2.0\">"), Some(&b"rss"[..]));
+        assert_eq!(
+            root_element(
+                b"\xEF\xBB\xBF<?xml version=\"1.0\"?>\n<?xml-stylesheet href=\"a.xsl\"?>\n\
+                  <!-- <rss> --><!DOCTYPE feed [<!ENTITY a \"<b>\">]>\n<feed xmlns=\"\">"
+            ),
+            Some(&b"feed"[..])
+        );
+        assert_eq!(root_element(b"<rdf:RDF>"), Some(&b"rdf:RDF"[..]));
+    }
+
+    #[test]
+    fn root_element_needs_whole_name() {
+        assert_eq!(root_element(b""), None);
+        assert_eq!(root_element(b"<?xml version="), None);
+        assert_eq!(root_element(b"<!-- comment"), None);
+        assert_eq!(root_element(b"<fe"), None);
+    }
+
+    #[test]
+    fn corpus() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/feeds");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut feed_paths: Vec<_> = fs::read_dir(&dir)
+            .expect("failed to read corpus directory")
+            .map(|entry| entry.expect("failed to read corpus entry").path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "xml"))
+            .collect();
+        feed_paths.sort();
+        assert!(!feed_paths.is_empty(), "corpus is empty");
+        let mut mismatches = Vec::new();
+        for feed_path in feed_paths {
+            let parsed = parse(&feed_path);
+            let expected_path = feed_path.with_extension("json");
+            if update {
+                let json = serde_json::to_string_pretty(&parsed).expect("failed to format JSON");
+                fs::write(&expected_path, json + "\n").expect("failed to write expected items");
+                continue;
+            }
+            let expected: serde_json::Value = serde_json::from_reader(
+                File::open(&expected_path)
+                    .unwrap_or_else(|e| panic!("missing {}: {e}", expected_path.display())),
+            )
+            .expect("failed to parse expected items");
+            if parsed != expected {
+                mismatches.push(format!(
+                    "{}:\n{}",
+                    feed_path.display(),
+                    serde_json::to_string_pretty(&parsed).expect("failed to format JSON")
+                ));
+            }
+        }
+        assert!(
+            mismatches.is_empty(),
+            "parsed feeds differ from expected:\n{}",
+            mismatches.join("\n")
+        );
+    }
+}
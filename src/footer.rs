@@ -0,0 +1,107 @@
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+
+use crate::{database, Config};
+
+/// State of fetching and sending when a digest is rendered, which templates get as `footer`
+/// when it's enabled.
+#[derive(Debug, Serialize)]
+pub struct Footer {
+    pub version: &'static str,
+    /// When the last fetch of feeds started, formatted for the locale.
+    pub fetched_at: Option<String>,
+    /// How long the last fetch of feeds took, in seconds.
+    pub fetch_seconds: Option<i64>,
+    /// Feeds whose last fetch failed, ordered by title.
+    pub failed_feeds: Vec<FailedFeed>,
+    /// When the next digest is expected, formatted for the locale.
+    pub next_send: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedFeed {
+    pub url: String,
+    pub title: String,
+    pub error: String,
+}
+
+/// Gather the state of fetching and sending for a recipient's digest.
+pub fn load(
+    config: &Config,
+    database: &mut database::Database,
+    recipient: &str,
+) -> Result<Footer, database::Error> {
+    let strings = config.locale.strings();
+    let last_run = database.get_last_fetch_run()?;
+    let errors = database.get_fetch_errors()?;
+    let mut failed_feeds = Vec::new();
+    for feed in &config.sources.feeds {
+        if let Some(error) = errors.get(&feed.url) {
+            let title = database
+                .get_feed_by_url(&feed.url)?
+                .map(|stored_feed| stored_feed.title)
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| feed.url.clone());
+            failed_feeds.push(FailedFeed {
+                url: feed.url.clone(),
+                title,
+                error: error.clone(),
+            });
+        }
+    }
+    failed_feeds.sort_by(|a, b| a.title.cmp(&b.title));
+    let last_sent = database
+        .get_last_digest(recipient)?
+        .map(|digest| digest.sent_at);
+    let next_send = next_send(config, last_sent, config.clock.now());
+    Ok(Footer {
+        version: env!("CARGO_PKG_VERSION"),
+        fetched_at: last_run
+            .as_ref()
+            .map(|run| strings.format_date(&config.clock.to_local(run.started_at))),
+        fetch_seconds: last_run.map(|run| (run.duration_ms + 500) / 1000),
+        failed_feeds,
+        next_send: next_send.map(|at| strings.format_date(&config.clock.to_local(at))),
+    })
+}
+
+/// When the next digest is expected, as long after the digest being rendered `now` as that was
+/// after the last one sent, and held until the send window starts, if one is configured.
+fn next_send(
+    config: &Config,
+    last_sent: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let interval = now - last_sent?;
+    if interval <= chrono::Duration::zero() {
+        return None;
+    }
+    let next = now + interval;
+    Some(
+        config
+            .delivery
+            .send_window
+            .as_ref()
+            .and_then(|send_window| send_window.held_until(&next.with_timezone(&Local)))
+            .unwrap_or(next),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn next_send_repeats_last_interval() {
+        let config = Config::default();
+        let now = Utc.ymd(2019, 11, 8).and_hms(7, 0, 0);
+        let last_sent = Utc.ymd(2019, 11, 7).and_hms(7, 0, 0);
+        assert_eq!(
+            next_send(&config, Some(last_sent), now),
+            Some(Utc.ymd(2019, 11, 9).and_hms(7, 0, 0))
+        );
+        assert_eq!(next_send(&config, None, now), None);
+    }
+}
@@ -0,0 +1,148 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use derive_more::{Display, From};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Shell command to run before fetching feeds. Fetching is skipped if it fails.
+    #[serde(default)]
+    pub pre_fetch: Option<String>,
+    /// Shell command to run after fetching feeds.
+    #[serde(default)]
+    pub post_fetch: Option<String>,
+    /// URL that the `post_fetch` summary is posted to as JSON, for monitoring.
+    #[serde(default)]
+    pub post_fetch_url: Option<String>,
+    /// Shell command to run after sending mail.
+    #[serde(default)]
+    pub post_mail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    PreFetch,
+    PostFetch,
+    PostMail,
+}
+
+/// Summary passed to the `pre_fetch` hook.
+#[derive(Serialize)]
+pub struct PreFetch<'a> {
+    pub feeds: Vec<&'a str>,
+}
+
+/// Summary passed to the `post_fetch` hook.
+#[derive(Serialize)]
+pub struct PostFetch {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub feeds: Vec<FeedFetch>,
+}
+
+#[derive(Serialize)]
+pub struct FeedFetch {
+    pub url: String,
+    pub status: FetchStatus,
+    /// Number of items stored for the first time.
+    pub new_items: usize,
+    pub error: Option<String>,
+    /// Code of the error, which stays the same between versions.
+    pub error_code: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchStatus {
+    Fetched,
+    NotModified,
+    Failed,
+    /// Not fetched because the deadline was reached.
+    Skipped,
+}
+
+/// Summary passed to the `post_mail` hook.
+#[derive(Serialize)]
+pub struct PostMail<'a> {
+    pub tag: Option<&'a str>,
+    pub mails: Vec<Mail>,
+    pub error: Option<String>,
+    /// Code of the error, which stays the same between versions.
+    pub error_code: Option<&'static str>,
+}
+
+#[derive(Debug, From, Display)]
+pub enum PostError {
+    Http(attohttpc::Error),
+    #[display(fmt = "unexpected status code: {_0}")]
+    UnexpectedStatusCode(u16),
+}
+
+#[derive(Serialize)]
+pub struct Mail {
+    pub recipient: String,
+    pub items: usize,
+}
+
+/// Run a hook with a summary as JSON on its stdin, if the hook is configured.
+pub fn run(config: Option<&Config>, hook: Hook, summary: &impl Serialize) -> io::Result<()> {
+    let command = config.and_then(|config| match hook {
+        Hook::PreFetch => config.pre_fetch.as_deref(),
+        Hook::PostFetch => config.post_fetch.as_deref(),
+        Hook::PostMail => config.post_mail.as_deref(),
+    });
+    let Some(command) = command else {
+        return Ok(());
+    };
+    let input = serde_json::to_vec(summary).expect("failed to serialize hook summary");
+    let mut child = shell(command).stdin(Stdio::piped()).spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    match stdin.write_all(&input) {
+        // Hooks don't have to read the summary.
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => {}
+    }
+    drop(stdin);
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{command:?} exited with {status}"
+        )))
+    }
+}
+
+/// Post a summary as JSON to a URL.
+pub fn post(url: &str, summary: &impl Serialize) -> Result<(), PostError> {
+    let resp = attohttpc::post(url)
+        .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+        .header(attohttpc::header::CONTENT_TYPE, "application/json")
+        .timeout(Duration::from_secs(30))
+        .bytes(serde_json::to_vec(summary).expect("failed to serialize hook summary"))
+        .send()?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(PostError::UnexpectedStatusCode(resp.status().as_u16()))
+    }
+}
+
+/// Build a command that runs a command line with `sh`, or `cmd` on Windows.
+pub fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
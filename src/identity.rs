@@ -0,0 +1,161 @@
+//! Strategies for telling which items of a feed are the same item, since feeds disagree about
+//! which of their fields stay stable.
+
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::feed;
+
+/// What an item's GUID is derived from, which decides whether a fetched item is new or an update
+/// of a stored one.
+#[derive(Debug, Clone, Default)]
+pub enum Identity {
+    /// The GUID or ID the feed gives the item.
+    #[default]
+    Guid,
+    /// The item link, or the GUID if the item has no link.
+    Link,
+    /// The item title and publication date together.
+    TitleDate,
+    /// The first capture group of a regular expression matched against the item link, or the
+    /// whole match if it has no groups. Items whose link doesn't match use the whole link.
+    LinkPattern(Regex),
+}
+impl Identity {
+    pub fn is_default(&self) -> bool {
+        matches!(self, Self::Guid)
+    }
+
+    /// Replace the GUID of each item with the one derived by the strategy.
+    pub fn apply(&self, items: &mut [feed::Item]) {
+        if self.is_default() {
+            return;
+        }
+        for item in items {
+            item.guid = self.guid(item);
+        }
+    }
+
+    fn guid(&self, item: &feed::Item) -> String {
+        match self {
+            Self::Guid => item.guid.clone(),
+            Self::Link if item.link.is_empty() => item.guid.clone(),
+            Self::Link => item.link.clone(),
+            Self::TitleDate => format!("{} {}", item.pub_date.to_rfc3339(), item.title),
+            Self::LinkPattern(pattern) => pattern
+                .captures(&item.link)
+                .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+                .map_or_else(|| item.link.clone(), |key| key.as_str().to_string()),
+        }
+    }
+}
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Guid => f.write_str("guid"),
+            Self::Link => f.write_str("link"),
+            Self::TitleDate => f.write_str("title+date"),
+            Self::LinkPattern(pattern) => write!(f, "link:{}", pattern.as_str()),
+        }
+    }
+}
+impl FromStr for Identity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "guid" => Ok(Self::Guid),
+            "link" => Ok(Self::Link),
+            "title+date" => Ok(Self::TitleDate),
+            _ => match s.strip_prefix("link:") {
+                Some(pattern) => Regex::new(pattern)
+                    .map(Self::LinkPattern)
+                    .map_err(|e| format!("invalid identity pattern {pattern:?}: {e}")),
+                None => Err(format!(
+                    "invalid identity {s:?}, expected \"guid\", \"link\", \"title+date\", or \"link:PATTERN\""
+                )),
+            },
+        }
+    }
+}
+impl Serialize for Identity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for Identity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn item(guid: &str, link: &str) -> feed::Item {
+        feed::Item {
+            guid: guid.to_string(),
+            title: "Title".to_string(),
+            link: link.to_string(),
+            comments_link: None,
+            pub_date: Utc.ymd(2019, 11, 7).and_hms(12, 30, 0),
+            author: None,
+            categories: Vec::new(),
+            content_hash: None,
+            content: None,
+        }
+    }
+
+    fn guid(identity: &str, item: &feed::Item) -> String {
+        identity.parse::<Identity>().unwrap().guid(item)
+    }
+
+    #[test]
+    fn guid_and_link_strategies() {
+        let linked = item("urn:1", "https://example.org/post?utm_source=feed");
+        assert_eq!(guid("guid", &linked), "urn:1");
+        assert_eq!(
+            guid("link", &linked),
+            "https://example.org/post?utm_source=feed"
+        );
+        assert_eq!(guid("link", &item("urn:1", "")), "urn:1");
+    }
+
+    #[test]
+    fn title_date_strategy() {
+        assert_eq!(
+            guid("title+date", &item("urn:1", "")),
+            "2019-11-07T12:30:00+00:00 Title"
+        );
+    }
+
+    #[test]
+    fn link_pattern_strategy() {
+        let linked = item("urn:1", "https://example.org/p/42/some-slug?ref=rss");
+        assert_eq!(guid(r"link:/p/(\d+)/", &linked), "42");
+        assert_eq!(guid(r"link:/p/\d+", &linked), "/p/42");
+        assert_eq!(
+            guid(r"link:/posts/(\d+)", &linked),
+            "https://example.org/p/42/some-slug?ref=rss"
+        );
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for identity in ["guid", "link", "title+date", r"link:/p/(\d+)"] {
+            assert_eq!(identity.parse::<Identity>().unwrap().to_string(), identity);
+        }
+        assert!("title".parse::<Identity>().is_err());
+        assert!("link:(".parse::<Identity>().is_err());
+    }
+}
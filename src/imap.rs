@@ -0,0 +1,149 @@
+//! Minimal IMAP client (RFC 3501), for reading the messages of a mailbox without changing it.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use native_tls::TlsConnector;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+/// Untagged data returned by a command.
+#[derive(Default)]
+struct Response {
+    lines: Vec<String>,
+    literals: Vec<Vec<u8>>,
+}
+
+pub struct Session {
+    stream: BufReader<Box<dyn Stream>>,
+    next_tag: u32,
+}
+impl Session {
+    /// Connect to a server, with TLS unless it's disabled, and log in.
+    pub fn connect(
+        host: &str,
+        port: u16,
+        tls: bool,
+        username: &str,
+        password: &str,
+    ) -> io::Result<Self> {
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let stream: Box<dyn Stream> = if tls {
+            let connector = TlsConnector::new().map_err(io::Error::other)?;
+            Box::new(connector.connect(host, stream).map_err(io::Error::other)?)
+        } else {
+            Box::new(stream)
+        };
+        let mut session = Self {
+            stream: BufReader::new(stream),
+            next_tag: 1,
+        };
+        let greeting = session.read_line()?;
+        if !greeting.starts_with("* OK") {
+            return Err(io::Error::other(format!(
+                "unexpected greeting {greeting:?}"
+            )));
+        }
+        session.command(&format!("LOGIN {} {}", quote(username), quote(password)))?;
+        Ok(session)
+    }
+
+    /// Open a mailbox read-only, returning its UIDVALIDITY, which changes when its UIDs are
+    /// reassigned.
+    pub fn examine(&mut self, mailbox: &str) -> io::Result<u32> {
+        let response = self.command(&format!("EXAMINE {}", quote(mailbox)))?;
+        response
+            .lines
+            .iter()
+            .find_map(|line| {
+                let (_, rest) = line.split_once("[UIDVALIDITY ")?;
+                rest.split(']').next()?.parse().ok()
+            })
+            .ok_or_else(|| io::Error::other("mailbox has no UIDVALIDITY"))
+    }
+
+    /// UIDs of the messages with UIDs greater than `after`, in ascending order.
+    pub fn uids_after(&mut self, after: u32) -> io::Result<Vec<u32>> {
+        let response = self.command(&format!("UID SEARCH UID {}:*", after + 1))?;
+        // The range includes the last message even if its UID is lower.
+        let mut uids: Vec<u32> = response
+            .lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("* SEARCH"))
+            .flat_map(str::split_whitespace)
+            .filter_map(|uid| uid.parse().ok())
+            .filter(|uid| *uid > after)
+            .collect();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// Fetch a whole message, without marking it seen.
+    pub fn fetch(&mut self, uid: u32) -> io::Result<Vec<u8>> {
+        self.command(&format!("UID FETCH {uid} BODY.PEEK[]"))?
+            .literals
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::other(format!("no message with UID {uid}")))
+    }
+
+    pub fn logout(mut self) -> io::Result<()> {
+        self.command("LOGOUT").map(|_| ())
+    }
+
+    /// Send a command and read its untagged data, failing unless it completes with OK.
+    fn command(&mut self, command: &str) -> io::Result<Response> {
+        let tag = format!("a{}", self.next_tag);
+        self.next_tag += 1;
+        let stream = self.stream.get_mut();
+        write!(stream, "{tag} {command}\r\n")?;
+        stream.flush()?;
+        let mut response = Response::default();
+        loop {
+            let mut line = self.read_line()?;
+            if let Some(status) = line.strip_prefix(&tag).map(str::trim_start) {
+                if status.starts_with("OK") {
+                    return Ok(response);
+                }
+                // The name only, since the arguments may have the password.
+                let name = command.split(' ').next().unwrap_or(command);
+                return Err(io::Error::other(format!("{name} failed: {status}")));
+            }
+            // A line ending with {N} continues after N bytes of literal data.
+            while let Some(size) = literal_size(&line) {
+                let mut literal = vec![0; size];
+                self.stream.read_exact(&mut literal)?;
+                response.literals.push(literal);
+                line = self.read_line()?;
+            }
+            response.lines.push(line);
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        if self.stream.read_until(b'\n', &mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(String::from_utf8_lossy(&line)
+            .trim_end_matches(['\r', '\n'])
+            .to_string())
+    }
+}
+
+/// Quote a string, escaping quotes and backslashes.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Size of the literal at the end of a line, such as `{42}`.
+fn literal_size(line: &str) -> Option<usize> {
+    let (_, size) = line.strip_suffix('}')?.rsplit_once('{')?;
+    size.parse().ok()
+}
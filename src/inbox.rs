@@ -0,0 +1,97 @@
+//! Reading the IMAP mailbox that mail to the from address arrives in, for bounces of digests and
+//! replies to them.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{bounces, database, imap, replies};
+
+/// IMAP mailbox that bounces of and replies to digests arrive in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Host name of the IMAP server.
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    /// Connect with TLS. Only disable this for a server on the same machine.
+    #[serde(default = "default_true")]
+    pub tls: bool,
+    /// Record and log bounces of digests.
+    #[serde(default = "default_true")]
+    pub bounces: bool,
+    /// Warn when this many of the latest digests to a recipient in a row bounced.
+    #[serde(default = "default_warn_after_bounces")]
+    pub warn_after_bounces: u32,
+    /// Follow commands in replies to digests, such as "save 3".
+    #[serde(default)]
+    pub replies: bool,
+}
+
+fn default_port() -> u16 {
+    993
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_warn_after_bounces() -> u32 {
+    2
+}
+
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum Error {
+    #[display(fmt = "failed to read mailbox: {_0}")]
+    Imap(io::Error),
+    #[display(fmt = "{_0}")]
+    Database(database::Error),
+}
+
+/// Read the messages that arrived in the mailbox since it was last polled, without marking them
+/// read, and handle the bounces and replies among them.
+pub fn poll(
+    config: &crate::Config,
+    inbox: &Config,
+    database: &mut database::Database,
+) -> Result<(), Error> {
+    let mut session = imap::Session::connect(
+        &inbox.host,
+        inbox.port,
+        inbox.tls,
+        &inbox.username,
+        &inbox.password,
+    )?;
+    let uid_validity = session.examine(&inbox.mailbox)?;
+    let last_uid = match database.get_last_uid(&inbox.mailbox)? {
+        Some((validity, last_uid)) if validity == uid_validity => last_uid,
+        _ => 0,
+    };
+    for uid in session.uids_after(last_uid)? {
+        let message = session.fetch(uid)?;
+        let message = String::from_utf8_lossy(&message);
+        if inbox.bounces {
+            bounces::record(database, &bounces::Bounce::parse(&message))?;
+        }
+        if inbox.replies {
+            if let Some(reply) = replies::Reply::parse(&message) {
+                replies::apply(config, database, &reply)?;
+            }
+        }
+        database.set_last_uid(&inbox.mailbox, uid_validity, uid)?;
+    }
+    session.logout()?;
+    if inbox.bounces {
+        bounces::warn(database, inbox.warn_after_bounces)?;
+    }
+    Ok(())
+}
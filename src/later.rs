@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use attohttpc::header::CONTENT_TYPE;
+use derive_more::{Display, From};
+use serde::{Deserialize, Serialize};
+
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// Read-it-later service to save item links to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "service", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Config {
+    /// Pocket, with the consumer key of an app and an access token from authorizing it.
+    Pocket {
+        consumer_key: String,
+        access_token: String,
+    },
+    /// Wallabag instance, with an API client created in its settings and the user's login.
+    Wallabag {
+        url: String,
+        client_id: String,
+        client_secret: String,
+        username: String,
+        password: String,
+    },
+    /// Instapaper, with the user's login. Accounts without a password don't need one.
+    Instapaper {
+        username: String,
+        #[serde(default)]
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, From, Display)]
+pub enum Error {
+    Http(attohttpc::Error),
+    #[display(fmt = "invalid response: {_0}")]
+    Json(serde_json::Error),
+    #[display(fmt = "unexpected status code: {_0}")]
+    UnexpectedStatusCode(u16),
+}
+
+/// Wallabag's response to a token request.
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+}
+
+/// Save a link to the read-it-later service.
+pub fn save(config: &Config, link: &str, title: &str) -> Result<(), Error> {
+    let resp = match config {
+        Config::Pocket {
+            consumer_key,
+            access_token,
+        } => {
+            let body = serde_json::json!({
+                "url": link,
+                "title": title,
+                "consumer_key": consumer_key,
+                "access_token": access_token,
+            });
+            post("https://getpocket.com/v3/add")
+                .header(CONTENT_TYPE, "application/json; charset=UTF-8")
+                .text(body.to_string())
+                .send()?
+        }
+        Config::Wallabag {
+            url,
+            client_id,
+            client_secret,
+            username,
+            password,
+        } => {
+            let url = url.trim_end_matches('/');
+            let resp = post(&format!("{url}/oauth/v2/token"))
+                .header(CONTENT_TYPE, FORM_CONTENT_TYPE)
+                .text(form(&[
+                    ("grant_type", "password"),
+                    ("client_id", client_id),
+                    ("client_secret", client_secret),
+                    ("username", username),
+                    ("password", password),
+                ]))
+                .send()?;
+            let token: Token = serde_json::from_slice(&check_status(resp)?.bytes()?)?;
+            post(&format!("{url}/api/entries.json"))
+                .bearer_auth(token.access_token)
+                .header(CONTENT_TYPE, FORM_CONTENT_TYPE)
+                .text(form(&[("url", link), ("title", title)]))
+                .send()?
+        }
+        Config::Instapaper { username, password } => post("https://www.instapaper.com/api/add")
+            .basic_auth(username, password.as_ref())
+            .header(CONTENT_TYPE, FORM_CONTENT_TYPE)
+            .text(form(&[("url", link), ("title", title)]))
+            .send()?,
+    };
+    check_status(resp)?;
+    Ok(())
+}
+
+fn post(url: &str) -> attohttpc::RequestBuilder {
+    attohttpc::post(url)
+        .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+        .timeout(Duration::from_secs(30))
+}
+
+fn form(pairs: &[(&str, &str)]) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .finish()
+}
+
+fn check_status(resp: attohttpc::Response) -> Result<attohttpc::Response, Error> {
+    if resp.status().is_success() {
+        Ok(resp)
+    } else {
+        Err(Error::UnexpectedStatusCode(resp.status().as_u16()))
+    }
+}
@@ -0,0 +1,114 @@
+//! Repair of slightly malformed XML, such as feeds with unescaped ampersands, so that it can be
+//! parsed after parsing it as it is failed.
+
+// Entities that XML defines without a doctype.
+const PREDEFINED_ENTITIES: [&str; 5] = ["amp", "lt", "gt", "quot", "apos"];
+
+/// Escape ampersands that don't start a character or predefined entity reference, and remove
+/// control characters that XML doesn't allow, outside of CDATA sections and comments. Returns
+/// `None` if there was nothing to repair.
+pub fn repair(xml: &str) -> Option<String> {
+    let mut repaired = String::with_capacity(xml.len());
+    let mut changed = false;
+    let mut rest = xml;
+    while let Some(c) = rest.chars().next() {
+        // CDATA sections and comments are copied as they are, since ampersands are text in them.
+        let section_end = if rest.starts_with("<![CDATA[") {
+            Some("]]>")
+        } else if rest.starts_with("<!--") {
+            Some("-->")
+        } else {
+            None
+        };
+        if let Some(section_end) = section_end {
+            let end = rest
+                .find(section_end)
+                .map_or(rest.len(), |i| i + section_end.len());
+            push_allowed(&mut repaired, &rest[..end], &mut changed);
+            rest = &rest[end..];
+            continue;
+        }
+        if c == '&' && !starts_with_reference(&rest[1..]) {
+            repaired.push_str("&amp;");
+            changed = true;
+        } else if is_allowed(c) {
+            repaired.push(c);
+        } else {
+            changed = true;
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    changed.then_some(repaired)
+}
+
+/// Whether a character is allowed in XML 1.0 documents.
+fn is_allowed(c: char) -> bool {
+    !c.is_control() || matches!(c, '\t' | '\n' | '\r') || u32::from(c) >= 0x7F
+}
+
+fn push_allowed(repaired: &mut String, text: &str, changed: &mut bool) {
+    for c in text.chars() {
+        if is_allowed(c) {
+            repaired.push(c);
+        } else {
+            *changed = true;
+        }
+    }
+}
+
+/// Whether text after an ampersand is the rest of a reference that XML parsers understand.
+fn starts_with_reference(text: &str) -> bool {
+    let Some(end) = text.find(';') else {
+        return false;
+    };
+    let name = &text[..end];
+    if let Some(hex) = name.strip_prefix("#x") {
+        !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit())
+    } else if let Some(decimal) = name.strip_prefix('#') {
+        !decimal.is_empty() && decimal.chars().all(|c| c.is_ascii_digit())
+    } else {
+        PREDEFINED_ENTITIES.contains(&name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repair_escapes_bare_ampersands() {
+        assert_eq!(
+            repair("<title>Q&A &amp; more</title><link>/?a=1&b=2</link>").as_deref(),
+            Some("<title>Q&amp;A &amp; more</title><link>/?a=1&amp;b=2</link>")
+        );
+    }
+
+    #[test]
+    fn repair_escapes_undefined_entities() {
+        assert_eq!(
+            repair("<title>a&nbsp;b &#160; &#xA0;</title>").as_deref(),
+            Some("<title>a&amp;nbsp;b &#160; &#xA0;</title>")
+        );
+    }
+
+    #[test]
+    fn repair_removes_control_characters() {
+        assert_eq!(
+            repair("<title>a\u{0}b\u{b}\tc\n</title>").as_deref(),
+            Some("<title>ab\tc\n</title>")
+        );
+    }
+
+    #[test]
+    fn repair_leaves_cdata_and_comments() {
+        assert_eq!(
+            repair("<![CDATA[a & b]]><!-- c & d --><t>e & f</t>").as_deref(),
+            Some("<![CDATA[a & b]]><!-- c & d --><t>e &amp; f</t>")
+        );
+    }
+
+    #[test]
+    fn repair_returns_none_for_well_formed_xml() {
+        assert_eq!(repair("<t a=\"&quot;\">&lt;b&gt; &#38;</t>"), None);
+    }
+}
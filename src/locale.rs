@@ -0,0 +1,223 @@
+use chrono::{DateTime, Datelike, TimeZone};
+use serde::{Deserialize, Serialize};
+
+/// Language of the fixed strings in digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+    Fr,
+}
+impl Locale {
+    pub fn strings(self) -> &'static Strings {
+        match self {
+            Self::En => &EN,
+            Self::De => &DE,
+            Self::Es => &ES,
+            Self::Fr => &FR,
+        }
+    }
+}
+
+/// Fixed strings of digests, which templates get as `strings`.
+///
+/// Placeholders in braces, like `{date}`, are replaced with values.
+#[derive(Debug, Serialize)]
+pub struct Strings {
+    pub subject: &'static str,
+    pub tag_subject: &'static str,
+    pub comments: &'static str,
+    pub dead_link: &'static str,
+    pub read_more: &'static str,
+    pub feed_renamed: &'static str,
+    pub feed_link_changed: &'static str,
+    pub mute_feed: &'static str,
+    pub unsubscribe: &'static str,
+    pub more: &'static str,
+    pub bulk: &'static str,
+    pub sampled: &'static str,
+    pub view_list: &'static str,
+    pub footer_version: &'static str,
+    pub footer_fetched: &'static str,
+    pub footer_failed: &'static str,
+    pub footer_next: &'static str,
+    pub from_the_archives: &'static str,
+    pub updated_articles: &'static str,
+    /// Format of dates, where `%a` and `%b` are replaced with the names below.
+    #[serde(skip)]
+    date_format: &'static str,
+    /// Abbreviated names of days of the week, from Monday.
+    #[serde(skip)]
+    weekdays: [&'static str; 7],
+    /// Abbreviated names of months, from January.
+    #[serde(skip)]
+    months: [&'static str; 12],
+}
+impl Strings {
+    /// Format a date with localized names of the day and month.
+    pub fn format_date<Tz: TimeZone>(&self, date: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let weekday = self.weekdays[date.weekday().num_days_from_monday() as usize];
+        let month = self.months[date.month0() as usize];
+        let format = self.date_format.replace("%a", weekday).replace("%b", month);
+        date.format(&format).to_string()
+    }
+
+    /// Subject of a digest sent at the given date, which may be scoped to a tag.
+    pub fn digest_subject<Tz: TimeZone>(&self, tag: Option<&str>, date: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let date = self.format_date(date);
+        match tag {
+            Some(tag) => self
+                .tag_subject
+                .replace("{tag}", tag)
+                .replace("{date}", &date),
+            None => self.subject.replace("{date}", &date),
+        }
+    }
+}
+
+const EN: Strings = Strings {
+    subject: "SqueakMail for {date}",
+    tag_subject: "SqueakMail {tag} for {date}",
+    comments: "comments",
+    dead_link: "(dead link)",
+    read_more: "Read more",
+    feed_renamed: "Feed renamed from “{old}” to “{new}”",
+    feed_link_changed: "Feed link changed from {old} to {new}",
+    mute_feed: "Mute this feed",
+    unsubscribe: "Unsubscribe",
+    more: "+{count} more",
+    bulk: "Republished {count} items",
+    sampled: "Skipped {count} more items",
+    view_list: "view list",
+    footer_version: "Sent by SqueakMail {version}",
+    footer_fetched: "Feeds last fetched {date}, in {seconds} s",
+    footer_failed: "Failed to fetch:",
+    footer_next: "Next digest expected {date}",
+    from_the_archives: "From the archives",
+    updated_articles: "Updated articles",
+    date_format: "%a %b %e %T %Y",
+    weekdays: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    months: [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ],
+};
+
+const DE: Strings = Strings {
+    subject: "SqueakMail vom {date}",
+    tag_subject: "SqueakMail {tag} vom {date}",
+    comments: "Kommentare",
+    dead_link: "(toter Link)",
+    read_more: "Weiterlesen",
+    feed_renamed: "Feed umbenannt von „{old}“ in „{new}“",
+    feed_link_changed: "Link des Feeds geändert von {old} in {new}",
+    mute_feed: "Diesen Feed stummschalten",
+    unsubscribe: "Abbestellen",
+    more: "+{count} weitere",
+    bulk: "{count} Beiträge erneut veröffentlicht",
+    sampled: "{count} weitere Beiträge übersprungen",
+    view_list: "Liste ansehen",
+    footer_version: "Gesendet von SqueakMail {version}",
+    footer_fetched: "Feeds zuletzt abgerufen am {date}, in {seconds} s",
+    footer_failed: "Abruf fehlgeschlagen:",
+    footer_next: "Nächster Digest voraussichtlich am {date}",
+    from_the_archives: "Aus dem Archiv",
+    updated_articles: "Aktualisierte Artikel",
+    date_format: "%a, %-d. %b %Y, %T",
+    weekdays: ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+    months: [
+        "Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+    ],
+};
+
+const ES: Strings = Strings {
+    subject: "SqueakMail del {date}",
+    tag_subject: "SqueakMail {tag} del {date}",
+    comments: "comentarios",
+    dead_link: "(enlace roto)",
+    read_more: "Seguir leyendo",
+    feed_renamed: "Feed renombrado de «{old}» a «{new}»",
+    feed_link_changed: "Enlace del feed cambiado de {old} a {new}",
+    mute_feed: "Silenciar este feed",
+    unsubscribe: "Cancelar la suscripción",
+    more: "+{count} más",
+    bulk: "{count} entradas publicadas de nuevo",
+    sampled: "{count} entradas más omitidas",
+    view_list: "ver la lista",
+    footer_version: "Enviado por SqueakMail {version}",
+    footer_fetched: "Feeds obtenidos por última vez el {date}, en {seconds} s",
+    footer_failed: "No se pudieron obtener:",
+    footer_next: "Próximo resumen previsto el {date}",
+    from_the_archives: "Del archivo",
+    updated_articles: "Artículos actualizados",
+    date_format: "%a, %-d de %b de %Y, %T",
+    weekdays: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+    months: [
+        "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sept", "oct", "nov", "dic",
+    ],
+};
+
+const FR: Strings = Strings {
+    subject: "SqueakMail du {date}",
+    tag_subject: "SqueakMail {tag} du {date}",
+    comments: "commentaires",
+    dead_link: "(lien mort)",
+    read_more: "Lire la suite",
+    feed_renamed: "Flux renommé de « {old} » en « {new} »",
+    feed_link_changed: "Lien du flux modifié de {old} en {new}",
+    mute_feed: "Ne plus recevoir ce flux",
+    unsubscribe: "Se désabonner",
+    more: "+{count} de plus",
+    bulk: "{count} articles republiés",
+    sampled: "{count} autres articles ignorés",
+    view_list: "voir la liste",
+    footer_version: "Envoyé par SqueakMail {version}",
+    footer_fetched: "Flux récupérés pour la dernière fois le {date}, en {seconds} s",
+    footer_failed: "Échec de la récupération :",
+    footer_next: "Prochain résumé prévu le {date}",
+    from_the_archives: "Dans les archives",
+    updated_articles: "Articles mis à jour",
+    date_format: "%a %-d %b %Y, %T",
+    weekdays: ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+    months: [
+        "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.", "nov.",
+        "déc.",
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn format_date_matches_chrono_in_english() {
+        let date = Utc.ymd(2019, 11, 2).and_hms(7, 5, 0);
+        assert_eq!(
+            Locale::En.strings().format_date(&date),
+            date.format("%c").to_string()
+        );
+    }
+
+    #[test]
+    fn digest_subject_is_localized() {
+        let date = Utc.ymd(2019, 11, 2).and_hms(7, 5, 0);
+        assert_eq!(
+            Locale::De.strings().digest_subject(Some("rust"), &date),
+            "SqueakMail rust vom Sa, 2. Nov 2019, 07:05:00"
+        );
+        assert_eq!(
+            Locale::Fr.strings().digest_subject(None, &date),
+            "SqueakMail du sam. 2 nov. 2019, 07:05:00"
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+
+use derive_more::Display;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{database, feed};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Lua script defining `transform`, `filter`, or `render` functions.
+    pub script: PathBuf,
+}
+
+#[derive(Debug, Display)]
+#[display(fmt = "{_0}")]
+pub struct Error(String);
+
+/// Lua script with functions that are called as items are fetched and rendered.
+///
+/// Lua states can't be shared between threads, so each thread loads its own.
+pub struct Script {
+    #[cfg(feature = "lua")]
+    lua: mlua::Lua,
+    #[cfg(not(feature = "lua"))]
+    never: std::convert::Infallible,
+}
+impl Script {
+    /// Load the configured script, if there is one.
+    pub fn load(config: Option<&Config>) -> Result<Option<Self>, Error> {
+        config.map(Self::load_script).transpose()
+    }
+
+    #[cfg(feature = "lua")]
+    fn load_script(config: &Config) -> Result<Self, Error> {
+        let source = std::fs::read_to_string(&config.script)
+            .map_err(|e| Error(format!("failed to read {}: {e}", config.script.display())))?;
+        let lua = mlua::Lua::new();
+        lua.load(&source)
+            .set_name(config.script.to_string_lossy())
+            .exec()
+            .map_err(|e| Error(e.to_string()))?;
+        Ok(Self { lua })
+    }
+
+    #[cfg(not(feature = "lua"))]
+    fn load_script(_config: &Config) -> Result<Self, Error> {
+        Err(Error(
+            "SqueakMail was built without Lua support, rebuild it with `--features lua`"
+                .to_string(),
+        ))
+    }
+
+    /// Call `transform(item, feed_url)`, which returns a replacement item, or `nil` to drop it.
+    pub fn transform(&self, feed_url: &str, item: feed::Item) -> Result<Option<feed::Item>, Error> {
+        if !self.has_function("transform") {
+            return Ok(Some(item));
+        }
+        self.call("transform", (&item, feed_url))
+    }
+
+    /// Call `filter(item, feed_url)`, which returns `false` to drop the item.
+    pub fn filter(&self, feed_url: &str, item: &feed::Item) -> Result<bool, Error> {
+        if !self.has_function("filter") {
+            return Ok(true);
+        }
+        Ok(self.call("filter", (item, feed_url))?.unwrap_or(true))
+    }
+
+    /// Call `render(item)` before an item is rendered in a digest, which returns a replacement
+    /// item, or `nil` to leave it out.
+    pub fn render(&self, item: database::Item) -> Result<Option<database::Item>, Error> {
+        if !self.has_function("render") {
+            return Ok(Some(item));
+        }
+        self.call("render", (&item,))
+    }
+
+    #[cfg(feature = "lua")]
+    fn has_function(&self, name: &str) -> bool {
+        matches!(
+            self.lua.globals().get::<_, mlua::Value>(name),
+            Ok(mlua::Value::Function(_))
+        )
+    }
+
+    #[cfg(not(feature = "lua"))]
+    fn has_function(&self, _name: &str) -> bool {
+        match self.never {}
+    }
+
+    /// Call a global function with arguments converted to Lua values, and convert its result
+    /// back, where `nil` is `None`.
+    #[cfg(feature = "lua")]
+    fn call<A: Serialize, R: DeserializeOwned>(
+        &self,
+        name: &str,
+        args: A,
+    ) -> Result<Option<R>, Error> {
+        use mlua::LuaSerdeExt;
+
+        let error = |e: mlua::Error| Error(format!("{name}: {e}"));
+        // Missing values are nil, so scripts can test for them with `if item.author then`.
+        let options = mlua::SerializeOptions::new()
+            .serialize_none_to_null(false)
+            .serialize_unit_to_null(false);
+        let function: mlua::Function = self.lua.globals().get(name).map_err(error)?;
+        let args = self.lua.to_value_with(&args, options).map_err(error)?;
+        let args = match args {
+            mlua::Value::Table(table) => table
+                .sequence_values::<mlua::Value>()
+                .collect::<mlua::Result<mlua::MultiValue>>()
+                .map_err(error)?,
+            _ => unreachable!("arguments are a tuple"),
+        };
+        match function.call(args).map_err(error)? {
+            mlua::Value::Nil => Ok(None),
+            value => self.lua.from_value(value).map(Some).map_err(error),
+        }
+    }
+
+    #[cfg(not(feature = "lua"))]
+    fn call<A: Serialize, R: DeserializeOwned>(
+        &self,
+        _name: &str,
+        _args: A,
+    ) -> Result<Option<R>, Error> {
+        match self.never {}
+    }
+}
@@ -1,106 +1,194 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::redundant_closure_for_method_calls)]
 
+use std::borrow::Cow;
 use std::cmp::min;
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 use std::io::{Read, Write};
-use std::num::NonZeroU16;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use attohttpc;
 use clap::{crate_version, App, AppSettings, Arg, SubCommand};
-use derive_more::{Display, From};
-use lettre::sendmail::SendmailTransport;
-use lettre::{EmailAddress, SendableEmail, Transport};
+use lettre::SendableEmail;
 use lettre_email::Email;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
 use tera::Tera;
 
+mod archive;
+mod article;
+mod atomic;
+mod audit;
+mod bidi;
+mod bookmarks;
+mod bounces;
+mod callback;
+mod challenge;
+mod clock;
+mod config;
+mod css;
+mod daemon;
 mod database;
+mod diff;
+mod dkim;
+mod dns;
+mod duplicates;
+mod error;
+mod events;
+mod excerpt;
 mod feed;
+mod footer;
+mod hooks;
+mod identity;
+mod imap;
+mod inbox;
+mod later;
+mod lenient;
+mod locale;
+mod lua;
+mod message;
+mod metrics;
+mod mojibake;
+mod mx;
+mod oauth;
+mod openssl;
+mod opml;
+mod outbox;
+mod parts;
+mod pipeline;
+mod preview;
+mod qr;
+mod query;
+mod raw;
+mod replies;
+mod resolve;
+mod robots;
+mod rules;
+mod sample;
+mod schedule;
+mod schema;
+mod send;
+mod shutdown;
+mod smime;
+mod state;
+mod subscriptions;
+mod summarize;
+mod templates;
+#[cfg(test)]
+mod tests;
+mod text;
+mod throttle;
+mod transform;
+mod translate;
+mod transport;
+mod updates;
+mod upgrade;
+mod validators;
+mod web;
+mod window;
 
-// Must have ".html" suffix to force tera to do escaping.
-const MAIL_TEMPLATE_NAME: &str = "mail.html";
-
-#[derive(Debug, From, Display)]
-enum Error {
-    #[display(fmt = "failed to parse config: {}", _0)]
-    ParseConfig(toml::de::Error),
-    #[display(fmt = "failed to read config: {}", _0)]
-    ReadConfig(std::io::Error),
-    #[display(fmt = "feed not modified")]
-    FeedNotModified,
-    #[display(fmt = "unexpected status code: {}", _0)]
-    UnexpectedStatusCode(u16),
-    Http(attohttpc::Error),
-    Parse(feed::Error),
-    #[display(fmt = "database error: {}", _0)]
-    Database(database::Error),
-    #[from(ignore)]
-    #[display(fmt = "failed to create config directory: {}", _0)]
-    CreateConfigDir(std::io::Error),
-    #[from(ignore)]
-    #[display(fmt = "failed to create config file: {}", _0)]
-    CreateConfigFile(std::io::Error),
-    #[from(ignore)]
-    #[display(fmt = "failed to create database directory: {}", _0)]
-    CreateDatabaseDir(std::io::Error),
-    #[display(fmt = "sendmail error: {}", _0)]
-    Sendmail(lettre::sendmail::error::Error),
-}
-
-type Result<T = ()> = std::result::Result<T, Error>;
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
-struct Config {
-    feeds: Vec<String>,
-    // TODO: EmailAddress should validate itself when deserializing.
-    from_email: EmailAddress,
-    to_email: EmailAddress,
-    concurrency: NonZeroU16,
-}
-impl Config {
-    fn from_path(path: &Path) -> Result<Self> {
-        let mut config_file = File::open(path)?;
-        let mut config_str = String::new();
-        config_file.read_to_string(&mut config_str)?;
-        Ok(toml::from_str(&config_str)?)
-    }
-}
-impl std::default::Default for Config {
-    fn default() -> Self {
-        Self {
-            feeds: vec!["https://blog.rust-lang.org/feed.xml".to_string()],
-            from_email: EmailAddress::new("squeakmail@example.com".to_string())
-                .expect("invalid default"),
-            to_email: EmailAddress::new("squeakmail@example.com".to_string())
-                .expect("invalid default"),
-            concurrency: NonZeroU16::new(1).expect("invalid default"),
-        }
-    }
+use config::{Config, FeedConfig, OnAdd};
+use error::{Error, Phase, Result};
+
+// Redirects followed when fetching a feed, as many as attohttpc follows by default.
+const MAX_REDIRECTS: u32 = 5;
+// Timeout of each request when fetching a feed.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+// Feeds larger than this aren't read, so a huge or endless response can't use up memory or disk.
+const MAX_FEED_BYTES: u64 = 64 * 1024 * 1024;
+// Article pages downloaded more recently than this aren't downloaded again.
+const ARTICLE_MAX_AGE: Duration = Duration::from_hours(24);
+// Article pages are evicted from the cache, least recently downloaded first, beyond this size.
+const MAX_ARTICLE_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+// Gmail clips messages larger than this, hiding the rest behind a link.
+const GMAIL_CLIP_BYTES: usize = 102 * 1024;
+// Default message size limit of Postfix, which many mail servers keep.
+const MESSAGE_SIZE_LIMIT_BYTES: usize = 10_240_000;
+// Suffixes of the database file and its journals, which belong to it and move with it.
+const DATABASE_SUFFIXES: [&str; 4] = ["", "-wal", "-shm", "-journal"];
+
+/// Value of a response header, if present and valid.
+fn header_string(
+    headers: &attohttpc::header::HeaderMap,
+    name: attohttpc::header::HeaderName,
+) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|header_value| header_value.to_str().ok())
+        .map(|header_str| header_str.to_string())
 }
 
 #[derive(Debug, Serialize)]
 struct FeedWithItems {
     feed: database::Feed,
     items: Vec<database::Item>,
+    /// Changes of the feed's title or link, which are only in the regular digest.
+    changes: Vec<database::FeedChange>,
+    mute_link: Option<String>,
+    unsubscribe_link: Option<String>,
+    /// Number of items that came in bulk, which are collapsed into one line.
+    bulk_items: usize,
+    /// Link to show for items that have no link, if any.
+    fallback_link: Option<String>,
+    /// Number the feed is listed with in the digest, for replies to refer to, if enabled.
+    number: Option<usize>,
+    /// How much of the feed's items to show.
+    tier: config::Tier,
+    /// Items left out by sampling, which are marked read with the items shown.
+    #[serde(skip)]
+    skipped: Vec<database::Item>,
+    skipped_items: usize,
+}
+
+/// Rendered digest and the items it includes.
+struct Digest {
+    mail: SendableEmail,
+    items: Vec<database::DigestItem>,
+    /// Outbox entry the digest is delivered from, if any.
+    outbox_id: Option<i64>,
+    /// Old items resurfaced from the archives.
+    resurfaced: Vec<database::DigestItem>,
+    /// Read items listed because their content changed.
+    updated: Vec<database::DigestItem>,
+}
+
+/// Message-IDs threading an email under its parent.
+struct Thread {
+    message_id: String,
+    parent: String,
 }
 
 #[derive(Debug, Serialize)]
-struct MailContext {
+struct MailContext<'a> {
     subject: String,
     feeds: Vec<FeedWithItems>,
+    accessibility: config::Accessibility,
+    strings: &'static locale::Strings,
+    footer: Option<&'a footer::Footer>,
+    archives: &'a [archive::Entry],
+    updated: &'a [updates::Entry],
+}
+
+/// Sections of a recipient's digest besides its feeds.
+#[derive(Debug, Default)]
+struct Sections {
+    footer: Option<footer::Footer>,
+    /// Old items resurfaced from the archives.
+    archives: Vec<archive::Entry>,
+    /// Read items whose content changed since they were mailed.
+    updated: Vec<updates::Entry>,
 }
 
 /// Create parent directory of path, if it doesn't exist.
 fn create_parent_dir(path: &Path) -> std::io::Result<()> {
     let parent = path.parent().unwrap_or_else(|| Path::new(""));
     if !parent.is_dir() && parent != Path::new("") {
-        std::fs::create_dir(parent)
+        std::fs::create_dir_all(parent)
     } else {
         Ok(())
     }
@@ -121,230 +209,3632 @@ fn create_example_config_file(path: &Path) -> std::io::Result<()> {
 
 fn main() {
     std::process::exit(match run() {
-        Ok(_) => 0,
+        Ok(()) => 0,
         Err(e) => {
-            eprintln!("error: {}", e);
-            1
+            eprintln!("error: {e}");
+            e.exit_code()
         }
     });
 }
 
 struct Args {
     config: PathBuf,
+    /// Ignore unknown config keys, with a warning.
+    lenient_config: bool,
+    /// Directory of custom templates, instead of the configured ones.
+    template_dir: Option<PathBuf>,
+    /// Custom mail template, instead of the configured one.
+    template_path: Option<PathBuf>,
     database: PathBuf,
+    cache: PathBuf,
+    /// Where older versions kept the database, if the default database path is used.
+    legacy_database: Option<PathBuf>,
+    /// Open the database read-only, such as while a daemon writes to it.
+    read_only: bool,
+    /// Clock to render mail with, which is fixed for deterministic output.
+    clock: clock::Clock,
     command: Command,
 }
 
 enum Command {
-    Fetch,
-    Mail { dry: bool },
+    Fetch {
+        /// How long fetching may take before no more feeds are started.
+        max_duration: Option<Duration>,
+        /// File to write metrics of the run to, for the node exporter.
+        metrics_textfile: Option<PathBuf>,
+    },
+    Mail {
+        dry: bool,
+        diff: bool,
+        tag: Option<String>,
+        pipeline: Option<String>,
+        metrics_textfile: Option<PathBuf>,
+    },
+    Render {
+        format: Format,
+        max_chars: Option<usize>,
+        tag: Option<String>,
+    },
+    Daemon,
+    Stats,
+    Audit,
+    Diff,
+    Check,
+    CheckLinks {
+        days: u32,
+    },
+    SnoozeItem {
+        guid: String,
+        feed_url: Option<String>,
+        duration: chrono::Duration,
+    },
+    ReadLater {
+        guid: String,
+        feed_url: Option<String>,
+    },
+    ExportBookmarks {
+        format: bookmarks::Format,
+    },
+    PreviewServer {
+        port: u16,
+        tag: Option<String>,
+    },
+    MigrateState,
+    ExportState,
+    TestRules {
+        feed_url: String,
+    },
+    Rebuild {
+        import: Option<PathBuf>,
+    },
+    UpgradeConfig {
+        dry: bool,
+    },
+    AddFeed {
+        url: String,
+        /// Fetch the feed before adding it, and don't add it if that fails.
+        fetch: bool,
+    },
+    RemoveFeed {
+        url: String,
+    },
+    ImportOpml {
+        path: PathBuf,
+    },
+    ExportOpml,
+}
+
+impl Command {
+    /// Whether the command only reads the database, so it can be run with `--read-only`.
+    fn only_reads_database(&self) -> bool {
+        matches!(
+            self,
+            Self::Render { .. }
+                | Self::Stats
+                | Self::Audit
+                | Self::Diff
+                | Self::TestRules { .. }
+                | Self::ExportBookmarks { .. }
+                | Self::ExportState
+                | Self::UpgradeConfig { .. }
+                | Self::ExportOpml
+        )
+    }
+}
+
+/// Output format of the render subcommand.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Html,
+    Text,
+    Ics,
+}
+
+/// Default paths of files, in the platform's directories for config, state and cache.
+struct DefaultPaths {
+    config: PathBuf,
+    database: PathBuf,
+    cache: PathBuf,
+    /// Where older versions kept the database.
+    legacy_database: PathBuf,
+}
+impl DefaultPaths {
+    fn new() -> Self {
+        let proj_dirs = directories::ProjectDirs::from("com", "tomdryer", "squeakmail");
+        let dir = |dir: fn(&directories::ProjectDirs) -> &Path| {
+            proj_dirs
+                .as_ref()
+                .map_or_else(PathBuf::new, |proj_dirs| dir(proj_dirs).to_path_buf())
+        };
+        Self {
+            config: dir(directories::ProjectDirs::config_dir).join("squeakmail.toml"),
+            database: dir(directories::ProjectDirs::data_dir).join("squeakmail.db"),
+            cache: dir(directories::ProjectDirs::cache_dir).join("cache.db"),
+            legacy_database: dir(directories::ProjectDirs::cache_dir).join("squeakmail.db"),
+        }
+    }
 }
 
 fn get_args() -> Args {
-    let proj_dirs = directories::ProjectDirs::from("com", "tomdryer", "squeakmail");
-    let default_config_path = proj_dirs
-        .as_ref()
-        .map_or_else(PathBuf::new, |proj_dirs| {
-            proj_dirs.config_dir().to_path_buf()
-        })
-        .join("squeakmail.toml");
-    let default_database_path = proj_dirs
-        .as_ref()
-        .map_or_else(PathBuf::new, |proj_dirs| {
-            proj_dirs.cache_dir().to_path_buf()
-        })
-        .join("squeakmail.db");
+    let default_paths = DefaultPaths::new();
     let matches = App::new("SqueakMail")
         .version(crate_version!())
         .setting(AppSettings::SubcommandRequiredElseHelp)
-        .arg(
-            Arg::with_name("config")
-                .long("config")
-                .default_value_os(default_config_path.as_os_str()),
+        .args(&config_args(&default_paths))
+        .args(&database_args(&default_paths))
+        .args(&clock_args())
+        .subcommand(fetch_subcommand())
+        .subcommand(add_subcommand())
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Removes a feed from the config file")
+                .arg(Arg::with_name("url").required(true)),
         )
-        .arg(
-            Arg::with_name("database")
-                .long("database")
-                .default_value_os(default_database_path.as_os_str()),
+        .subcommand(
+            SubCommand::with_name("import-opml")
+                .about("Adds the feeds of an OPML file to the config file, with folders as tags")
+                .arg(Arg::with_name("file").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("export-opml")
+                .about("Prints the feeds as OPML, for other feed readers, with tags as folders"),
+        )
+        .subcommand(
+            SubCommand::with_name("audit")
+                .about("Reports how each feed handles conditional requests"),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Prints how feeds changed since they were last fetched, without storing"),
         )
-        .subcommand(SubCommand::with_name("fetch").about("Fetches feeds"))
+        .subcommand(SubCommand::with_name("stats").about("Prints download totals for each feed"))
         .subcommand(
-            SubCommand::with_name("mail").about("Mails feeds").arg(
-                Arg::with_name("dry")
-                    .long("dry")
-                    .help("Print email body instead of sending it"),
+            SubCommand::with_name("check")
+                .about("Finds feeds that are the same feed by their self links and redirects"),
+        )
+        .subcommand(
+            SubCommand::with_name("check-links")
+                .about("Checks links of recent unread items, to flag dead ones in digests")
+                .arg(
+                    Arg::with_name("days")
+                        .long("days")
+                        .default_value("7")
+                        .validator(validate_parse::<u32>)
+                        .help("Check items published within this many days"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate-state")
+                .about("Moves the database from where older versions kept it, in the cache"),
+        )
+        .subcommand(config_subcommand())
+        .subcommand(rules_subcommand())
+        .subcommand(
+            SubCommand::with_name("daemon").about(
+                "Serves links for muting feeds from digests, and fetches feeds periodically",
             ),
         )
+        .subcommand(mail_subcommand())
+        .subcommand(render_subcommand())
+        .subcommand(preview_server_subcommand())
+        .subcommand(snooze_item_subcommand())
+        .subcommand(
+            SubCommand::with_name("read-later")
+                .about("Saves an item to the read-it-later service, and marks it read")
+                .args(&item_args()),
+        )
+        .subcommand(export_bookmarks_subcommand())
+        .subcommand(
+            SubCommand::with_name("export-state")
+                .about("Prints which items were read, highlighted, or saved, and muted feeds"),
+        )
+        .subcommand(rebuild_subcommand())
         .get_matches();
     Args {
         config: PathBuf::from(matches.value_of_os("config").expect("impossible none")),
+        lenient_config: matches.is_present("lenient-config"),
+        template_dir: matches.value_of_os("template-dir").map(PathBuf::from),
+        template_path: matches.value_of_os("template").map(PathBuf::from),
         database: PathBuf::from(matches.value_of_os("database").expect("impossible none")),
-        command: match matches.subcommand() {
-            ("fetch", Some(_)) => Command::Fetch,
-            ("mail", Some(sub_matches)) => Command::Mail {
-                dry: sub_matches.is_present("dry"),
+        cache: PathBuf::from(matches.value_of_os("cache").expect("impossible none")),
+        legacy_database: if matches.occurrences_of("database") == 0 {
+            Some(default_paths.legacy_database.clone())
+        } else {
+            None
+        },
+        read_only: matches.is_present("read-only"),
+        clock: get_clock(&matches),
+        command: get_command(&matches),
+    }
+}
+
+fn config_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("config")
+        .about("Manages the config file")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("upgrade")
+                .about("Rewrites the config file for this version")
+                .arg(
+                    Arg::with_name("dry")
+                        .long("dry")
+                        .help("Print the upgraded config instead of writing it"),
+                ),
+        )
+}
+
+fn rules_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("rules")
+        .about("Helps write rules")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Prints which rules match each stored item of a feed, and what they do")
+                .arg(
+                    Arg::with_name("feed")
+                        .long("feed")
+                        .takes_value(true)
+                        .required(true)
+                        .help("URL of the feed"),
+                ),
+        )
+}
+
+fn mail_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("mail")
+        .about("Mails feeds")
+        .arg(
+            Arg::with_name("dry")
+                .long("dry")
+                .help("Print email body instead of sending it"),
+        )
+        .arg(
+            Arg::with_name("diff")
+                .long("diff")
+                .requires("dry")
+                .help("Print which items are new since the last digest, instead of the email"),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .help("Mail only items with this tag"),
+        )
+        .arg(
+            Arg::with_name("pipeline")
+                .long("pipeline")
+                .takes_value(true)
+                .conflicts_with("tag")
+                .help("Mail the items of a pipeline defined in the config, with its options"),
+        )
+        .arg(metrics_textfile_arg())
+}
+
+fn render_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("render")
+        .about("Prints digests without sending them or marking items read")
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .possible_values(&["html", "text", "ics"])
+                .default_value("html")
+                .help("Format of the digests"),
+        )
+        .arg(
+            Arg::with_name("max-chars")
+                .long("max-chars")
+                .takes_value(true)
+                .validator(validate_parse::<usize>)
+                .help("Drop items to fit each text digest in this many characters"),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .help("Render only items with this tag"),
+        )
+}
+
+fn preview_server_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("preview-server")
+        .about("Serves digests without sending them, reloading them when templates change")
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .default_value("8025")
+                .validator(validate_parse::<u16>)
+                .help("Port to serve digests on, on localhost"),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .help("Serve only items with this tag"),
+        )
+}
+
+fn export_bookmarks_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("export-bookmarks")
+        .about("Prints highlighted items as bookmarks to import elsewhere")
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .possible_values(&["netscape-html", "json"])
+                .default_value("netscape-html")
+                .help("Format of the bookmarks"),
+        )
+}
+
+fn rebuild_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("rebuild")
+        .about("Moves the database aside and creates a new one by fetching every feed again")
+        .arg(
+            Arg::with_name("import")
+                .long("import")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Restore the state of items from the output of export-state"),
+        )
+}
+
+fn config_args(default_paths: &DefaultPaths) -> [Arg<'_, '_>; 4] {
+    [
+        Arg::with_name("config")
+            .long("config")
+            .default_value_os(default_paths.config.as_os_str()),
+        Arg::with_name("lenient-config")
+            .long("lenient-config")
+            .help("Warn about unknown config keys instead of failing, such as for newer versions"),
+        Arg::with_name("template-dir")
+            .long("template-dir")
+            .takes_value(true)
+            .help("Directory of custom templates, instead of template_dir in the config file"),
+        Arg::with_name("template")
+            .long("template")
+            .takes_value(true)
+            .help("Custom mail template, instead of template_path in the config file"),
+    ]
+}
+
+fn database_args(default_paths: &DefaultPaths) -> [Arg<'_, '_>; 3] {
+    [
+        Arg::with_name("database")
+            .long("database")
+            .default_value_os(default_paths.database.as_os_str())
+            .help("Database of state, such as which items have been read"),
+        Arg::with_name("cache")
+            .long("cache")
+            .default_value_os(default_paths.cache.as_os_str())
+            .help("Database of data that can be fetched again, which is safe to delete"),
+        Arg::with_name("read-only").long("read-only").help(
+            "Open the database read-only, to inspect it while a daemon writes to it, for \
+             commands that don't write to it",
+        ),
+    ]
+}
+
+fn clock_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("deterministic")
+            .long("deterministic")
+            .help("Render the same mail for the same items, with a fixed clock and Message-ID"),
+        Arg::with_name("now")
+            .long("now")
+            .takes_value(true)
+            .value_name("TIME")
+            .requires("deterministic")
+            .validator(validate_parse::<chrono::DateTime<chrono::Utc>>)
+            .help("RFC 3339 time to fix the clock at [default: 2000-01-01T00:00:00Z]"),
+    ]
+}
+
+fn get_clock(matches: &clap::ArgMatches) -> clock::Clock {
+    match matches.value_of("now") {
+        Some(now) => clock::Clock::Fixed(now.parse().expect("invalid validated time")),
+        None if matches.is_present("deterministic") => clock::Clock::fixed_default(),
+        None => clock::Clock::System,
+    }
+}
+
+/// Arguments of subcommands that act on an item.
+fn item_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("guid")
+            .required(true)
+            .help("GUID of the item"),
+        Arg::with_name("feed")
+            .long("feed")
+            .takes_value(true)
+            .help("URL of the item's feed, if items of other feeds have the same GUID"),
+    ]
+}
+
+fn fetch_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("fetch")
+        .about("Fetches feeds")
+        .arg(
+            Arg::with_name("max-duration")
+                .long("max-duration")
+                .takes_value(true)
+                .validator(|value| parse_duration(&value).map(|_| ()))
+                .help(
+                    "Stop starting fetches this long after starting, less the timeout of a \
+                     request, such as 50m; fetches that were started aren't cut short",
+                ),
+        )
+        .arg(metrics_textfile_arg())
+}
+
+fn metrics_textfile_arg() -> Arg<'static, 'static> {
+    Arg::with_name("metrics-textfile")
+        .long("metrics-textfile")
+        .takes_value(true)
+        .help("Write metrics of the run to this file, for the node exporter's textfile collector")
+}
+
+fn add_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("add")
+        .about("Adds a feed to the config file")
+        .arg(Arg::with_name("url").required(true))
+        .arg(
+            Arg::with_name("fetch")
+                .long("fetch")
+                .help("Fetch the feed too, and don't add it if that fails"),
+        )
+}
+
+fn snooze_item_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("snooze-item")
+        .about("Hides an unread item from digests for a while")
+        .args(&item_args())
+        .arg(
+            Arg::with_name("for")
+                .long("for")
+                .default_value("7d")
+                .validator(|value| parse_duration(&value).map(|_| ()))
+                .help(
+                    "How long to hide the item, in minutes (m), hours (h), days (d), or weeks (w)",
+                ),
+        )
+}
+
+/// Parse a duration written as a number and a unit, such as `30m`, `12h`, `7d`, or `2w`.
+fn parse_duration(value: &str) -> std::result::Result<chrono::Duration, String> {
+    let invalid = || format!("invalid duration {value:?}, expected a number and m, h, d, or w");
+    let unit_index = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let count: i64 = value[..unit_index].parse().map_err(|_| invalid())?;
+    let unit_seconds = match &value[unit_index..] {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    // Durations are at most i64::MAX milliseconds, and chrono panics on longer ones.
+    let seconds = count
+        .checked_mul(unit_seconds)
+        .filter(|&seconds| seconds <= i64::MAX / 1000)
+        .ok_or_else(invalid)?;
+    Ok(chrono::Duration::seconds(seconds))
+}
+
+fn get_max_duration(sub_matches: &clap::ArgMatches) -> Option<Duration> {
+    let max_duration = sub_matches.value_of("max-duration")?;
+    let max_duration = parse_duration(max_duration).expect("impossible invalid value");
+    Some(max_duration.to_std().unwrap_or_default())
+}
+
+/// Validate that an argument parses as `T`.
+#[allow(clippy::needless_pass_by_value)]
+fn validate_parse<T: std::str::FromStr>(value: String) -> std::result::Result<(), String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse::<T>().map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn get_metrics_textfile(sub_matches: &clap::ArgMatches) -> Option<PathBuf> {
+    sub_matches
+        .value_of_os("metrics-textfile")
+        .map(PathBuf::from)
+}
+
+fn get_url(sub_matches: &clap::ArgMatches) -> String {
+    sub_matches
+        .value_of("url")
+        .expect("impossible none")
+        .to_string()
+}
+
+fn get_render_command(sub_matches: &clap::ArgMatches) -> Command {
+    let format = match sub_matches.value_of("format") {
+        Some("text") => Format::Text,
+        Some("ics") => Format::Ics,
+        _ => Format::Html,
+    };
+    let max_chars = sub_matches
+        .value_of("max-chars")
+        .map(|max_chars| max_chars.parse().expect("impossible invalid value"));
+    if max_chars.is_some() && format != Format::Text {
+        clap::Error::with_description(
+            "--max-chars requires --format text",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    Command::Render {
+        format,
+        max_chars,
+        tag: sub_matches.value_of("tag").map(|tag| tag.to_string()),
+    }
+}
+
+fn get_command(matches: &clap::ArgMatches) -> Command {
+    match matches.subcommand() {
+        ("fetch", Some(sub_matches)) => Command::Fetch {
+            max_duration: get_max_duration(sub_matches),
+            metrics_textfile: get_metrics_textfile(sub_matches),
+        },
+        ("mail", Some(sub_matches)) => Command::Mail {
+            dry: sub_matches.is_present("dry"),
+            diff: sub_matches.is_present("diff"),
+            tag: sub_matches.value_of("tag").map(|tag| tag.to_string()),
+            pipeline: sub_matches
+                .value_of("pipeline")
+                .map(|pipeline| pipeline.to_string()),
+            metrics_textfile: get_metrics_textfile(sub_matches),
+        },
+        ("render", Some(sub_matches)) => get_render_command(sub_matches),
+        ("add", Some(sub_matches)) => Command::AddFeed {
+            url: get_url(sub_matches),
+            fetch: sub_matches.is_present("fetch"),
+        },
+        ("remove", Some(sub_matches)) => Command::RemoveFeed {
+            url: get_url(sub_matches),
+        },
+        ("import-opml", Some(sub_matches)) => Command::ImportOpml {
+            path: PathBuf::from(sub_matches.value_of_os("file").expect("impossible none")),
+        },
+        ("export-opml", Some(_)) => Command::ExportOpml,
+        ("daemon", Some(_)) => Command::Daemon,
+        ("stats", Some(_)) => Command::Stats,
+        ("audit", Some(_)) => Command::Audit,
+        ("diff", Some(_)) => Command::Diff,
+        ("check", Some(_)) => Command::Check,
+        ("check-links", Some(sub_matches)) => Command::CheckLinks {
+            days: sub_matches
+                .value_of("days")
+                .expect("impossible none")
+                .parse()
+                .expect("impossible invalid value"),
+        },
+        ("snooze-item", Some(sub_matches)) => Command::SnoozeItem {
+            guid: sub_matches
+                .value_of("guid")
+                .expect("impossible none")
+                .to_string(),
+            feed_url: sub_matches.value_of("feed").map(ToString::to_string),
+            duration: parse_duration(sub_matches.value_of("for").expect("impossible none"))
+                .expect("impossible invalid value"),
+        },
+        ("read-later", Some(sub_matches)) => Command::ReadLater {
+            guid: sub_matches
+                .value_of("guid")
+                .expect("impossible none")
+                .to_string(),
+            feed_url: sub_matches.value_of("feed").map(ToString::to_string),
+        },
+        ("export-bookmarks", Some(sub_matches)) => Command::ExportBookmarks {
+            format: match sub_matches.value_of("format") {
+                Some("json") => bookmarks::Format::Json,
+                _ => bookmarks::Format::NetscapeHtml,
+            },
+        },
+        ("preview-server", Some(sub_matches)) => Command::PreviewServer {
+            port: sub_matches
+                .value_of("port")
+                .expect("impossible none")
+                .parse()
+                .expect("impossible invalid value"),
+            tag: sub_matches.value_of("tag").map(|tag| tag.to_string()),
+        },
+        ("migrate-state", Some(_)) => Command::MigrateState,
+        ("export-state", Some(_)) => Command::ExportState,
+        ("rebuild", Some(sub_matches)) => Command::Rebuild {
+            import: sub_matches.value_of_os("import").map(PathBuf::from),
+        },
+        ("config", Some(sub_matches)) => match sub_matches.subcommand() {
+            ("upgrade", Some(upgrade_matches)) => Command::UpgradeConfig {
+                dry: upgrade_matches.is_present("dry"),
+            },
+            _ => panic!("impossible subcommand"),
+        },
+        ("rules", Some(sub_matches)) => match sub_matches.subcommand() {
+            ("test", Some(test_matches)) => Command::TestRules {
+                feed_url: test_matches
+                    .value_of("feed")
+                    .expect("impossible none")
+                    .to_string(),
             },
             _ => panic!("impossible subcommand"),
         },
+        _ => panic!("impossible subcommand"),
+    }
+}
+
+/// Load the config with the options given on the command line, warning about what had to be
+/// changed to read it. Returns the config and the unknown keys that were ignored.
+fn load_config(args: &Args) -> Result<(Config, Vec<String>)> {
+    let is_check = matches!(args.command, Command::Check);
+    let config::Loaded {
+        mut config,
+        upgraded,
+        unknown_keys,
+    } = Config::load(&args.config, args.lenient_config || is_check)?;
+    config.clock = args.clock;
+    // Templates given on the command line replace pipelines' templates too.
+    if let Some(template_dir) = &args.template_dir {
+        config.template_dir = Some(template_dir.clone());
+        for pipeline in &mut config.delivery.pipelines {
+            pipeline.template_dir = None;
+        }
+    }
+    if let Some(template_path) = &args.template_path {
+        config.template_path = Some(template_path.clone());
+    }
+    if !upgraded.is_empty() {
+        for warning in &upgraded {
+            eprintln!("warning: {warning}");
+        }
+        eprintln!(
+            "warning: run `squeakmail config upgrade` to update {}",
+            args.config.display()
+        );
     }
+    // The check subcommand reports unknown keys itself.
+    if !is_check {
+        for key in &unknown_keys {
+            eprintln!("warning: ignoring unknown config key `{key}`");
+        }
+    }
+    Ok((config, unknown_keys))
 }
 
 fn run() -> Result<()> {
     let args = get_args();
+    if args.read_only && !args.command.only_reads_database() {
+        return Err(Error::ReadOnlyCommand);
+    }
 
     create_parent_dir(&args.config).map_err(Error::CreateConfigDir)?;
     create_example_config_file(&args.config).map_err(Error::CreateConfigFile)?;
-    let config = Config::from_path(&args.config)?;
+    if let Command::UpgradeConfig { dry } = args.command {
+        return upgrade_config(&args.config, args.lenient_config, dry);
+    }
+    let (config, unknown_keys) = load_config(&args)?;
 
-    create_parent_dir(&args.database).map_err(Error::CreateDatabaseDir)?;
-    let mut database = database::Database::open(&args.database)?;
+    if let Command::MigrateState = args.command {
+        return migrate_state(&args);
+    }
+    if let Command::Rebuild { import } = &args.command {
+        return rebuild(&args, config, import.as_deref());
+    }
+    let mut database = open_database(&args)?;
 
     match args.command {
-        Command::Fetch => {
-            fetch_feeds(config, database);
+        Command::Fetch {
+            max_duration,
+            metrics_textfile,
+        } => fetch_command(config, database, max_duration, metrics_textfile.as_deref())?,
+        Command::Mail {
+            dry,
+            diff,
+            tag,
+            pipeline,
+            metrics_textfile,
+        } => {
+            let result = mail_command(&config, &mut database, dry, diff, tag, pipeline);
+            let run = metrics::Run::mail(config.clock.now(), result.as_deref().ok());
+            let written = write_metrics(metrics_textfile.as_deref(), &run);
+            result?;
+            written?;
+        }
+        Command::Render {
+            format,
+            max_chars,
+            tag,
+        } => print_digests(&config, &mut database, format, max_chars, tag.as_deref())?,
+        Command::Stats => print_stats(&mut database)?,
+        Command::Audit => print_audit(&config, &mut database)?,
+        Command::Diff => print_diff(&config, &mut database)?,
+        Command::TestRules { feed_url } => print_rule_test(&config, &mut database, &feed_url)?,
+        Command::Check => check_config(&config, &unknown_keys, &mut database)?,
+        Command::CheckLinks { days } => check_links(&config, &mut database, days)?,
+        Command::SnoozeItem {
+            guid,
+            feed_url,
+            duration,
+        } => snooze_item(&config, &mut database, &guid, feed_url.as_deref(), duration)?,
+        Command::ReadLater { guid, feed_url } => {
+            save_item_for_later(&config, &mut database, &guid, feed_url.as_deref())?;
+        }
+        Command::ExportBookmarks { format } => export_bookmarks(&mut database, format)?,
+        Command::ExportState => print_state(&mut database)?,
+        Command::PreviewServer { port, tag } => preview::serve(
+            &config,
+            &mut database,
+            SocketAddr::from(([127, 0, 0, 1], port)),
+            tag.as_deref(),
+        )?,
+        Command::Daemon => {
+            shutdown::install()?;
+            daemon::run(config, &args.config, database)?;
+        }
+        Command::MigrateState | Command::Rebuild { .. } => {
+            unreachable!("handled before opening the database")
+        }
+        Command::AddFeed { url, fetch } => {
+            add_feed(&args.config, &config, database, &url, fetch)?;
+        }
+        Command::RemoveFeed { url } => remove_feed(&args.config, &url)?,
+        Command::ImportOpml { path } => import_opml(&args.config, &path)?,
+        Command::ExportOpml => export_opml(&config, &mut database)?,
+        Command::UpgradeConfig { .. } => unreachable!("handled before reading the config"),
+    }
+    Ok(())
+}
+
+/// Open the database, creating it unless it's opened read-only.
+fn open_database(args: &Args) -> Result<database::Database> {
+    if let Some(legacy_database) = &args.legacy_database {
+        if legacy_database.exists() && !args.database.exists() {
+            return Err(Error::StateNotMigrated(legacy_database.clone()));
+        }
+    }
+    if args.read_only {
+        return Ok(database::Database::open_read_only(
+            &args.database,
+            &args.cache,
+        )?);
+    }
+    create_parent_dir(&args.database).map_err(Error::CreateDatabaseDir)?;
+    create_parent_dir(&args.cache).map_err(Error::CreateDatabaseDir)?;
+    Ok(database::Database::open(&args.database, &args.cache)?)
+}
+
+/// Fetch every feed, and write metrics of the run if a file is given, even if fetching failed.
+fn fetch_command(
+    config: Config,
+    database: database::Database,
+    max_duration: Option<Duration>,
+    metrics_textfile: Option<&Path>,
+) -> Result<()> {
+    shutdown::install()?;
+    let clock = config.clock;
+    let feeds = config.sources.feeds.clone();
+    // A deadline too far away to represent is no deadline.
+    let deadline = max_duration.and_then(|max_duration| Instant::now().checked_add(max_duration));
+    let result = fetch_feeds(
+        &Arc::new(config),
+        feeds,
+        &Arc::new(Mutex::new(database)),
+        deadline,
+    )
+    .map_err(|e| e.context(Phase::Fetch, None));
+    let run = metrics::Run::fetch(clock.now(), result.as_ref().ok());
+    let written = write_metrics(metrics_textfile, &run);
+    result?;
+    written
+}
+
+/// Write the metrics of a run, if a file is given.
+fn write_metrics(path: Option<&Path>, run: &metrics::Run) -> Result<()> {
+    match path {
+        Some(path) => metrics::write(path, run).map_err(Error::WriteMetrics),
+        None => Ok(()),
+    }
+}
+
+/// Mail the digests of a pipeline, or of the tag given, or print what would change if `diff`
+/// is set. Returns the digests that were mailed.
+fn mail_command(
+    config: &Config,
+    database: &mut database::Database,
+    dry: bool,
+    diff: bool,
+    tag: Option<String>,
+    pipeline: Option<String>,
+) -> Result<Vec<hooks::Mail>> {
+    let (config, tag) = match pipeline {
+        Some(name) => pipeline_config(config, name)?,
+        None => (Cow::Borrowed(config), tag),
+    };
+    if dry && diff {
+        print_digest_diff(&config, database, tag.as_deref())?;
+        Ok(Vec::new())
+    } else {
+        mail(&config, database, dry, tag.as_deref()).map_err(|e| e.context(Phase::Mail, None))
+    }
+}
+
+/// The config and tag to mail a pipeline with.
+fn pipeline_config(config: &Config, name: String) -> Result<(Cow<'_, Config>, Option<String>)> {
+    let pipeline = config
+        .delivery
+        .pipelines
+        .iter()
+        .find(|pipeline| pipeline.name == name)
+        .ok_or(Error::UnknownPipeline(name))?;
+    Ok((Cow::Owned(pipeline.apply(config)), pipeline.tag.clone()))
+}
+
+/// Print the digests that would be sent, without sending them or marking items read.
+fn print_digests(
+    config: &Config,
+    database: &mut database::Database,
+    format: Format,
+    max_chars: Option<usize>,
+    tag: Option<&str>,
+) -> Result<()> {
+    let subject = digest_subject(config, tag);
+    let digests = group_digests(config, database, tag)?;
+    let tera = load_templates(config)?;
+    for (i, (recipient, feeds)) in digests.into_iter().enumerate() {
+        if i > 0 {
+            println!();
         }
-        Command::Mail { dry } => {
-            let mail = render_mail(&config, &mut database)?;
-            if dry {
+        match format {
+            Format::Html => {
+                let sections = digest_sections(config, database, recipient.as_deref())?;
                 println!(
                     "{}",
-                    mail.message_to_string()
-                        .expect("message cannot be converted to string")
+                    render_html(config, &tera, &subject, feeds, &sections)?
                 );
-            } else {
-                eprintln!("Sending mail...");
-                SendmailTransport::new().send(mail)?;
-                database.mark_all_items_read()?;
             }
+            Format::Text => print!(
+                "{}",
+                text::render(config.locale.strings(), &subject, &feeds, max_chars)
+            ),
+            Format::Ics => print!("{}", render_calendar(config, &feeds)),
         }
-    };
+    }
     Ok(())
 }
 
-fn fetch_feeds(config: Config, database: database::Database) {
-    let num_threads = min(config.concurrency.get() as usize, config.feeds.len());
-    let database = Arc::new(Mutex::new(database));
-    let queue = Arc::new(Mutex::new(config.feeds));
-    let mut handles = vec![];
-    for _ in 0..num_threads {
-        let queue = queue.clone();
-        let database = database.clone();
-        handles.push(thread::spawn(move || {
-            // Clippy fails to account for lifetime of MutexGuard
-            #[allow(clippy::while_let_loop)]
-            loop {
-                let feed_url = match queue
-                    .lock()
-                    .expect("thread panicked while holding queue mutex")
-                    .pop()
-                {
-                    Some(feed_url) => feed_url,
-                    None => break,
-                };
-                match fetch_feed(&feed_url, &database) {
-                    Ok(()) => {}
-                    Err(e) => eprintln!("Failed to fetch feed: {}", e),
-                };
-            }
-        }));
+/// Render digests of unread items and send them, or print them if `dry` is set. Returns the
+/// digests that were sent.
+fn mail(
+    config: &Config,
+    database: &mut database::Database,
+    dry: bool,
+    tag: Option<&str>,
+) -> Result<Vec<hooks::Mail>> {
+    if !dry {
+        poll_inbox(config, database);
     }
-    for handle in handles {
-        handle.join().expect("thread panicked");
+    let digests = render_mails(config, database, tag)?;
+    if digests.is_empty() {
+        eprintln!("No items to mail");
+        if !dry && (config.delivery.mx.is_some() || config.delivery.send_window.is_some()) {
+            outbox::deliver(config, database)?;
+        }
+    } else if dry {
+        for digest in digests {
+            let recipient = digest.mail.envelope().to()[0].to_string();
+            let items = digest.items.len();
+            let message = digest
+                .mail
+                .message_to_string()
+                .expect("message cannot be converted to string");
+            println!("{message}");
+            report_size(&recipient, items, &message);
+        }
+    } else {
+        for digest in &digests {
+            database.mark_items_resurfaced(&digest.resurfaced)?;
+            database.clear_items_updated(&digest.updated)?;
+        }
+        let mails = digests
+            .iter()
+            .map(|digest| hooks::Mail {
+                recipient: digest.mail.envelope().to()[0].to_string(),
+                items: digest.items.len(),
+            })
+            .collect();
+        let result = send_mails(config, database, digests, tag);
+        let summary = hooks::PostMail {
+            tag,
+            mails,
+            error: result.as_ref().err().map(ToString::to_string),
+            error_code: result.as_ref().err().map(Error::code),
+        };
+        hooks::run(config.hooks.as_ref(), hooks::Hook::PostMail, &summary).map_err(Error::Hook)?;
+        result?;
+        return Ok(summary.mails);
     }
+    Ok(Vec::new())
 }
 
-fn fetch_feed(feed_url: &str, database: &Mutex<database::Database>) -> Result<()> {
-    let feed = database
-        .lock()
-        .expect("thread panicked while holding database mutex")
-        .get_feed_by_url(feed_url)?;
-    eprintln!("Fetching {}...", feed_url);
-    let mut builder = attohttpc::get(feed_url)
-        .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
-        .timeout(Duration::from_secs(30));
-    if let Some(feed) = feed {
-        if let Some(etag) = feed.etag {
-            builder = builder.header(attohttpc::header::IF_NONE_MATCH, etag);
-        }
-        if let Some(last_modified) = feed.last_modified {
-            builder = builder.header(attohttpc::header::IF_MODIFIED_SINCE, last_modified);
+/// Move the database from the cache directory, where older versions kept it, and move its
+/// cached data into the cache.
+fn migrate_state(args: &Args) -> Result<()> {
+    let Some(legacy_database) = args.legacy_database.as_ref().filter(|path| path.exists()) else {
+        eprintln!("No database to migrate");
+        return Ok(());
+    };
+    // A stale journal left next to the new path would be applied to the moved database.
+    for suffix in DATABASE_SUFFIXES {
+        let path = path_with_suffix(&args.database, suffix);
+        if path.exists() {
+            return Err(Error::StateExists(path));
         }
     }
-    let resp = builder.send()?;
-    if resp.status() == attohttpc::StatusCode::NOT_MODIFIED {
-        return Err(Error::FeedNotModified);
-    } else if !resp.status().is_success() {
-        return Err(Error::UnexpectedStatusCode(resp.status().as_u16()));
+    create_parent_dir(&args.database).map_err(Error::CreateDatabaseDir)?;
+    create_parent_dir(&args.cache).map_err(Error::CreateDatabaseDir)?;
+    for suffix in DATABASE_SUFFIXES {
+        let from = path_with_suffix(legacy_database, suffix);
+        if !from.exists() {
+            continue;
+        }
+        let to = path_with_suffix(&args.database, suffix);
+        // Renaming fails if the directories are on different file systems.
+        if std::fs::rename(&from, &to).is_err() {
+            std::fs::copy(&from, &to).map_err(Error::MigrateState)?;
+            std::fs::remove_file(&from).map_err(Error::MigrateState)?;
+        }
     }
-    let etag = resp
-        .headers()
-        .get(attohttpc::header::ETAG)
-        .and_then(|header_value| header_value.to_str().ok())
-        .map(|header_str| header_str.to_string());
-    let last_modified = resp
-        .headers()
-        .get(attohttpc::header::LAST_MODIFIED)
-        .and_then(|header_value| header_value.to_str().ok())
-        .map(|header_str| header_str.to_string());
-    let feed = feed::Feed::read_from(resp.text_reader())?;
+    database::Database::open(&args.database, &args.cache)?;
+    eprintln!(
+        "Moved {} to {}",
+        legacy_database.display(),
+        args.database.display()
+    );
+    Ok(())
+}
 
-    database
-        .lock()
-        .expect("thread panicked while holding database mutex")
-        .insert_update_feed(&database::Feed {
-            url: feed_url.to_string(),
-            link: feed.link().to_string(),
-            title: feed.title().to_string(),
-            etag,
-            last_modified,
-        })?;
-    for item in feed.items() {
-        database
-            .lock()
-            .expect("thread panicked while hold database mutex")
-            .insert_update_item(&database::Item {
-                feed_url: feed_url.to_string(),
-                guid: item.guid,
-                title: item.title,
-                link: item.link,
-                comments_link: item.comments_link,
-                pub_date: item.pub_date,
-                is_read: false,
-            })?;
-    }
+/// Print the state that can't be fetched again as JSON, to import when rebuilding.
+fn print_state(database: &mut database::Database) -> Result<()> {
+    let state = state::Export::load(database)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&state).expect("failed to serialize state")
+    );
     Ok(())
 }
 
-fn render_mail(config: &Config, database: &mut database::Database) -> Result<SendableEmail> {
-    let subject = format!("SqueakMail for {}", chrono::Local::now().format("%c"));
-    let mut feeds_with_items = Vec::new();
-    for feed_url in &config.feeds {
-        // skips feed that don't exist in database
-        if let Some(feed) = database.get_feed_by_url(feed_url)? {
-            feeds_with_items.push(FeedWithItems {
-                feed,
-                items: database.get_unread_items(feed_url)?,
-            })
+/// Move the database aside and delete the cache, then create them again by fetching every feed,
+/// and restore the state of items from an export if one is given.
+fn rebuild(args: &Args, config: Config, import: Option<&Path>) -> Result<()> {
+    // Read the export first, so a bad one is found before the database is moved.
+    let export: Option<state::Export> = match import {
+        Some(path) => {
+            let json = std::fs::read(path).map_err(Error::ReadState)?;
+            Some(serde_json::from_slice(&json)?)
         }
-    }
-    let context = MailContext {
-        subject: subject.to_string(),
-        feeds: feeds_with_items,
+        None => None,
     };
-    let mut tera = Tera::default();
-    tera.add_raw_template(MAIL_TEMPLATE_NAME, include_str!("../resources/mail.html"))
-        .expect("invalid mail template");
-    let context = tera::Context::from_serialize(context).expect("failed to build tera context");
-    let html_content = tera
-        .render(MAIL_TEMPLATE_NAME, &context)
-        .expect("failed to render mail from template");
-    Ok(Email::builder()
-        // TODO: Convert directly from EmailAddress to Mailbox in next version of lettre.
-        .to(config.to_email.to_string())
-        .from(config.from_email.to_string())
-        .subject(subject)
-        .html(html_content)
-        .build()
-        .expect("failed to build email")
-        .into())
+    let backup = path_with_suffix(&args.database, ".bak");
+    if backup.exists() {
+        return Err(Error::StateExists(backup));
+    }
+    for suffix in DATABASE_SUFFIXES {
+        let path = path_with_suffix(&args.database, suffix);
+        if path.exists() {
+            std::fs::rename(&path, path_with_suffix(&backup, suffix)).map_err(Error::Rebuild)?;
+        }
+        let cache = path_with_suffix(&args.cache, suffix);
+        if cache.exists() {
+            std::fs::remove_file(&cache).map_err(Error::Rebuild)?;
+        }
+    }
+    if backup.exists() {
+        eprintln!("Moved the database to {}", backup.display());
+    }
+    create_parent_dir(&args.database).map_err(Error::CreateDatabaseDir)?;
+    create_parent_dir(&args.cache).map_err(Error::CreateDatabaseDir)?;
+    let database = Arc::new(Mutex::new(database::Database::open(
+        &args.database,
+        &args.cache,
+    )?));
+    shutdown::install()?;
+    let feeds = config.sources.feeds.clone();
+    // Restore what was fetched even if fetching was interrupted.
+    let fetched = fetch_feeds(&Arc::new(config), feeds, &database, None);
+    if let Some(export) = export {
+        let restored = export.restore(
+            &mut database
+                .lock()
+                .expect("thread panicked while holding database mutex"),
+        )?;
+        eprintln!(
+            "Restored the state of {restored} of {} items",
+            export.items.len()
+        );
+        if restored < export.items.len() {
+            eprintln!("The other items are no longer in their feeds");
+        }
+    }
+    fetched.map(|_| ())
+}
+
+/// Path with a suffix added to its file name, such as `.bak`.
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Rewrite the config file with the keys of this version, and every key that has a default, or
+/// print it if `dry` is set. Keys that this version doesn't know are kept, for newer versions
+/// sharing the config. The previous config file is kept next to it, and the file is replaced in
+/// one step, unless it changed while it was being upgraded.
+fn upgrade_config(path: &Path, lenient: bool, dry: bool) -> Result<()> {
+    let (original, value) = read_config_value(path)?;
+    let loaded = Config::load(path, lenient)?;
+    for warning in &loaded.upgraded {
+        eprintln!("{warning}");
+    }
+    for key in &loaded.unknown_keys {
+        eprintln!("`{key}` is unknown to this version, so it was kept as it is");
+    }
+    let upgraded = if loaded.unknown_keys.is_empty() {
+        toml::to_string_pretty(&loaded.config)?
+    } else {
+        // Tables of a TOML value are sorted by key, rather than in the order of the config's
+        // fields.
+        let mut upgraded = toml::Value::try_from(&loaded.config)?;
+        schema::copy_keys(&value, &mut upgraded, &loaded.unknown_keys);
+        toml::to_string_pretty(&upgraded)?
+    };
+    if dry {
+        print!("{upgraded}");
+        return Ok(());
+    }
+    let backup = replace_config(path, &original, &upgraded)?;
+    eprintln!(
+        "Upgraded {}, the previous config is in {}",
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Read the text of a config file, and its TOML value with the keys of older versions replaced.
+fn read_config_value(path: &Path) -> Result<(String, toml::Value)> {
+    let original = std::fs::read_to_string(path).map_err(Error::ReadConfig)?;
+    let value = upgrade::upgrade(toml::from_str(&original)?).value;
+    Ok((original, value))
+}
+
+/// Read a config file to edit its feeds in place. A config with keys of older versions has to be
+/// upgraded first, since an edit could conflict with them, such as a feed added to `sources.feeds`
+/// next to a `feeds` of an older version.
+fn read_config_document(path: &Path) -> Result<(String, subscriptions::Document)> {
+    let original = std::fs::read_to_string(path).map_err(Error::ReadConfig)?;
+    let document = subscriptions::Document::parse(&original)?;
+    if upgrade::upgrade(document.value().clone()).value != *document.value() {
+        return Err(Error::ConfigNeedsUpgrade(path.to_path_buf()));
+    }
+    Ok((original, document))
+}
+
+/// Replace a config file that was read as `original`, keeping the previous file next to it, and
+/// return where. The file is replaced in one step, unless it changed since it was read.
+fn replace_config(path: &Path, original: &str, contents: &str) -> Result<PathBuf> {
+    // Another program, such as an editor, may have written the file since it was read.
+    if std::fs::read_to_string(path).map_err(Error::ReadConfig)? != original {
+        return Err(Error::ConfigChanged(path.to_path_buf()));
+    }
+    let backup = path_with_suffix(path, ".bak");
+    std::fs::copy(path, &backup).map_err(Error::WriteConfig)?;
+    atomic::write(path, contents.as_bytes()).map_err(Error::WriteConfig)?;
+    Ok(backup)
+}
+
+/// Add a feed to the config file. With `fetch`, the feed is fetched after the config file is
+/// written, and the config file is put back if that fails.
+fn add_feed(
+    path: &Path,
+    config: &Config,
+    database: database::Database,
+    url: &str,
+    fetch: bool,
+) -> Result<()> {
+    url::Url::parse(url)?;
+    let (original, mut document) = read_config_document(path)?;
+    document.add(url, None)?;
+    let edited = document.text();
+    // Keys this version doesn't know were allowed when the config was loaded.
+    let mut edited_config = Config::parse(edited, true)?.config;
+    // Written before fetching, so items aren't stored for a feed that a concurrent change to the
+    // config file kept from being added.
+    let backup = replace_config(path, &original, edited)?;
+    if fetch {
+        edited_config.clock = config.clock;
+        if let Err(e) = fetch_added_feed(config, &edited_config, database, url) {
+            // Unless the config file was changed again since it was written.
+            if std::fs::read_to_string(path).map_err(Error::ReadConfig)? == edited {
+                atomic::write(path, original.as_bytes()).map_err(Error::WriteConfig)?;
+            }
+            return Err(e);
+        }
+    }
+    eprintln!(
+        "Added {url} to {}, the previous config is in {}",
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Fetch a feed that was just added to the config.
+fn fetch_added_feed(
+    config: &Config,
+    edited_config: &Config,
+    database: database::Database,
+    url: &str,
+) -> Result<()> {
+    let robots = if config.sources.respect_robots_txt {
+        Some(robots::Cache::default())
+    } else {
+        None
+    };
+    let script = lua::Script::load(config.lua.as_ref())?;
+    let feed_config = edited_config
+        .sources
+        .feeds
+        .iter()
+        .find(|feed| feed.url == url)
+        .expect("feed was just added");
+    let database = Mutex::new(database);
+    match fetch_feed(
+        edited_config,
+        feed_config,
+        robots.as_ref(),
+        script.as_ref(),
+        &database,
+    ) {
+        Ok(new_items) => eprintln!("Fetched {url}, with {new_items} new items"),
+        // The feed was subscribed to before, and hasn't changed since.
+        Err(Error::FeedNotModified) => eprintln!("Fetched {url}, which wasn't modified"),
+        Err(e) => return Err(e),
+    }
+    Ok(())
+}
+
+/// Add the feeds of an OPML file to the config file, with the folders they're in. Feeds that are
+/// subscribed to already are skipped.
+fn import_opml(path: &Path, opml_path: &Path) -> Result<()> {
+    let opml = std::fs::read(opml_path).map_err(Error::ReadOpml)?;
+    let outlines = opml::parse(&opml)?;
+    let (original, mut document) = read_config_document(path)?;
+    let mut added = 0;
+    for outline in &outlines {
+        if url::Url::parse(&outline.url).is_err() {
+            eprintln!("Skipping {}, which isn't a valid URL", outline.url);
+            continue;
+        }
+        match document.add(&outline.url, outline.folder.as_deref()) {
+            Ok(()) => added += 1,
+            Err(Error::AlreadySubscribed(url)) => {
+                eprintln!("Skipping {}, already subscribed to {url}", outline.url);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if added == 0 {
+        eprintln!("No feeds to add from {}", opml_path.display());
+        return Ok(());
+    }
+    Config::parse(document.text(), true)?;
+    let backup = replace_config(path, &original, document.text())?;
+    eprintln!(
+        "Added {added} feeds to {}, the previous config is in {}",
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Print the feeds as OPML, with the titles and links they were last fetched with.
+fn export_opml(config: &Config, database: &mut database::Database) -> Result<()> {
+    let mut outlines = Vec::new();
+    for feed in &config.sources.feeds {
+        let stored = database.get_feed_by_url(&feed.url)?;
+        outlines.push(opml::Outline {
+            url: feed.url.clone(),
+            title: stored
+                .as_ref()
+                .map(|stored| stored.title.clone())
+                .filter(|title| !title.is_empty())
+                .or_else(|| feed.name.clone()),
+            link: stored
+                .map(|stored| stored.link)
+                .filter(|link| !link.is_empty()),
+            folder: feed.folder.clone(),
+        });
+    }
+    print!("{}", opml::render(&outlines));
+    Ok(())
+}
+
+/// Remove a feed from the config file. Its items stay in the database.
+fn remove_feed(path: &Path, url: &str) -> Result<()> {
+    let (original, mut document) = read_config_document(path)?;
+    let removed = document.remove(url)?;
+    // Rules and pipelines that refer to the feed would fail to load.
+    Config::parse(document.text(), true)?;
+    let backup = replace_config(path, &original, document.text())?;
+    eprintln!(
+        "Removed {removed} from {}, the previous config is in {}",
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Fetch feeds from several threads, stopping early if Ctrl-C is pressed. No more fetches are
+/// started once they might not finish by the deadline, if there is one. Returns the summary
+/// passed to the `post_fetch` hook.
+fn fetch_feeds(
+    config: &Arc<Config>,
+    feeds: Vec<FeedConfig>,
+    database: &Arc<Mutex<database::Database>>,
+    deadline: Option<Instant>,
+) -> Result<hooks::PostFetch> {
+    // Items are stored by feed URL, so a feed under two spellings of its URL would be fetched
+    // twice into the same items.
+    let (feeds, dropped) = duplicates::first_by_canonical_url(feeds, |feed| &feed.url);
+    for (url, first_url) in dropped {
+        eprintln!("warning: {url} is the same feed as {first_url}, fetching it once");
+    }
+    let summary = hooks::PreFetch {
+        feeds: feeds.iter().map(|feed| feed.url.as_str()).collect(),
+    };
+    hooks::run(config.hooks.as_ref(), hooks::Hook::PreFetch, &summary).map_err(Error::Hook)?;
+    // Check the script loads before each thread loads its own.
+    lua::Script::load(config.lua.as_ref())?;
+    let _active = shutdown::Active::new();
+    let started_at = chrono::Utc::now();
+    let started = Instant::now();
+    let num_threads = min(config.sources.concurrency.get() as usize, feeds.len());
+    // Each feed is queued with whether it's being retried.
+    let queue: VecDeque<(FeedConfig, bool)> = order_fetches(database, feeds, deadline.is_some())?
+        .into_iter()
+        .map(|feed| (feed, false))
+        .collect();
+    let queue = Arc::new(Mutex::new(queue));
+    let throttle = Arc::new(throttle::Throttle::new(num_threads));
+    let robots = if config.sources.respect_robots_txt {
+        Some(Arc::new(robots::Cache::default()))
+    } else {
+        None
+    };
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let mut handles = vec![];
+    for _ in 0..num_threads {
+        let queue = queue.clone();
+        let throttle = throttle.clone();
+        let results = results.clone();
+        let database = database.clone();
+        let config = config.clone();
+        let robots = robots.clone();
+        handles.push(thread::spawn(move || {
+            fetch_queued_feeds(
+                &config,
+                &queue,
+                &throttle,
+                robots.as_deref(),
+                &database,
+                &results,
+                deadline,
+            );
+        }));
+    }
+    for handle in handles {
+        handle.join().expect("thread panicked");
+    }
+    if shutdown::is_requested() {
+        return Err(Error::Interrupted);
+    }
+    let mut feeds = std::mem::take(
+        &mut *results
+            .lock()
+            .expect("thread panicked while holding results mutex"),
+    );
+    let duration = started.elapsed();
+    record_fetch_run(database, started_at, duration, &feeds)?;
+    let skipped = queue
+        .lock()
+        .expect("thread panicked while holding queue mutex")
+        .drain(..)
+        .map(|(feed, _)| hooks::FeedFetch {
+            url: feed.url,
+            status: hooks::FetchStatus::Skipped,
+            new_items: 0,
+            error: None,
+            error_code: None,
+        })
+        .collect::<Vec<_>>();
+    if !skipped.is_empty() {
+        eprintln!(
+            "Reached the fetch deadline, skipped {} feeds until the next fetch",
+            skipped.len()
+        );
+    }
+    feeds.extend(skipped);
+    let summary = hooks::PostFetch {
+        started_at,
+        duration_ms: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+        feeds,
+    };
+    if let Some(url) = config
+        .hooks
+        .as_ref()
+        .and_then(|hooks| hooks.post_fetch_url.as_deref())
+    {
+        if let Err(e) = hooks::post(url, &summary) {
+            eprintln!("Failed to post fetch summary to {url}: {e}");
+        }
+    }
+    hooks::run(config.hooks.as_ref(), hooks::Hook::PostFetch, &summary).map_err(Error::Hook)?;
+    Ok(summary)
+}
+
+/// Order feeds to be fetched so slow feeds don't hold up the end of a run. With a deadline,
+/// feeds that weren't fetched by the last run come first, so the same feeds aren't left out
+/// every time.
+fn order_fetches(
+    database: &Mutex<database::Database>,
+    feeds: Vec<FeedConfig>,
+    has_deadline: bool,
+) -> Result<Vec<FeedConfig>> {
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    let durations = database.get_fetch_durations()?;
+    let feeds = schedule::order(feeds, |feed| durations.get(&feed.url).copied());
+    let Some(last_run) = database.get_last_fetch_run()?.filter(|_| has_deadline) else {
+        return Ok(feeds);
+    };
+    let last_fetches = database.get_last_fetches()?;
+    let (mut missed, fetched): (Vec<_>, Vec<_>) = feeds.into_iter().partition(|feed| {
+        last_fetches
+            .get(&feed.url)
+            .is_none_or(|fetched_at| *fetched_at < last_run.started_at)
+    });
+    missed.extend(fetched);
+    Ok(missed)
+}
+
+/// Record a run of fetching feeds, and which feeds failed, for digest footers.
+fn record_fetch_run(
+    database: &Mutex<database::Database>,
+    started_at: chrono::DateTime<chrono::Utc>,
+    duration: Duration,
+    feeds: &[hooks::FeedFetch],
+) -> Result<()> {
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    database.insert_fetch_run(&database::FetchRun {
+        started_at,
+        duration_ms: i64::try_from(duration.as_millis()).unwrap_or(i64::MAX),
+    })?;
+    for feed in feeds {
+        database.set_fetch_error(&feed.url, chrono::Utc::now(), feed.error.as_deref())?;
+    }
+    Ok(())
+}
+
+/// Fetch feeds from the queue until it's empty, retrying feeds once if the fetch looks
+/// overloaded.
+fn fetch_queued_feeds(
+    config: &Config,
+    queue: &Mutex<VecDeque<(FeedConfig, bool)>>,
+    throttle: &throttle::Throttle,
+    robots: Option<&robots::Cache>,
+    database: &Mutex<database::Database>,
+    results: &Mutex<Vec<hooks::FeedFetch>>,
+    deadline: Option<Instant>,
+) {
+    // Only starting fetches is stopped. A fetch started before this makes a request that may
+    // run up to the deadline, and more requests, commands, and translations after it may run
+    // past the deadline.
+    let is_late = || deadline.is_some_and(|deadline| Instant::now() + FETCH_TIMEOUT >= deadline);
+    let script = match lua::Script::load(config.lua.as_ref()) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("Failed to load Lua script: {e}");
+            return;
+        }
+    };
+    // Clippy fails to account for lifetime of MutexGuard
+    #[allow(clippy::while_let_loop)]
+    loop {
+        if shutdown::is_requested() || is_late() {
+            break;
+        }
+        let Some((feed, is_retry)) = queue
+            .lock()
+            .expect("thread panicked while holding queue mutex")
+            .pop_front()
+        else {
+            break;
+        };
+        throttle.acquire();
+        if shutdown::is_requested() || is_late() {
+            // Leave the feed for the summary of skipped feeds.
+            queue
+                .lock()
+                .expect("thread panicked while holding queue mutex")
+                .push_front((feed, is_retry));
+            throttle.release(false);
+            break;
+        }
+        let result = fetch_feed(config, &feed, robots, script.as_ref(), database)
+            .map_err(|e| e.context(Phase::Fetch, Some(&feed.url)));
+        let is_overloaded = result.as_ref().is_err_and(is_overload_error);
+        if let Some(limit) = throttle.release(is_overloaded) {
+            eprintln!("Many fetches are failing, slowing down to {limit} at a time");
+        }
+        if is_overloaded && !is_retry {
+            if let Err(e) = &result {
+                eprintln!("Failed {e}, retrying later");
+            }
+            queue
+                .lock()
+                .expect("thread panicked while holding queue mutex")
+                .push_back((feed, true));
+            continue;
+        }
+        if let Err(e) = &result {
+            eprintln!("Failed {e}");
+        }
+        if let Some(daemon) = &config.daemon {
+            if let Err(e) = daemon::schedule_next_fetch(daemon, &feed.url, database) {
+                eprintln!("Failed to schedule next fetch: {e}");
+            }
+        }
+        let (status, new_items, error) = match result {
+            Ok(new_items) => (hooks::FetchStatus::Fetched, new_items, None),
+            Err(e) if matches!(e.root(), Error::FeedNotModified) => {
+                (hooks::FetchStatus::NotModified, 0, None)
+            }
+            Err(e) => (hooks::FetchStatus::Failed, 0, Some(e)),
+        };
+        results
+            .lock()
+            .expect("thread panicked while holding results mutex")
+            .push(hooks::FeedFetch {
+                url: feed.url,
+                status,
+                new_items,
+                error_code: error.as_ref().map(Error::code),
+                // The URL is in the summary already.
+                error: error.as_ref().map(|e| e.root().to_string()),
+            });
+    }
+}
+
+/// Whether a fetch failed in a way that suggests the network or server is overloaded, rather
+/// than a problem with the feed.
+fn is_overload_error(error: &Error) -> bool {
+    match error.root() {
+        Error::Http(e) => matches!(e.kind(), attohttpc::ErrorKind::Io(_)),
+        Error::UnexpectedStatusCode(status) => *status == 429 || *status == 503,
+        _ => false,
+    }
+}
+
+/// Fetch a feed and store its items, returning how many of them are new.
+fn fetch_feed(
+    config: &Config,
+    feed_config: &FeedConfig,
+    robots: Option<&robots::Cache>,
+    script: Option<&lua::Script>,
+    database: &Mutex<database::Database>,
+) -> Result<usize> {
+    let feed_url = feed_config.url.as_str();
+    check_robots(feed_url, robots)?;
+    check_challenge_backoff(feed_url, database)?;
+    let stored_feed = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_feed_by_url(feed_url)?;
+    eprintln!("Fetching {feed_url}...");
+    let request_url = query::url(feed_url, &feed_config.query, chrono::Utc::now())?;
+    let is_conditional = stored_feed
+        .as_ref()
+        .is_some_and(|feed| feed.etag.is_some() || feed.last_modified.is_some());
+    let fetched_at = chrono::Utc::now();
+    let started = Instant::now();
+    let (resp, final_url) =
+        request_feed(feed_config, &request_url, stored_feed.as_ref(), database)?;
+    let status = resp.status();
+    let etag = header_string(resp.headers(), attohttpc::header::ETAG)
+        .and_then(|etag| validators::normalize_etag(&etag));
+    let last_modified = header_string(resp.headers(), attohttpc::header::LAST_MODIFIED)
+        .and_then(|last_modified| validators::normalize_last_modified(&last_modified));
+    let raw = config
+        .sources
+        .raw_dir
+        .as_deref()
+        .map(|raw_dir| (raw_dir, config.sources.raw_keep));
+    let body = read_feed_body(resp, feed_url, feed_config.lenient_xml, raw)?;
+    let duration = started.elapsed();
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .insert_fetch(&database::Fetch {
+            feed_url: feed_url.to_string(),
+            fetched_at,
+            status: status.as_u16(),
+            bytes: i64::try_from(body.bytes).unwrap_or(i64::MAX),
+            is_conditional,
+            etag: etag.clone(),
+            last_modified: last_modified.clone(),
+            body_hash: body.hash,
+            duration_ms: Some(i64::try_from(duration.as_millis()).unwrap_or(i64::MAX)),
+        })?;
+    check_challenge(feed_url, fetched_at, body.challenge, database)?;
+    let source = database::Source {
+        url: final_url,
+        fetched_at,
+        fetch_ms: i64::try_from(duration.as_millis()).unwrap_or(i64::MAX),
+    };
+    if status == attohttpc::StatusCode::NOT_MODIFIED {
+        return Err(Error::FeedNotModified);
+    } else if !status.is_success() {
+        return Err(Error::UnexpectedStatusCode(status.as_u16()));
+    }
+    let feed = body
+        .feed
+        .expect("successful responses that aren't challenge pages are parsed")?;
+    let mut items: Vec<feed::Item> = feed.items().collect();
+    if feed_config.repair_encoding {
+        items.iter_mut().for_each(mojibake::repair_item);
+    }
+    // Transform items before the feed is updated, so a failure doesn't lose them.
+    let mut items = transform_items(feed_config, script, items)?;
+    feed_config.identity.apply(&mut items);
+    let read_on_add = read_on_add(feed_config, stored_feed.as_ref(), &items);
+
+    if let Some(stored_feed) = &stored_feed {
+        record_feed_change(stored_feed, &feed, database)?;
+    }
+    let self_url = feed.self_link();
+    if let Some(self_url) = &self_url {
+        check_self_url(feed_url, stored_feed.as_ref(), self_url, database)?;
+    }
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .insert_update_feed(&database::Feed {
+            url: feed_url.to_string(),
+            link: feed.link(),
+            title: feed.title(),
+            etag,
+            last_modified,
+            is_muted: false,
+            hub: feed.hub(),
+            self_url,
+            identity: feed_config.identity.to_string(),
+        })?;
+    store_items(
+        config,
+        feed_config,
+        script,
+        items,
+        &source,
+        &read_on_add,
+        database,
+    )
+}
+
+/// GUIDs of items to mark read because the feed is new, or because its identity strategy changed
+/// and every item would look new otherwise.
+fn read_on_add(
+    feed_config: &FeedConfig,
+    stored_feed: Option<&database::Feed>,
+    items: &[feed::Item],
+) -> HashSet<String> {
+    let identity = feed_config.identity.to_string();
+    match stored_feed {
+        None => feed_config.on_add.read_guids(items),
+        Some(stored_feed) if stored_feed.identity != identity => {
+            eprintln!(
+                "Feed {} changed its identity from {} to {identity}, marking its items read",
+                stored_feed.url, stored_feed.identity
+            );
+            OnAdd::MarkRead.read_guids(items)
+        }
+        Some(_) => HashSet::new(),
+    }
+}
+
+/// Body of a response to a feed request.
+struct FeedBody {
+    bytes: u64,
+    /// SHA-256 hash of the body of a successful response.
+    hash: Option<String>,
+    /// Service that sent the body, if it's a challenge page.
+    challenge: Option<&'static str>,
+    /// Feed of a successful response that isn't a challenge page.
+    feed: Option<Result<feed::Feed>>,
+}
+
+/// Download the body of a response to a feed request, parsing the feed as it's read, and saving
+/// it as a raw body in a directory if given, with how many bodies to keep. HTML bodies, which may
+/// be challenge pages, and feeds that may have to be repaired are read into memory first. Bodies
+/// of other unsuccessful responses aren't downloaded.
+fn read_feed_body(
+    resp: attohttpc::Response,
+    feed_url: &str,
+    lenient_xml: bool,
+    raw: Option<(&Path, usize)>,
+) -> Result<FeedBody> {
+    let (status, headers, reader) = resp.split();
+    let html = challenge::is_html(&headers, &[]);
+    if !status.is_success() && !html {
+        return Ok(FeedBody {
+            bytes: 0,
+            hash: None,
+            challenge: None,
+            feed: None,
+        });
+    }
+    let charset = response_charset(&headers);
+    let raw_body = raw
+        .filter(|_| status.is_success())
+        .and_then(|(raw_dir, _)| match raw::Body::create(raw_dir, feed_url) {
+            Ok(raw_body) => Some(raw_body),
+            Err(e) => {
+                eprintln!("Failed to save raw feed: {e}");
+                None
+            }
+        });
+    let mut reader = BodyReader::new(reader, raw_body);
+    let (challenge, feed) = if html || lenient_xml {
+        let mut body = Vec::new();
+        // Read errors are kept by the reader.
+        let _ = reader.read_to_end(&mut body);
+        reader.check()?;
+        let challenge = challenge::detect(status.as_u16(), &headers, &body);
+        let feed = (status.is_success() && challenge.is_none())
+            .then(|| parse_feed(feed_url, &body, charset, lenient_xml));
+        (challenge, feed)
+    } else {
+        let feed = feed::Feed::read_from(attohttpc::TextReader::new(&mut reader, charset));
+        // What's after the end of the feed, so the whole body is hashed and saved.
+        let _ = std::io::copy(&mut reader, &mut std::io::sink());
+        // Feeds fail to parse when they can't be read, so why is found out first.
+        reader.check()?;
+        (None, Some(feed.map_err(Error::from)))
+    };
+    let (bytes, hash, raw_body) = reader.finish();
+    if let (Some(raw_body), Some((_, raw_keep))) = (raw_body, raw) {
+        if bytes > 0 {
+            if let Err(e) = raw_body.finish(raw_keep) {
+                eprintln!("Failed to save raw feed: {e}");
+            }
+        }
+    }
+    Ok(FeedBody {
+        bytes,
+        hash: status.is_success().then_some(hash),
+        challenge,
+        feed,
+    })
+}
+
+/// Reader of a response body that counts and hashes what's read, copies it to a raw body, and
+/// fails once the body is larger than `MAX_FEED_BYTES`. It keeps the first error, which parsers
+/// replace with their own.
+struct BodyReader<R> {
+    inner: R,
+    bytes: u64,
+    hasher: Sha256,
+    raw_body: Option<raw::Body>,
+    error: Option<Error>,
+}
+
+impl<R: Read> BodyReader<R> {
+    fn new(inner: R, raw_body: Option<raw::Body>) -> Self {
+        Self {
+            inner,
+            bytes: 0,
+            hasher: Sha256::new(),
+            raw_body,
+            error: None,
+        }
+    }
+
+    /// Fail if reading failed.
+    fn check(&mut self) -> Result<()> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    /// How many bytes were read, their hash, and the raw body they were copied to.
+    fn finish(self) -> (u64, String, Option<raw::Body>) {
+        (
+            self.bytes,
+            format!("{:x}", self.hasher.finalize()),
+            self.raw_body,
+        )
+    }
+}
+
+impl<R: Read> Read for BodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.error.is_some() {
+            return Err(std::io::Error::other("the body failed to be read"));
+        }
+        let n = match self.inner.read(buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => return Err(e),
+            Err(e) => {
+                let copy = std::io::Error::new(e.kind(), e.to_string());
+                self.error = Some(Error::Http(e.into()));
+                return Err(copy);
+            }
+        };
+        self.bytes += n as u64;
+        if self.bytes > MAX_FEED_BYTES {
+            self.error = Some(Error::FeedTooLarge(MAX_FEED_BYTES));
+            return Err(std::io::Error::other("the feed is too large"));
+        }
+        self.hasher.update(&buf[..n]);
+        if let Some(raw_body) = &mut self.raw_body {
+            if let Err(e) = raw_body.write_all(&buf[..n]) {
+                eprintln!("Failed to save raw feed: {e}");
+                self.raw_body = None;
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Record whether a feed got a challenge page instead of the feed, failing if it did.
+fn check_challenge(
+    feed_url: &str,
+    fetched_at: chrono::DateTime<chrono::Utc>,
+    provider: Option<&str>,
+    database: &Mutex<database::Database>,
+) -> Result<()> {
+    let challenge = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .set_challenge(feed_url, fetched_at, provider)?;
+    match challenge {
+        Some(challenge) => Err(Error::Challenge(challenge.provider)),
+        None => Ok(()),
+    }
+}
+
+/// Fail without fetching a feed that got a challenge page recently, so a host that blocks
+/// automated clients isn't asked again every fetch. The wait doubles with each challenge page in
+/// a row.
+fn check_challenge_backoff(feed_url: &str, database: &Mutex<database::Database>) -> Result<()> {
+    let last = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_challenge(feed_url)?;
+    if let Some(last) = last {
+        let until = last.challenged_at + challenge::backoff(last.count);
+        if until > chrono::Utc::now() {
+            return Err(Error::ChallengeBackoff(last.provider, until));
+        }
+    }
+    Ok(())
+}
+
+/// Access token for a feed, from the cache unless it expires soon, or else from the token
+/// endpoint.
+fn access_token(auth: &oauth::Config, database: &Mutex<database::Database>) -> Result<String> {
+    let client_id = auth.client_id()?;
+    let now = chrono::Utc::now();
+    let cached = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_access_token(&auth.token_url, &client_id, auth.scope())?;
+    if let Some(cached) = cached.filter(|token| oauth::is_fresh(token, now)) {
+        return Ok(cached.token);
+    }
+    eprintln!("Requesting access token from {}...", auth.token_url);
+    let (token, expires_at) = oauth::request_token(auth, &client_id, now)?;
+    if let Some(expires_at) = expires_at {
+        database
+            .lock()
+            .expect("thread panicked while holding database mutex")
+            .set_access_token(
+                &auth.token_url,
+                &client_id,
+                auth.scope(),
+                Some(&database::AccessToken {
+                    token: token.clone(),
+                    expires_at,
+                }),
+            )?;
+    }
+    Ok(token)
+}
+
+/// Send a request for a feed, with an access token if it needs one. If the feed rejects the
+/// token, which may have been revoked before it expired, the token is forgotten so the next fetch
+/// gets a new one.
+fn request_feed(
+    feed_config: &FeedConfig,
+    url: &str,
+    stored_feed: Option<&database::Feed>,
+    database: &Mutex<database::Database>,
+) -> Result<(attohttpc::Response, String)> {
+    let Some(auth) = &feed_config.auth else {
+        return send_feed_request(url, stored_feed, None);
+    };
+    let token = access_token(auth, database)?;
+    let (resp, final_url) = send_feed_request(url, stored_feed, Some(&token))?;
+    if resp.status() == attohttpc::StatusCode::UNAUTHORIZED {
+        database
+            .lock()
+            .expect("thread panicked while holding database mutex")
+            .set_access_token(&auth.token_url, &auth.client_id()?, auth.scope(), None)?;
+    }
+    Ok((resp, final_url))
+}
+
+/// Send a request for a feed, with the validators of the stored feed and an access token,
+/// following redirects. The access token isn't sent to other origins that the feed redirects to.
+/// Returns the response with the URL it came from.
+fn send_feed_request(
+    url: &str,
+    stored_feed: Option<&database::Feed>,
+    access_token: Option<&str>,
+) -> Result<(attohttpc::Response, String)> {
+    let mut url = url::Url::parse(url)?;
+    let origin = url.origin();
+    let mut redirects = 0;
+    loop {
+        let mut builder = attohttpc::get(url.as_str())
+            .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+            .timeout(FETCH_TIMEOUT)
+            .follow_redirects(false);
+        if let Some(access_token) = access_token.filter(|_| url.origin() == origin) {
+            builder = builder.bearer_auth(access_token);
+        }
+        if let Some(stored_feed) = stored_feed {
+            if let Some(etag) = stored_feed.etag.clone() {
+                builder = builder.header(attohttpc::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = stored_feed.last_modified.clone() {
+                builder = builder.header(attohttpc::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let resp = builder.send()?;
+        let location = resp
+            .headers()
+            .get(attohttpc::header::LOCATION)
+            .and_then(|location| location.to_str().ok());
+        match location {
+            Some(location) if resp.status().is_redirection() && redirects < MAX_REDIRECTS => {
+                url = url.join(location)?;
+                redirects += 1;
+            }
+            _ => return Ok((resp, url.to_string())),
+        }
+    }
+}
+
+/// Log when a feed gives a canonical URL that differs from the configured URL, or that another
+/// feed gives too, since the same feed may be subscribed to under several URLs.
+fn check_self_url(
+    feed_url: &str,
+    stored_feed: Option<&database::Feed>,
+    self_url: &str,
+    database: &Mutex<database::Database>,
+) -> Result<()> {
+    let is_known = stored_feed.is_some_and(|feed| feed.self_url.as_deref() == Some(self_url));
+    if is_known {
+        return Ok(());
+    }
+    if self_url != feed_url {
+        eprintln!("Feed {feed_url} gives its canonical URL as {self_url}");
+    }
+    let duplicates = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_feeds_with_self_url(feed_url, self_url)?;
+    for duplicate in duplicates {
+        eprintln!("Feed {feed_url} is the same feed as {duplicate}");
+    }
+    Ok(())
+}
+
+/// Log and record a change of a feed's title or link since it was last fetched, which may mean
+/// someone else took it over, so the change can be noted in the next digest.
+fn record_feed_change(
+    stored_feed: &database::Feed,
+    feed: &feed::Feed,
+    database: &Mutex<database::Database>,
+) -> Result<()> {
+    let (title, link) = (feed.title(), feed.link());
+    if stored_feed.title == title && stored_feed.link == link {
+        return Ok(());
+    }
+    if stored_feed.title != title {
+        eprintln!(
+            "Feed {} renamed from {:?} to {title:?}",
+            stored_feed.url, stored_feed.title
+        );
+    }
+    if stored_feed.link != link {
+        eprintln!(
+            "Feed {} changed its link from {} to {link}",
+            stored_feed.url, stored_feed.link
+        );
+    }
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .insert_feed_change(&database::FeedChange {
+            feed_url: stored_feed.url.clone(),
+            changed_at: chrono::Utc::now(),
+            old_title: stored_feed.title.clone(),
+            new_title: title,
+            old_link: stored_feed.link.clone(),
+            new_link: link,
+        })?;
+    Ok(())
+}
+
+/// Pipe items through the feed's transform command, if it has one, and then the Lua script's
+/// `transform` function.
+fn transform_items(
+    feed_config: &FeedConfig,
+    script: Option<&lua::Script>,
+    items: Vec<feed::Item>,
+) -> Result<Vec<feed::Item>> {
+    let items = match &feed_config.transform {
+        Some(command) => transform::apply(command, items).map_err(Error::Transform)?,
+        None => items,
+    };
+    match script {
+        Some(script) => {
+            let mut transformed = Vec::new();
+            for item in items {
+                transformed.extend(script.transform(&feed_config.url, item)?);
+            }
+            Ok(transformed)
+        }
+        None => Ok(items),
+    }
+}
+
+/// Remove items that are dropped by the rules or the Lua script's `filter` function.
+fn filter_items(
+    config: &Config,
+    script: Option<&lua::Script>,
+    feed_url: &str,
+    items: Vec<feed::Item>,
+) -> Result<Vec<feed::Item>> {
+    let mut kept = Vec::new();
+    for item in items {
+        if rules::evaluate(&config.filters.rules, feed_url, &item).is_dropped {
+            continue;
+        }
+        if let Some(script) = script {
+            if !script.filter(feed_url, &item)? {
+                continue;
+            }
+        }
+        kept.push(item);
+    }
+    Ok(kept)
+}
+
+/// Store the items of a feed, applying rules and per-feed options, and return how many of them
+/// are new.
+fn store_items(
+    config: &Config,
+    feed_config: &FeedConfig,
+    script: Option<&lua::Script>,
+    items: Vec<feed::Item>,
+    source: &database::Source,
+    read_on_add: &HashSet<String>,
+    database: &Mutex<database::Database>,
+) -> Result<usize> {
+    let feed_url = feed_config.url.as_str();
+    let mut stored_guids: HashSet<String> = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(feed_url)?
+        .into_iter()
+        .map(|item| item.guid)
+        .collect();
+    let is_bulk = is_bulk_fetch(config, &stored_guids, &items);
+    let mut new_items = 0;
+    for item in items {
+        let mut outcome = rules::evaluate(&config.filters.rules, feed_url, &item);
+        if outcome.is_dropped {
+            continue;
+        }
+        if let Some(script) = script {
+            if !script.filter(feed_url, &item)? {
+                continue;
+            }
+        }
+        if stored_guids.insert(item.guid.clone()) {
+            new_items += 1;
+        }
+        outcome.is_read |= read_on_add.contains(&item.guid);
+        outcome.tags.extend(feed_config.tags.iter().cloned());
+        let link = if config.filters.resolve_links && !item.link.is_empty() {
+            resolve_link(item.link.clone(), database)?
+        } else {
+            item.link.clone()
+        };
+        let translated_title = match &config.translation {
+            Some(translation) if feed_config.translate => {
+                translate_title(translation, &item.title, database)?
+            }
+            _ => None,
+        };
+        let content = stored_content(config, feed_config, &item, &link, database)?;
+        let is_saved = outcome.read_later
+            && !outcome.is_read
+            && save_new_item_for_later(config, feed_url, &item.guid, &link, &item.title, database)?;
+        outcome.is_read |= is_saved;
+        let stored = database::DigestItem {
+            feed_url: feed_url.to_string(),
+            guid: item.guid.clone(),
+            link: link.clone(),
+        };
+        let mut database = database
+            .lock()
+            .expect("thread panicked while hold database mutex");
+        let is_updated =
+            database.update_content_hash(feed_url, &item.guid, item.content_hash.as_deref())?
+                && config.updated_items;
+        if is_updated {
+            eprintln!("{feed_url}: \"{}\" changed since it was mailed", item.title);
+        }
+        if let Some(content) = &content {
+            database.set_item_content(feed_url, &item.guid, content)?;
+        }
+        database.insert_update_item(
+            &database::Item {
+                feed_url: feed_url.to_string(),
+                guid: item.guid,
+                title: item.title,
+                link,
+                comments_link: item.comments_link,
+                pub_date: item.pub_date,
+                is_read: outcome.is_read,
+                is_highlighted: outcome.is_highlighted,
+                recipient: outcome.recipient.map(|recipient| recipient.to_string()),
+                tags: outcome.tags,
+                is_dead: false,
+                translated_title,
+                is_bulk,
+                source: Some(source.clone()),
+                parts: Vec::new(),
+                qr_code: None,
+                number: None,
+                content: Vec::new(),
+                excerpt: Vec::new(),
+                is_truncated: false,
+                images: Vec::new(),
+                summary: None,
+            },
+            item.content_hash.as_deref(),
+        )?;
+        if is_saved {
+            database.mark_items_saved(std::slice::from_ref(&stored))?;
+        }
+        if is_updated {
+            database.mark_items_updated(std::slice::from_ref(&stored))?;
+        }
+    }
+    Ok(new_items)
+}
+
+/// Whether a fetch found more new items than the bulk threshold.
+fn is_bulk_fetch(config: &Config, stored_guids: &HashSet<String>, items: &[feed::Item]) -> bool {
+    let Some(threshold) = config.filters.bulk_threshold else {
+        return false;
+    };
+    let new_items = items
+        .iter()
+        .filter(|item| !stored_guids.contains(&item.guid))
+        .count();
+    new_items > threshold
+}
+
+/// Save an item to the read-it-later service if it hasn't been stored yet, returning whether it
+/// was saved.
+///
+/// Items that fail to save are mailed instead.
+fn save_new_item_for_later(
+    config: &Config,
+    feed_url: &str,
+    guid: &str,
+    link: &str,
+    title: &str,
+    database: &Mutex<database::Database>,
+) -> Result<bool> {
+    let is_stored = !database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items_by_guid(guid, Some(feed_url))?
+        .is_empty();
+    if is_stored || link.is_empty() {
+        return Ok(false);
+    }
+    let result = config
+        .read_later
+        .as_ref()
+        .ok_or(Error::ReadLaterNotConfigured)
+        .and_then(|read_later| later::save(read_later, link, title).map_err(Error::from));
+    match result {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            eprintln!("Failed to save {link} for later: {e}");
+            Ok(false)
+        }
+    }
+}
+
+/// Resolve an item link to the URL it redirects to, reusing the result from an earlier fetch.
+///
+/// Keeps the link if it can't be resolved, so it's tried again on the next fetch.
+fn resolve_link(link: String, database: &Mutex<database::Database>) -> Result<String> {
+    let resolved = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_resolved_link(&link)?;
+    if let Some(resolved) = resolved {
+        return Ok(resolved);
+    }
+    match resolve::resolve(&link) {
+        Ok(resolved) => {
+            database
+                .lock()
+                .expect("thread panicked while holding database mutex")
+                .insert_resolved_link(&link, &resolved, chrono::Utc::now())?;
+            Ok(resolved)
+        }
+        Err(e) => {
+            eprintln!("Failed to resolve {link}: {e}");
+            Ok(link)
+        }
+    }
+}
+
+/// Content of an item to keep for digests, which is the article it links to for feeds with
+/// `full_content`, summarized if it's long.
+fn stored_content(
+    config: &Config,
+    feed_config: &FeedConfig,
+    item: &feed::Item,
+    link: &str,
+    database: &Mutex<database::Database>,
+) -> Result<Option<String>> {
+    // Only feeds that show content keep it, which would take a lot of space for every feed.
+    if !config.shows_content(feed_config) {
+        return Ok(None);
+    }
+    let article = if feed_config.full_content && !link.is_empty() {
+        article_content(feed_config, link, database)?
+    } else {
+        None
+    };
+    let content = article.or_else(|| item.content.clone());
+    if let (Some(summarizer), Some(content)) = (&config.summarizer, &content) {
+        let feed_url = feed_config.url.as_str();
+        summarize_item(
+            summarizer,
+            feed_url,
+            &item.guid,
+            &item.title,
+            link,
+            content,
+            database,
+        )?;
+    }
+    Ok(content)
+}
+
+/// Article that an item links to, as HTML, from the page downloaded by an earlier fetch if it was
+/// downloaded recently, and else downloaded again if it changed.
+///
+/// If the article fails to download, the page from an earlier fetch is used, and without one the
+/// item's own content is shown until the next fetch downloads it.
+fn article_content(
+    feed_config: &FeedConfig,
+    link: &str,
+    database: &Mutex<database::Database>,
+) -> Result<Option<String>> {
+    let cleanup = article::Cleanup {
+        remove: &feed_config.remove_selectors,
+        min_paragraph_chars: feed_config.min_paragraph_chars.map_or(0, NonZeroUsize::get),
+        keep_images: feed_config.keep_images,
+    };
+    let extract = |article: &database::Article| article::extract(&article.body, link, &cleanup);
+    let cached = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_article(link)?;
+    let now = chrono::Utc::now();
+    let is_recent = |cached: &database::Article| {
+        (now - cached.fetched_at)
+            .to_std()
+            .is_ok_and(|age| age < ARTICLE_MAX_AGE)
+    };
+    if let Some(cached) = cached.as_ref().filter(|cached| is_recent(cached)) {
+        return Ok(Some(extract(cached)));
+    }
+    let etag = cached.as_ref().and_then(|cached| cached.etag.as_deref());
+    let last_modified = cached
+        .as_ref()
+        .and_then(|cached| cached.last_modified.as_deref());
+    let article = match (article::fetch(link, etag, last_modified), cached) {
+        (Ok(Some(page)), _) => database::Article {
+            body: page.body,
+            etag: page.etag,
+            last_modified: page.last_modified,
+            fetched_at: now,
+        },
+        (Ok(None), Some(cached)) => database::Article {
+            fetched_at: now,
+            ..cached
+        },
+        (Ok(None), None) => return Ok(None),
+        (Err(e), cached) => {
+            eprintln!("Failed to download article {link}: {e}");
+            return Ok(cached.as_ref().map(extract));
+        }
+    };
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .insert_article(link, &article, MAX_ARTICLE_CACHE_BYTES)?;
+    Ok(Some(extract(&article)))
+}
+
+/// Summarize the text of an item's content if it's long, unless the same text was summarized by
+/// an earlier fetch.
+///
+/// Items that fail to be summarized are shown without a summary, and summarized again on the next
+/// fetch.
+fn summarize_item(
+    summarizer: &summarize::Config,
+    feed_url: &str,
+    guid: &str,
+    title: &str,
+    link: &str,
+    content: &str,
+    database: &Mutex<database::Database>,
+) -> Result<()> {
+    let text = excerpt::paragraphs(content).join("\n\n");
+    if text.chars().count() < summarizer.min_chars {
+        return Ok(());
+    }
+    let text_hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+    let stored_hash = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_summary_text_hash(feed_url, guid)?;
+    if stored_hash.as_deref() == Some(text_hash.as_str()) {
+        return Ok(());
+    }
+    match summarize::summarize(summarizer, title, link, &text) {
+        Ok(summary) => database
+            .lock()
+            .expect("thread panicked while holding database mutex")
+            .set_item_summary(feed_url, guid, &text_hash, &summary)?,
+        Err(e) => eprintln!("Failed to summarize {title:?}: {e}"),
+    }
+    Ok(())
+}
+
+/// Translate an item title, reusing the translation from an earlier fetch.
+///
+/// Titles that fail to translate, or that are already in the target language, have no
+/// translation.
+fn translate_title(
+    translation: &translate::Config,
+    title: &str,
+    database: &Mutex<database::Database>,
+) -> Result<Option<String>> {
+    let target_language = translation.target_language.as_str();
+    let stored = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_translation(title, target_language)?;
+    let translated = match stored {
+        Some(translated) => translated,
+        None => match translate::translate(translation, title) {
+            Ok(translated) => {
+                database
+                    .lock()
+                    .expect("thread panicked while holding database mutex")
+                    .insert_translation(title, target_language, &translated, chrono::Utc::now())?;
+                translated
+            }
+            Err(e) => {
+                eprintln!("Failed to translate {title:?}: {e}");
+                return Ok(None);
+            }
+        },
+    };
+    Ok(Some(translated).filter(|translated| translated != title))
+}
+
+/// Return the charset of a response, defaulting to Windows-1252 like attohttpc does.
+fn response_charset(headers: &attohttpc::header::HeaderMap) -> attohttpc::Charset {
+    headers
+        .get(attohttpc::header::CONTENT_TYPE)
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|content_type| {
+            content_type.split(';').skip(1).find_map(|parameter| {
+                let (name, value) = parameter.split_once('=')?;
+                if name.trim().eq_ignore_ascii_case("charset") {
+                    encoding_rs::Encoding::for_label(value.trim().trim_matches('"').as_bytes())
+                } else {
+                    None
+                }
+            })
+        })
+        .unwrap_or(attohttpc::charsets::WINDOWS_1252)
+}
+
+/// Print how much each feed has downloaded, to find feeds that ignore conditional requests, and
+/// recipients whose digests are bouncing.
+fn print_stats(database: &mut database::Database) -> Result<()> {
+    println!(
+        "{:>10}  {:>10}  {:>8}  {:>12}  {:<25}  FEED",
+        "TOTAL", "PER FETCH", "FETCHES", "NOT MODIFIED", "LAST FETCHED"
+    );
+    for stats in database.get_fetch_stats()? {
+        println!(
+            "{:>10}  {:>10}  {:>8}  {:>12}  {:<25}  {}",
+            format_bytes(stats.bytes),
+            format_bytes(stats.bytes / i64::from(stats.fetches.max(1))),
+            stats.fetches,
+            stats.not_modified,
+            stats
+                .last_fetched_at
+                .with_timezone(&chrono::Local)
+                .format("%F %T"),
+            stats.feed_url
+        );
+    }
+    let failures = database.get_delivery_failures()?;
+    if !failures.is_empty() {
+        println!("\nDelivery failures:");
+    }
+    for failure in failures {
+        println!(
+            "  {}: last {} digest(s) bounced, most recently sent at {}: {}",
+            failure.recipient,
+            failure.bounced,
+            failure
+                .last
+                .sent_at
+                .with_timezone(&chrono::Local)
+                .format("%F %T"),
+            bounces::describe(&failure.last)
+        );
+    }
+    Ok(())
+}
+
+/// Hide unread items with the GUID from digests for the duration.
+fn snooze_item(
+    config: &Config,
+    database: &mut database::Database,
+    guid: &str,
+    feed_url: Option<&str>,
+    duration: chrono::Duration,
+) -> Result<()> {
+    // Snoozes that end after the last date that can be represented don't end.
+    let until = config
+        .clock
+        .now()
+        .checked_add_signed(duration)
+        .unwrap_or_else(|| chrono::MAX_DATE.pred().and_hms(0, 0, 0));
+    match database.snooze_item(guid, feed_url, until)? {
+        0 => Err(Error::NoUnreadItem(guid.to_string())),
+        count => {
+            eprintln!(
+                "Snoozed {count} item(s) until {}",
+                until.with_timezone(&chrono::Local).format("%c")
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Print highlighted items as bookmarks, in a folder for each feed.
+fn export_bookmarks(database: &mut database::Database, format: bookmarks::Format) -> Result<()> {
+    let mut folders: Vec<bookmarks::Folder> = Vec::new();
+    // Bookmarks need a link.
+    for item in database
+        .get_highlighted_items()?
+        .into_iter()
+        .filter(|item| !item.link.is_empty())
+    {
+        match folders.last_mut() {
+            Some(folder) if folder.items[0].feed_url == item.feed_url => folder.items.push(item),
+            _ => {
+                let title = database
+                    .get_feed_by_url(&item.feed_url)?
+                    .map_or_else(|| item.feed_url.clone(), |feed| feed.title);
+                folders.push(bookmarks::Folder {
+                    title,
+                    items: vec![item],
+                });
+            }
+        }
+    }
+    print!("{}", bookmarks::export(format, &folders));
+    Ok(())
+}
+
+/// Save items with the GUID to the read-it-later service, and mark them read.
+fn save_item_for_later(
+    config: &Config,
+    database: &mut database::Database,
+    guid: &str,
+    feed_url: Option<&str>,
+) -> Result<()> {
+    let read_later = config
+        .read_later
+        .as_ref()
+        .ok_or(Error::ReadLaterNotConfigured)?;
+    let items = database.get_items_by_guid(guid, feed_url)?;
+    if items.is_empty() {
+        return Err(Error::NoItem(guid.to_string()));
+    }
+    let mut saved = Vec::new();
+    for item in items {
+        if item.link.is_empty() {
+            return Err(Error::NoLink(item.guid));
+        }
+        later::save(read_later, &item.link, &item.title)?;
+        eprintln!("Saved {} for later", item.link);
+        saved.push(database::DigestItem {
+            feed_url: item.feed_url,
+            guid: item.guid,
+            link: item.link,
+        });
+    }
+    database.mark_digest_items_read(&saved)?;
+    database.mark_items_saved(&saved)?;
+    Ok(())
+}
+
+/// Check the links of items published within the given number of days that haven't been
+/// mailed, so digests can flag links that are gone.
+fn check_links(config: &Config, database: &mut database::Database, days: u32) -> Result<()> {
+    let robots = if config.sources.respect_robots_txt {
+        Some(robots::Cache::default())
+    } else {
+        None
+    };
+    let since = chrono::Utc::now() - chrono::Duration::days(i64::from(days));
+    let mut dead = 0;
+    for link in database.get_unread_links(since)? {
+        let status = match check_link(&link, robots.as_ref()) {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("Failed to check {link}: {e}");
+                continue;
+            }
+        };
+        if status == 404 || status == 410 {
+            println!("{status} {link}");
+            dead += 1;
+        }
+        database.insert_link_check(&link, chrono::Utc::now(), status)?;
+    }
+    eprintln!("Found {dead} dead links");
+    Ok(())
+}
+
+/// Send a HEAD request for a link, returning the status it responds with.
+fn check_link(link: &str, robots: Option<&robots::Cache>) -> Result<u16> {
+    check_robots(link, robots)?;
+    let resp = attohttpc::head(link)
+        .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+        .timeout(Duration::from_secs(30))
+        .send()?;
+    Ok(resp.status().as_u16())
+}
+
+/// Check robots.txt if enabled, waiting for its crawl delay.
+fn check_robots(feed_url: &str, robots: Option<&robots::Cache>) -> Result<()> {
+    if let Some(robots) = robots {
+        let url = url::Url::parse(feed_url)?;
+        if !robots.wait_until_allowed(&url) {
+            return Err(Error::RobotsDisallowed);
+        }
+    }
+    Ok(())
+}
+
+/// Parse a feed, and if that fails and `lenient_xml` is set, parse it again with malformed XML
+/// repaired.
+fn parse_feed(
+    feed_url: &str,
+    body: &[u8],
+    charset: attohttpc::Charset,
+    lenient_xml: bool,
+) -> Result<feed::Feed> {
+    match feed::Feed::read_from(attohttpc::TextReader::new(body, charset)) {
+        Err(feed::Error::Parse) if lenient_xml => {
+            let mut text = String::new();
+            attohttpc::TextReader::new(body, charset)
+                .read_to_string(&mut text)
+                .map_err(feed::Error::Io)?;
+            let repaired = lenient::repair(&text).ok_or(feed::Error::Parse)?;
+            let feed = feed::Feed::read_from(repaired.as_bytes())?;
+            eprintln!("Repaired malformed XML of {feed_url}");
+            Ok(feed)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Download and parse a feed, without conditional requests or recording the fetch. An access
+/// token is requested for feeds that need one, without caching it.
+fn download_feed(
+    feed_url: &str,
+    feed_config: &FeedConfig,
+    robots: Option<&robots::Cache>,
+) -> Result<feed::Feed> {
+    check_robots(feed_url, robots)?;
+    let mut builder = attohttpc::get(feed_url)
+        .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+        .timeout(Duration::from_secs(30));
+    if let Some(auth) = &feed_config.auth {
+        let (token, _) = oauth::request_token(auth, &auth.client_id()?, chrono::Utc::now())?;
+        builder = builder.bearer_auth(token);
+    }
+    let resp = builder.send()?;
+    let status = resp.status();
+    let body = read_feed_body(resp, feed_url, feed_config.lenient_xml, None)?;
+    if let Some(provider) = body.challenge {
+        return Err(Error::Challenge(provider.to_string()));
+    } else if !status.is_success() {
+        return Err(Error::UnexpectedStatusCode(status.as_u16()));
+    }
+    body.feed
+        .expect("successful responses that aren't challenge pages are parsed")
+}
+
+/// Print which rules match each stored item of a feed, and what would be done with the item.
+fn print_rule_test(
+    config: &Config,
+    database: &mut database::Database,
+    feed_url: &str,
+) -> Result<()> {
+    for (index, rule) in config.filters.rules.iter().enumerate() {
+        if rule.applies_to_feed(feed_url) && rule.needs_unstored_fields() {
+            eprintln!(
+                "warning: rule {} can't match stored items, since their authors and categories \
+                 aren't stored",
+                index + 1
+            );
+        }
+    }
+    let items = database.get_items(feed_url)?;
+    if items.is_empty() {
+        println!("No items of {feed_url} are stored");
+    }
+    for item in items {
+        let item = feed::Item {
+            guid: item.guid,
+            title: item.title,
+            link: item.link,
+            comments_link: item.comments_link,
+            pub_date: item.pub_date,
+            author: None,
+            categories: Vec::new(),
+            content_hash: None,
+            content: None,
+        };
+        println!("{}", item.title);
+        let applied = rules::applied(&config.filters.rules, feed_url, &item);
+        if applied.is_empty() {
+            println!("  no rules match");
+        }
+        for (index, rule) in applied {
+            println!("  rule {}: {}", index + 1, rule.action);
+        }
+        println!(
+            "  => {}",
+            rules::evaluate(&config.filters.rules, feed_url, &item)
+        );
+    }
+    Ok(())
+}
+
+/// Print new, updated, and disappeared items of each feed compared with the database.
+fn print_diff(config: &Config, database: &mut database::Database) -> Result<()> {
+    let robots = if config.sources.respect_robots_txt {
+        Some(robots::Cache::default())
+    } else {
+        None
+    };
+    let script = lua::Script::load(config.lua.as_ref())?;
+    for feed_config in &config.sources.feeds {
+        println!("{}", feed_config.url);
+        let feed = match query::url(&feed_config.url, &feed_config.query, chrono::Utc::now())
+            .map_err(Error::from)
+            .and_then(|url| download_feed(&url, feed_config, robots.as_ref()))
+        {
+            Ok(feed) => feed,
+            Err(e) => {
+                println!("  failed to fetch feed: {e}");
+                continue;
+            }
+        };
+        // Dropped items are never stored, so they would always look new.
+        let items = match transform_items(feed_config, script.as_ref(), feed.items().collect())
+            .map(|mut items| {
+                feed_config.identity.apply(&mut items);
+                items
+            })
+            .and_then(|items| filter_items(config, script.as_ref(), &feed_config.url, items))
+        {
+            Ok(items) => items,
+            Err(e) => {
+                println!("  {e}");
+                continue;
+            }
+        };
+        let stored = database.get_items(&feed_config.url)?;
+        let diff = diff::FeedDiff::new(&stored, &items);
+        if diff.is_empty() {
+            println!("  no changes");
+        }
+        for item in &diff.new {
+            println!("  + {}", item.title);
+        }
+        for (item, fields) in &diff.updated {
+            println!("  ~ {} ({} changed)", item.title, fields.join(", "));
+        }
+        for item in &diff.disappeared {
+            println!("  - {}", item.title);
+        }
+    }
+    Ok(())
+}
+
+/// Print which items of the digests that would be sent are new since the last digest sent to
+/// each recipient, which were carried over, and which were dropped.
+fn print_digest_diff(
+    config: &Config,
+    database: &mut database::Database,
+    tag: Option<&str>,
+) -> Result<()> {
+    for (recipient, feeds) in group_digests(config, database, tag)? {
+        let recipient = recipient.unwrap_or_else(|| config.delivery.to_email.to_string());
+        let last = database.get_last_digest(&recipient)?;
+        match &last {
+            Some(last) => println!(
+                "{recipient} (compared with the digest sent {})",
+                last.sent_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+            ),
+            None => println!("{recipient} (no digest sent yet)"),
+        }
+        let feed_titles: HashMap<&str, &str> = feeds
+            .iter()
+            .map(|feed| (feed.feed.url.as_str(), feed.feed.title.as_str()))
+            .collect();
+        let items: Vec<&database::Item> = feeds
+            .iter()
+            .flat_map(|feed| &feed.items)
+            .flat_map(|item| std::iter::once(item).chain(&item.parts))
+            .collect();
+        let last_items = last.map(|last| last.items).unwrap_or_default();
+        let diff = diff::DigestDiff::new(&items, &last_items);
+        for (marker, items) in [("+", &diff.new), ("=", &diff.carried_over)] {
+            for item in items {
+                let feed_title = feed_titles.get(item.feed_url.as_str()).unwrap_or(&"");
+                println!("  {marker} [{feed_title}] {}", item.title);
+            }
+        }
+        for (feed_url, guid) in &diff.dropped {
+            let title = database
+                .get_items_by_guid(guid, Some(feed_url))?
+                .into_iter()
+                .next()
+                .map_or_else(|| guid.clone(), |item| item.title);
+            println!("  - [{feed_url}] {title}");
+        }
+        println!(
+            "  {} new, {} carried over, {} dropped",
+            diff.new.len(),
+            diff.carried_over.len(),
+            diff.dropped.len()
+        );
+    }
+    Ok(())
+}
+
+/// Print how each feed's server handles conditional requests.
+fn print_audit(config: &Config, database: &mut database::Database) -> Result<()> {
+    for feed in &config.sources.feeds {
+        let report = audit::Report::new(&database.get_fetches(&feed.url)?);
+        let validators: Vec<&str> = [
+            (report.sends_etag, "ETag"),
+            (report.sends_last_modified, "Last-Modified"),
+        ]
+        .iter()
+        .filter(|(sends, _)| *sends)
+        .map(|(_, name)| *name)
+        .collect();
+        println!("{}\n  {}", feed.url, report.verdict());
+        println!(
+            "  {} fetches, {} conditional, {} not modified; validators: {}",
+            report.fetches,
+            report.conditional_fetches,
+            report.not_modified,
+            if validators.is_empty() {
+                "none".to_string()
+            } else {
+                validators.join(", ")
+            }
+        );
+        if let Some(stored_feed) = database.get_feed_by_url(&feed.url)? {
+            if let Some(self_url) = stored_feed.self_url.filter(|url| *url != feed.url) {
+                println!("  canonical URL: {self_url}");
+            }
+            if let Some(hub) = stored_feed.hub {
+                println!("  hub: {hub}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print config keys that aren't in this version, and groups of configured feeds that are the
+/// same feed, by the self links they gave when they were fetched and the URLs they redirect to.
+fn check_config(
+    config: &Config,
+    unknown_keys: &[String],
+    database: &mut database::Database,
+) -> Result<()> {
+    if unknown_keys.is_empty() {
+        eprintln!("No unknown config keys");
+    } else {
+        println!("Unknown config keys:");
+        for key in unknown_keys {
+            println!("  {key}");
+        }
+    }
+    let mut feeds = Vec::new();
+    for feed in &config.sources.feeds {
+        let mut canonical_urls = Vec::new();
+        if let Some(self_url) = database
+            .get_feed_by_url(&feed.url)?
+            .and_then(|stored_feed| stored_feed.self_url)
+        {
+            canonical_urls.push(self_url);
+        }
+        match resolve::resolve(&feed.url) {
+            Ok(target) => canonical_urls.push(target),
+            Err(e) => eprintln!("Failed to follow redirects from {}: {e}", feed.url),
+        }
+        feeds.push((feed.url.as_str(), canonical_urls));
+    }
+    let groups = duplicates::group(&feeds);
+    if groups.is_empty() {
+        eprintln!("No feeds are subscribed to more than once");
+    }
+    for group in &groups {
+        println!("Same feed:");
+        for url in group {
+            println!("  {url}");
+        }
+    }
+    if !groups.is_empty() {
+        Err(Error::DuplicateFeeds)
+    } else if !unknown_keys.is_empty() {
+        Err(Error::UnknownConfigKeys(unknown_keys.to_vec()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Print the size of a digest, and warn if it's too large for common mail providers.
+fn report_size(recipient: &str, items: usize, message: &str) {
+    let bytes = message.len();
+    let images = message.to_lowercase().matches("<img").count();
+    eprintln!(
+        "Digest to {recipient}: {}, {items} items, {images} images",
+        format_bytes(i64::try_from(bytes).unwrap_or(i64::MAX))
+    );
+    if bytes > MESSAGE_SIZE_LIMIT_BYTES {
+        eprintln!("Warning: digest is larger than many mail servers accept (about 10 MB)");
+    } else if bytes > GMAIL_CLIP_BYTES {
+        eprintln!("Warning: digest is larger than 102 KB, so Gmail will clip it");
+    }
+}
+
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024 * 10 && unit < UNITS.len() - 1 {
+        size /= 1024;
+        unit += 1;
+    }
+    format!("{} {}", size, UNITS[unit])
+}
+
+/// Render a digest for the configured recipient and for each recipient that rules route items
+/// to.
+///
+/// If tag is set, only items with the tag are included. Otherwise, items with exclusive tags are
+/// left out. Items with a link that was included in a recently sent digest are skipped, as are
+/// items that were already delivered to their recipient.
+///
+/// Digests are rendered in parallel, sharing the compiled templates.
+fn render_mails(
+    config: &Config,
+    database: &mut database::Database,
+    tag: Option<&str>,
+) -> Result<Vec<Digest>> {
+    let subject = digest_subject(config, tag);
+    let mut digests = Vec::new();
+    for (recipient, feeds) in group_digests(config, database, tag)? {
+        let sections = digest_sections(config, database, recipient.as_deref())?;
+        digests.push((recipient, feeds, sections));
+    }
+    let tera = load_templates(config)?;
+    let num_threads = min(
+        thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+        digests.len(),
+    );
+    // Each digest is queued with its index, to keep the order of recipients.
+    let queue = Mutex::new(digests.into_iter().enumerate().collect::<Vec<_>>());
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| {
+                // Clippy fails to account for lifetime of MutexGuard
+                #[allow(clippy::while_let_loop)]
+                loop {
+                    let Some((index, (recipient, feeds, sections))) = queue
+                        .lock()
+                        .expect("thread panicked while holding queue mutex")
+                        .pop()
+                    else {
+                        break;
+                    };
+                    let result = render_recipient_mails(
+                        config, &tera, &subject, recipient, feeds, &sections,
+                    );
+                    results
+                        .lock()
+                        .expect("thread panicked while holding results mutex")
+                        .push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results
+        .into_inner()
+        .expect("thread panicked while holding results mutex");
+    results.sort_by_key(|(index, _)| *index);
+    let mut mails = Vec::new();
+    for (_, result) in results {
+        mails.extend(result?);
+    }
+    Ok(mails)
+}
+
+/// Render the digest of a recipient, and an email for each feed that gets its own, or an email
+/// per item if configured.
+fn render_recipient_mails(
+    config: &Config,
+    tera: &Tera,
+    subject: &str,
+    recipient: Option<String>,
+    feeds: Vec<FeedWithItems>,
+    sections: &Sections,
+) -> Result<Vec<Digest>> {
+    let to_email = recipient.unwrap_or_else(|| config.delivery.to_email.to_string());
+    if config.delivery.email_per_item {
+        return render_item_mails(config, tera, &to_email, feeds);
+    }
+    let (separate, feeds): (Vec<_>, Vec<_>) = feeds.into_iter().partition(|feed| {
+        config.delivery.email_per_feed || is_separate_feed(config, &feed.feed.url)
+    });
+    let mut mails = Vec::new();
+    // Without a digest, the sections go in the first feed's email.
+    let mut digest_sections = Some(sections);
+    if !config.delivery.email_per_feed {
+        mails.push(render_mail(
+            config,
+            tera,
+            subject,
+            to_email.clone(),
+            feeds,
+            None,
+            sections,
+        )?);
+        digest_sections = None;
+    }
+    let no_sections = Sections::default();
+    // Feeds with nothing new get no email, rather than an empty one.
+    for feed in separate.into_iter().filter(|feed| {
+        !feed.items.is_empty() || !feed.skipped.is_empty() || !feed.changes.is_empty()
+    }) {
+        let subject = format!("[{}] {subject}", feed.feed.title);
+        mails.push(render_mail(
+            config,
+            tera,
+            &subject,
+            to_email.clone(),
+            vec![feed],
+            None,
+            digest_sections.take().unwrap_or(&no_sections),
+        )?);
+    }
+    // Archives and updated items are still mailed when no feed has anything new.
+    if let Some(sections) = digest_sections
+        .filter(|sections| !sections.archives.is_empty() || !sections.updated.is_empty())
+    {
+        mails.push(render_mail(
+            config,
+            tera,
+            subject,
+            to_email,
+            Vec::new(),
+            None,
+            sections,
+        )?);
+    }
+    Ok(mails)
+}
+
+/// Sections of a recipient's digest besides its feeds, as enabled.
+fn digest_sections(
+    config: &Config,
+    database: &mut database::Database,
+    recipient: Option<&str>,
+) -> Result<Sections> {
+    let archives = match &config.archives {
+        Some(archives) => archive::load(config, archives, database, recipient, config.clock.now())?,
+        None => Vec::new(),
+    };
+    let updated = if config.updated_items {
+        updates::load(database, recipient)?
+    } else {
+        Vec::new()
+    };
+    Ok(Sections {
+        footer: digest_footer(config, database, recipient)?,
+        archives,
+        updated,
+    })
+}
+
+/// Footer of a recipient's digest, if footers are enabled.
+fn digest_footer(
+    config: &Config,
+    database: &mut database::Database,
+    recipient: Option<&str>,
+) -> Result<Option<footer::Footer>> {
+    if !config.footer {
+        return Ok(None);
+    }
+    let recipient =
+        recipient.map_or_else(|| config.delivery.to_email.to_string(), ToString::to_string);
+    Ok(Some(footer::load(config, database, &recipient)?))
+}
+
+/// Render an email for each item, replying to a message per feed so items thread by feed.
+fn render_item_mails(
+    config: &Config,
+    tera: &Tera,
+    to_email: &str,
+    feeds: Vec<FeedWithItems>,
+) -> Result<Vec<Digest>> {
+    let mut mails = Vec::new();
+    for FeedWithItems {
+        feed,
+        items,
+        mut changes,
+        mute_link,
+        unsubscribe_link,
+        bulk_items: _,
+        fallback_link,
+        number: _,
+        tier,
+        mut skipped,
+        skipped_items: _,
+    } in feeds
+    {
+        let feed_message_id = message_id(config, &[&feed.url]);
+        // Items that came in bulk share one email, instead of flooding the mailbox.
+        let (bulk, items): (Vec<_>, Vec<_>) = items.into_iter().partition(|item| item.is_bulk);
+        let mut emails: Vec<Vec<database::Item>> =
+            items.into_iter().map(|item| vec![item]).collect();
+        if !bulk.is_empty() {
+            emails.push(bulk);
+        }
+        for items in emails {
+            let subject = if items[0].is_bulk {
+                let count = items.len().to_string();
+                let bulk = config.locale.strings().bulk.replace("{count}", &count);
+                format!("[{}] {bulk}", feed.title)
+            } else {
+                format!("[{}] {}", feed.title, items[0].title)
+            };
+            let thread = Thread {
+                message_id: message_id(config, &[&feed.url, &items[0].guid]),
+                parent: feed_message_id.clone(),
+            };
+            let feeds = vec![FeedWithItems {
+                feed: feed.clone(),
+                bulk_items: items.iter().filter(|item| item.is_bulk).count(),
+                items,
+                // Note changes and skipped items with the first item only.
+                changes: std::mem::take(&mut changes),
+                skipped_items: skipped.len(),
+                skipped: std::mem::take(&mut skipped),
+                mute_link: mute_link.clone(),
+                unsubscribe_link: unsubscribe_link.clone(),
+                fallback_link: fallback_link.clone(),
+                number: None,
+                tier,
+            }];
+            mails.push(render_mail(
+                config,
+                tera,
+                &subject,
+                to_email.to_string(),
+                feeds,
+                Some(thread),
+                &Sections::default(),
+            )?);
+        }
+    }
+    Ok(mails)
+}
+
+/// Message-ID derived from the given parts, so it's the same every time it's rendered.
+fn message_id(config: &Config, parts: &[&str]) -> String {
+    let hash = format!("{:x}", Sha256::digest(parts.join("\n").as_bytes()));
+    // Half the hash is plenty, and keeps headers from being folded.
+    format!("<{}@{}>", &hash[..32], config.sender_domain())
+}
+
+/// Replace the random Message-ID lettre gives every email.
+fn set_message_id(mail: SendableEmail, message_id: &str) -> SendableEmail {
+    let random_header = format!("Message-ID: {}", message::message_id(mail.message_id()));
+    let envelope = mail.envelope().clone();
+    let message = mail
+        .message_to_string()
+        .expect("failed to read email")
+        .replacen(&random_header, &format!("Message-ID: {message_id}"), 1);
+    SendableEmail::new(envelope, message_id.to_string(), message.into_bytes())
+}
+
+/// Date an email with a fixed time instead of when it was built, and number its MIME boundaries
+/// instead of drawing them at random, so rendering the same items gives the same email.
+fn make_deterministic(mail: SendableEmail, now: chrono::DateTime<chrono::Utc>) -> SendableEmail {
+    let envelope = mail.envelope().clone();
+    let message_id = mail.message_id().to_string();
+    let message =
+        message::crlf_line_endings(&mail.message_to_string().expect("failed to read email"));
+    let message = message::set_field(&message, "Date", &now.to_rfc2822());
+    let message = message::number_boundaries(&message);
+    SendableEmail::new(envelope, message_id, message.into_bytes())
+}
+
+fn digest_subject(config: &Config, tag: Option<&str>) -> String {
+    config
+        .locale
+        .strings()
+        .digest_subject(tag, &config.clock.local_now())
+}
+
+/// Show the text of an item's content, or an excerpt of it, depending on its feed's tier, cut
+/// short to the feed's budget, under its summary.
+fn show_content(
+    feed_config: &FeedConfig,
+    item: &mut database::Item,
+    content: database::ItemContent,
+) {
+    let database::ItemContent { content, summary } = content;
+    item.summary = summary;
+    let is_primary = feed_config.tier == config::Tier::Primary;
+    let default_chars = if is_primary {
+        usize::MAX
+    } else {
+        excerpt::MAX_EXCERPT_CHARS
+    };
+    let max_chars = feed_config
+        .max_content_chars
+        .map_or(default_chars, NonZeroUsize::get);
+    let max_paragraphs = feed_config
+        .max_paragraphs
+        .map_or(usize::MAX, NonZeroUsize::get);
+    let (paragraphs, is_truncated) = excerpt::excerpt(&content, max_chars, max_paragraphs);
+    item.is_truncated = is_truncated;
+    if is_primary {
+        item.content = paragraphs;
+        if feed_config.keep_images {
+            item.images = article::images(&content);
+        }
+    } else {
+        item.excerpt = paragraphs;
+    }
+}
+
+/// Signed links to mute a feed and to unsubscribe from it, if callbacks are configured.
+fn callback_links(config: &Config, feed_url: &str) -> (Option<String>, Option<String>) {
+    let link = |action| {
+        config
+            .callback
+            .as_ref()
+            .map(|callback| callback::link(callback, action, feed_url))
+    };
+    (
+        link(callback::Action::Mute),
+        link(callback::Action::Unsubscribe),
+    )
+}
+
+/// Group unread items into digests by recipient, where `None` is the configured recipient.
+fn group_digests(
+    config: &Config,
+    database: &mut database::Database,
+    tag: Option<&str>,
+) -> Result<BTreeMap<Option<String>, Vec<FeedWithItems>>> {
+    // The configured recipient always gets a digest listing every feed, unless it's scoped to a
+    // tag.
+    let mut digests: BTreeMap<Option<String>, Vec<FeedWithItems>> = BTreeMap::new();
+    if tag.is_none() {
+        digests.insert(None, Vec::new());
+    }
+    let now = config.clock.now();
+    let sent_links = match config.filters.dedup_days {
+        Some(days) => database.get_sent_links(now - chrono::Duration::days(i64::from(days)))?,
+        None => HashSet::new(),
+    };
+    let script = lua::Script::load(config.lua.as_ref())?;
+    let merger = config.filters.merge_parts.then(parts::Merger::default);
+    let exclusive_tags = config.exclusive_tags();
+    for feed_config in &config.sources.feeds {
+        let feed_url = feed_config.url.as_str();
+        // skips feed that don't exist in database or are muted
+        if let Some(feed) = database.get_feed_by_url(feed_url)? {
+            if feed.is_muted {
+                continue;
+            }
+            let mut items_by_recipient: BTreeMap<Option<String>, Vec<database::Item>> =
+                BTreeMap::new();
+            if tag.is_none() {
+                items_by_recipient.insert(None, Vec::new());
+            }
+            let deliveries = database.get_deliveries(feed_url)?;
+            let mut contents = if config.shows_content(feed_config) {
+                database.get_item_contents(feed_url)?
+            } else {
+                HashMap::new()
+            };
+            for mut item in database.get_unread_items(feed_url, tag, &exclusive_tags, now)? {
+                if sent_links.contains(&item.link) {
+                    continue;
+                }
+                let recipient = item
+                    .recipient
+                    .clone()
+                    .unwrap_or_else(|| config.delivery.to_email.to_string());
+                if deliveries.contains(&(item.guid.clone(), recipient)) {
+                    continue;
+                }
+                if let Some(content) = contents.remove(&item.guid) {
+                    show_content(feed_config, &mut item, content);
+                }
+                let item = match &script {
+                    Some(script) => match script.render(item)? {
+                        Some(item) => item,
+                        None => continue,
+                    },
+                    None => item,
+                };
+                items_by_recipient
+                    .entry(item.recipient.clone())
+                    .or_default()
+                    .push(item);
+            }
+            let changes = if tag.is_none() {
+                database.get_unread_feed_changes(feed_url)?
+            } else {
+                Vec::new()
+            };
+            // Links are signed for the feed rather than the recipient, so only the configured
+            // recipient gets them, rather than whoever items are routed to.
+            let (mute_link, unsubscribe_link) = callback_links(config, feed_url);
+            for (recipient, items) in items_by_recipient {
+                let items = match &merger {
+                    Some(merger) => merger.merge(items),
+                    None => items,
+                };
+                let (items, skipped) = match feed_config.sample {
+                    Some(sample) => sample::split(items, sample.get(), config.clock.is_fixed()),
+                    None => (items, Vec::new()),
+                };
+                digests
+                    .entry(recipient.clone())
+                    .or_default()
+                    .push(FeedWithItems {
+                        feed: feed.clone(),
+                        bulk_items: items.iter().filter(|item| item.is_bulk).count(),
+                        items,
+                        changes: if recipient.is_none() {
+                            changes.clone()
+                        } else {
+                            Vec::new()
+                        },
+                        mute_link: mute_link.clone().filter(|_| recipient.is_none()),
+                        unsubscribe_link: unsubscribe_link.clone().filter(|_| recipient.is_none()),
+                        fallback_link: (config.missing_link == config::MissingLink::FeedLink)
+                            .then(|| feed.link.clone()),
+                        number: None,
+                        tier: feed_config.tier,
+                        skipped_items: skipped.len(),
+                        skipped,
+                    });
+            }
+        }
+    }
+    for feeds in digests.values_mut() {
+        order_feeds(config, feeds);
+    }
+    Ok(digests)
+}
+
+/// Sort feeds of a digest by the configured order, with pinned feeds first.
+fn order_feeds(config: &Config, feeds: &mut [FeedWithItems]) {
+    let is_pinned = |feed: &FeedWithItems| {
+        config
+            .sources
+            .feeds
+            .iter()
+            .any(|feed_config| feed_config.url == feed.feed.url && feed_config.pinned)
+    };
+    // Sorting is stable, so feeds are otherwise left in config order.
+    match config.feed_order {
+        config::FeedOrder::Config => {}
+        config::FeedOrder::Alphabetical => {
+            feeds.sort_by_cached_key(|feed| feed.feed.title.to_lowercase());
+        }
+        config::FeedOrder::UnreadCount => {
+            feeds.sort_by_key(|feed| std::cmp::Reverse(feed.items.len()));
+        }
+    }
+    feeds.sort_by_key(|feed| !is_pinned(feed));
+}
+
+/// Load custom templates from the template directory and the custom mail template, and built-in
+/// templates they don't replace.
+fn load_templates(config: &Config) -> Result<Cow<'static, Tera>> {
+    templates::load(
+        config.template_dir.as_deref(),
+        config.template_path.as_deref(),
+    )
+    .map_err(|e| template_error(config, e))
+}
+
+/// Point template errors at the custom template they're in, if they're in one.
+fn template_error(config: &Config, error: tera::Error) -> Error {
+    let file = templates::error_file(
+        &error,
+        config.template_dir.as_deref(),
+        config.template_path.as_deref(),
+    );
+    match file {
+        Some(file) => Error::CustomTemplate(file, error),
+        None => Error::Template(error),
+    }
+}
+
+fn render_html(
+    config: &Config,
+    tera: &Tera,
+    subject: &str,
+    mut feeds: Vec<FeedWithItems>,
+    sections: &Sections,
+) -> Result<String> {
+    if config.qr_codes {
+        for item in feeds.iter_mut().flat_map(|feed| &mut feed.items) {
+            if !item.link.is_empty() {
+                item.qr_code = qr::data_uri(&item.link);
+            }
+        }
+    }
+    let context = MailContext {
+        subject: subject.to_string(),
+        feeds,
+        accessibility: config.accessibility.clone().unwrap_or_default(),
+        strings: config.locale.strings(),
+        footer: sections.footer.as_ref(),
+        archives: &sections.archives,
+        updated: &sections.updated,
+    };
+    let context = tera::Context::from_serialize(context).expect("failed to build tera context");
+    let html = tera
+        .render(templates::MAIL, &context)
+        .map_err(|e| template_error(config, e))?;
+    Ok(css::Inliner::default().inline(&html))
+}
+
+/// Handle the bounces and replies that arrived in the inbox, if one is configured, logging
+/// failures instead of failing.
+fn poll_inbox(config: &Config, database: &mut database::Database) {
+    if let Some(inbox) = &config.inbox {
+        if let Err(e) = inbox::poll(config, inbox, database) {
+            eprintln!("Failed to check the inbox: {e}");
+        }
+    }
+}
+
+/// Send digests and record them, marking items read only if their digest was sent.
+///
+/// Deliveries of items are recorded before sending, so an item is sent to a recipient at most
+/// once, even if sending is interrupted.
+///
+/// When delivering directly to mail servers, digests go through the outbox instead, so they can
+/// be retried later. Outside the send window, digests are held in the outbox until it starts.
+fn send_mails(
+    config: &Config,
+    database: &mut database::Database,
+    digests: Vec<Digest>,
+    tag: Option<&str>,
+) -> Result<()> {
+    let transport = transport::name(
+        config.delivery.mx.as_ref(),
+        config.delivery.transport.as_ref(),
+    );
+    for digest in &digests {
+        let recipient = digest.mail.envelope().to()[0].to_string();
+        database.start_deliveries(&recipient, transport, chrono::Utc::now(), &digest.items)?;
+    }
+    let rendered = digests
+        .iter()
+        .flat_map(|digest| digest.items.iter().cloned())
+        .collect::<Vec<_>>();
+    let held_until = config
+        .delivery
+        .send_window
+        .as_ref()
+        .and_then(|send_window| send_window.held_until(&chrono::Local::now()));
+    if config.delivery.mx.is_some() || held_until.is_some() {
+        outbox::queue(
+            database,
+            digests,
+            held_until.unwrap_or_else(chrono::Utc::now),
+        )?;
+        mark_read(config, database, tag, &rendered)?;
+        return outbox::deliver(config, database);
+    }
+    if config.delivery.send_window.is_some() {
+        // Mail held from before the send window goes first.
+        outbox::deliver(config, database)?;
+    }
+    let total = digests.len();
+    let mut failures = 0;
+    let mut sent_items = Vec::new();
+    for sent in send::send_all(digests, config)? {
+        match sent.result {
+            Ok(()) => {
+                database.insert_digest(
+                    &sent.recipient,
+                    chrono::Utc::now(),
+                    &sent.message_id,
+                    &sent.items,
+                )?;
+                sent_items.extend(sent.items);
+            }
+            Err(e) => {
+                eprintln!("Failed to send mail to {}: {e}", sent.recipient);
+                database.cancel_deliveries(&sent.recipient, &sent.items)?;
+                failures += 1;
+            }
+        }
+    }
+    if failures == 0 {
+        mark_read(config, database, tag, &rendered)?;
+        Ok(())
+    } else {
+        database.mark_digest_items_read(&sent_items)?;
+        Err(Error::SendFailed(failures, total))
+    }
+}
+
+/// Mark items read after their digests were sent, and feed changes if the regular digest was.
+/// Digests of a tag only mark the items they listed read, so items with the tag in feeds that
+/// weren't mailed, such as muted or removed ones, are left for the feeds' own digests.
+fn mark_read(
+    config: &Config,
+    database: &mut database::Database,
+    tag: Option<&str>,
+    rendered: &[database::DigestItem],
+) -> Result<()> {
+    if tag.is_some() {
+        database.mark_digest_items_read(rendered)?;
+    } else {
+        database.mark_items_read(&config.exclusive_tags(), config.clock.now())?;
+        database.mark_feed_changes_read()?;
+    }
+    Ok(())
+}
+
+/// Whether any of the feeds has event detection enabled.
+fn events_enabled(config: &Config, feeds: &[FeedWithItems]) -> bool {
+    feeds
+        .iter()
+        .any(|feed| is_events_feed(config, &feed.feed.url))
+}
+
+/// Number the feeds that have items, and their items and parts, in the order they're listed, which
+/// is also the order of the digest's items.
+fn number_items(feeds: &mut [FeedWithItems]) {
+    let mut number = 0;
+    for (i, feed) in feeds
+        .iter_mut()
+        .filter(|feed| !feed.items.is_empty())
+        .enumerate()
+    {
+        feed.number = Some(i + 1);
+        for item in &mut feed.items {
+            number += 1;
+            item.number = Some(number);
+            for part in &mut item.parts {
+                number += 1;
+                part.number = Some(number);
+            }
+        }
+    }
+}
+
+fn is_separate_feed(config: &Config, feed_url: &str) -> bool {
+    config
+        .sources
+        .feeds
+        .iter()
+        .any(|feed_config| feed_config.url == feed_url && feed_config.separate_email)
+}
+
+fn is_events_feed(config: &Config, feed_url: &str) -> bool {
+    config
+        .sources
+        .feeds
+        .iter()
+        .any(|feed_config| feed_config.url == feed_url && feed_config.events)
+}
+
+/// Render a calendar of events detected in items of feeds with event detection enabled.
+fn render_calendar(config: &Config, feeds: &[FeedWithItems]) -> String {
+    let detector = events::Detector::default();
+    let events: Vec<events::Event> = feeds
+        .iter()
+        .filter(|feed| is_events_feed(config, &feed.feed.url))
+        .flat_map(|feed| &feed.items)
+        .filter_map(|item| detector.detect(item))
+        .collect();
+    events::to_ics(&events, config.clock.now())
+}
+
+fn render_mail(
+    config: &Config,
+    tera: &Tera,
+    subject: &str,
+    to_email: String,
+    mut feeds: Vec<FeedWithItems>,
+    thread: Option<Thread>,
+    sections: &Sections,
+) -> Result<Digest> {
+    if config.inbox.as_ref().is_some_and(|inbox| inbox.replies) {
+        number_items(&mut feeds);
+    }
+    let items = feeds
+        .iter()
+        .flat_map(|feed| feed.items.iter().chain(&feed.skipped))
+        .flat_map(|item| std::iter::once(item).chain(&item.parts))
+        .map(|item| database::DigestItem {
+            feed_url: item.feed_url.clone(),
+            guid: item.guid.clone(),
+            link: item.link.clone(),
+        })
+        .collect();
+    let calendar = events_enabled(config, &feeds).then(|| render_calendar(config, &feeds));
+    let text_content = config
+        .accessibility
+        .as_ref()
+        .filter(|accessibility| accessibility.plaintext)
+        .map(|_| text::render(config.locale.strings(), subject, &feeds, None));
+    let html_content = render_html(config, tera, subject, feeds, sections)?;
+    // Without a thread, a fixed clock derives the Message-ID from what the email is, instead of
+    // using a random one.
+    let derived_message_id = (thread.is_none() && config.clock.is_fixed())
+        .then(|| message_id(config, &[&to_email, subject]));
+    let mut builder = Email::builder()
+        // TODO: Convert directly from EmailAddress to Mailbox in next version of lettre.
+        .to(to_email)
+        .from(config.delivery.from_email.to_string())
+        .subject(subject);
+    builder = match text_content {
+        Some(text_content) => builder.alternative(html_content, text_content),
+        None => builder.html(html_content),
+    };
+    if let Some(calendar) = calendar.filter(|calendar| calendar.contains("BEGIN:VEVENT")) {
+        let content_type = "text/calendar; charset=utf-8"
+            .parse()
+            .expect("invalid content type");
+        builder = builder
+            .attachment(calendar.as_bytes(), "events.ics", &content_type)
+            .expect("failed to attach calendar");
+    }
+    if let Some(thread) = &thread {
+        builder = builder
+            .in_reply_to(thread.parent.clone())
+            .references(thread.parent.clone());
+    }
+    let mut mail: SendableEmail = builder.build().expect("failed to build email").into();
+    if let Some(message_id) = thread
+        .map(|thread| thread.message_id)
+        .or(derived_message_id)
+    {
+        mail = set_message_id(mail, &message_id);
+    }
+    if let clock::Clock::Fixed(now) = config.clock {
+        mail = make_deterministic(mail, now);
+    }
+    if let Some(smime) = &config.delivery.smime {
+        mail = smime::sign(smime, mail).map_err(Error::Sign)?;
+    }
+    // DKIM goes last, since it signs the final header and body.
+    if let Some(dkim) = &config.delivery.dkim {
+        let domain = dkim
+            .domain
+            .clone()
+            .unwrap_or_else(|| config.sender_domain());
+        mail = dkim::sign(dkim, &domain, mail, config.clock.now()).map_err(Error::Sign)?;
+    }
+    let digest_item = |item: &database::Item| database::DigestItem {
+        feed_url: item.feed_url.clone(),
+        guid: item.guid.clone(),
+        link: item.link.clone(),
+    };
+    let resurfaced = sections
+        .archives
+        .iter()
+        .map(|entry| digest_item(&entry.item))
+        .collect();
+    let updated = sections
+        .updated
+        .iter()
+        .map(|entry| digest_item(&entry.item))
+        .collect();
+    Ok(Digest {
+        mail,
+        items,
+        outbox_id: None,
+        resurfaced,
+        updated,
+    })
 }
@@ -0,0 +1,103 @@
+//! Helpers for editing rendered email messages.
+
+/// Use CRLF line endings, which signatures are computed over.
+pub fn crlf_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+/// Split a message with CRLF line endings into its header and body.
+pub fn split(message: &str) -> (&str, &str) {
+    message.split_once("\r\n\r\n").unwrap_or((message, ""))
+}
+
+/// Header fields, with folded lines joined to the line they continue.
+pub fn header_fields(header: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    for line in header.split("\r\n") {
+        match fields.last_mut() {
+            Some(field) if line.starts_with(' ') || line.starts_with('\t') => {
+                field.push_str("\r\n");
+                field.push_str(line);
+            }
+            _ => fields.push(line.to_string()),
+        }
+    }
+    fields
+}
+
+/// Name of a header field.
+pub fn field_name(field: &str) -> &str {
+    field.split(':').next().unwrap_or("").trim()
+}
+
+/// Message-ID field value of an email, given the ID lettre reports for it, which leaves out the
+/// angle brackets and domain of the random IDs it generates.
+pub fn message_id(id: &str) -> String {
+    if id.starts_with('<') {
+        id.to_string()
+    } else {
+        format!("<{id}.lettre@localhost>")
+    }
+}
+
+/// Replace the value of a header field, if the message has it.
+pub fn set_field(message: &str, name: &str, value: &str) -> String {
+    let (header, body) = split(message);
+    let fields: Vec<String> = header_fields(header)
+        .into_iter()
+        .map(|field| {
+            if field_name(&field).eq_ignore_ascii_case(name) {
+                format!("{}: {value}", field_name(&field))
+            } else {
+                field
+            }
+        })
+        .collect();
+    format!("{}\r\n\r\n{body}", fields.join("\r\n"))
+}
+
+/// Replace the random boundaries between MIME parts with ones numbered in the order they appear.
+pub fn number_boundaries(message: &str) -> String {
+    let mut boundaries: Vec<&str> = Vec::new();
+    for (start, _) in message.match_indices("boundary=") {
+        let value = message[start + "boundary=".len()..].trim_start_matches('"');
+        let end = value
+            .find(|c: char| c == '"' || c == ';' || c.is_whitespace())
+            .unwrap_or(value.len());
+        if end > 0 && !boundaries.contains(&&value[..end]) {
+            boundaries.push(&value[..end]);
+        }
+    }
+    let mut numbered = message.to_string();
+    for (i, boundary) in boundaries.iter().enumerate() {
+        numbered = numbered.replace(boundary, &format!("boundary-{}", i + 1));
+    }
+    numbered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_field_replaces_value() {
+        let message = "Date: Mon, 1 Jan 2001 00:00:00 +0000\r\nSubject: Hi\r\n\r\nDate: body";
+        assert_eq!(
+            set_field(message, "date", "Sat, 1 Jan 2000 00:00:00 +0000"),
+            "Date: Sat, 1 Jan 2000 00:00:00 +0000\r\nSubject: Hi\r\n\r\nDate: body"
+        );
+    }
+
+    #[test]
+    fn number_boundaries_in_order() {
+        let message = "Content-Type: multipart/mixed; boundary=\"Xy7\"\r\n\r\n--Xy7\r\n\
+                       Content-Type: multipart/alternative; boundary=Ab3\r\n\r\n--Ab3\r\n\
+                       --Ab3--\r\n--Xy7--\r\n";
+        assert_eq!(
+            number_boundaries(message),
+            "Content-Type: multipart/mixed; boundary=\"boundary-1\"\r\n\r\n--boundary-1\r\n\
+             Content-Type: multipart/alternative; boundary=boundary-2\r\n\r\n--boundary-2\r\n\
+             --boundary-2--\r\n--boundary-1--\r\n"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use crate::{atomic, hooks};
+
+/// Outcome of a run of a subcommand, to export to the node exporter's textfile collector.
+pub struct Run {
+    /// Name of the subcommand, such as `fetch`.
+    pub command: &'static str,
+    pub finished_at: DateTime<Utc>,
+    pub succeeded: bool,
+    /// Counts of what the run did, as metric names after `squeakmail_<command>_`, with help text.
+    pub counts: Vec<(&'static str, &'static str, usize)>,
+}
+impl Run {
+    /// Run of the fetch subcommand, with counts from its summary if it got that far.
+    pub fn fetch(finished_at: DateTime<Utc>, summary: Option<&hooks::PostFetch>) -> Self {
+        let count = |status: fn(&hooks::FetchStatus) -> bool| {
+            summary.map_or(0, |summary| {
+                summary
+                    .feeds
+                    .iter()
+                    .filter(|feed| status(&feed.status))
+                    .count()
+            })
+        };
+        Self {
+            command: "fetch",
+            finished_at,
+            succeeded: summary.is_some(),
+            counts: vec![
+                (
+                    "new_items",
+                    "Number of items stored for the first time.",
+                    summary.map_or(0, |summary| {
+                        summary.feeds.iter().map(|feed| feed.new_items).sum()
+                    }),
+                ),
+                (
+                    "fetched_feeds",
+                    "Number of feeds fetched, including feeds that weren't modified.",
+                    count(|status| {
+                        matches!(
+                            status,
+                            hooks::FetchStatus::Fetched | hooks::FetchStatus::NotModified
+                        )
+                    }),
+                ),
+                (
+                    "failed_feeds",
+                    "Number of feeds that failed to be fetched.",
+                    count(|status| matches!(status, hooks::FetchStatus::Failed)),
+                ),
+                (
+                    "skipped_feeds",
+                    "Number of feeds not fetched because the deadline was reached.",
+                    count(|status| matches!(status, hooks::FetchStatus::Skipped)),
+                ),
+            ],
+        }
+    }
+
+    /// Round of fetching and mailing of the daemon, with counts from the summary of its fetch if
+    /// any feeds were due, and the errors of every round since the daemon started.
+    pub fn daemon(
+        finished_at: DateTime<Utc>,
+        summary: Option<&hooks::PostFetch>,
+        succeeded: bool,
+        errors: usize,
+    ) -> Self {
+        let mut run = Self::fetch(finished_at, summary);
+        run.command = "daemon";
+        run.succeeded = succeeded;
+        run.counts.push((
+            "errors",
+            "Number of times fetching or mailing failed since the daemon started.",
+            errors,
+        ));
+        run
+    }
+
+    /// Run of the mail subcommand, with the mails it sent if it succeeded.
+    pub fn mail(finished_at: DateTime<Utc>, mails: Option<&[hooks::Mail]>) -> Self {
+        Self {
+            command: "mail",
+            finished_at,
+            succeeded: mails.is_some(),
+            counts: vec![
+                (
+                    "mails",
+                    "Number of digests mailed.",
+                    mails.map_or(0, <[_]>::len),
+                ),
+                (
+                    "items",
+                    "Number of items in the digests mailed.",
+                    mails.map_or(0, |mails| mails.iter().map(|mail| mail.items).sum()),
+                ),
+            ],
+        }
+    }
+
+    /// Metrics in the Prometheus text format. Metrics of every subcommand have a `command` label,
+    /// so runs of different subcommands can be written to files next to each other.
+    pub fn render(&self) -> String {
+        let mut metrics = String::new();
+        let mut gauge = |name: &str, help: &str, value: String| {
+            writeln!(
+                metrics,
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name}{{command=\"{}\"}} {value}",
+                self.command
+            )
+            .expect("writing to a string");
+        };
+        gauge(
+            "squeakmail_last_run_timestamp_seconds",
+            "When the last run finished, in seconds since the epoch.",
+            self.finished_at.timestamp().to_string(),
+        );
+        gauge(
+            "squeakmail_last_run_success",
+            "Whether the last run succeeded.",
+            u8::from(self.succeeded).to_string(),
+        );
+        for &(name, help, value) in &self.counts {
+            gauge(
+                &format!("squeakmail_{}_{name}", self.command),
+                help,
+                value.to_string(),
+            );
+        }
+        metrics
+    }
+}
+
+/// Replace the file with the metrics of a run, so the node exporter never reads a partly written
+/// file.
+pub fn write(path: &Path, run: &Run) -> io::Result<()> {
+    atomic::write(path, run.render().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn render_fetch_run() {
+        let summary = hooks::PostFetch {
+            started_at: Utc.timestamp(1_700_000_000, 0),
+            duration_ms: 1000,
+            feeds: vec![
+                hooks::FeedFetch {
+                    url: "a".to_string(),
+                    status: hooks::FetchStatus::Fetched,
+                    new_items: 3,
+                    error: None,
+                    error_code: None,
+                },
+                hooks::FeedFetch {
+                    url: "b".to_string(),
+                    status: hooks::FetchStatus::Failed,
+                    new_items: 0,
+                    error: Some("timed out".to_string()),
+                    error_code: Some("fetch"),
+                },
+            ],
+        };
+        let run = Run::fetch(Utc.timestamp(1_700_000_001, 0), Some(&summary));
+        let metrics = run.render();
+        assert!(metrics.contains(
+            "# TYPE squeakmail_last_run_timestamp_seconds gauge\n\
+             squeakmail_last_run_timestamp_seconds{command=\"fetch\"} 1700000001\n"
+        ));
+        assert!(metrics.contains("squeakmail_last_run_success{command=\"fetch\"} 1\n"));
+        assert!(metrics.contains("squeakmail_fetch_new_items{command=\"fetch\"} 3\n"));
+        assert!(metrics.contains("squeakmail_fetch_fetched_feeds{command=\"fetch\"} 1\n"));
+        assert!(metrics.contains("squeakmail_fetch_failed_feeds{command=\"fetch\"} 1\n"));
+    }
+
+    #[test]
+    fn render_daemon_run_with_errors() {
+        let run = Run::daemon(Utc.timestamp(1_700_000_000, 0), None, false, 3);
+        let metrics = run.render();
+        assert!(metrics.contains("squeakmail_last_run_success{command=\"daemon\"} 0\n"));
+        assert!(metrics.contains("squeakmail_daemon_new_items{command=\"daemon\"} 0\n"));
+        assert!(metrics.contains("squeakmail_daemon_errors{command=\"daemon\"} 3\n"));
+    }
+
+    #[test]
+    fn render_failed_mail_run() {
+        let run = Run::mail(Utc.timestamp(1_700_000_000, 0), None);
+        let metrics = run.render();
+        assert!(metrics.contains("squeakmail_last_run_success{command=\"mail\"} 0\n"));
+        assert!(metrics.contains("squeakmail_mail_mails{command=\"mail\"} 0\n"));
+    }
+}
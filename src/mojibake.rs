@@ -0,0 +1,137 @@
+//! Repair of text that was encoded as UTF-8 and decoded as Windows-1252 or Latin-1, one or more
+//! times, such as "cafÃ©" for "café".
+
+use std::convert::TryFrom;
+
+use crate::feed;
+
+// Characters of the bytes 0x80 to 0x9F in Windows-1252. The bytes it leaves undefined are
+// decoded as the Latin-1 control characters of the same value.
+const WINDOWS_1252_80_TO_9F: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+// Text encoded more times than this is left as it is after this many repairs.
+const MAX_PASSES: usize = 3;
+
+/// Repair the text of an item.
+pub fn repair_item(item: &mut feed::Item) {
+    item.title = repair(&item.title);
+    if let Some(author) = &mut item.author {
+        *author = repair(author);
+    }
+    for category in &mut item.categories {
+        *category = repair(category);
+    }
+}
+
+/// Replace sequences of characters that are the UTF-8 encoding of a character decoded as
+/// Windows-1252 or Latin-1 with that character, until there are none left.
+pub fn repair(text: &str) -> String {
+    let mut repaired = text.to_string();
+    for _ in 0..MAX_PASSES {
+        match repair_once(&repaired) {
+            Some(text) => repaired = text,
+            None => break,
+        }
+    }
+    repaired
+}
+
+/// Repair one level of encoding, or `None` if there was nothing to repair.
+fn repair_once(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut repaired = String::with_capacity(text.len());
+    let mut changed = false;
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some((c, len)) = decode_sequence(&chars[i..]) {
+            repaired.push(c);
+            changed = true;
+            i += len;
+        } else {
+            repaired.push(chars[i]);
+            i += 1;
+        }
+    }
+    changed.then_some(repaired)
+}
+
+/// Character encoded as UTF-8 by the bytes of the characters at the start, with the number of
+/// characters it takes.
+fn decode_sequence(chars: &[char]) -> Option<(char, usize)> {
+    let lead = to_byte(*chars.first()?)?;
+    let len = match lead {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return None,
+    };
+    let bytes = chars
+        .get(..len)?
+        .iter()
+        .map(|&c| to_byte(c))
+        .collect::<Option<Vec<u8>>>()?;
+    let decoded = std::str::from_utf8(&bytes).ok()?;
+    Some((decoded.chars().next()?, len))
+}
+
+/// Byte that a non-ASCII character is decoded from in Windows-1252 or Latin-1.
+fn to_byte(c: char) -> Option<u8> {
+    match u32::from(c) {
+        code @ 0x80..=0xFF => u8::try_from(code).ok(),
+        _ => WINDOWS_1252_80_TO_9F
+            .iter()
+            .position(|&other| other == c)
+            .and_then(|i| u8::try_from(0x80 + i).ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repair_fixes_known_bad_samples() {
+        for (bad, good) in [
+            ("cafÃ©", "café"),
+            ("Itâ€™s", "It’s"),
+            ("â€œQuotedâ€\u{9d}", "“Quoted”"),
+            ("Ã‰tÃ© Ã\u{a0} Paris", "Été à Paris"),
+            ("GrÃ¶ÃŸe", "Größe"),
+            ("1 â€“ 2 â€” 3â€¦", "1 – 2 — 3…"),
+            ("Â£5", "£5"),
+            ("ÐŸÑ€Ð¸Ð²ÐµÑ‚", "Привет"),
+            ("ðŸ˜€", "😀"),
+        ] {
+            assert_eq!(repair(bad), good);
+        }
+    }
+
+    #[test]
+    fn repair_fixes_double_encoding() {
+        assert_eq!(repair("cafÃƒÂ©"), "café");
+    }
+
+    #[test]
+    fn repair_keeps_correct_text() {
+        for text in [
+            "café",
+            "naïve",
+            "Größe",
+            "“Quoted” – it’s",
+            "日本語",
+            "Ça va? À bientôt!",
+            "ASCII only",
+        ] {
+            assert_eq!(repair(text), text);
+        }
+    }
+
+    #[test]
+    fn repair_keeps_correct_text_around_mojibake() {
+        assert_eq!(repair("日本 cafÃ© “ok”"), "日本 café “ok”");
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+
+use derive_more::Display;
+use lettre::smtp::error::Error as SmtpError;
+use lettre::smtp::extension::ClientId;
+use lettre::smtp::SMTP_PORT;
+use lettre::{
+    ClientSecurity, ClientTlsParameters, Envelope, SendableEmail, SmtpClient, Transport as _,
+};
+use native_tls::TlsConnector;
+use serde::{Deserialize, Serialize};
+
+use crate::dns;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Only deliver over STARTTLS with a valid certificate, instead of falling back to plain
+    /// text.
+    #[serde(default)]
+    pub require_tls: bool,
+    /// Name to greet mail servers with, instead of the hostname.
+    #[serde(default)]
+    pub hello_name: Option<String>,
+    /// Nameserver to look up mail servers with, instead of the system's.
+    #[serde(default)]
+    pub nameserver: Option<SocketAddr>,
+    /// Keep retrying mail that can't be delivered for this long.
+    #[serde(default = "default_retry_hours")]
+    pub retry_hours: NonZeroU32,
+}
+
+fn default_retry_hours() -> NonZeroU32 {
+    NonZeroU32::new(48).expect("invalid default")
+}
+
+/// Failure to deliver mail, which may succeed later if it's transient.
+#[derive(Debug, Display)]
+#[display(fmt = "{message}")]
+pub struct Error {
+    pub message: String,
+    pub is_transient: bool,
+}
+impl Error {
+    fn transient(message: &impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+            is_transient: true,
+        }
+    }
+
+    fn permanent(message: &impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+            is_transient: false,
+        }
+    }
+}
+impl From<SmtpError> for Error {
+    fn from(error: SmtpError) -> Self {
+        // Wrapped errors are displayed with their deprecated descriptions.
+        let message = match &error {
+            SmtpError::Transient(response) | SmtpError::Permanent(response) => {
+                format!("{} {}", response.code, response.message.join(" "))
+            }
+            SmtpError::Io(e) => e.to_string(),
+            SmtpError::Tls(e) => e.to_string(),
+            _ => error.to_string(),
+        };
+        Self {
+            message,
+            // Only a server rejecting the mail means it can't be delivered.
+            is_transient: !matches!(error, SmtpError::Permanent(_)),
+        }
+    }
+}
+
+/// Delivers mail directly to the mail servers of recipients' domains.
+pub struct Transport {
+    config: Config,
+}
+impl Transport {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn send(&mut self, mail: SendableEmail) -> Result<(), Error> {
+        let envelope = mail.envelope().clone();
+        let message_id = mail.message_id().to_string();
+        let message = mail.message_to_string().map_err(|e| Error::transient(&e))?;
+        let mut recipients_by_domain = BTreeMap::new();
+        for recipient in envelope.to() {
+            let domain = recipient
+                .to_string()
+                .rsplit('@')
+                .next()
+                .unwrap_or("")
+                .to_string();
+            recipients_by_domain
+                .entry(domain)
+                .or_insert_with(Vec::new)
+                .push(recipient.clone());
+        }
+        for (domain, recipients) in recipients_by_domain {
+            let envelope = Envelope::new(envelope.from().cloned(), recipients)
+                .map_err(|e| Error::permanent(&e))?;
+            self.send_to_domain(&domain, &envelope, &message_id, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Try each mail server of the domain in order of preference.
+    fn send_to_domain(
+        &self,
+        domain: &str,
+        envelope: &Envelope,
+        message_id: &str,
+        message: &str,
+    ) -> Result<(), Error> {
+        let mut last_error = Error::transient(&format!("no mail servers for {domain}"));
+        for host in self.mail_servers(domain)? {
+            let mail = SendableEmail::new(
+                envelope.clone(),
+                message_id.to_string(),
+                message.as_bytes().to_vec(),
+            );
+            match self.send_to_host(&host, mail) {
+                Ok(()) => return Ok(()),
+                Err(error) if !error.is_transient => return Err(error),
+                Err(error) => {
+                    eprintln!("Failed to deliver mail to {host}: {error}");
+                    last_error = error;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Mail servers of a domain, most preferred first (RFC 5321 section 5.1).
+    fn mail_servers(&self, domain: &str) -> Result<Vec<String>, Error> {
+        let mut records = dns::lookup_mx(domain, self.config.nameserver)
+            .map_err(|e| Error::transient(&format!("failed to look up {domain}: {e}")))?
+            .ok_or_else(|| Error::permanent(&format!("domain {domain} doesn't exist")))?;
+        if records.is_empty() {
+            // Without MX records, the domain is its own mail server.
+            return Ok(vec![domain.to_string()]);
+        }
+        // A "null MX" record means the domain doesn't accept mail (RFC 7505).
+        if records
+            .iter()
+            .any(|(_, exchange)| exchange.is_empty() || exchange == ".")
+        {
+            return Err(Error::permanent(&format!(
+                "domain {domain} doesn't accept mail"
+            )));
+        }
+        records.sort_by_key(|(preference, _)| *preference);
+        Ok(records.into_iter().map(|(_, exchange)| exchange).collect())
+    }
+
+    fn send_to_host(&self, host: &str, mail: SendableEmail) -> Result<(), Error> {
+        // Most mail servers don't have certificates that can be verified, so unless TLS is
+        // required, it only protects against eavesdropping.
+        let connector = TlsConnector::builder()
+            .danger_accept_invalid_certs(!self.config.require_tls)
+            .build()
+            .map_err(|e| Error::transient(&e))?;
+        let tls_parameters = ClientTlsParameters::new(host.to_string(), connector);
+        let security = if self.config.require_tls {
+            ClientSecurity::Required(tls_parameters)
+        } else {
+            ClientSecurity::Opportunistic(tls_parameters)
+        };
+        let mut client = SmtpClient::new((host, SMTP_PORT), security)?;
+        if let Some(hello_name) = &self.config.hello_name {
+            client = client.hello_name(ClientId::Domain(hello_name.clone()));
+        }
+        let mut transport = client.transport();
+        let result = transport.send(mail);
+        transport.close();
+        result?;
+        Ok(())
+    }
+}
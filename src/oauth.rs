@@ -0,0 +1,119 @@
+//! OAuth 2.0 client credentials grant, for feeds that need an access token to fetch.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use attohttpc::header::CONTENT_TYPE;
+use chrono::{DateTime, Utc};
+use derive_more::{Display, From};
+use serde::{Deserialize, Serialize};
+
+use crate::database;
+
+/// How long before an access token expires to request a new one, so it doesn't expire while a
+/// feed is being fetched.
+const REFRESH_MARGIN: Duration = Duration::from_mins(1);
+
+/// Token endpoint to get access tokens from, with the client's credentials in environment
+/// variables so they aren't written in the config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub token_url: String,
+    /// Environment variable with the client ID.
+    pub client_id_env: String,
+    /// Environment variable with the client secret.
+    pub client_secret_env: String,
+    /// Scope to request, as space-separated scope names.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+impl Config {
+    pub fn client_id(&self) -> Result<String, Error> {
+        env(&self.client_id_env)
+    }
+
+    /// Scope to cache tokens under, which is empty if none is requested.
+    pub fn scope(&self) -> &str {
+        self.scope.as_deref().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, From, Display)]
+pub enum Error {
+    Http(attohttpc::Error),
+    #[display(fmt = "invalid token response: {_0}")]
+    Json(serde_json::Error),
+    #[display(fmt = "token endpoint returned status code {_0}")]
+    UnexpectedStatusCode(u16),
+    #[from(ignore)]
+    #[display(fmt = "environment variable {_0} isn't set")]
+    MissingEnv(String),
+}
+
+/// Token endpoint's response to a token request.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// Lifetime of the token in seconds, if the endpoint says.
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// Request an access token from the token endpoint. The token expires at the returned time, or
+/// is only good for the fetch it was requested for if the endpoint doesn't say when it expires.
+pub fn request_token(
+    config: &Config,
+    client_id: &str,
+    now: DateTime<Utc>,
+) -> Result<(String, Option<DateTime<Utc>>), Error> {
+    let client_secret = env(&config.client_secret_env)?;
+    let mut form = url::form_urlencoded::Serializer::new(String::new());
+    form.append_pair("grant_type", "client_credentials");
+    if let Some(scope) = &config.scope {
+        form.append_pair("scope", scope);
+    }
+    let resp = attohttpc::post(&config.token_url)
+        .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+        .timeout(Duration::from_secs(30))
+        .basic_auth(client_id, Some(client_secret))
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .text(form.finish())
+        .send()?;
+    if !resp.is_success() {
+        return Err(Error::UnexpectedStatusCode(resp.status().as_u16()));
+    }
+    let token: TokenResponse = serde_json::from_slice(&resp.bytes()?)?;
+    let expires_at = token.expires_in.map(|expires_in| {
+        now + chrono::Duration::seconds(i64::from(u32::try_from(expires_in).unwrap_or(u32::MAX)))
+    });
+    Ok((token.access_token, expires_at))
+}
+
+/// Whether a cached access token can still be used, rather than requesting a new one.
+pub fn is_fresh(token: &database::AccessToken, now: DateTime<Utc>) -> bool {
+    let margin = chrono::Duration::from_std(REFRESH_MARGIN).expect("margin is in range");
+    token.expires_at - margin > now
+}
+
+fn env(name: &str) -> Result<String, Error> {
+    std::env::var(name).map_err(|_| Error::MissingEnv(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn token_is_refreshed_shortly_before_it_expires() {
+        let token = database::AccessToken {
+            token: "token".to_string(),
+            expires_at: Utc.timestamp(1_700_000_000, 0),
+        };
+        assert!(is_fresh(&token, Utc.timestamp(1_699_999_000, 0)));
+        assert!(!is_fresh(&token, Utc.timestamp(1_699_999_950, 0)));
+        assert!(!is_fresh(&token, Utc.timestamp(1_700_000_100, 0)));
+    }
+}
@@ -0,0 +1,293 @@
+//! OPML files of feeds, for exchanging the feed list with other feed readers, with the folders
+//! that feeds are in.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::BufRead;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+/// Feed in an OPML file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outline {
+    pub url: String,
+    pub title: Option<String>,
+    /// Link to the website of the feed.
+    pub link: Option<String>,
+    /// Folder the feed is in, with nested folders separated by `/`, such as `Tech/Rust`.
+    pub folder: Option<String>,
+}
+
+/// Feeds in an OPML file, in the order they're listed.
+pub fn parse(xml: &[u8]) -> quick_xml::Result<Vec<Outline>> {
+    let mut reader = Reader::from_reader(xml);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    // Name of each outline element that's open, if it's a folder.
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let mut outlines = Vec::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(element) if element.name() == b"outline" => {
+                let folder = read_outline(&reader, &element, &folders, &mut outlines)?;
+                folders.push(folder);
+            }
+            Event::Empty(element) if element.name() == b"outline" => {
+                read_outline(&reader, &element, &folders, &mut outlines)?;
+            }
+            Event::End(element) if element.name() == b"outline" => {
+                folders.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(outlines)
+}
+
+/// Add an outline element to the feeds if it's a feed, or else return its name as a folder.
+fn read_outline<B: BufRead>(
+    reader: &Reader<B>,
+    element: &BytesStart,
+    folders: &[Option<String>],
+    outlines: &mut Vec<Outline>,
+) -> quick_xml::Result<Option<String>> {
+    let mut attributes = HashMap::new();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        // Some readers write attribute names in other cases, such as `xmlURL`.
+        let name = String::from_utf8_lossy(attribute.key).to_ascii_lowercase();
+        attributes.insert(name, attribute.unescape_and_decode_value(reader)?);
+    }
+    let title = attributes
+        .get("title")
+        .or_else(|| attributes.get("text"))
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty());
+    let Some(url) = attributes.get("xmlurl") else {
+        return Ok(title);
+    };
+    let folders: Vec<&str> = folders.iter().flatten().map(String::as_str).collect();
+    // Feeds that aren't in a folder may have categories instead, which are comma-separated
+    // paths, such as `/Tech/Rust,/News`.
+    let folder = if folders.is_empty() {
+        attributes.get("category").and_then(|categories| {
+            categories
+                .split(',')
+                .map(folder_path)
+                .find(|folder| !folder.is_empty())
+        })
+    } else {
+        Some(folders.join("/"))
+    };
+    outlines.push(Outline {
+        url: url.trim().to_string(),
+        title,
+        link: attributes
+            .get("htmlurl")
+            .filter(|link| !link.is_empty())
+            .cloned(),
+        folder,
+    });
+    Ok(None)
+}
+
+/// Folder of a category path, without empty names, such as `Tech/Rust` for `/Tech/Rust/`.
+fn folder_path(path: &str) -> String {
+    path.split('/')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Folder of feeds in an OPML file, and the folders in it.
+#[derive(Default)]
+struct Folder<'a> {
+    name: &'a str,
+    /// Feeds and folders, in the order they're first listed.
+    entries: Vec<Entry<'a>>,
+}
+
+enum Entry<'a> {
+    Feed(&'a Outline),
+    Folder(Folder<'a>),
+}
+
+impl<'a> Folder<'a> {
+    fn insert(&mut self, path: &[&'a str], outline: &'a Outline) {
+        let Some((name, path)) = path.split_first() else {
+            self.entries.push(Entry::Feed(outline));
+            return;
+        };
+        let position = self
+            .entries
+            .iter()
+            .position(|entry| matches!(entry, Entry::Folder(folder) if folder.name == *name));
+        let index = position.unwrap_or_else(|| {
+            self.entries.push(Entry::Folder(Folder {
+                name,
+                entries: Vec::new(),
+            }));
+            self.entries.len() - 1
+        });
+        if let Entry::Folder(folder) = &mut self.entries[index] {
+            folder.insert(path, outline);
+        }
+    }
+
+    fn render(&self, opml: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        for entry in &self.entries {
+            match entry {
+                Entry::Feed(outline) => render_feed(opml, &indent, outline),
+                Entry::Folder(folder) => {
+                    let name = escape(folder.name);
+                    writeln!(opml, "{indent}<outline text=\"{name}\" title=\"{name}\">")
+                        .expect("writing to a string");
+                    folder.render(opml, depth + 1);
+                    writeln!(opml, "{indent}</outline>").expect("writing to a string");
+                }
+            }
+        }
+    }
+}
+
+/// OPML file of feeds, in nested folders by their folder paths. Feeds in a folder have its path
+/// as their category too, for readers that read categories instead.
+pub fn render(outlines: &[Outline]) -> String {
+    let mut body = Folder::default();
+    for outline in outlines {
+        let path: Vec<&str> = outline
+            .folder
+            .iter()
+            .flat_map(|folder| folder.split('/'))
+            .filter(|name| !name.is_empty())
+            .collect();
+        body.insert(&path, outline);
+    }
+    let mut opml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <opml version=\"2.0\">\n  <head>\n    <title>SqueakMail feeds</title>\n  </head>\n  \
+         <body>\n",
+    );
+    body.render(&mut opml, 2);
+    opml.push_str("  </body>\n</opml>\n");
+    opml
+}
+
+fn render_feed(opml: &mut String, indent: &str, outline: &Outline) {
+    let title = escape(outline.title.as_deref().unwrap_or(&outline.url));
+    write!(
+        opml,
+        "{indent}<outline type=\"rss\" text=\"{title}\" title=\"{title}\" xmlUrl=\"{}\"",
+        escape(&outline.url)
+    )
+    .expect("writing to a string");
+    if let Some(link) = &outline.link {
+        write!(opml, " htmlUrl=\"{}\"", escape(link)).expect("writing to a string");
+    }
+    if let Some(folder) = &outline.folder {
+        write!(opml, " category=\"/{}\"", escape(folder)).expect("writing to a string");
+    }
+    opml.push_str("/>\n");
+}
+
+/// Escape text for an attribute value.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(url: &str, title: Option<&str>, folder: Option<&str>) -> Outline {
+        Outline {
+            url: url.to_string(),
+            title: title.map(ToString::to_string),
+            link: None,
+            folder: folder.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn parse_feeds_in_folders() {
+        let opml = br#"<?xml version="1.0"?>
+<opml version="1.0">
+  <head><title>Subscriptions</title></head>
+  <body>
+    <outline text="Tech">
+      <outline text="Rust">
+        <outline type="rss" text="This Week in Rust" xmlUrl="https://this-week-in-rust.org/rss.xml" htmlUrl="https://this-week-in-rust.org/"/>
+      </outline>
+      <outline type="rss" text="Q &amp; A" xmlURL="https://qa.example/feed" category="/News"/>
+    </outline>
+    <outline type="rss" title="Loose" xmlUrl="https://loose.example/feed"></outline>
+    <outline type="rss" text="Sorted" xmlUrl="https://sorted.example/feed" category=",/News/Daily/"/>
+  </body>
+</opml>"#;
+        let outlines = parse(opml).expect("failed to parse");
+        assert_eq!(
+            outlines,
+            [
+                Outline {
+                    link: Some("https://this-week-in-rust.org/".to_string()),
+                    ..outline(
+                        "https://this-week-in-rust.org/rss.xml",
+                        Some("This Week in Rust"),
+                        Some("Tech/Rust")
+                    )
+                },
+                outline("https://qa.example/feed", Some("Q & A"), Some("Tech")),
+                outline("https://loose.example/feed", Some("Loose"), None),
+                outline(
+                    "https://sorted.example/feed",
+                    Some("Sorted"),
+                    Some("News/Daily")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_feeds_in_nested_folders() {
+        let opml = render(&[
+            outline("https://a.example/feed", Some("A & B"), Some("news/daily")),
+            outline("https://b.example/feed?x=1&y=2", None, None),
+            outline("https://c.example/feed", Some("C"), Some("news")),
+        ]);
+        assert_eq!(
+            opml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <opml version=\"2.0\">\n  <head>\n    <title>SqueakMail feeds</title>\n  </head>\n  \
+             <body>\n    <outline text=\"news\" title=\"news\">\n      \
+             <outline text=\"daily\" title=\"daily\">\n        \
+             <outline type=\"rss\" text=\"A &amp; B\" title=\"A &amp; B\" \
+             xmlUrl=\"https://a.example/feed\" category=\"/news/daily\"/>\n      \
+             </outline>\n      \
+             <outline type=\"rss\" text=\"C\" title=\"C\" xmlUrl=\"https://c.example/feed\" \
+             category=\"/news\"/>\n    </outline>\n    \
+             <outline type=\"rss\" text=\"https://b.example/feed?x=1&amp;y=2\" \
+             title=\"https://b.example/feed?x=1&amp;y=2\" \
+             xmlUrl=\"https://b.example/feed?x=1&amp;y=2\"/>\n  </body>\n</opml>\n"
+        );
+    }
+
+    #[test]
+    fn render_then_parse_keeps_feeds() {
+        let outlines = [
+            outline("https://a.example/feed", Some("A"), Some("news/daily")),
+            outline("https://b.example/feed", Some("B"), None),
+        ];
+        assert_eq!(
+            parse(render(&outlines).as_bytes()).expect("failed to parse"),
+            outlines
+        );
+    }
+}
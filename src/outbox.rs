@@ -0,0 +1,135 @@
+use std::cmp::min;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Local, Utc};
+use lettre::{Envelope, SendableEmail};
+
+use crate::{database, send, Config, Digest, Error, Result};
+
+// Delay before the first retry, which doubles with each failed attempt.
+const FIRST_RETRY_MINUTES: i64 = 5;
+const MAX_RETRY_MINUTES: i64 = 4 * 60;
+
+/// Add digests to the outbox, to be delivered by `deliver` from the given time.
+pub fn queue(
+    database: &mut database::Database,
+    digests: Vec<Digest>,
+    deliver_at: DateTime<Utc>,
+) -> Result<()> {
+    let now = Utc::now();
+    for digest in digests {
+        let envelope = digest.mail.envelope();
+        let recipient = envelope.to()[0].to_string();
+        let envelope = serde_json::to_string(envelope).expect("failed to serialize envelope");
+        let message_id = digest.mail.message_id().to_string();
+        let message = digest
+            .mail
+            .message_to_string()
+            .expect("message cannot be converted to string")
+            .into_bytes();
+        database.insert_outbox_mail(&database::OutboxMail {
+            id: 0,
+            recipient,
+            envelope,
+            message_id,
+            message,
+            items: digest.items,
+            queued_at: now,
+            attempts: 0,
+            next_attempt_at: deliver_at,
+            last_error: None,
+        })?;
+    }
+    Ok(())
+}
+
+/// Try to deliver the mail in the outbox that is due, and retry transient failures later.
+///
+/// Mail that fails permanently, or keeps failing for longer than the retry period, is dropped,
+/// and its items are marked unread so they're included in the next digest.
+///
+/// Nothing is delivered outside the send window.
+pub fn deliver(config: &Config, database: &mut database::Database) -> Result<()> {
+    if let Some(held_until) = config
+        .delivery
+        .send_window
+        .as_ref()
+        .and_then(|send_window| send_window.held_until(&Local::now()))
+    {
+        eprintln!("Holding mail in the outbox until the send window starts at {held_until}");
+        return Ok(());
+    }
+    let retry_period = Duration::hours(i64::from(
+        config
+            .delivery
+            .mx
+            .as_ref()
+            .map_or(0, |mx| mx.retry_hours.get()),
+    ));
+    let mut queued = HashMap::new();
+    let mut digests = Vec::new();
+    for mail in database.get_due_outbox_mails(Utc::now())? {
+        let envelope: Envelope =
+            serde_json::from_str(&mail.envelope).expect("invalid envelope in outbox");
+        if let Some(last_error) = &mail.last_error {
+            eprintln!(
+                "Retrying mail to {} (attempt {}, last error: {last_error})",
+                mail.recipient,
+                mail.attempts + 1
+            );
+        }
+        queued.insert(mail.id, (mail.queued_at, mail.attempts));
+        digests.push(Digest {
+            mail: SendableEmail::new(envelope, mail.message_id, mail.message),
+            items: mail.items,
+            outbox_id: Some(mail.id),
+            resurfaced: Vec::new(),
+            updated: Vec::new(),
+        });
+    }
+    let total = digests.len();
+    let mut failures = 0;
+    for sent in send::send_all(digests, config)? {
+        let id = sent.outbox_id.expect("mail is from the outbox");
+        let error = match sent.result {
+            Ok(()) => {
+                database.insert_digest(
+                    &sent.recipient,
+                    Utc::now(),
+                    &sent.message_id,
+                    &sent.items,
+                )?;
+                database.delete_outbox_mail(id)?;
+                continue;
+            }
+            Err(error) => error,
+        };
+        let (queued_at, attempts) = queued[&id];
+        let next_attempt_at = Utc::now() + retry_delay(attempts + 1);
+        let is_transient = matches!(&error, Error::Mx(error) if error.is_transient);
+        if is_transient && next_attempt_at < queued_at + retry_period {
+            eprintln!(
+                "Failed to send mail to {}, retrying at {next_attempt_at}: {error}",
+                sent.recipient
+            );
+            database.reschedule_outbox_mail(id, next_attempt_at, &error.to_string())?;
+        } else {
+            eprintln!("Failed to send mail to {}: {error}", sent.recipient);
+            database.delete_outbox_mail(id)?;
+            database.cancel_deliveries(&sent.recipient, &sent.items)?;
+            database.mark_digest_items_unread(&sent.items)?;
+            failures += 1;
+        }
+    }
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(Error::SendFailed(failures, total))
+    }
+}
+
+/// Delay before retrying mail that failed the given number of times.
+fn retry_delay(attempts: u32) -> Duration {
+    let minutes = FIRST_RETRY_MINUTES << min(attempts.saturating_sub(1), 16);
+    Duration::minutes(min(minutes, MAX_RETRY_MINUTES))
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::database;
+
+/// Parts are only merged if they were published within this many hours of the first one seen.
+const WINDOW_HOURS: i64 = 24;
+
+/// Pattern for a part number at the end of an item title.
+pub struct Merger {
+    part: Regex,
+}
+impl Default for Merger {
+    fn default() -> Self {
+        Self {
+            // Matches "Part 2", "Pt. 2", "Part 2 of 3", "(2/3)", and the separator before them.
+            part: Regex::new(
+                r"(?i)[\s:,\-–—]*(?:\(?\b(?:part|pt\.?)\s*(?P<part>\d+)(?:\s*(?:of|/)\s*\d+)?\)?|\((?P<fraction>\d+)\s*/\s*\d+\))\s*$",
+            )
+            .expect("invalid regex"),
+        }
+    }
+}
+impl Merger {
+    /// Normalized title without its part number, and the part number, if the title has one.
+    fn split(&self, title: &str) -> Option<(String, u32)> {
+        let captures = self.part.captures(title)?;
+        let number = captures
+            .name("part")
+            .or_else(|| captures.name("fraction"))?
+            .as_str()
+            .parse()
+            .ok()?;
+        let prefix = title[..captures.get(0)?.start()].to_lowercase();
+        let prefix: Vec<&str> = prefix.split_whitespace().collect();
+        if prefix.is_empty() {
+            return None;
+        }
+        Some((prefix.join(" "), number))
+    }
+
+    /// Merge items whose titles share a prefix before a part number, and that were published
+    /// within a day of each other, into one item with the later parts in `parts`.
+    ///
+    /// Merged items take the place of the earliest item, and are ordered by part number.
+    pub fn merge(&self, items: Vec<database::Item>) -> Vec<database::Item> {
+        let mut groups: Vec<Vec<(u32, database::Item)>> = Vec::new();
+        // Index in `groups` of the latest group of each title prefix.
+        let mut group_indexes: HashMap<String, usize> = HashMap::new();
+        for item in items {
+            let Some((prefix, number)) = self.split(&item.title) else {
+                groups.push(vec![(0, item)]);
+                continue;
+            };
+            if let Some(&index) = group_indexes.get(&prefix) {
+                let first = &groups[index][0].1;
+                if (item.pub_date - first.pub_date).num_hours().abs() < WINDOW_HOURS {
+                    groups[index].push((number, item));
+                    continue;
+                }
+            }
+            group_indexes.insert(prefix, groups.len());
+            groups.push(vec![(number, item)]);
+        }
+        groups
+            .into_iter()
+            .map(|mut group| {
+                group.sort_by_key(|(number, _)| *number);
+                let mut group = group.into_iter().map(|(_, item)| item);
+                let mut first = group.next().expect("groups aren't empty");
+                first.parts.extend(group);
+                first
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn item(title: &str, hour: u32) -> database::Item {
+        database::Item {
+            feed_url: "http://example.org/feed.xml".to_string(),
+            guid: title.to_string(),
+            title: title.to_string(),
+            link: format!("http://example.org/{title}"),
+            comments_link: None,
+            pub_date: Utc.ymd(2019, 11, 2).and_hms(0, 0, 0) + chrono::Duration::hours(hour.into()),
+            is_read: false,
+            is_highlighted: false,
+            recipient: None,
+            tags: Vec::new(),
+            is_dead: false,
+            translated_title: None,
+            is_bulk: false,
+            source: None,
+            parts: Vec::new(),
+            qr_code: None,
+            number: None,
+            content: Vec::new(),
+            excerpt: Vec::new(),
+            is_truncated: false,
+            images: Vec::new(),
+            summary: None,
+        }
+    }
+
+    fn merged_titles(items: Vec<database::Item>) -> Vec<(String, Vec<String>)> {
+        Merger::default()
+            .merge(items)
+            .into_iter()
+            .map(|item| {
+                let parts = item.parts.into_iter().map(|part| part.title).collect();
+                (item.title, parts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_finds_part_numbers() {
+        let merger = Merger::default();
+        for (title, expected) in &[
+            (
+                "Building a Compiler, Part 2",
+                Some(("building a compiler", 2)),
+            ),
+            (
+                "Building a  compiler: pt. 3",
+                Some(("building a compiler", 3)),
+            ),
+            (
+                "Building a Compiler (Part 4 of 5)",
+                Some(("building a compiler", 4)),
+            ),
+            (
+                "Building a Compiler (1/3)",
+                Some(("building a compiler", 1)),
+            ),
+            ("Part 2", None),
+            ("Rust 1.40 released", None),
+            ("Counterpart 2", None),
+        ] {
+            assert_eq!(
+                merger.split(title),
+                expected.map(|(prefix, number)| (prefix.to_string(), number)),
+                "{title}"
+            );
+        }
+    }
+
+    #[test]
+    fn merge_collapses_parts_into_first() {
+        assert_eq!(
+            merged_titles(vec![
+                item("Compilers, Part 2", 1),
+                item("Unrelated", 2),
+                item("Compilers, Part 1", 3),
+                item("Compilers, Part 3", 4),
+            ]),
+            vec![
+                (
+                    "Compilers, Part 1".to_string(),
+                    vec![
+                        "Compilers, Part 2".to_string(),
+                        "Compilers, Part 3".to_string()
+                    ]
+                ),
+                ("Unrelated".to_string(), Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_parts_published_on_different_days() {
+        assert_eq!(
+            merged_titles(vec![
+                item("Compilers, Part 1", 0),
+                item("Compilers, Part 2", 30)
+            ]),
+            vec![
+                ("Compilers, Part 1".to_string(), Vec::new()),
+                ("Compilers, Part 2".to_string(), Vec::new()),
+            ]
+        );
+    }
+}
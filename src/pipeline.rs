@@ -0,0 +1,218 @@
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use lettre::EmailAddress;
+use serde::{Deserialize, Serialize};
+
+use crate::{transport, window};
+
+/// Named way of mailing the items with a tag, with its own recipient, templates, and transport.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Name to mail the pipeline by, with `mail --pipeline`.
+    pub name: String,
+    /// Mail only items with this tag, or the items of the regular digest if it isn't set.
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Send to this address instead of `to_email`.
+    #[serde(default)]
+    pub to_email: Option<EmailAddress>,
+    /// Send each item in its own email, instead of following `email_per_item`.
+    #[serde(default)]
+    pub email_per_item: Option<bool>,
+    /// Send each feed's items in their own email, instead of following `email_per_feed`.
+    #[serde(default)]
+    pub email_per_feed: Option<bool>,
+    /// Directory of custom templates, instead of `template_dir`.
+    #[serde(default)]
+    pub template_dir: Option<PathBuf>,
+    /// Send mail this way instead of with the top-level `transport` or `mx`.
+    #[serde(default)]
+    pub transport: Option<transport::Config>,
+    /// Have the daemon mail the pipeline this often, after fetching feeds.
+    #[serde(default)]
+    pub interval_minutes: Option<NonZeroU32>,
+    /// Have the daemon mail the pipeline once a day at this local time, such as "07:00", instead
+    /// of at an interval.
+    #[serde(
+        default,
+        serialize_with = "serialize_at",
+        deserialize_with = "deserialize_at"
+    )]
+    pub at: Option<NaiveTime>,
+}
+impl Config {
+    /// The config to mail the pipeline with.
+    pub fn apply(&self, config: &crate::Config) -> crate::Config {
+        let mut config = config.clone();
+        if let Some(to_email) = &self.to_email {
+            config.delivery.to_email = to_email.clone();
+        }
+        if let Some(email_per_item) = self.email_per_item {
+            config.delivery.email_per_item = email_per_item;
+        }
+        if let Some(email_per_feed) = self.email_per_feed {
+            config.delivery.email_per_feed = email_per_feed;
+        }
+        if let Some(template_dir) = &self.template_dir {
+            config.template_dir = Some(template_dir.clone());
+        }
+        if let Some(transport) = &self.transport {
+            config.delivery.transport = Some(transport.clone());
+            config.delivery.mx = None;
+        }
+        config
+    }
+
+    /// Whether the daemon mails the pipeline on its own.
+    pub fn is_scheduled(&self) -> bool {
+        self.interval_minutes.is_some() || self.at.is_some()
+    }
+
+    /// When the daemon should next mail the pipeline, given when it last did, or `None` if it
+    /// isn't scheduled. A pipeline that was never mailed is due `now`.
+    pub fn next_run<Tz: TimeZone>(
+        &self,
+        last_run: Option<DateTime<Utc>>,
+        now: &DateTime<Tz>,
+    ) -> Option<DateTime<Utc>> {
+        if !self.is_scheduled() {
+            return None;
+        }
+        let Some(last_run) = last_run else {
+            return Some(now.with_timezone(&Utc));
+        };
+        if let Some(minutes) = self.interval_minutes {
+            return Some(last_run + Duration::minutes(i64::from(minutes.get())));
+        }
+        let at = self.at?;
+        let last_run = last_run.with_timezone(&now.timezone()).naive_local();
+        let mut next = last_run.date().and_time(at);
+        if next <= last_run {
+            next += Duration::days(1);
+        }
+        // Clocks skip over times when daylight saving time starts.
+        let next = now
+            .timezone()
+            .from_local_datetime(&next)
+            .earliest()
+            .or_else(|| {
+                now.timezone()
+                    .from_local_datetime(&(next + Duration::hours(1)))
+                    .earliest()
+            })?;
+        Some(next.with_timezone(&Utc))
+    }
+
+    /// Whether the daemon should mail the pipeline, given when it last did.
+    pub fn is_due<Tz: TimeZone>(
+        &self,
+        last_run: Option<DateTime<Utc>>,
+        now: &DateTime<Tz>,
+    ) -> bool {
+        self.next_run(last_run, now)
+            .is_some_and(|next| next <= now.with_timezone(&Utc))
+    }
+}
+
+// Serde passes fields by reference.
+#[allow(clippy::trivially_copy_pass_by_ref, clippy::ref_option)]
+fn serialize_at<S: serde::Serializer>(
+    at: &Option<NaiveTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match at {
+        Some(at) => window::serialize_time(at, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_at<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveTime>, D::Error> {
+    window::deserialize_time(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    fn pipeline(toml: &str) -> Config {
+        toml::from_str(toml).expect("invalid pipeline")
+    }
+
+    #[test]
+    fn apply_overrides_set_options() {
+        let config = crate::Config {
+            delivery: crate::config::Delivery {
+                email_per_item: true,
+                mx: Some(toml::from_str("").expect("invalid mx config")),
+                ..crate::config::Delivery::default()
+            },
+            template_dir: Some(PathBuf::from("templates")),
+            ..crate::Config::default()
+        };
+        let alerts = pipeline(
+            "name = \"alerts\"\n\
+             to_email = \"alerts@example.com\"\n\
+             transport = { type = \"stdout\" }",
+        )
+        .apply(&config);
+        assert_eq!(alerts.delivery.to_email.to_string(), "alerts@example.com");
+        assert!(alerts.delivery.email_per_item);
+        assert_eq!(alerts.template_dir, Some(PathBuf::from("templates")));
+        assert!(matches!(
+            alerts.delivery.transport,
+            Some(transport::Config::Stdout)
+        ));
+        assert!(alerts.delivery.mx.is_none());
+
+        let weekly = pipeline("name = \"weekly\"\nemail_per_item = false").apply(&config);
+        assert_eq!(
+            weekly.delivery.to_email.to_string(),
+            config.delivery.to_email.to_string()
+        );
+        assert!(!weekly.delivery.email_per_item);
+        assert!(weekly.delivery.mx.is_some());
+    }
+
+    #[test]
+    fn due_after_interval() {
+        let now = Utc.ymd(2019, 11, 7).and_hms(12, 0, 0);
+        let hourly = pipeline("name = \"hourly\"\ninterval_minutes = 60");
+        assert!(hourly.is_due(None, &now));
+        assert!(hourly.is_due(Some(now - Duration::minutes(60)), &now));
+        assert!(!hourly.is_due(Some(now - Duration::minutes(59)), &now));
+        assert!(!pipeline("name = \"manual\"").is_due(None, &now));
+    }
+
+    #[test]
+    fn due_daily_at_local_time() {
+        let tz = FixedOffset::west(5 * 3600);
+        let morning = pipeline("name = \"morning\"\nat = \"07:00\"");
+        let last_run = tz.ymd(2019, 11, 7).and_hms(7, 5, 0).with_timezone(&Utc);
+        assert_eq!(
+            morning.next_run(Some(last_run), &tz.ymd(2019, 11, 7).and_hms(12, 0, 0)),
+            Some(tz.ymd(2019, 11, 8).and_hms(7, 0, 0).with_timezone(&Utc))
+        );
+        assert!(!morning.is_due(Some(last_run), &tz.ymd(2019, 11, 8).and_hms(6, 59, 0)));
+        assert!(morning.is_due(Some(last_run), &tz.ymd(2019, 11, 8).and_hms(7, 0, 0)));
+        // A day that was missed is mailed once, as soon as possible.
+        assert!(morning.is_due(Some(last_run), &tz.ymd(2019, 11, 10).and_hms(3, 0, 0)));
+    }
+
+    #[test]
+    fn at_round_trips() {
+        let evening = pipeline("name = \"evening\"\nat = \"18:30\"");
+        assert_eq!(evening.at, NaiveTime::from_hms_opt(18, 30, 0));
+        assert_eq!(
+            toml::to_string(&evening).expect("failed to serialize"),
+            "name = \"evening\"\nat = \"18:30\"\n"
+        );
+        assert!(toml::from_str::<Config>("name = \"x\"\nat = \"7am\"").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{database, Config, Error, Result};
+
+// Reloads the page once the templates change from the version it was rendered with.
+const RELOAD_SCRIPT: &str = "<script>\n\
+setInterval(async () => {\n\
+  const response = await fetch('/version');\n\
+  if (response.ok && (await response.text()) !== VERSION) location.reload();\n\
+}, 1000);\n\
+</script>\n";
+
+/// Digest rendered for a recipient.
+struct Preview {
+    recipient: String,
+    html: String,
+}
+
+/// Serve the digests that would be sent, rendered again on each request, until the process is
+/// killed.
+pub fn serve(
+    config: &Config,
+    database: &mut database::Database,
+    listen: SocketAddr,
+    tag: Option<&str>,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).map_err(Error::PreviewServer)?;
+    eprintln!("Serving digests at http://{listen}/");
+    for stream in listener.incoming() {
+        match stream.and_then(|stream| handle(config, database, &stream, tag)) {
+            Ok(()) => {}
+            Err(e) => eprintln!("Failed to handle request: {e}"),
+        }
+    }
+    Ok(())
+}
+
+fn handle(
+    config: &Config,
+    database: &mut database::Database,
+    stream: &TcpStream,
+    tag: Option<&str>,
+) -> std::io::Result<()> {
+    let path = read_path(stream)?;
+    let version = templates_version(config);
+    if path == "/version" {
+        return respond(stream, "200 OK", "text/plain", &version);
+    }
+    let previews = match render(config, database, tag) {
+        Ok(previews) => previews,
+        Err(e) => {
+            let page = format!("<pre>{}</pre>\n", escape_html(&e.to_string()));
+            return respond(
+                stream,
+                "500 Internal Server Error",
+                "text/html",
+                &with_reload(&page, &version),
+            );
+        }
+    };
+    let index = match path.as_str() {
+        "/" if previews.len() == 1 => 0,
+        "/" => {
+            let mut page = String::from("<ul>\n");
+            for (i, preview) in previews.iter().enumerate() {
+                writeln!(
+                    page,
+                    "<li><a href=\"/digest/{i}\">{}</a></li>",
+                    escape_html(&preview.recipient)
+                )
+                .expect("writing to a string");
+            }
+            page.push_str("</ul>\n");
+            return respond(stream, "200 OK", "text/html", &with_reload(&page, &version));
+        }
+        path => match path
+            .strip_prefix("/digest/")
+            .and_then(|index| index.parse::<usize>().ok())
+            .filter(|&index| index < previews.len())
+        {
+            Some(index) => index,
+            None => return respond(stream, "404 Not Found", "text/plain", "Not found"),
+        },
+    };
+    respond(
+        stream,
+        "200 OK",
+        "text/html",
+        &with_reload(&previews[index].html, &version),
+    )
+}
+
+/// Render the digest of each recipient, with the templates as they are now.
+fn render(
+    config: &Config,
+    database: &mut database::Database,
+    tag: Option<&str>,
+) -> Result<Vec<Preview>> {
+    let subject = crate::digest_subject(config, tag);
+    let digests = crate::group_digests(config, database, tag)?;
+    let tera = crate::load_templates(config)?;
+    let mut previews = Vec::new();
+    for (recipient, feeds) in digests {
+        let sections = crate::digest_sections(config, database, recipient.as_deref())?;
+        previews.push(Preview {
+            recipient: recipient.unwrap_or_else(|| config.delivery.to_email.to_string()),
+            html: crate::render_html(config, &tera, &subject, feeds, &sections)?,
+        });
+    }
+    Ok(previews)
+}
+
+/// Path of a request, ignoring the rest of it.
+fn read_path(stream: &TcpStream) -> std::io::Result<String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    Ok(target.split('?').next().unwrap_or(target).to_string())
+}
+
+fn respond(
+    mut stream: &TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Add the reload script to a page, before the end of its body if it has one.
+fn with_reload(html: &str, version: &str) -> String {
+    let script = format!(
+        "<script>const VERSION = {};</script>\n{RELOAD_SCRIPT}",
+        serde_json::to_string(version).expect("string cannot be serialized")
+    );
+    match html.rfind("</body>") {
+        Some(index) => format!("{}{script}{}", &html[..index], &html[index..]),
+        None => format!("{html}{script}"),
+    }
+}
+
+/// Version of the custom templates, which changes when any of them is modified, added, or
+/// removed.
+fn templates_version(config: &Config) -> String {
+    let mut latest = UNIX_EPOCH;
+    let mut count = 0;
+    if let Some(template_dir) = &config.template_dir {
+        visit_files(template_dir, &mut |modified| {
+            latest = latest.max(modified);
+            count += 1;
+        });
+    }
+    if let Some(modified) = config
+        .template_path
+        .as_ref()
+        .and_then(|template_path| fs::metadata(template_path).ok()?.modified().ok())
+    {
+        latest = latest.max(modified);
+        count += 1;
+    }
+    let since_epoch = latest.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "{}.{}-{count}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    )
+}
+
+fn visit_files(dir: &Path, visit: &mut dyn FnMut(SystemTime)) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            visit_files(&entry.path(), visit);
+        } else if let Ok(modified) = metadata.modified() {
+            visit(modified);
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,531 @@
+//! QR codes of item links, for opening items from printed digests.
+//!
+//! Links are encoded in byte mode with medium error correction, in the smallest version they fit
+//! in, following ISO/IEC 18004.
+
+use std::convert::TryFrom;
+
+// Error correction codewords per block, by version.
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+// Error correction blocks, by version.
+const ERROR_CORRECTION_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23,
+    25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+];
+// Light modules around the code, as required for scanning.
+const QUIET_ZONE: usize = 4;
+// Pixels per module of rendered images.
+const SCALE: usize = 4;
+
+/// QR code, as rows of modules, with `true` for dark ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    size: usize,
+    modules: Vec<Vec<bool>>,
+}
+
+impl Code {
+    /// Encode text in the smallest version it fits in, or `None` if it's too long for any.
+    pub fn encode(text: &str) -> Option<Self> {
+        let bytes = text.as_bytes();
+        let version = (1..=40).find(|&version| {
+            let count_bits = if version < 10 { 8 } else { 16 };
+            bytes.len() < 1 << count_bits
+                && 4 + count_bits + bytes.len() * 8 <= data_codewords(version) * 8
+        })?;
+        let mut code = Self::empty(version);
+        let mut is_function = vec![vec![false; code.size]; code.size];
+        code.draw_function_patterns(version, &mut is_function);
+        code.draw_codewords(
+            &add_error_correction(version, &data(version, bytes)),
+            &is_function,
+        );
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                let mut masked = code.clone();
+                masked.apply_mask(mask, &is_function);
+                masked.draw_format_bits(mask);
+                masked.penalty()
+            })
+            .unwrap_or_default();
+        code.apply_mask(mask, &is_function);
+        code.draw_format_bits(mask);
+        Some(code)
+    }
+
+    fn empty(version: usize) -> Self {
+        let size = version * 4 + 17;
+        Self {
+            size,
+            modules: vec![vec![false; size]; size],
+        }
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y][x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, dark: bool, is_function: &mut [Vec<bool>]) {
+        self.modules[y][x] = dark;
+        is_function[y][x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize, is_function: &mut [Vec<bool>]) {
+        let size = self.size;
+        for i in 0..size {
+            self.set(6, i, i % 2 == 0, is_function);
+            self.set(i, 6, i % 2 == 0, is_function);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4_isize..=4 {
+                for dx in -4_isize..=4 {
+                    let (Some(xx), Some(yy)) = (x.checked_add_signed(dx), y.checked_add_signed(dy))
+                    else {
+                        continue;
+                    };
+                    if xx < size && yy < size {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set(xx, yy, distance != 2 && distance != 4, is_function);
+                    }
+                }
+            }
+        }
+        let positions = alignment_positions(version, size);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // Alignment patterns overlapping the finder patterns are left out.
+                if (i, j) == (0, 0) || (i, j) == (0, last) || (i, j) == (last, 0) {
+                    continue;
+                }
+                for dy in 0..5_usize {
+                    for dx in 0..5_usize {
+                        let distance = dx.abs_diff(2).max(dy.abs_diff(2));
+                        self.set(x + dx - 2, y + dy - 2, distance != 1, is_function);
+                    }
+                }
+            }
+        }
+        // Format bits are reserved here, and drawn once the mask is chosen.
+        self.draw_format_bits_into(0, is_function);
+        if version >= 7 {
+            let mut remainder = version;
+            for _ in 0..12 {
+                remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+            }
+            let bits = version << 12 | remainder;
+            for i in 0..18 {
+                let dark = bits >> i & 1 == 1;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set(a, b, dark, is_function);
+                self.set(b, a, dark, is_function);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: usize) {
+        let mut is_function = vec![vec![false; self.size]; self.size];
+        self.draw_format_bits_into(mask, &mut is_function);
+    }
+
+    fn draw_format_bits_into(&mut self, mask: usize, is_function: &mut [Vec<bool>]) {
+        // Medium error correction is 0b00, so the data is just the mask.
+        let mut remainder = mask;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (mask << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| bits >> i & 1 == 1;
+        let size = self.size;
+        for i in 0..6 {
+            self.set(8, i, bit(i), is_function);
+        }
+        self.set(8, 7, bit(6), is_function);
+        self.set(8, 8, bit(7), is_function);
+        self.set(7, 8, bit(8), is_function);
+        for i in 9..15 {
+            self.set(14 - i, 8, bit(i), is_function);
+        }
+        for i in 0..8 {
+            self.set(size - 1 - i, 8, bit(i), is_function);
+        }
+        for i in 8..15 {
+            self.set(8, size - 15 + i, bit(i), is_function);
+        }
+        self.set(8, size - 8, true, is_function);
+    }
+
+    /// Place codewords in the zigzag order of pairs of columns, from the bottom right.
+    fn draw_codewords(&mut self, codewords: &[u8], is_function: &[Vec<bool>]) {
+        let mut i = 0;
+        let mut right = self.size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..self.size {
+                for x in [right, right - 1] {
+                    let y = if upward {
+                        self.size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !is_function[y][x] && i < codewords.len() * 8 {
+                        self.modules[y][x] = codewords[i >> 3] >> (7 - (i & 7)) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: usize, is_function: &[Vec<bool>]) {
+        for (y, (row, is_function)) in self.modules.iter_mut().zip(is_function).enumerate() {
+            for (x, (dark, &is_function)) in row.iter_mut().zip(is_function).enumerate() {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && !is_function {
+                    *dark = !*dark;
+                }
+            }
+        }
+    }
+
+    /// Penalty of the patterns that make a code harder to scan, to choose the mask with.
+    fn penalty(&self) -> usize {
+        const FINDER_LIKE: [bool; 11] = [
+            true, false, true, true, true, false, true, false, false, false, false,
+        ];
+        let lines = (0..self.size).flat_map(|i| {
+            [
+                (0..self.size)
+                    .map(|j| self.is_dark(j, i))
+                    .collect::<Vec<_>>(),
+                (0..self.size).map(|j| self.is_dark(i, j)).collect(),
+            ]
+        });
+        let mut penalty = 0;
+        for line in lines {
+            for run in line.chunk_by(|a, b| a == b) {
+                if run.len() >= 5 {
+                    penalty += run.len() - 2;
+                }
+            }
+            for window in line.windows(FINDER_LIKE.len()) {
+                if window == FINDER_LIKE || window.iter().rev().eq(FINDER_LIKE.iter()) {
+                    penalty += 40;
+                }
+            }
+        }
+        for y in 1..self.size {
+            for x in 1..self.size {
+                let dark = self.is_dark(x, y);
+                if dark == self.is_dark(x - 1, y)
+                    && dark == self.is_dark(x, y - 1)
+                    && dark == self.is_dark(x - 1, y - 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().flatten().filter(|&&dark| dark).count();
+        let percent = dark * 100 / (self.size * self.size);
+        penalty + percent.abs_diff(50) / 5 * 10
+    }
+
+    /// Render as a black and white PNG image, with a quiet zone around the code.
+    pub fn to_png(&self) -> Vec<u8> {
+        let width = (self.size + QUIET_ZONE * 2) * SCALE;
+        let mut pixels = Vec::with_capacity((width / 8 + 2) * width);
+        for y in 0..width {
+            // Each row starts with its filter type, which is none.
+            pixels.push(0);
+            let mut byte = 0;
+            for x in 0..width {
+                let (mx, my) = (x / SCALE, y / SCALE);
+                let dark = (QUIET_ZONE..QUIET_ZONE + self.size).contains(&mx)
+                    && (QUIET_ZONE..QUIET_ZONE + self.size).contains(&my)
+                    && self.is_dark(mx - QUIET_ZONE, my - QUIET_ZONE);
+                // In 1-bit grayscale, 1 is white.
+                byte = byte << 1 | u8::from(!dark);
+                if x % 8 == 7 {
+                    pixels.push(byte);
+                    byte = 0;
+                }
+            }
+            if !width.is_multiple_of(8) {
+                pixels.push(byte << (8 - width % 8));
+            }
+        }
+        let width = u32::try_from(width).expect("QR code too large");
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&width.to_be_bytes());
+        // Bit depth 1, grayscale, default compression, filtering, and no interlacing.
+        header.extend_from_slice(&[1, 0, 0, 0, 0]);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_chunk(&mut png, *b"IHDR", &header);
+        write_chunk(
+            &mut png,
+            *b"IDAT",
+            &miniz_oxide::deflate::compress_to_vec_zlib(&pixels, 9),
+        );
+        write_chunk(&mut png, *b"IEND", &[]);
+        png
+    }
+}
+
+/// PNG image of the QR code of a link, as a data URI, or `None` if the link is too long.
+pub fn data_uri(link: &str) -> Option<String> {
+    let png = Code::encode(link)?.to_png();
+    Some(format!("data:image/png;base64,{}", base64::encode(&png)))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) {
+    let length = u32::try_from(data.len()).expect("PNG chunk too large");
+    png.extend_from_slice(&length.to_be_bytes());
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&kind);
+    hasher.update(data);
+    png.extend_from_slice(&kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+/// Modules available for codewords, after function patterns and format and version bits.
+fn raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        modules -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8
+        - ECC_CODEWORDS_PER_BLOCK[version] * ERROR_CORRECTION_BLOCKS[version]
+}
+
+/// Centers of alignment patterns, along each axis.
+fn alignment_positions(version: usize, size: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let alignments = version / 7 + 2;
+    let step = (version * 8 + alignments * 3 + 5) / (alignments * 4 - 4) * 2;
+    let mut positions: Vec<usize> = (0..alignments - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Data codewords of text in byte mode, padded to the capacity of the version.
+fn data(version: usize, bytes: &[u8]) -> Vec<u8> {
+    let mut bits = Vec::new();
+    let mut push = |value: usize, count: usize| {
+        bits.extend((0..count).rev().map(|i| value >> i & 1 == 1));
+    };
+    push(0b0100, 4);
+    push(bytes.len(), if version < 10 { 8 } else { 16 });
+    for &byte in bytes {
+        push(usize::from(byte), 8);
+    }
+    let capacity = data_codewords(version) * 8;
+    let terminator = (capacity - bits.len()).min(4);
+    bits.resize(bits.len() + terminator, false);
+    bits.resize(bits.len().div_ceil(8) * 8, false);
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+        .collect();
+    for pad in [0xEC, 0x11].iter().cycle() {
+        if codewords.len() * 8 >= capacity {
+            break;
+        }
+        codewords.push(*pad);
+    }
+    codewords
+}
+
+/// Split data into blocks, add their error correction codewords, and interleave them.
+fn add_error_correction(version: usize, data: &[u8]) -> Vec<u8> {
+    let blocks_len = ERROR_CORRECTION_BLOCKS[version];
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    let short_blocks = blocks_len - raw_codewords % blocks_len;
+    let short_block_len = raw_codewords / blocks_len;
+    let divisor = reed_solomon_divisor(ecc_len);
+    let mut blocks = Vec::with_capacity(blocks_len);
+    let mut start = 0;
+    for i in 0..blocks_len {
+        let len = short_block_len - ecc_len + usize::from(i >= short_blocks);
+        let mut block = data[start..start + len].to_vec();
+        start += len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        if i < short_blocks {
+            // Padding to line up short blocks with long ones, skipped when interleaving.
+            block.push(0);
+        }
+        block.extend(ecc);
+        blocks.push(block);
+    }
+    let mut codewords = Vec::with_capacity(raw_codewords);
+    for i in 0..blocks[0].len() {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_block_len - ecc_len || j >= short_blocks {
+                codewords.push(block[i]);
+            }
+        }
+    }
+    codewords
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut divisor = vec![0; degree - 1];
+    divisor.push(1);
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            divisor[j] = multiply(divisor[j], root);
+            if j + 1 < degree {
+                divisor[j] ^= divisor[j + 1];
+            }
+        }
+        root = multiply(root, 0x02);
+    }
+    divisor
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut remainder = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ remainder.remove(0);
+        remainder.push(0);
+        for (r, &d) in remainder.iter_mut().zip(divisor) {
+            *r ^= multiply(d, factor);
+        }
+    }
+    remainder
+}
+
+/// Product in GF(2^8), modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn multiply(x: u8, y: u8) -> u8 {
+    let mut product: u8 = 0;
+    for i in (0..8).rev() {
+        product = (product << 1) ^ ((product >> 7) * 0x1D);
+        product ^= ((y >> i) & 1) * x;
+    }
+    product
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_chooses_smallest_version() {
+        assert_eq!(
+            Code::encode("https://example.com/").map(|c| c.size),
+            Some(25)
+        );
+        assert_eq!(Code::encode(&"a".repeat(2331)).map(|c| c.size), Some(177));
+        assert_eq!(Code::encode(&"a".repeat(2332)), None);
+    }
+
+    #[test]
+    fn encode_draws_format_bits_of_medium_error_correction() {
+        // Format bits of medium error correction with each mask, from ISO/IEC 18004.
+        const FORMAT_BITS: [&str; 8] = [
+            "101010000010010",
+            "101000100100101",
+            "101111001111100",
+            "101101101001011",
+            "100010111111001",
+            "100000011001110",
+            "100111110010111",
+            "100101010100000",
+        ];
+        let code = Code::encode("https://example.com/").expect("failed to encode");
+        let bit = |x, y| if code.is_dark(x, y) { '1' } else { '0' };
+        // Most significant bit first, along the row below the top left finder pattern, then up
+        // the column to its right.
+        let first: String = [
+            (0, 8),
+            (1, 8),
+            (2, 8),
+            (3, 8),
+            (4, 8),
+            (5, 8),
+            (7, 8),
+            (8, 8),
+        ]
+        .iter()
+        .chain(&[(8, 7), (8, 5), (8, 4), (8, 3), (8, 2), (8, 1), (8, 0)])
+        .map(|&(x, y)| bit(x, y))
+        .collect();
+        let size = code.size;
+        let second: String = (0..7)
+            .map(|i| bit(8, size - 1 - i))
+            .chain((0..8).map(|i| bit(size - 8 + i, 8)))
+            .collect();
+        assert!(FORMAT_BITS.contains(&first.as_str()), "{}", first);
+        assert_eq!(first, second);
+        assert!(code.is_dark(8, size - 8));
+    }
+
+    #[test]
+    fn encode_draws_version_bits() {
+        let code = Code::encode(&"a".repeat(110)).expect("failed to encode");
+        assert_eq!(code.size, 45);
+        // Version 7, from ISO/IEC 18004, least significant bit first.
+        let bits = 0b00_0111_1100_1001_0100;
+        for i in 0..18 {
+            let (a, b) = (code.size - 11 + i % 3, i / 3);
+            assert_eq!(code.is_dark(a, b), bits >> i & 1 == 1);
+            assert_eq!(code.is_dark(b, a), bits >> i & 1 == 1);
+        }
+    }
+
+    #[test]
+    fn reed_solomon_remainder_of_known_block() {
+        // Error correction of the data of a version 1-M code of "01234567", from ISO/IEC 18004.
+        let data = [
+            0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11,
+            0xEC, 0x11,
+        ];
+        assert_eq!(
+            reed_solomon_remainder(&data, &reed_solomon_divisor(10)),
+            [0xA5, 0x24, 0xD4, 0xC1, 0xED, 0x36, 0xC7, 0x87, 0x2C, 0x55]
+        );
+    }
+
+    #[test]
+    fn to_png_has_signature_and_size() {
+        let png = Code::encode("https://example.com/")
+            .expect("failed to encode")
+            .to_png();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"));
+        assert_eq!(&png[16..24], [0, 0, 0, 132, 0, 0, 0, 132]);
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Value of a query parameter, with expressions such as `{epoch}` evaluated each time the feed
+/// is fetched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// Seconds since the Unix epoch.
+    Epoch,
+    /// Milliseconds since the Unix epoch.
+    EpochMs,
+    /// UTC date, such as "2019-11-07".
+    Date,
+    /// UTC date and time in RFC 3339 format, such as "2019-11-07T05:00:00Z".
+    DateTime,
+}
+
+impl Template {
+    pub fn render(&self, now: DateTime<Utc>) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Epoch => now.timestamp().to_string(),
+                Part::EpochMs => now.timestamp_millis().to_string(),
+                Part::Date => now.format("%Y-%m-%d").to_string(),
+                Part::DateTime => now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            })
+            .collect()
+    }
+}
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => f.write_str(&text.replace('{', "{{").replace('}', "}}"))?,
+                Part::Epoch => f.write_str("{epoch}")?,
+                Part::EpochMs => f.write_str("{epoch_ms}")?,
+                Part::Date => f.write_str("{date}")?,
+                Part::DateTime => f.write_str("{datetime}")?,
+            }
+        }
+        Ok(())
+    }
+}
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("unclosed expression in {s:?}"))?;
+                    let part = match &rest[..end] {
+                        "epoch" => Part::Epoch,
+                        "epoch_ms" => Part::EpochMs,
+                        "date" => Part::Date,
+                        "datetime" => Part::DateTime,
+                        name => {
+                            return Err(format!(
+                                "unknown expression {{{name}}} in {s:?}, expected {{epoch}}, \
+                                 {{epoch_ms}}, {{date}}, or {{datetime}}"
+                            ))
+                        }
+                    };
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                    chars = rest[end + 1..].chars();
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self(parts))
+    }
+}
+impl Serialize for Template {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// URL of a feed with query parameters added, evaluated at `now`.
+pub fn url(
+    feed_url: &str,
+    query: &BTreeMap<String, Template>,
+    now: DateTime<Utc>,
+) -> Result<String, url::ParseError> {
+    if query.is_empty() {
+        return Ok(feed_url.to_string());
+    }
+    let mut url = url::Url::parse(feed_url)?;
+    url.query_pairs_mut().extend_pairs(
+        query
+            .iter()
+            .map(|(name, template)| (name, template.render(now))),
+    );
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn template(s: &str) -> Template {
+        s.parse().expect("invalid template")
+    }
+
+    #[test]
+    fn template_renders_expressions() {
+        let now = Utc.ymd(2019, 11, 7).and_hms_milli(5, 0, 0, 250);
+        assert_eq!(template("{epoch}").render(now), "1573102800");
+        assert_eq!(template("{epoch_ms}").render(now), "1573102800250");
+        assert_eq!(template("since-{date}").render(now), "since-2019-11-07");
+        assert_eq!(template("{datetime}").render(now), "2019-11-07T05:00:00Z");
+        assert_eq!(template("{{epoch}}").render(now), "{epoch}");
+    }
+
+    #[test]
+    fn template_rejects_unknown_expressions() {
+        assert!("{now}".parse::<Template>().is_err());
+        assert!("{epoch".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn template_displays_as_parsed() {
+        for s in ["key-{epoch}", "{{literal}}", "{date}T{datetime}"] {
+            assert_eq!(template(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn url_adds_query_parameters() {
+        let now = Utc.ymd(2019, 11, 7).and_hms(5, 0, 0);
+        let query = BTreeMap::from([
+            ("ts".to_string(), template("{epoch}")),
+            ("q".to_string(), template("a b")),
+        ]);
+        assert_eq!(
+            url("https://example.com/feed?format=rss", &query, now),
+            Ok("https://example.com/feed?format=rss&q=a+b&ts=1573102800".to_string())
+        );
+        assert_eq!(
+            url("https://example.com/feed", &BTreeMap::new(), now),
+            Ok("https://example.com/feed".to_string())
+        );
+    }
+}
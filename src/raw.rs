@@ -0,0 +1,135 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+/// Raw body of a feed, saved as it's read, and only kept once it's finished.
+///
+/// Bodies are saved in a directory per feed, named after the time they were fetched.
+pub struct Body {
+    file: fs::File,
+    feed_dir: PathBuf,
+    name: String,
+    finished: bool,
+}
+
+impl Body {
+    pub fn create(dir: &Path, feed_url: &str) -> io::Result<Self> {
+        let feed_dir = dir.join(dir_name(feed_url));
+        fs::create_dir_all(&feed_dir)?;
+        let name = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+        // Named so it isn't taken for a body, or removed as one, until it's finished.
+        let file = fs::File::create(feed_dir.join(format!("{name}.part")))?;
+        Ok(Self {
+            file,
+            feed_dir,
+            name,
+            finished: false,
+        })
+    }
+
+    /// Keep the body, and only the newest `keep` bodies of the feed. Only the bodies are removed,
+    /// not other files that were put in the directory.
+    pub fn finish(mut self, keep: usize) -> io::Result<PathBuf> {
+        self.finished = true;
+        self.file.flush()?;
+        let path = self.feed_dir.join(format!("{}.raw", self.name));
+        fs::rename(self.feed_dir.join(format!("{}.part", self.name)), &path)?;
+        let mut paths = fs::read_dir(&self.feed_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|extension| extension == "raw"));
+        // Names sort in the order the bodies were fetched.
+        paths.sort();
+        for old_path in paths.iter().rev().skip(keep) {
+            fs::remove_file(old_path)?;
+        }
+        Ok(path)
+    }
+}
+
+impl Write for Body {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for Body {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(self.feed_dir.join(format!("{}.part", self.name)));
+        }
+    }
+}
+
+/// Directory name for a feed, made from its URL, and a short hash of it so URLs that only differ
+/// in their scheme or in characters that are replaced get their own directory.
+fn dir_name(feed_url: &str) -> String {
+    let url = feed_url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let name: String = url
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let hash = format!("{:x}", Sha256::digest(feed_url.as_bytes()));
+    format!("{name}-{}", &hash[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dir_names_of_different_urls_differ() {
+        let names = [
+            "http://example.com/feed.xml",
+            "https://example.com/feed.xml",
+            "https://example.com/feed?id=1",
+            "https://example.com/feed_id_1",
+        ]
+        .map(dir_name);
+        assert!(names[0].starts_with("example.com_feed.xml-"));
+        for (i, name) in names.iter().enumerate() {
+            assert!(!names[..i].contains(name), "{} is used twice", name);
+        }
+        assert_eq!(dir_name("https://example.com/feed.xml"), names[1]);
+    }
+
+    #[test]
+    fn finish_keeps_the_newest_bodies_and_other_files() {
+        let dir = std::env::temp_dir().join(format!("squeakmail-raw-{}", std::process::id()));
+        let feed_url = "https://example.com/feed.xml";
+        let feed_dir = dir.join(dir_name(feed_url));
+        fs::create_dir_all(&feed_dir).expect("failed to create dir");
+        fs::write(feed_dir.join("notes.txt"), "parse error on line 3").expect("failed to write");
+        // A body that wasn't finished, such as one that was too large, isn't kept.
+        Body::create(&dir, feed_url).expect("failed to create");
+        let mut saved = Vec::new();
+        for body in ["first", "second", "third"] {
+            let mut raw = Body::create(&dir, feed_url).expect("failed to create");
+            raw.write_all(body.as_bytes()).expect("failed to write");
+            saved.push(raw.finish(2).expect("failed to save"));
+            // Bodies are named after the millisecond they were fetched.
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let kept = (
+            saved.iter().map(|path| path.exists()).collect::<Vec<_>>(),
+            feed_dir.join("notes.txt").exists(),
+            fs::read_dir(&feed_dir).expect("failed to read dir").count(),
+        );
+        fs::remove_dir_all(&dir).expect("failed to remove dir");
+        assert_eq!(kept, (vec![false, true, true], true, 3));
+    }
+}
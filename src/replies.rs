@@ -0,0 +1,234 @@
+//! Commands in replies to digests, such as "save 3", which refer to items and feeds by the numbers
+//! they're listed with.
+
+use std::fmt;
+
+use regex::Regex;
+
+use crate::{database, message};
+
+/// What to do with a numbered item or feed of a digest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Save an item for later, to the read-it-later service if one is configured.
+    Save,
+    Highlight,
+    MuteFeed,
+    UnmuteFeed,
+}
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Save => "save",
+            Self::Highlight => "highlight",
+            Self::MuteFeed => "mute feed",
+            Self::UnmuteFeed => "unmute feed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    pub action: Action,
+    /// Number the item or feed is listed with in the digest, starting from 1.
+    pub number: usize,
+}
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.action, self.number)
+    }
+}
+
+/// Reply to a digest.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Reply {
+    /// Address the reply is from.
+    pub from: String,
+    /// Message-ID of the message replied to.
+    pub in_reply_to: String,
+    pub commands: Vec<Command>,
+}
+impl Reply {
+    /// Read the commands of a reply, one per line, above the quoted message. Returns `None` if
+    /// the message isn't a reply.
+    pub fn parse(message: &str) -> Option<Self> {
+        let message = message::crlf_line_endings(message);
+        let (header, body) = message::split(&message);
+        let mut from = None;
+        let mut in_reply_to = None;
+        for field in message::header_fields(header) {
+            let name = message::field_name(&field);
+            let value = field.split_once(':').map_or("", |(_, value)| value).trim();
+            if name.eq_ignore_ascii_case("from") {
+                from = Some(address(value));
+            } else if name.eq_ignore_ascii_case("in-reply-to") {
+                in_reply_to = value
+                    .split_once('<')
+                    .and_then(|(_, rest)| rest.split_once('>'))
+                    .map(|(id, _)| format!("<{id}>"));
+            }
+        }
+        let pattern = Regex::new(r"(?i)^(save|highlight|mute feed|unmute feed)((?:[\s,]+\d+)+)$")
+            .expect("invalid command pattern");
+        let mut commands = Vec::new();
+        for line in body.lines().map(str::trim) {
+            // The quoted digest is below the commands.
+            if line.starts_with('>') {
+                break;
+            }
+            let Some(captures) = pattern.captures(line) else {
+                continue;
+            };
+            let action = match captures[1].to_lowercase().as_str() {
+                "save" => Action::Save,
+                "highlight" => Action::Highlight,
+                "mute feed" => Action::MuteFeed,
+                _ => Action::UnmuteFeed,
+            };
+            commands.extend(
+                captures[2]
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter_map(|number| number.parse().ok())
+                    .map(|number| Command { action, number }),
+            );
+        }
+        Some(Self {
+            from: from?,
+            in_reply_to: in_reply_to?,
+            commands,
+        })
+    }
+}
+
+/// Address of a mailbox, such as the `a@example.com` of `A <a@example.com>`.
+fn address(mailbox: &str) -> String {
+    mailbox
+        .rsplit_once('<')
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map_or(mailbox, |(address, _)| address)
+        .trim()
+        .to_string()
+}
+
+/// Follow the commands of a reply to a digest, if it's from the digest's recipient. Replies to
+/// other messages are ignored.
+pub fn apply(
+    config: &crate::Config,
+    database: &mut database::Database,
+    reply: &Reply,
+) -> Result<(), database::Error> {
+    let Some((recipient, items)) = database.get_digest_by_message_id(&reply.in_reply_to)? else {
+        return Ok(());
+    };
+    if !reply.from.eq_ignore_ascii_case(&recipient) {
+        eprintln!(
+            "Ignoring reply from {} to a digest sent to {recipient}",
+            reply.from
+        );
+        return Ok(());
+    }
+    // Feeds are numbered in the order their items are listed.
+    let mut feeds: Vec<&str> = Vec::new();
+    for item in &items {
+        if !feeds.contains(&item.feed_url.as_str()) {
+            feeds.push(&item.feed_url);
+        }
+    }
+    for command in &reply.commands {
+        let index = command.number.checked_sub(1);
+        let item = index.and_then(|i| items.get(i));
+        let feed_url = index.and_then(|i| feeds.get(i));
+        match (command.action, item, feed_url) {
+            (Action::Save, Some(item), _) => save(config, database, item)?,
+            (Action::Highlight, Some(item), _) => {
+                database.mark_items_highlighted(std::slice::from_ref(item))?;
+                eprintln!("Highlighted {}", item.guid);
+            }
+            (Action::MuteFeed, _, Some(feed_url)) => {
+                database.set_feed_muted(feed_url, true)?;
+                eprintln!("Muted {feed_url}");
+            }
+            (Action::UnmuteFeed, _, Some(feed_url)) => {
+                database.set_feed_muted(feed_url, false)?;
+                eprintln!("Unmuted {feed_url}");
+            }
+            _ => eprintln!(
+                "Ignoring \"{command}\" in reply from {}: the digest has no such number",
+                reply.from
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Save an item to the read-it-later service, or only mark it saved if there isn't one.
+fn save(
+    config: &crate::Config,
+    database: &mut database::Database,
+    item: &database::DigestItem,
+) -> Result<(), database::Error> {
+    if let Some(read_later) = &config.read_later {
+        let Some(stored) = database
+            .get_items_by_guid(&item.guid, Some(&item.feed_url))?
+            .into_iter()
+            .next()
+        else {
+            return Ok(());
+        };
+        if let Err(e) = crate::later::save(read_later, &item.link, &stored.title) {
+            eprintln!("Failed to save {} for later: {e}", item.link);
+            return Ok(());
+        }
+    }
+    database.mark_items_saved(std::slice::from_ref(item))?;
+    eprintln!("Saved {} for later", item.link);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands_above_quote() {
+        let message = "From: Reader <reader@example.org>\r\n\
+                       In-Reply-To: <0123abcd@example.com>\r\n\
+                       Subject: Re: Digest\r\n\
+                       \r\n\
+                       Save 3, 5\r\n\
+                       mute feed 2\r\n\
+                       Thanks!\r\n\
+                       \r\n\
+                       On Thu, 7 Nov 2019, digest@example.com wrote:\r\n\
+                       > highlight 1\r\n";
+        assert_eq!(
+            Reply::parse(message),
+            Some(Reply {
+                from: "reader@example.org".to_string(),
+                in_reply_to: "<0123abcd@example.com>".to_string(),
+                commands: vec![
+                    Command {
+                        action: Action::Save,
+                        number: 3
+                    },
+                    Command {
+                        action: Action::Save,
+                        number: 5
+                    },
+                    Command {
+                        action: Action::MuteFeed,
+                        number: 2
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn parse_requires_reply() {
+        assert_eq!(
+            Reply::parse("From: reader@example.org\r\n\r\nsave 1\r\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use derive_more::{Display, From};
+use url::Url;
+
+/// Maximum number of redirects to follow from a link.
+const MAX_HOPS: usize = 10;
+
+#[derive(Debug, From, Display)]
+pub enum Error {
+    #[display(fmt = "invalid URL: {_0}")]
+    InvalidUrl(url::ParseError),
+    Http(attohttpc::Error),
+}
+
+/// Follow redirects from a link, such as through a tracking service, to its final URL.
+///
+/// Gives up after `MAX_HOPS` redirects and returns the last URL reached.
+pub fn resolve(link: &str) -> Result<String, Error> {
+    let mut url = Url::parse(link)?;
+    for _ in 0..MAX_HOPS {
+        let resp = attohttpc::head(url.as_str())
+            .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+            .timeout(Duration::from_secs(30))
+            .follow_redirects(false)
+            .send()?;
+        if !resp.status().is_redirection() {
+            break;
+        }
+        let Some(location) = resp
+            .headers()
+            .get(attohttpc::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+        else {
+            break;
+        };
+        url = url.join(location)?;
+    }
+    Ok(url.into_string())
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rules from a robots.txt file that apply to us.
+#[derive(Debug, Default)]
+pub struct Robots {
+    /// Path patterns, and whether they are allowed.
+    rules: Vec<(String, bool)>,
+    crawl_delay: Option<Duration>,
+}
+impl Robots {
+    /// Parse the group of a robots.txt file for the user agent, or the `*` group if there is none.
+    pub fn parse(text: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_lowercase();
+        let mut specific = None;
+        let mut wildcard = None;
+        // User agents of the current group, and whether its rules have started.
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        let mut group = Self::default();
+        let mut finish_group = |agents: &[String], group: Self| {
+            if agents
+                .iter()
+                .any(|agent| user_agent.contains(agent.as_str()))
+            {
+                specific.get_or_insert(group);
+            } else if agents.iter().any(|agent| agent == "*") {
+                wildcard.get_or_insert(group);
+            }
+        };
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match field.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        finish_group(&agents, std::mem::take(&mut group));
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_lowercase());
+                }
+                "allow" | "disallow" if !value.is_empty() => {
+                    in_rules = true;
+                    let allow = field.trim().eq_ignore_ascii_case("allow");
+                    group.rules.push((value.to_string(), allow));
+                }
+                "disallow" => in_rules = true,
+                "crawl-delay" => {
+                    in_rules = true;
+                    group.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|delay| delay.is_finite() && *delay >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+        finish_group(&agents, group);
+        specific.or(wildcard).unwrap_or_default()
+    }
+
+    /// Whether the path (including any query) may be fetched.
+    ///
+    /// The longest matching rule wins, and allow rules win ties.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| pattern_matches(pattern, path))
+            .max_by_key(|(pattern, allow)| (pattern.len(), *allow))
+            .is_none_or(|(_, allow)| *allow)
+    }
+}
+
+/// Match a robots.txt path pattern, which may contain `*` wildcards and a trailing `$` anchor.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+struct Host {
+    robots: Robots,
+    next_request: Instant,
+}
+
+/// Per-host robots.txt rules, shared between fetch threads.
+#[derive(Default)]
+pub struct Cache {
+    hosts: Mutex<HashMap<String, Host>>,
+}
+impl Cache {
+    /// Check whether robots.txt allows fetching the URL, and wait for its crawl delay.
+    ///
+    /// Returns `false` if the URL may not be fetched.
+    pub fn wait_until_allowed(&self, url: &url::Url) -> bool {
+        let origin = url.origin().ascii_serialization();
+        if !self.lock().contains_key(&origin) {
+            // Fetch without holding the lock, so other hosts aren't blocked.
+            let robots = fetch_robots(&origin);
+            self.lock().entry(origin.clone()).or_insert(Host {
+                robots,
+                next_request: Instant::now(),
+            });
+        }
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+        let wait_until = {
+            let mut hosts = self.lock();
+            let host = hosts.get_mut(&origin).expect("host was inserted");
+            if !host.robots.is_allowed(&path) {
+                return false;
+            }
+            let wait_until = std::cmp::max(host.next_request, Instant::now());
+            host.next_request = wait_until + host.robots.crawl_delay.unwrap_or_default();
+            wait_until
+        };
+        thread::sleep(wait_until.saturating_duration_since(Instant::now()));
+        true
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Host>> {
+        self.hosts
+            .lock()
+            .expect("thread panicked while holding robots mutex")
+    }
+}
+
+/// Fetch robots.txt of an origin, treating a missing or unreadable file as allowing everything.
+fn fetch_robots(origin: &str) -> Robots {
+    let text = attohttpc::get(format!("{origin}/robots.txt"))
+        .header(attohttpc::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+        .timeout(Duration::from_secs(30))
+        .send()
+        .ok()
+        .filter(|resp| resp.is_success())
+        .and_then(|resp| resp.text().ok());
+    text.map_or_else(Robots::default, |text| {
+        Robots::parse(&text, env!("CARGO_PKG_NAME"))
+    })
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+
+use lettre::EmailAddress;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::feed;
+
+/// Regular expression that can be read from and written to the config file.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+impl Pattern {
+    fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Don't store the item.
+    Drop,
+    /// Store the item as already read.
+    MarkRead,
+    /// Emphasize the item in the digest.
+    Highlight,
+    /// Attach a tag to the item.
+    Tag(String),
+    /// Send the item in a separate digest to another address.
+    Route(EmailAddress),
+    /// Save the item to the read-it-later service, and store it as read instead of mailing it.
+    ReadLater,
+}
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Drop => f.write_str("drop"),
+            Self::MarkRead => f.write_str("mark read"),
+            Self::Highlight => f.write_str("highlight"),
+            Self::Tag(tag) => write!(f, "tag {tag:?}"),
+            Self::Route(recipient) => write!(f, "route to {recipient}"),
+            Self::ReadLater => f.write_str("read later"),
+        }
+    }
+}
+
+/// Rule that applies an action to items matching all of its conditions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Name or URL of the feed the item belongs to.
+    pub feed: Option<String>,
+    /// URL of the feed that `feed` refers to, once the config is loaded.
+    #[serde(skip)]
+    pub feed_url: Option<String>,
+    /// Regular expression matching the item title.
+    pub title: Option<Pattern>,
+    /// Category of the item, compared case-insensitively.
+    pub category: Option<String>,
+    /// Regular expression matching the item author.
+    pub author: Option<Pattern>,
+    pub action: Action,
+}
+impl Rule {
+    /// Whether the rule has conditions on fields that aren't stored with items.
+    pub fn needs_unstored_fields(&self) -> bool {
+        self.category.is_some() || self.author.is_some()
+    }
+
+    pub fn applies_to_feed(&self, feed_url: &str) -> bool {
+        self.feed_url
+            .as_ref()
+            .or(self.feed.as_ref())
+            .is_none_or(|feed| feed == feed_url)
+    }
+
+    fn matches(&self, feed_url: &str, item: &feed::Item) -> bool {
+        self.applies_to_feed(feed_url)
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|title| title.is_match(&item.title))
+            && self.category.as_ref().is_none_or(|category| {
+                item.categories
+                    .iter()
+                    .any(|item_category| item_category.eq_ignore_ascii_case(category))
+            })
+            && self.author.as_ref().is_none_or(|author| {
+                item.author
+                    .as_ref()
+                    .is_some_and(|item_author| author.is_match(item_author))
+            })
+    }
+}
+
+/// Result of evaluating rules against an item.
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Outcome {
+    pub is_dropped: bool,
+    pub is_read: bool,
+    pub is_highlighted: bool,
+    pub read_later: bool,
+    pub recipient: Option<EmailAddress>,
+    pub tags: Vec<String>,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_dropped {
+            return f.write_str("dropped");
+        }
+        let mut effects = Vec::new();
+        if self.is_read {
+            effects.push("read".to_string());
+        }
+        if self.is_highlighted {
+            effects.push("highlighted".to_string());
+        }
+        if self.read_later {
+            effects.push("saved for later".to_string());
+        }
+        if let Some(recipient) = &self.recipient {
+            effects.push(format!("routed to {recipient}"));
+        }
+        if !self.tags.is_empty() {
+            effects.push(format!("tagged {}", self.tags.join(", ")));
+        }
+        if effects.is_empty() {
+            f.write_str("stored")
+        } else {
+            write!(f, "stored as {}", effects.join(", "))
+        }
+    }
+}
+
+/// Matching rules whose actions apply to an item, with their indexes, in order up to the first
+/// that drops it.
+pub fn applied<'a>(rules: &'a [Rule], feed_url: &str, item: &feed::Item) -> Vec<(usize, &'a Rule)> {
+    let mut applied = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        if rule.matches(feed_url, item) {
+            applied.push((index, rule));
+            if let Action::Drop = rule.action {
+                break;
+            }
+        }
+    }
+    applied
+}
+
+/// Apply the actions of every matching rule in order, stopping if the item is dropped.
+pub fn evaluate(rules: &[Rule], feed_url: &str, item: &feed::Item) -> Outcome {
+    let mut outcome = Outcome::default();
+    for (_, rule) in applied(rules, feed_url, item) {
+        match &rule.action {
+            Action::Drop => outcome.is_dropped = true,
+            Action::MarkRead => outcome.is_read = true,
+            Action::Highlight => outcome.is_highlighted = true,
+            Action::Tag(tag) => outcome.tags.push(tag.clone()),
+            Action::Route(recipient) => outcome.recipient = Some(recipient.clone()),
+            Action::ReadLater => outcome.read_later = true,
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn rule(toml: &str) -> Rule {
+        toml::from_str(toml).expect("invalid rule")
+    }
+
+    #[test]
+    fn applied_rules_stop_at_drop() {
+        let rules = [
+            rule("title = \"Rust\"\naction = \"highlight\""),
+            rule("feed = \"https://example.org/other.xml\"\naction = \"drop\""),
+            rule("action = {tag = \"news\"}"),
+            rule("title = \"Async\"\naction = \"drop\""),
+            rule("action = \"mark_read\""),
+        ];
+        let item = |title: &str| feed::Item {
+            guid: "1".to_string(),
+            title: title.to_string(),
+            link: String::new(),
+            comments_link: None,
+            pub_date: Utc::now(),
+            author: None,
+            categories: Vec::new(),
+            content_hash: None,
+            content: None,
+        };
+        let feed_url = "https://example.org/feed.xml";
+        let indexes = |title: &str| -> Vec<usize> {
+            applied(&rules, feed_url, &item(title))
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect()
+        };
+        assert_eq!(indexes("Rust 1.39"), [0, 2, 4]);
+        assert_eq!(indexes("Rust and Async"), [0, 2, 3]);
+        assert_eq!(
+            evaluate(&rules, feed_url, &item("Rust 1.39")).to_string(),
+            "stored as read, highlighted, tagged news"
+        );
+        assert_eq!(
+            evaluate(&rules, feed_url, &item("Rust and Async")).to_string(),
+            "dropped"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+
+use crate::database;
+
+/// Split a feed's items into up to `count` items to include in a digest, and the items left out.
+///
+/// Highlighted items are picked first, and the others at random. With `is_fixed`, items are
+/// picked by hashing them instead, so the same items are picked every time. Picked items stay in
+/// the order they were given in.
+pub fn split(
+    items: Vec<database::Item>,
+    count: usize,
+    is_fixed: bool,
+) -> (Vec<database::Item>, Vec<database::Item>) {
+    if items.len() <= count {
+        return (items, Vec::new());
+    }
+    let random = RandomState::new();
+    let mut keyed: Vec<(bool, u64, usize)> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let key = if is_fixed {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(item.feed_url.as_bytes());
+                hasher.update(b"\n");
+                hasher.update(item.guid.as_bytes());
+                u64::from(hasher.finalize())
+            } else {
+                random.hash_one((&item.feed_url, &item.guid))
+            };
+            (!item.is_highlighted, key, index)
+        })
+        .collect();
+    keyed.sort_unstable();
+    let picked: HashSet<usize> = keyed
+        .into_iter()
+        .take(count)
+        .map(|(_, _, index)| index)
+        .collect();
+    let (picked, left_out): (Vec<_>, Vec<_>) = items
+        .into_iter()
+        .enumerate()
+        .partition(|(index, _)| picked.contains(index));
+    (
+        picked.into_iter().map(|(_, item)| item).collect(),
+        left_out.into_iter().map(|(_, item)| item).collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn item(guid: &str, is_highlighted: bool) -> database::Item {
+        database::Item {
+            feed_url: "http://example.org/feed.xml".to_string(),
+            guid: guid.to_string(),
+            title: guid.to_string(),
+            link: String::new(),
+            comments_link: None,
+            pub_date: chrono::Utc.ymd(2019, 11, 7).and_hms(0, 0, 0),
+            is_read: false,
+            is_highlighted,
+            recipient: None,
+            tags: Vec::new(),
+            is_dead: false,
+            is_bulk: false,
+            translated_title: None,
+            source: None,
+            parts: Vec::new(),
+            qr_code: None,
+            number: None,
+            content: Vec::new(),
+            excerpt: Vec::new(),
+            is_truncated: false,
+            images: Vec::new(),
+            summary: None,
+        }
+    }
+
+    fn guids(items: &[database::Item]) -> Vec<&str> {
+        items.iter().map(|item| item.guid.as_str()).collect()
+    }
+
+    #[test]
+    fn split_keeps_items_that_fit() {
+        let (picked, left_out) = split(vec![item("a", false), item("b", false)], 2, false);
+        assert_eq!(guids(&picked), ["a", "b"]);
+        assert!(left_out.is_empty());
+    }
+
+    #[test]
+    fn split_picks_highlighted_items_first_in_order() {
+        let items = vec![
+            item("a", false),
+            item("b", true),
+            item("c", false),
+            item("d", true),
+        ];
+        let (picked, left_out) = split(items, 2, false);
+        assert_eq!(guids(&picked), ["b", "d"]);
+        assert_eq!(guids(&left_out), ["a", "c"]);
+    }
+
+    #[test]
+    fn split_with_fixed_clock_picks_the_same_items() {
+        let items = || (0..10).map(|i| item(&i.to_string(), false)).collect();
+        let (first, _) = split(items(), 3, true);
+        let (second, left_out) = split(items(), 3, true);
+        assert_eq!(guids(&first), guids(&second));
+        assert_eq!(left_out.len(), 7);
+    }
+}
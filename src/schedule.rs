@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+/// Feeds that take longer than this multiple of the median duration are slow.
+const SLOW_FACTOR: u32 = 2;
+
+/// Order feeds to be fetched by how long they took before, so slow feeds don't hold up the end
+/// of a run.
+///
+/// Fast feeds come first, fastest first, and slow feeds are spread evenly between them, slowest
+/// first. Feeds without a recorded duration count as taking the median duration. Feeds that
+/// take the same time keep their order.
+pub fn order<T>(feeds: Vec<T>, duration: impl Fn(&T) -> Option<Duration>) -> Vec<T> {
+    let mut durations: Vec<Duration> = feeds.iter().filter_map(&duration).collect();
+    durations.sort();
+    let median = durations
+        .get(durations.len() / 2)
+        .copied()
+        .unwrap_or_default();
+    let mut feeds: Vec<(Duration, T)> = feeds
+        .into_iter()
+        .map(|feed| (duration(&feed).unwrap_or(median), feed))
+        .collect();
+    feeds.sort_by_key(|(duration, _)| *duration);
+    let (mut slow, fast): (Vec<_>, Vec<_>) = feeds
+        .into_iter()
+        .partition(|(duration, _)| *duration > median * SLOW_FACTOR);
+    slow.reverse();
+
+    let (num_fast, num_slow) = (fast.len(), slow.len());
+    let mut ordered = Vec::with_capacity(num_fast + num_slow);
+    let mut fast = fast.into_iter();
+    for (i, (_, feed)) in slow.into_iter().enumerate() {
+        // Put each slow feed after its share of the fast feeds, and none at the end.
+        let fast_before = (i + 1) * num_fast / (num_slow + 1);
+        while ordered.len() - i < fast_before {
+            ordered.extend(fast.next().map(|(_, feed)| feed));
+        }
+        ordered.push(feed);
+    }
+    ordered.extend(fast.map(|(_, feed)| feed));
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order_ms(feeds: &[(&'static str, Option<u64>)]) -> Vec<&'static str> {
+        order(feeds.to_vec(), |(_, ms)| ms.map(Duration::from_millis))
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn order_keeps_order_without_durations() {
+        assert_eq!(
+            order_ms(&[("a", None), ("b", None), ("c", None)]),
+            ["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn order_puts_fast_feeds_first() {
+        assert_eq!(
+            order_ms(&[("a", Some(300)), ("b", Some(100)), ("c", Some(200))]),
+            ["b", "c", "a"]
+        );
+    }
+
+    #[test]
+    fn order_spreads_slow_feeds_between_fast_feeds() {
+        assert_eq!(
+            order_ms(&[
+                ("slow", Some(5000)),
+                ("a", Some(100)),
+                ("b", Some(110)),
+                ("slower", Some(9000)),
+                ("c", Some(120)),
+                ("d", Some(130)),
+                ("e", Some(140)),
+                ("f", Some(150)),
+                ("g", Some(160)),
+            ]),
+            ["a", "b", "slower", "c", "d", "slow", "e", "f", "g"]
+        );
+    }
+
+    #[test]
+    fn order_treats_unknown_feeds_as_median() {
+        assert_eq!(
+            order_ms(&[
+                ("new", None),
+                ("a", Some(100)),
+                ("b", Some(200)),
+                ("c", Some(300))
+            ]),
+            ["a", "new", "b", "c"]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use toml::Value;
+
+use crate::config::{Accessibility, Config, Delivery, FeedConfig, Filters, Sources};
+use crate::{
+    archive, callback, daemon, dkim, hooks, inbox, lua, mx, oauth, pipeline, rules, smime,
+    summarize, translate, web, window,
+};
+
+/// Remove the keys of a config that aren't in this version, returning them as dotted paths.
+///
+/// The tables of `transport` and `read_later` are skipped, since their keys depend on their
+/// type, so unknown keys in them are still errors.
+pub fn strip_unknown_keys(value: &mut Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    strip(value, &mut Vec::new(), &mut unknown);
+    unknown
+}
+
+fn strip(value: &mut Value, path: &mut Vec<String>, unknown: &mut Vec<String>) {
+    match value {
+        Value::Table(table) => {
+            let Some(fields) = table_fields(path) else {
+                return;
+            };
+            let unknown_keys: Vec<String> = table
+                .keys()
+                .filter(|key| !fields.contains(&key.as_str()))
+                .cloned()
+                .collect();
+            for key in unknown_keys {
+                table.remove(&key);
+                let mut key_path = path.clone();
+                key_path.push(key);
+                unknown.push(key_path.join("."));
+            }
+            for (key, child) in table.iter_mut() {
+                path.push(key.clone());
+                strip(child, path, unknown);
+                path.pop();
+            }
+        }
+        Value::Array(array) => {
+            for (i, element) in array.iter_mut().enumerate() {
+                path.push(i.to_string());
+                strip(element, path, unknown);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Copy keys from one config to another, given as dotted paths like those of unknown keys,
+/// creating the tables they're in. Keys in arrays that the other config doesn't have are skipped.
+pub fn copy_keys(from: &Value, to: &mut Value, keys: &[String]) {
+    for key in keys {
+        let path: Vec<&str> = key.split('.').collect();
+        let Some((name, parents)) = path.split_last() else {
+            continue;
+        };
+        if let (Some(copied), Some(table)) = (lookup(from, &path), table_at(to, from, parents)) {
+            table.insert((*name).to_string(), copied.clone());
+        }
+    }
+}
+
+/// Value at a path, with array indexes as segments.
+fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match value {
+        Value::Table(table) => table.get(*segment),
+        Value::Array(array) => array.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Table at a path, with array indexes as segments, creating missing tables. A value written
+/// short, such as a feed written as its URL, is replaced by the table that `from` has for it.
+fn table_at<'a>(
+    value: &'a mut Value,
+    from: &Value,
+    path: &[&str],
+) -> Option<&'a mut toml::value::Table> {
+    let mut value = value;
+    for (i, segment) in path.iter().enumerate() {
+        value = match value {
+            Value::Table(table) => table
+                .entry((*segment).to_string())
+                .or_insert_with(|| Value::Table(toml::value::Table::new())),
+            Value::Array(array) => array.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+        if !value.is_table() && !value.is_array() {
+            if let Some(long @ Value::Table(_)) = lookup(from, &path[..=i]) {
+                *value = long.clone();
+            }
+        }
+    }
+    match value {
+        Value::Table(table) => Some(table),
+        _ => None,
+    }
+}
+
+/// Keys of the table at a path, with array indexes as segments.
+fn table_fields(path: &[String]) -> Option<&'static [&'static str]> {
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    Some(match path.as_slice() {
+        [] => fields::<Config>(),
+        ["sources"] => fields::<Sources>(),
+        ["sources", "feeds", _] => fields::<FeedConfig>(),
+        ["sources", "feeds", _, "auth"] => fields::<oauth::Config>(),
+        ["filters"] => fields::<Filters>(),
+        ["filters", "rules", _] => fields::<rules::Rule>(),
+        ["delivery"] => fields::<Delivery>(),
+        ["delivery", "pipelines", _] => fields::<pipeline::Config>(),
+        ["accessibility"] => fields::<Accessibility>(),
+        ["archives"] => fields::<archive::Config>(),
+        ["callback"] => fields::<callback::Config>(),
+        ["daemon"] => fields::<daemon::Config>(),
+        ["web"] => fields::<web::Config>(),
+        ["delivery", "smime"] => fields::<smime::Config>(),
+        ["delivery", "dkim"] => fields::<dkim::Config>(),
+        ["delivery", "mx"] => fields::<mx::Config>(),
+        ["delivery", "send_window"] => fields::<window::Config>(),
+        ["hooks"] => fields::<hooks::Config>(),
+        ["inbox"] => fields::<inbox::Config>(),
+        ["lua"] => fields::<lua::Config>(),
+        ["translation"] => fields::<translate::Config>(),
+        ["summarizer"] => fields::<summarize::Config>(),
+        _ => return None,
+    })
+}
+
+/// Names of the fields of a struct, as given to its deserializer.
+fn fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields = None;
+    // Probing always fails, after recording the fields.
+    let _ = T::deserialize(FieldsProbe(&mut fields));
+    fields.unwrap_or_default()
+}
+
+/// Deserializer that records the fields of the struct deserialized from it.
+struct FieldsProbe<'a>(&'a mut Option<&'static [&'static str]>);
+impl<'de> Deserializer<'de> for FieldsProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("fields recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_unknown_keys_in_known_tables() {
+        let mut value: Value = toml::from_str(
+            "colour = \"red\"\n\
+             [sources]\nconcurrency = 1\n\
+             [[sources.feeds]]\nurl = \"a\"\ntgas = [\"x\"]\n\
+             [accessibility]\nplaintext = true\nfont = \"serif\"\n\
+             [delivery.transport]\ntype = \"stdout\"\nextra = 1\n",
+        )
+        .expect("invalid config");
+        assert_eq!(
+            strip_unknown_keys(&mut value),
+            ["colour", "accessibility.font", "sources.feeds.0.tgas"]
+        );
+        let expected: Value = toml::from_str(
+            "[sources]\nconcurrency = 1\n\
+             [[sources.feeds]]\nurl = \"a\"\n\
+             [accessibility]\nplaintext = true\n\
+             [delivery.transport]\ntype = \"stdout\"\nextra = 1\n",
+        )
+        .expect("invalid config");
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn copy_keys_into_tables() {
+        let from: Value = toml::from_str(
+            "colour = \"red\"\n\
+             [[sources.feeds]]\nurl = \"a\"\ntgas = [\"x\"]\n\
+             [accessibility]\nfont = \"serif\"\n",
+        )
+        .expect("invalid config");
+        // Feeds without options are written as their URLs.
+        let mut to: Value = toml::from_str("[sources]\nfeeds = [\"a\"]\n").expect("invalid config");
+        copy_keys(
+            &from,
+            &mut to,
+            &[
+                "colour".to_string(),
+                "accessibility.font".to_string(),
+                "sources.feeds.0.tgas".to_string(),
+                "sources.feeds.1.tgas".to_string(),
+            ],
+        );
+        let expected: Value = toml::from_str(
+            "colour = \"red\"\n\
+             [[sources.feeds]]\nurl = \"a\"\ntgas = [\"x\"]\n\
+             [accessibility]\nfont = \"serif\"\n",
+        )
+        .expect("invalid config");
+        assert_eq!(to, expected);
+    }
+
+    #[test]
+    fn fields_of_struct() {
+        assert!(fields::<window::Config>() == ["start", "end"]);
+        assert!(fields::<String>().is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use std::cmp::{max, min};
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::transport::Transport;
+use crate::{database, message, Config, Digest, Result};
+
+/// Outcome of sending a digest.
+pub struct Sent {
+    pub recipient: String,
+    /// Message-ID field value of the digest.
+    pub message_id: String,
+    pub items: Vec<database::DigestItem>,
+    pub outbox_id: Option<i64>,
+    pub result: Result<()>,
+}
+
+/// Spaces out sends shared between threads.
+struct RateLimit {
+    interval: Duration,
+    next_send: Mutex<Instant>,
+}
+impl RateLimit {
+    fn new(per_minute: NonZeroU32) -> Self {
+        Self {
+            interval: Duration::from_mins(1) / per_minute.get(),
+            next_send: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn wait(&self) {
+        let send_at = {
+            let mut next_send = self
+                .next_send
+                .lock()
+                .expect("thread panicked while holding rate limit mutex");
+            let send_at = max(*next_send, Instant::now());
+            *next_send = send_at + self.interval;
+            send_at
+        };
+        thread::sleep(send_at.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Send digests from several threads, returning the outcome of each instead of stopping at the
+/// first error.
+///
+/// Fails without sending anything if the transport can't be set up.
+pub fn send_all(digests: Vec<Digest>, config: &Config) -> Result<Vec<Sent>> {
+    let num_threads = min(
+        config.delivery.mail_concurrency.get() as usize,
+        digests.len(),
+    );
+    let transports = (0..num_threads)
+        .map(|_| {
+            Transport::new(
+                config.delivery.mx.clone(),
+                config.delivery.transport.as_ref(),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let queue = Arc::new(Mutex::new(digests));
+    let rate_limit = Arc::new(config.delivery.mail_per_minute.map(RateLimit::new));
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let mut handles = vec![];
+    for mut transport in transports {
+        let queue = queue.clone();
+        let rate_limit = rate_limit.clone();
+        let results = results.clone();
+        handles.push(thread::spawn(move || {
+            // Clippy fails to account for lifetime of MutexGuard
+            #[allow(clippy::while_let_loop)]
+            loop {
+                let Some(digest) = queue
+                    .lock()
+                    .expect("thread panicked while holding queue mutex")
+                    .pop()
+                else {
+                    break;
+                };
+                if let Some(rate_limit) = rate_limit.as_ref() {
+                    rate_limit.wait();
+                }
+                let recipient = digest.mail.envelope().to()[0].to_string();
+                let message_id = message::message_id(digest.mail.message_id());
+                eprintln!("Sending mail to {recipient}...");
+                let result = transport.send(digest.mail);
+                results
+                    .lock()
+                    .expect("thread panicked while holding results mutex")
+                    .push(Sent {
+                        recipient,
+                        message_id,
+                        items: digest.items,
+                        outbox_id: digest.outbox_id,
+                        result,
+                    });
+            }
+        }));
+    }
+    for handle in handles {
+        handle.join().expect("thread panicked");
+    }
+    Ok(Arc::try_unwrap(results)
+        .ok()
+        .expect("threads were joined")
+        .into_inner()
+        .expect("thread panicked while holding results mutex"))
+}
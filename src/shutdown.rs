@@ -0,0 +1,48 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// Exit status of a process killed by SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+// How long in-flight fetches may take to finish after Ctrl-C.
+const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Handle Ctrl-C by letting active work finish, or exiting immediately if there is none.
+///
+/// Pressing Ctrl-C again, or waiting longer than the grace period, also exits immediately.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) || ACTIVE.load(Ordering::SeqCst) == 0 {
+            process::exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("Interrupted, finishing in-flight fetches (press Ctrl-C again to quit now)...");
+        thread::spawn(|| {
+            thread::sleep(GRACE_PERIOD);
+            eprintln!("In-flight fetches didn't finish in time");
+            process::exit(EXIT_INTERRUPTED);
+        });
+    })
+}
+
+/// Whether Ctrl-C was pressed, so no new work should be started.
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Marks work that Ctrl-C should wait for while it's alive.
+pub struct Active(());
+impl Active {
+    pub fn new() -> Self {
+        ACTIVE.fetch_add(1, Ordering::SeqCst);
+        Self(())
+    }
+}
+impl Drop for Active {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
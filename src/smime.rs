@@ -0,0 +1,68 @@
+use std::ffi::OsStr;
+use std::io;
+use std::path::PathBuf;
+
+use lettre::SendableEmail;
+use serde::{Deserialize, Serialize};
+
+use crate::{message, openssl};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// PEM certificate to sign mail with.
+    pub certificate: PathBuf,
+    /// PEM private key of the certificate.
+    pub key: PathBuf,
+    /// PEM intermediate certificates to include, if recipients may not have them.
+    #[serde(default)]
+    pub chain: Option<PathBuf>,
+}
+
+/// Sign an email with S/MIME using the `openssl` command.
+///
+/// The signature is detached, so mail clients without S/MIME support still show the content.
+pub fn sign(config: &Config, mail: SendableEmail) -> io::Result<SendableEmail> {
+    let envelope = mail.envelope().clone();
+    let message_id = mail.message_id().to_string();
+    let text = message::crlf_line_endings(&mail.message_to_string()?);
+    let (header, body) = message::split(&text);
+    // The content headers are signed with the body, and the rest stay outside the signature.
+    let mut outer = String::new();
+    let mut content = String::new();
+    for field in message::header_fields(header) {
+        let name = message::field_name(&field).to_ascii_lowercase();
+        if name.starts_with("content-") {
+            content.push_str(&field);
+            content.push_str("\r\n");
+        } else if name != "mime-version" {
+            outer.push_str(&field);
+            outer.push_str("\r\n");
+        }
+    }
+    content.push_str("\r\n");
+    content.push_str(body);
+    // The signed entity comes with its own MIME-Version header.
+    outer.push_str(&message::crlf_line_endings(&openssl_sign(
+        config,
+        content.into_bytes(),
+    )?));
+    Ok(SendableEmail::new(envelope, message_id, outer.into_bytes()))
+}
+
+fn openssl_sign(config: &Config, content: Vec<u8>) -> io::Result<String> {
+    let mut args: Vec<&OsStr> = vec![
+        "smime".as_ref(),
+        "-sign".as_ref(),
+        "-binary".as_ref(),
+        "-signer".as_ref(),
+        config.certificate.as_ref(),
+        "-inkey".as_ref(),
+        config.key.as_ref(),
+    ];
+    if let Some(chain) = &config.chain {
+        args.extend(&["-certfile".as_ref(), chain.as_ref()]);
+    }
+    let output = openssl::run(args, content)?;
+    String::from_utf8(output).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,32 @@
+//! Export of the state that can't be fetched again, such as which items were read, to restore
+//! into a rebuilt database.
+
+use serde::{Deserialize, Serialize};
+
+use crate::database;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Export {
+    /// URLs of muted feeds.
+    #[serde(default)]
+    pub muted_feeds: Vec<String>,
+    #[serde(default)]
+    pub items: Vec<database::ItemState>,
+}
+impl Export {
+    pub fn load(database: &mut database::Database) -> Result<Self, database::Error> {
+        Ok(Self {
+            muted_feeds: database.get_muted_feeds()?,
+            items: database.get_item_states()?,
+        })
+    }
+
+    /// Restore the state of the feeds and items that are stored, returning how many items were
+    /// restored. Items that are no longer in their feeds can't be restored.
+    pub fn restore(&self, database: &mut database::Database) -> Result<usize, database::Error> {
+        for url in &self.muted_feeds {
+            database.set_feed_muted(url, true)?;
+        }
+        database.set_item_states(&self.items)
+    }
+}
@@ -0,0 +1,683 @@
+//! Adding and removing feeds in a config from the command line, by editing its text in place, so
+//! its comments, formatting, and keys that this version doesn't know are kept.
+
+use std::ops::Range;
+
+use toml::value::{Table, Value};
+
+use crate::{duplicates, Error, Result};
+
+// Path of the feeds, as a key or the header of an array of tables.
+const FEEDS: &str = "sources.feeds";
+
+/// Text of a config, with the value it parses to.
+pub struct Document {
+    text: String,
+    value: Value,
+}
+
+impl Document {
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(Self {
+            text: text.to_string(),
+            value: toml::from_str(text)?,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Add a feed after the config's other feeds, written like them, in a folder if given. Fails
+    /// if the config has the feed already, under its URL or another spelling of it.
+    pub fn add(&mut self, url: &str, folder: Option<&str>) -> Result<()> {
+        let mut value = self.value.clone();
+        add(&mut value, url, folder)?;
+        let layout = Layout::scan(&self.text);
+        let text = if let Some(array) = layout.feeds_array() {
+            add_to_array(&self.text, array, url, folder)
+        } else if let Some(&last) = layout.feed_tables().last() {
+            let at = layout.table_end(&self.text, last, false);
+            let keys = feed_keys(url, folder).join("\n");
+            insert_lines(&self.text, at, &format!("\n[[{FEEDS}]]\n{keys}\n"))
+        } else {
+            let feed = if folder.is_some() {
+                format!("{{ {} }}", feed_keys(url, folder).join(", "))
+            } else {
+                string(url)
+            };
+            let sources = layout
+                .headers
+                .iter()
+                .find(|h| !h.array && h.name == "sources");
+            if let Some(sources) = sources {
+                insert_lines(&self.text, sources.body, &format!("feeds = [{feed}]\n"))
+            } else {
+                let end = self.text.len();
+                insert_lines(&self.text, end, &format!("\n[sources]\nfeeds = [{feed}]\n"))
+            }
+        };
+        self.replace(text, value)
+    }
+
+    /// Remove a feed from the config, given its URL or another spelling of it, returning the URL
+    /// it was listed under.
+    pub fn remove(&mut self, url: &str) -> Result<String> {
+        let mut value = self.value.clone();
+        let (index, removed) = remove(&mut value, url)?;
+        let layout = Layout::scan(&self.text);
+        let range = if let Some(array) = layout.feeds_array() {
+            element_lines(&self.text, &array_elements(&self.text, array), index)
+        } else {
+            let header = *layout
+                .feed_tables()
+                .get(index)
+                .ok_or(Error::FeedsNotEditable)?;
+            let start = comments_start(&self.text, layout.headers[header].start);
+            let end = layout.table_end(&self.text, header, true);
+            if end == self.text.len() {
+                // So the file doesn't end with the blank lines that separated the feed.
+                blank_lines_start(&self.text, start)..end
+            } else {
+                start..end
+            }
+        };
+        let mut text = self.text.clone();
+        text.replace_range(range, "");
+        self.replace(text, value)?;
+        Ok(removed)
+    }
+
+    /// Replace the text with the edited one, if it parses to the value it was meant to. It may
+    /// not if the feeds are written in a way that the edits don't handle.
+    fn replace(&mut self, text: String, value: Value) -> Result<()> {
+        if toml::from_str::<Value>(&text).ok().as_ref() != Some(&value) {
+            return Err(Error::FeedsNotEditable);
+        }
+        self.text = text;
+        self.value = value;
+        Ok(())
+    }
+}
+
+/// Add a feed to the end of a config's feeds, in a folder if given.
+fn add(config: &mut Value, url: &str, folder: Option<&str>) -> Result<()> {
+    let feeds = feeds(config);
+    let canonical = duplicates::canonical_url(url);
+    if let Some(existing) = feeds
+        .iter()
+        .filter_map(feed_url)
+        .find(|existing| duplicates::canonical_url(existing) == canonical)
+    {
+        return Err(Error::AlreadySubscribed(existing.to_string()));
+    }
+    if folder.is_none() && !feeds.iter().any(Value::is_table) {
+        feeds.push(Value::String(url.to_string()));
+        return Ok(());
+    }
+    // TOML arrays can't mix strings and tables, so feeds written as their URLs are written as
+    // tables instead.
+    for feed in feeds.iter_mut() {
+        if let Some(url) = feed.as_str().map(ToString::to_string) {
+            *feed = url_table(&url);
+        }
+    }
+    let mut feed = url_table(url);
+    if let Some(folder) = folder {
+        feed.as_table_mut()
+            .expect("feed is a table")
+            .insert("folder".to_string(), Value::String(folder.to_string()));
+    }
+    feeds.push(feed);
+    Ok(())
+}
+
+/// Remove a feed from a config, returning where it was and the URL it was listed under.
+fn remove(config: &mut Value, url: &str) -> Result<(usize, String)> {
+    let feeds = feeds(config);
+    let canonical = duplicates::canonical_url(url);
+    let index = feeds
+        .iter()
+        .position(|feed| feed_url(feed).is_some_and(|u| duplicates::canonical_url(u) == canonical))
+        .ok_or_else(|| Error::NotSubscribed(url.to_string()))?;
+    let removed = feeds.remove(index);
+    Ok((index, feed_url(&removed).unwrap_or(url).to_string()))
+}
+
+/// Feeds of a config that was loaded, creating the table and array they're in if it has none.
+fn feeds(config: &mut Value) -> &mut Vec<Value> {
+    config
+        .as_table_mut()
+        .expect("config is a table")
+        .entry("sources")
+        .or_insert_with(|| Value::Table(Table::new()))
+        .as_table_mut()
+        .expect("sources is a table")
+        .entry("feeds")
+        .or_insert_with(|| Value::Array(Vec::new()))
+        .as_array_mut()
+        .expect("sources.feeds is an array")
+}
+
+fn url_table(url: &str) -> Value {
+    let mut table = Table::new();
+    table.insert("url".to_string(), Value::String(url.to_string()));
+    Value::Table(table)
+}
+
+/// URL of a feed, which is written either as its URL or as a table with options.
+fn feed_url(feed: &Value) -> Option<&str> {
+    match feed {
+        Value::String(url) => Some(url),
+        Value::Table(table) => table.get("url")?.as_str(),
+        _ => None,
+    }
+}
+
+/// Keys of a feed written as a table.
+fn feed_keys(url: &str, folder: Option<&str>) -> Vec<String> {
+    let mut keys = vec![format!("url = {}", string(url))];
+    keys.extend(folder.map(|folder| format!("folder = {}", string(folder))));
+    keys
+}
+
+/// A string as TOML.
+fn string(value: &str) -> String {
+    Value::String(value.to_string()).to_string()
+}
+
+/// Headers and keys of a config's text, with where they are. Values are only scanned for where
+/// they end, since the config was parsed already.
+struct Layout {
+    headers: Vec<Header>,
+    /// Keys, as dotted paths, with the header of the table they're in and their value.
+    keys: Vec<(Option<usize>, String, Range<usize>)>,
+}
+
+struct Header {
+    name: String,
+    /// Whether it's a header of a table in an array, such as `[[sources.feeds]]`.
+    array: bool,
+    /// Start of the header's line.
+    start: usize,
+    /// Start of the line after the header.
+    body: usize,
+}
+
+impl Layout {
+    fn scan(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let mut layout = Self {
+            headers: Vec::new(),
+            keys: Vec::new(),
+        };
+        let mut i = skip_blank(bytes, 0);
+        while i < bytes.len() {
+            let end = line_end(bytes, i);
+            if bytes[i] == b'[' {
+                let array = bytes.get(i + 1) == Some(&b'[');
+                let name_start = i + if array { 2 } else { 1 };
+                let name_end = text[name_start..end]
+                    .find(']')
+                    .map_or(end, |n| name_start + n);
+                layout.headers.push(Header {
+                    name: key_path(&text[name_start..name_end]),
+                    array,
+                    start: line_start(bytes, i),
+                    body: (end + 1).min(bytes.len()),
+                });
+                i = end;
+            } else {
+                let equals = key_end(bytes, i);
+                let value = skip_spaces(bytes, equals + 1);
+                let value_end = value_end(bytes, value);
+                layout.keys.push((
+                    layout.headers.len().checked_sub(1),
+                    key_path(&text[i..equals]),
+                    value..value_end,
+                ));
+                i = value_end;
+            }
+            i = skip_blank(bytes, i);
+        }
+        layout
+    }
+
+    /// Array of the feeds, if they're written as one rather than as tables.
+    fn feeds_array(&self) -> Option<Range<usize>> {
+        self.keys
+            .iter()
+            .find(|(header, key, _)| match header {
+                Some(header) => {
+                    let header = &self.headers[*header];
+                    !header.array && header.name == "sources" && key == "feeds"
+                }
+                None => key == FEEDS,
+            })
+            .map(|(_, _, value)| value.clone())
+    }
+
+    /// Headers of the feeds, if they're written as tables.
+    fn feed_tables(&self) -> Vec<usize> {
+        (0..self.headers.len())
+            .filter(|&i| self.headers[i].array && self.headers[i].name == FEEDS)
+            .collect()
+    }
+
+    /// End of the table of a header, with its sub-tables, such as `[sources.feeds.oauth]`.
+    /// Comments right above the next header are about it, so they're left out, and so are the
+    /// blank lines before them unless `with_blank_lines` is set.
+    fn table_end(&self, text: &str, header: usize, with_blank_lines: bool) -> usize {
+        let prefix = format!("{}.", self.headers[header].name);
+        let next = self.headers[header + 1..]
+            .iter()
+            .find(|h| !h.name.starts_with(&prefix))
+            .map_or(text.len(), |h| comments_start(text, h.start));
+        if with_blank_lines {
+            next
+        } else {
+            blank_lines_start(text, next)
+        }
+    }
+}
+
+/// Key of a header or assignment as a dotted path without quotes or spaces.
+fn key_path(key: &str) -> String {
+    key.split('.')
+        .map(|part| part.trim().trim_matches('"').trim_matches('\''))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Add a feed to the end of an array of feeds, on a line of its own if the others are. Feeds
+/// written as their URLs are written as inline tables if tables are needed.
+fn add_to_array(text: &str, array: Range<usize>, url: &str, folder: Option<&str>) -> String {
+    let bytes = text.as_bytes();
+    let elements = array_elements(text, array.clone());
+    let tables = folder.is_some() || elements.iter().any(|e| bytes[e.start] == b'{');
+    let feed = if tables {
+        format!("{{ {} }}", feed_keys(url, folder).join(", "))
+    } else {
+        string(url)
+    };
+    let mut edited = text.to_string();
+    let Some(last) = elements.last() else {
+        edited.replace_range(array, &format!("[{feed}]"));
+        return edited;
+    };
+    let line = line_start(bytes, last.start);
+    let mut after = skip_spaces(bytes, last.end);
+    let comma = bytes.get(after) == Some(&b',');
+    if comma {
+        after = skip_spaces(bytes, after + 1);
+    }
+    let own_line = line > array.start
+        && text[line..last.start].trim().is_empty()
+        && matches!(bytes.get(after), None | Some(b'\n' | b'\r' | b'#'));
+    if own_line {
+        let indent = &text[line..last.start];
+        let trailing_comma = if comma { "," } else { "" };
+        edited.insert_str(
+            line_end(bytes, after),
+            &format!("\n{indent}{feed}{trailing_comma}"),
+        );
+        if !comma {
+            edited.insert(last.end, ',');
+        }
+    } else {
+        edited.insert_str(last.end, &format!(", {feed}"));
+    }
+    if tables {
+        for element in elements.iter().rev() {
+            if matches!(bytes[element.start], b'"' | b'\'') {
+                let url = &text[element.clone()];
+                edited.replace_range(element.clone(), &format!("{{ url = {url} }}"));
+            }
+        }
+    }
+    edited
+}
+
+/// Where an element of an array is, with its line if it's on one of its own, or else a comma
+/// next to it.
+fn element_lines(text: &str, elements: &[Range<usize>], index: usize) -> Range<usize> {
+    let bytes = text.as_bytes();
+    let element = elements[index].clone();
+    let line = line_start(bytes, element.start);
+    let mut after = skip_spaces(bytes, element.end);
+    let comma = bytes.get(after) == Some(&b',');
+    if comma {
+        after = skip_spaces(bytes, after + 1);
+    }
+    let own_line = text[line..element.start].trim().is_empty()
+        && matches!(bytes.get(after), None | Some(b'\n' | b'\r' | b'#'));
+    if own_line {
+        line..(line_end(bytes, after) + 1).min(bytes.len())
+    } else if comma {
+        element.start..after
+    } else if index > 0 {
+        elements[index - 1].end..element.end
+    } else {
+        element
+    }
+}
+
+/// Elements of an array value, without the commas and comments between them.
+fn array_elements(text: &str, array: Range<usize>) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut elements = Vec::new();
+    let mut i = skip_blank(bytes, array.start + 1);
+    while i < array.end - 1 {
+        let end = value_end(bytes, i);
+        elements.push(i..end);
+        i = skip_blank(bytes, end);
+        if bytes.get(i) == Some(&b',') {
+            i = skip_blank(bytes, i + 1);
+        }
+    }
+    elements
+}
+
+/// Insert lines at the start of a line, or at the end of the text.
+fn insert_lines(text: &str, at: usize, lines: &str) -> String {
+    let mut edited = text.to_string();
+    if at == text.len() {
+        if !text.is_empty() && !text.ends_with('\n') {
+            edited.push('\n');
+        }
+        edited.push_str(lines);
+    } else {
+        edited.insert_str(at, lines);
+    }
+    edited
+}
+
+/// Start of the comment lines right above a line, or the line if there are none.
+fn comments_start(text: &str, line: usize) -> usize {
+    lines_above(text, line, |l| l.trim_start().starts_with('#'))
+}
+
+/// Start of the blank lines right above a line, or the line if there are none.
+fn blank_lines_start(text: &str, line: usize) -> usize {
+    lines_above(text, line, |l| l.trim().is_empty())
+}
+
+fn lines_above(text: &str, mut line: usize, matches: impl Fn(&str) -> bool) -> usize {
+    while line > 0 {
+        let above = text[..line - 1].rfind('\n').map_or(0, |n| n + 1);
+        if !matches(&text[above..line]) {
+            break;
+        }
+        line = above;
+    }
+    line
+}
+
+fn line_start(bytes: &[u8], i: usize) -> usize {
+    bytes[..i]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |n| n + 1)
+}
+
+fn line_end(bytes: &[u8], i: usize) -> usize {
+    bytes[i..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |n| i + n)
+}
+
+fn skip_spaces(bytes: &[u8], mut i: usize) -> usize {
+    while matches!(bytes.get(i), Some(b' ' | b'\t')) {
+        i += 1;
+    }
+    i
+}
+
+/// Skip whitespace, line breaks, and comments.
+fn skip_blank(bytes: &[u8], mut i: usize) -> usize {
+    loop {
+        match bytes.get(i) {
+            Some(b' ' | b'\t' | b'\r' | b'\n') => i += 1,
+            Some(b'#') => i = line_end(bytes, i),
+            _ => return i,
+        }
+    }
+}
+
+/// Where the `=` after a key is.
+fn key_end(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i] != b'=' {
+        i = if matches!(bytes[i], b'"' | b'\'') {
+            string_end(bytes, i)
+        } else {
+            i + 1
+        };
+    }
+    i
+}
+
+fn value_end(bytes: &[u8], start: usize) -> usize {
+    match bytes.get(start) {
+        Some(b'"' | b'\'') => string_end(bytes, start),
+        Some(b'[' | b'{') => {
+            let mut depth = 0;
+            let mut i = start;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'"' | b'\'' => {
+                        i = string_end(bytes, i);
+                        continue;
+                    }
+                    b'#' => {
+                        i = line_end(bytes, i);
+                        continue;
+                    }
+                    b'[' | b'{' => depth += 1,
+                    b']' | b'}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return i + 1;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            i
+        }
+        // Numbers, booleans, and dates, which may have a space between the date and time.
+        _ => {
+            let end = bytes[start..]
+                .iter()
+                .position(|b| matches!(b, b',' | b']' | b'}' | b'#' | b'\r' | b'\n'))
+                .map_or(bytes.len(), |n| start + n);
+            start + bytes[start..end].trim_ascii_end().len()
+        }
+    }
+}
+
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let triple = bytes[start..].starts_with(&[quote; 3]);
+    let mut i = start + if triple { 3 } else { 1 };
+    while i < bytes.len() {
+        if quote == b'"' && bytes[i] == b'\\' {
+            i += 2;
+        } else if triple && bytes[i..].starts_with(&[quote; 3]) {
+            i += 3;
+            // Up to two quotes at the end are part of the string.
+            while bytes.get(i) == Some(&quote) {
+                i += 1;
+            }
+            return i;
+        } else if !triple && bytes[i] == quote {
+            return i + 1;
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(text: &str, url: &str, folder: Option<&str>) -> String {
+        let mut document = Document::parse(text).expect("invalid config");
+        document.add(url, folder).expect("failed to add");
+        document.text
+    }
+
+    fn remove(text: &str, url: &str) -> String {
+        let mut document = Document::parse(text).expect("invalid config");
+        document.remove(url).expect("failed to remove");
+        document.text
+    }
+
+    #[test]
+    fn add_appends_feed_like_the_others() {
+        assert_eq!(
+            add(
+                "[sources]\nfeeds = [\"https://a.example/feed\"] # blogs\n",
+                "https://b.example/feed",
+                None
+            ),
+            "[sources]\nfeeds = [\"https://a.example/feed\", \"https://b.example/feed\"] # blogs\n"
+        );
+        assert_eq!(
+            add(
+                "[sources]\nfeeds = [\n    # Rust\n    \"https://a.example/feed\",\n]\n",
+                "https://b.example/feed",
+                None
+            ),
+            "[sources]\nfeeds = [\n    # Rust\n    \"https://a.example/feed\",\n    \
+             \"https://b.example/feed\",\n]\n"
+        );
+        assert_eq!(
+            add(
+                "# Feeds\n[[sources.feeds]]\nurl = \"https://a.example/feed\"\n\
+                 [sources.feeds.oauth]\ntoken_url = \"https://a.example/token\"\n\n\
+                 # Where to mail\n[delivery]\nto_email = \"a@example.com\"\n",
+                "https://b.example/feed",
+                None
+            ),
+            "# Feeds\n[[sources.feeds]]\nurl = \"https://a.example/feed\"\n\
+             [sources.feeds.oauth]\ntoken_url = \"https://a.example/token\"\n\n\
+             [[sources.feeds]]\nurl = \"https://b.example/feed\"\n\n\
+             # Where to mail\n[delivery]\nto_email = \"a@example.com\"\n"
+        );
+        assert_eq!(
+            add(
+                "[sources]\nconcurrency = 2\n",
+                "https://a.example/feed",
+                None
+            ),
+            "[sources]\nfeeds = [\"https://a.example/feed\"]\nconcurrency = 2\n"
+        );
+        assert_eq!(
+            add("colour = \"red\"", "https://a.example/feed", None),
+            "colour = \"red\"\n\n[sources]\nfeeds = [\"https://a.example/feed\"]\n"
+        );
+    }
+
+    #[test]
+    fn add_with_folder_writes_feeds_as_tables() {
+        assert_eq!(
+            add(
+                "[sources]\nfeeds = [\"https://a.example/feed\"]\n",
+                "https://b.example/feed",
+                Some("news")
+            ),
+            "[sources]\nfeeds = [{ url = \"https://a.example/feed\" }, \
+             { url = \"https://b.example/feed\", folder = \"news\" }]\n"
+        );
+        assert_eq!(
+            add(
+                "[[sources.feeds]]\nurl = \"https://a.example/feed\"\n",
+                "https://b.example/feed",
+                Some("news")
+            ),
+            "[[sources.feeds]]\nurl = \"https://a.example/feed\"\n\n\
+             [[sources.feeds]]\nurl = \"https://b.example/feed\"\nfolder = \"news\"\n"
+        );
+    }
+
+    #[test]
+    fn add_rejects_feeds_already_subscribed_to() {
+        let mut document = Document::parse("[sources]\nfeeds = [\"https://a.example/feed\"]\n")
+            .expect("invalid config");
+        assert!(matches!(
+            document.add("https://A.example/feed#top", None),
+            Err(Error::AlreadySubscribed(url)) if url == "https://a.example/feed"
+        ));
+    }
+
+    #[test]
+    fn add_rejects_feeds_it_cant_edit_in_place() {
+        let mut document = Document::parse("sources = { feeds = [] }\n").expect("invalid config");
+        assert!(matches!(
+            document.add("https://a.example/feed", None),
+            Err(Error::FeedsNotEditable)
+        ));
+        assert_eq!(document.text(), "sources = { feeds = [] }\n");
+    }
+
+    #[test]
+    fn remove_finds_feed_by_another_spelling() {
+        let mut document = Document::parse(
+            "[[sources.feeds]]\nurl = \"https://a.example/feed\"\n\n\
+             # Pinned\n[[sources.feeds]]\nurl = \"https://b.example/feed\"\npinned = true\n",
+        )
+        .expect("invalid config");
+        assert_eq!(
+            document
+                .remove("https://B.example/feed")
+                .expect("failed to remove"),
+            "https://b.example/feed"
+        );
+        assert_eq!(
+            document.text(),
+            "[[sources.feeds]]\nurl = \"https://a.example/feed\"\n"
+        );
+        assert!(matches!(
+            document.remove("https://c.example/feed"),
+            Err(Error::NotSubscribed(_))
+        ));
+    }
+
+    #[test]
+    fn remove_keeps_the_rest_of_the_config() {
+        assert_eq!(
+            remove(
+                "[sources]\nfeeds = [\n    \"https://a.example/feed\", # Rust\n    \
+                 \"https://b.example/feed\",\n]\n",
+                "https://a.example/feed"
+            ),
+            "[sources]\nfeeds = [\n    \"https://b.example/feed\",\n]\n"
+        );
+        assert_eq!(
+            remove(
+                "[sources]\nfeeds = [\"https://a.example/feed\", \"https://b.example/feed\"]\n",
+                "https://b.example/feed"
+            ),
+            "[sources]\nfeeds = [\"https://a.example/feed\"]\n"
+        );
+        assert_eq!(
+            remove(
+                "[[sources.feeds]]\nurl = \"https://a.example/feed\"\n\
+                 [sources.feeds.oauth]\ntoken_url = \"https://a.example/token\"\n\n\
+                 [[sources.feeds]]\nurl = \"https://b.example/feed\"\n\n\
+                 # Where to mail\n[delivery]\nto_email = \"a@example.com\"\n",
+                "https://a.example/feed"
+            ),
+            "[[sources.feeds]]\nurl = \"https://b.example/feed\"\n\n\
+             # Where to mail\n[delivery]\nto_email = \"a@example.com\"\n"
+        );
+    }
+}
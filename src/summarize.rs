@@ -0,0 +1,88 @@
+use std::io;
+use std::time::Duration;
+
+use attohttpc::header::{CONTENT_TYPE, USER_AGENT};
+use derive_more::{Display, From};
+use serde::{Deserialize, Serialize};
+
+use crate::transform;
+
+/// Command or HTTP API that summarizes the text of long items in a few sentences. Exactly one of
+/// `command` and `url` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Shell command that's given the text of an item on stdin, and outputs its summary.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// URL of an HTTP API that's posted the title, link, and text of an item as JSON, and
+    /// responds with its summary as JSON.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// API key, sent as a bearer token.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Only items with at least this many characters of text are summarized.
+    #[serde(default = "default_min_chars")]
+    pub min_chars: usize,
+}
+
+fn default_min_chars() -> usize {
+    2000
+}
+
+#[derive(Debug, From, Display)]
+pub enum Error {
+    Command(io::Error),
+    Http(attohttpc::Error),
+    #[display(fmt = "invalid response: {_0}")]
+    Json(serde_json::Error),
+    #[display(fmt = "unexpected status code: {_0}")]
+    UnexpectedStatusCode(u16),
+    #[display(fmt = "summary is empty")]
+    Empty,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    title: &'a str,
+    link: &'a str,
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    summary: String,
+}
+
+/// Summarize the text of an item.
+pub fn summarize(config: &Config, title: &str, link: &str, text: &str) -> Result<String, Error> {
+    let summary = match (&config.command, &config.url) {
+        (Some(command), _) => {
+            let output = transform::run(command, text.as_bytes().to_vec())?;
+            String::from_utf8_lossy(&output).into_owned()
+        }
+        (None, Some(url)) => {
+            let body = serde_json::to_string(&Request { title, link, text })?;
+            let mut builder = attohttpc::post(url)
+                .header(USER_AGENT, env!("CARGO_PKG_NAME"))
+                .header(CONTENT_TYPE, "application/json")
+                .timeout(Duration::from_mins(1));
+            if let Some(api_key) = &config.api_key {
+                builder = builder.bearer_auth(api_key);
+            }
+            let resp = builder.text(body).send()?;
+            if !resp.status().is_success() {
+                return Err(Error::UnexpectedStatusCode(resp.status().as_u16()));
+            }
+            let resp: Response = serde_json::from_slice(&resp.bytes()?)?;
+            resp.summary
+        }
+        (None, None) => unreachable!("the config is checked for a summarizer"),
+    };
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err(Error::Empty);
+    }
+    Ok(summary.to_string())
+}
@@ -0,0 +1,213 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use tera::Tera;
+
+use crate::bidi;
+
+// Must have ".html" suffix to force tera to do escaping.
+pub const MAIL: &str = "mail.html";
+// Built-in templates, which are replaced by custom templates with the same name.
+const BUILT_IN: [(&str, &str); 7] = [
+    (
+        "base.html",
+        include_str!("../resources/templates/base.html"),
+    ),
+    (
+        "feed.html",
+        include_str!("../resources/templates/feed.html"),
+    ),
+    (
+        "item.html",
+        include_str!("../resources/templates/item.html"),
+    ),
+    (
+        "archives.html",
+        include_str!("../resources/templates/archives.html"),
+    ),
+    (
+        "updated.html",
+        include_str!("../resources/templates/updated.html"),
+    ),
+    (
+        "footer.html",
+        include_str!("../resources/templates/footer.html"),
+    ),
+    (MAIL, include_str!("../resources/templates/mail.html")),
+];
+
+/// Built-in templates, compiled the first time they're used and shared after that.
+fn built_in() -> &'static Tera {
+    static BUILT_IN_TERA: OnceLock<Tera> = OnceLock::new();
+    BUILT_IN_TERA.get_or_init(|| {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(BUILT_IN.to_vec())
+            .expect("invalid built-in template");
+        tera.register_filter("dir", bidi::dir_filter);
+        tera
+    })
+}
+
+/// Custom templates from a template directory, a custom mail template in place of `mail.html`,
+/// and built-in templates they don't replace.
+///
+/// Without custom templates, the shared built-in templates are returned without compiling them
+/// again. Custom templates are compiled on each call, so changes to them are picked up.
+pub fn load(
+    template_dir: Option<&Path>,
+    template_path: Option<&Path>,
+) -> tera::Result<Cow<'static, Tera>> {
+    if template_dir.is_none() && template_path.is_none() {
+        return Ok(Cow::Borrowed(built_in()));
+    }
+    let mut tera = match template_dir {
+        Some(template_dir) => Tera::parse(&format!("{}/**/*", template_dir.display()))?,
+        None => Tera::default(),
+    };
+    tera.extend(built_in())?;
+    // Added last, so it can extend the other templates.
+    if let Some(template_path) = template_path {
+        tera.add_template_file(template_path, Some(MAIL))?;
+    }
+    Ok(Cow::Owned(tera))
+}
+
+/// File of the custom template a template error is in, if it names one.
+///
+/// The innermost error that names a custom template is used, since it names the template that
+/// failed rather than the templates that include it.
+pub fn error_file(
+    error: &tera::Error,
+    template_dir: Option<&Path>,
+    template_path: Option<&Path>,
+) -> Option<PathBuf> {
+    // The custom mail template comes first, since it replaces a `mail.html` in the directory.
+    let mut files = Vec::new();
+    if let Some(template_path) = template_path {
+        files.push((MAIL.to_string(), template_path.to_path_buf()));
+    }
+    if let Some(template_dir) = template_dir {
+        list_files(template_dir, template_dir, &mut files);
+    }
+    let mut messages = Vec::new();
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(error) = source {
+        messages.push(error.to_string());
+        source = error.source();
+    }
+    messages.iter().rev().find_map(|message| {
+        files
+            .iter()
+            .find(|(name, path)| {
+                // Rendering errors quote template names, and parsing errors quote paths.
+                message.contains(&format!("'{name}'"))
+                    || message.contains(&format!("\"{}\"", path.display()))
+            })
+            .map(|(_, path)| path.clone())
+    })
+}
+
+/// Add the files under a directory, with their names as templates, relative to the template
+/// directory.
+fn list_files(template_dir: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(template_dir, &path, files);
+        } else if let Ok(name) = path.strip_prefix(template_dir) {
+            let name = name.to_string_lossy().replace('\\', "/");
+            files.push((name, path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_without_template_dir_shares_built_in_templates() {
+        let (Cow::Borrowed(first), Cow::Borrowed(second)) = (
+            load(None, None).expect("failed to load templates"),
+            load(None, None).expect("failed to load templates"),
+        ) else {
+            panic!("built-in templates were compiled again");
+        };
+        assert!(std::ptr::eq(first, second));
+        assert!(first.get_template(MAIL).is_ok());
+    }
+
+    #[test]
+    fn custom_templates_replace_built_in_ones() {
+        let dir = std::env::temp_dir().join(format!("squeakmail-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create dir");
+        std::fs::write(
+            dir.join("item.html"),
+            "<li>{{ item.title | dir }}: custom</li>",
+        )
+        .expect("failed to write template");
+        let tera = load(Some(&dir), None);
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+        let tera = tera.expect("failed to load templates");
+        let mut context = tera::Context::new();
+        context.insert("item", &serde_json::json!({"title": "Title"}));
+        assert_eq!(
+            tera.render("item.html", &context)
+                .expect("failed to render template"),
+            "<li>ltr: custom</li>"
+        );
+        assert!(tera.get_template(MAIL).is_ok());
+    }
+
+    #[test]
+    fn custom_mail_template_replaces_mail_html() {
+        let dir = std::env::temp_dir().join(format!("squeakmail-template-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create dir");
+        let template_path = dir.join("digest.html");
+        std::fs::write(
+            &template_path,
+            "{% extends \"base.html\" %}{% block body %}custom{% endblock body %}",
+        )
+        .expect("failed to write template");
+        let tera = load(None, Some(&template_path));
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+        let tera = tera.expect("failed to load templates");
+        let mut context = tera::Context::new();
+        context.insert("subject", "Subject");
+        context.insert("accessibility", &serde_json::json!({}));
+        let html = tera
+            .render(MAIL, &context)
+            .expect("failed to render template");
+        assert!(html.contains("custom"));
+        assert!(tera.get_template("item.html").is_ok());
+    }
+
+    #[test]
+    fn error_file_names_the_failing_custom_template() {
+        let dir = std::env::temp_dir().join(format!("squeakmail-errors-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("parts")).expect("failed to create dir");
+        std::fs::write(dir.join("parts/entry.html"), "{{ item.missing }}")
+            .expect("failed to write template");
+        std::fs::write(dir.join("item.html"), "{% include \"parts/entry.html\" %}")
+            .expect("failed to write template");
+        let render_error = load(Some(&dir), None)
+            .expect("failed to load templates")
+            .render("item.html", &tera::Context::new())
+            .expect_err("broken template rendered");
+        std::fs::write(dir.join("broken.html"), "{% if %}").expect("failed to write template");
+        let parse_error = load(Some(&dir), None).expect_err("broken template parsed");
+        let files = (
+            error_file(&render_error, Some(&dir), None),
+            error_file(&parse_error, Some(&dir), None),
+            error_file(&render_error, None, Some(&dir.join("digest.html"))),
+        );
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+        assert_eq!(files.0, Some(dir.join("parts/entry.html")));
+        assert_eq!(files.1, Some(dir.join("broken.html")));
+        assert_eq!(files.2, None);
+    }
+}
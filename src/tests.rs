@@ -0,0 +1,2809 @@
+//! End-to-end tests of fetching feeds from a local HTTP server into an in-memory database.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::TimeZone;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::*;
+use crate::config::{Delivery, Filters, Sources};
+
+const FEED: &str = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Mock Feed</title>
+    <link>http://example.org/</link>
+    <description>Served by a mock server</description>
+    <item>
+      <title>First post</title>
+      <link>http://example.org/1</link>
+      <guid>1</guid>
+      <pubDate>Sat, 02 Nov 2019 00:00:00 GMT</pubDate>
+    </item>
+    <item>
+      <title>Second post</title>
+      <link>http://example.org/2</link>
+      <guid>2</guid>
+      <pubDate>Sun, 03 Nov 2019 00:00:00 GMT</pubDate>
+    </item>
+  </channel>
+</rss>"#;
+const ETAG: &str = "\"abc123\"";
+const LAST_MODIFIED: &str = "Sun, 03 Nov 2019 00:00:00 GMT";
+
+/// Request received by the mock server, with lowercase header names.
+#[derive(Debug, Clone)]
+struct Request {
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Response from the mock server.
+struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+impl Response {
+    fn new(status: &'static str, body: &[u8]) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    fn header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// HTTP server on a local port, answering each request with a handler and recording it.
+struct MockServer {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+impl MockServer {
+    fn start<F: Fn(&Request) -> Response + Send + 'static>(handler: F) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+        let address = listener
+            .local_addr()
+            .expect("failed to get mock server address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let server_requests = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.expect("failed to accept connection");
+                let request = read_request(&mut BufReader::new(&stream));
+                let response = handler(&request);
+                server_requests
+                    .lock()
+                    .expect("thread panicked while holding requests mutex")
+                    .push(request);
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                )
+                .expect("failed to write response");
+                for (name, value) in &response.headers {
+                    write!(stream, "{name}: {value}\r\n").expect("failed to write response");
+                }
+                stream.write_all(b"\r\n").expect("failed to write response");
+                stream
+                    .write_all(&response.body)
+                    .expect("failed to write response");
+            }
+        });
+        Self { address, requests }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.address)
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.requests
+            .lock()
+            .expect("thread panicked while holding requests mutex")
+            .clone()
+    }
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> Request {
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .expect("failed to read request line");
+    let path = line.split(' ').nth(1).unwrap_or("").to_string();
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).expect("failed to read header");
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        headers.insert(name.to_lowercase(), value.trim().to_string());
+    }
+    let content_length = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).expect("failed to read body");
+    Request {
+        path,
+        headers,
+        body,
+    }
+}
+
+/// IMAP server with a mailbox of messages by UID, which records the commands it receives.
+fn start_imap_server(messages: Vec<(u32, String)>) -> (u16, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+    let port = listener
+        .local_addr()
+        .expect("failed to get mock server address")
+        .port();
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let server_commands = commands.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.expect("failed to accept connection");
+            let mut reader = BufReader::new(stream.try_clone().expect("failed to clone stream"));
+            stream
+                .write_all(b"* OK ready\r\n")
+                .expect("failed to write greeting");
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).expect("failed to read command") == 0 {
+                    break;
+                }
+                let (tag, command) = line.trim_end().split_once(' ').expect("missing tag");
+                server_commands
+                    .lock()
+                    .expect("thread panicked while holding commands mutex")
+                    .push(command.to_string());
+                let words: Vec<&str> = command.split(' ').collect();
+                let data = match words.as_slice() {
+                    ["EXAMINE", _] => "* OK [UIDVALIDITY 42] ok\r\n".to_string(),
+                    ["UID", "SEARCH", "UID", range] => {
+                        let first: u32 =
+                            range.trim_end_matches(":*").parse().expect("invalid range");
+                        let uids: Vec<String> = messages
+                            .iter()
+                            .filter(|(uid, _)| *uid >= first)
+                            .map(|(uid, _)| uid.to_string())
+                            .collect();
+                        format!("* SEARCH {}\r\n", uids.join(" "))
+                    }
+                    ["UID", "FETCH", uid, "BODY.PEEK[]"] => {
+                        let (uid, message) = messages
+                            .iter()
+                            .find(|(id, _)| id.to_string() == *uid)
+                            .expect("unknown UID");
+                        format!(
+                            "* 1 FETCH (UID {uid} BODY[] {{{}}}\r\n{message})\r\n",
+                            message.len()
+                        )
+                    }
+                    _ => String::new(),
+                };
+                write!(stream, "{data}{tag} OK done\r\n").expect("failed to write response");
+                if command == "LOGOUT" {
+                    break;
+                }
+            }
+        }
+    });
+    (port, commands)
+}
+
+fn open_database() -> Mutex<database::Database> {
+    Mutex::new(
+        database::Database::open(Path::new(":memory:"), Path::new(":memory:"))
+            .expect("failed to open database"),
+    )
+}
+
+fn fetch(database: &Mutex<database::Database>, feed_url: &str) -> Result<()> {
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(feed_url)],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+    fetch_feed(&config, &config.sources.feeds[0], None, None, database).map(|_| ())
+}
+
+fn item_titles(database: &Mutex<database::Database>, feed_url: &str) -> Vec<String> {
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(feed_url)
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| item.title)
+        .collect()
+}
+
+fn fetches(database: &Mutex<database::Database>, feed_url: &str) -> Vec<database::Fetch> {
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_fetches(feed_url)
+        .expect("failed to get fetches")
+}
+
+/// Serve the feed with validators, responding "not modified" to matching conditional requests.
+fn conditional_handler(request: &Request) -> Response {
+    if request.headers.get("if-none-match").map(String::as_str) == Some(ETAG) {
+        Response::new("304 Not Modified", b"")
+    } else {
+        Response::new("200 OK", FEED.as_bytes())
+            .header("ETag", ETAG)
+            .header("Last-Modified", LAST_MODIFIED)
+    }
+}
+
+#[test]
+fn fetch_stores_feed_items_and_validators() {
+    let server = MockServer::start(conditional_handler);
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+
+    fetch(&database, &feed_url).expect("fetch failed");
+
+    let feed = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_feed_by_url(&feed_url)
+        .expect("failed to get feed")
+        .expect("feed was not stored");
+    assert_eq!(feed.title, "Mock Feed");
+    assert_eq!(feed.etag.as_deref(), Some(ETAG));
+    assert_eq!(feed.last_modified.as_deref(), Some(LAST_MODIFIED));
+    assert_eq!(
+        item_titles(&database, &feed_url),
+        ["First post", "Second post"]
+    );
+    let fetches = fetches(&database, &feed_url);
+    assert_eq!(fetches.len(), 1);
+    assert_eq!(fetches[0].status, 200);
+    assert!(!fetches[0].is_conditional);
+    assert_eq!(fetches[0].bytes, i64::try_from(FEED.len()).unwrap());
+}
+
+#[test]
+fn fetch_sends_validators_and_handles_not_modified() {
+    let server = MockServer::start(conditional_handler);
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+
+    fetch(&database, &feed_url).expect("fetch failed");
+    let result = fetch(&database, &feed_url);
+
+    assert!(
+        matches!(result, Err(Error::FeedNotModified)),
+        "{:?}",
+        result
+    );
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].headers.get("if-none-match"), None);
+    assert_eq!(
+        requests[1].headers.get("if-none-match").map(String::as_str),
+        Some(ETAG)
+    );
+    assert_eq!(
+        requests[1]
+            .headers
+            .get("if-modified-since")
+            .map(String::as_str),
+        Some(LAST_MODIFIED)
+    );
+    let fetches = fetches(&database, &feed_url);
+    assert_eq!(fetches.len(), 2);
+    assert_eq!(fetches[1].status, 304);
+    assert!(fetches[1].is_conditional);
+    assert_eq!(fetches[1].bytes, 0);
+    // Validators from the first fetch are kept.
+    let feed = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_feed_by_url(&feed_url)
+        .expect("failed to get feed")
+        .expect("feed was not stored");
+    assert_eq!(feed.etag.as_deref(), Some(ETAG));
+}
+
+#[test]
+fn fetch_follows_redirects() {
+    let server = MockServer::start(|request| {
+        if request.path == "/old.xml" {
+            Response::new("301 Moved Permanently", b"").header("Location", "/new.xml")
+        } else {
+            Response::new("200 OK", FEED.as_bytes())
+        }
+    });
+    let database = open_database();
+    let feed_url = server.url("/old.xml");
+
+    fetch(&database, &feed_url).expect("fetch failed");
+
+    let paths: Vec<String> = server
+        .requests()
+        .into_iter()
+        .map(|request| request.path)
+        .collect();
+    assert_eq!(paths, ["/old.xml", "/new.xml"]);
+    // Items are stored under the configured URL.
+    assert_eq!(
+        item_titles(&database, &feed_url),
+        ["First post", "Second post"]
+    );
+    // The URL they came from is kept.
+    let items = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(&feed_url)
+        .expect("failed to get items");
+    let fetched_at = fetches(&database, &feed_url)[0].fetched_at;
+    for item in items {
+        let source = item.source.expect("no source");
+        assert_eq!(source.url, server.url("/new.xml"));
+        assert_eq!(source.fetched_at, fetched_at);
+    }
+}
+
+#[test]
+fn fetch_adds_query_parameters_evaluated_per_fetch() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml?format=rss");
+    let mut feed_config = FeedConfig::new(&feed_url);
+    feed_config.query.insert(
+        "ts".to_string(),
+        "{epoch}".parse().expect("invalid template"),
+    );
+    let config = Config {
+        sources: Sources {
+            feeds: vec![feed_config],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+    let before = chrono::Utc::now().timestamp();
+
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+
+    let requests = server.requests();
+    let timestamp: i64 = requests[0]
+        .path
+        .strip_prefix("/feed.xml?format=rss&ts=")
+        .and_then(|timestamp| timestamp.parse().ok())
+        .expect("no timestamp parameter");
+    assert!(timestamp >= before);
+    // Items are stored under the configured URL.
+    assert_eq!(
+        item_titles(&database, &feed_url),
+        ["First post", "Second post"]
+    );
+}
+
+#[test]
+fn fetch_hashes_and_saves_the_whole_body_of_a_feed_read_as_its_parsed() {
+    let body = format!("{FEED}\n<!-- served from cache -->\n");
+    let server_body = body.clone();
+    let server = MockServer::start(move |_| Response::new("200 OK", server_body.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    let raw_dir = std::env::temp_dir().join(format!("squeakmail-stream-{}", std::process::id()));
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            raw_dir: Some(raw_dir.clone()),
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+
+    let saved = std::fs::read_dir(&raw_dir)
+        .and_then(|mut dirs| dirs.next().expect("no feed directory"))
+        .and_then(|dir| std::fs::read_dir(dir.path()))
+        .map(|bodies| {
+            bodies
+                .map(|body| std::fs::read_to_string(body.expect("failed to list").path()))
+                .collect::<std::io::Result<Vec<_>>>()
+        });
+    std::fs::remove_dir_all(&raw_dir).expect("failed to remove dir");
+    assert_eq!(
+        saved.expect("failed to list").expect("failed to read"),
+        [body.as_str()]
+    );
+    assert_eq!(
+        item_titles(&database, &feed_url),
+        ["First post", "Second post"]
+    );
+    let fetches = fetches(&database, &feed_url);
+    assert_eq!(fetches[0].bytes, i64::try_from(body.len()).unwrap());
+    assert_eq!(
+        fetches[0].body_hash,
+        Some(format!("{:x}", Sha256::digest(body.as_bytes())))
+    );
+}
+
+#[test]
+fn fetch_decodes_gzip() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(FEED.as_bytes())
+        .expect("failed to compress feed");
+    let compressed = encoder.finish().expect("failed to compress feed");
+    let server = MockServer::start(move |_| {
+        Response::new("200 OK", &compressed).header("Content-Encoding", "gzip")
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+
+    fetch(&database, &feed_url).expect("fetch failed");
+
+    assert!(server.requests()[0]
+        .headers
+        .get("accept-encoding")
+        .is_some_and(|encodings| encodings.contains("gzip")));
+    assert_eq!(
+        item_titles(&database, &feed_url),
+        ["First post", "Second post"]
+    );
+}
+
+#[test]
+fn fetch_decodes_charset_from_content_type() {
+    let latin1_feed: Vec<u8> = FEED
+        .replace("First post", "Caf\u{e9} post")
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).expect("feed is not Latin-1"))
+        .collect();
+    let server = MockServer::start(move |_| {
+        Response::new("200 OK", &latin1_feed)
+            .header("Content-Type", "application/rss+xml; charset=ISO-8859-1")
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+
+    fetch(&database, &feed_url).expect("fetch failed");
+
+    assert_eq!(
+        item_titles(&database, &feed_url),
+        ["Caf\u{e9} post", "Second post"]
+    );
+}
+
+#[test]
+fn fetch_reports_error_status() {
+    let server = MockServer::start(|_| Response::new("500 Internal Server Error", b"oops"));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+
+    let result = fetch(&database, &feed_url);
+
+    assert!(
+        matches!(result, Err(Error::UnexpectedStatusCode(500))),
+        "{:?}",
+        result
+    );
+    assert!(item_titles(&database, &feed_url).is_empty());
+    let fetches = fetches(&database, &feed_url);
+    assert_eq!(fetches.len(), 1);
+    assert_eq!(fetches[0].status, 500);
+    assert_eq!(fetches[0].body_hash, None);
+}
+
+#[test]
+fn fetch_reports_unparsable_feed() {
+    let server = MockServer::start(|_| Response::new("200 OK", b"<html>not a feed</html>"));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+
+    let result = fetch(&database, &feed_url);
+
+    assert!(matches!(result, Err(Error::Parse(_))), "{:?}", result);
+    assert!(item_titles(&database, &feed_url).is_empty());
+    // The download is still recorded.
+    assert_eq!(fetches(&database, &feed_url).len(), 1);
+}
+
+#[test]
+fn fetch_records_feed_title_and_link_changes() {
+    let renamed = FEED
+        .replace("<title>Mock Feed</title>", "<title>Cheap Pills</title>")
+        .replace(
+            "<link>http://example.org/</link>",
+            "<link>http://example.net/</link>",
+        );
+    let fetch_count = Arc::new(Mutex::new(0));
+    let server = MockServer::start(move |_| {
+        let mut fetch_count = fetch_count.lock().unwrap();
+        *fetch_count += 1;
+        match *fetch_count {
+            1 | 2 => Response::new("200 OK", FEED.as_bytes()),
+            _ => Response::new("200 OK", renamed.as_bytes()),
+        }
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+
+    fetch(&database, &feed_url).expect("fetch failed");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let get_changes = || {
+        database
+            .lock()
+            .expect("thread panicked while holding database mutex")
+            .get_unread_feed_changes(&feed_url)
+            .expect("failed to get feed changes")
+    };
+    assert!(get_changes().is_empty());
+    fetch(&database, &feed_url).expect("fetch failed");
+
+    let changes = get_changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].old_title, "Mock Feed");
+    assert_eq!(changes[0].new_title, "Cheap Pills");
+    assert_eq!(changes[0].old_link, "http://example.org/");
+    assert_eq!(changes[0].new_link, "http://example.net/");
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .mark_feed_changes_read()
+        .expect("failed to mark feed changes read");
+    assert!(get_changes().is_empty());
+}
+
+#[test]
+fn check_links_flags_dead_links() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/feed.xml" => {
+            let origin = format!("http://{}", request.headers["host"]);
+            let feed = FEED.replace("http://example.org", &origin);
+            Response::new("200 OK", feed.as_bytes())
+        }
+        "/1" => Response::new("200 OK", b""),
+        _ => Response::new("404 Not Found", b""),
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    check_links(&Config::default(), &mut database, 100_000).expect("check failed");
+
+    let dead: Vec<(String, bool)> = database
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| (item.title, item.is_dead))
+        .collect();
+    assert_eq!(
+        dead,
+        vec![
+            ("First post".to_string(), false),
+            ("Second post".to_string(), true)
+        ]
+    );
+}
+
+#[test]
+fn fetch_resolves_redirected_links() {
+    let server = MockServer::start(|request| {
+        let origin = format!("http://{}", request.headers["host"]);
+        match request.path.as_str() {
+            "/feed.xml" => {
+                let feed = FEED.replace("http://example.org", &origin);
+                Response::new("200 OK", feed.as_bytes())
+            }
+            "/1" => Response::new("301 Moved Permanently", b"").header("Location", "/hop"),
+            "/hop" => Response::new("302 Found", b"").header("Location", "/final"),
+            _ => Response::new("200 OK", b""),
+        }
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        filters: Filters {
+            resolve_links: true,
+            ..Filters::default()
+        },
+        ..Config::default()
+    };
+
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+
+    let links: Vec<String> = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| item.link)
+        .collect();
+    assert_eq!(links, vec![server.url("/final"), server.url("/2")]);
+    // Links are resolved once and then reused.
+    let requests = server.requests();
+    assert_eq!(requests.iter().filter(|r| r.path == "/1").count(), 1);
+}
+
+#[test]
+fn snoozed_items_are_hidden_until_snooze_expires() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    // Snoozes expire by the configured clock, which may be fixed, rather than the system's.
+    let now = chrono::Utc.ymd(2019, 11, 1).and_hms(12, 0, 0);
+    let unread_titles = |database: &mut database::Database, now| -> Vec<String> {
+        database
+            .get_unread_items(&feed_url, None, &[], now)
+            .expect("failed to get unread items")
+            .into_iter()
+            .map(|item| item.title)
+            .collect()
+    };
+
+    let snoozed = database
+        .snooze_item("1", None, now + chrono::Duration::days(7))
+        .expect("failed to snooze item");
+    assert_eq!(snoozed, 1);
+    assert_eq!(unread_titles(&mut database, now), vec!["Second post"]);
+    // Mailing the digest doesn't mark the snoozed item read.
+    database
+        .mark_items_read(&[], now)
+        .expect("failed to mark items read");
+    assert!(unread_titles(&mut database, now).is_empty());
+
+    let later = now + chrono::Duration::days(8);
+    assert_eq!(unread_titles(&mut database, later), vec!["First post"]);
+}
+
+#[test]
+fn durations_too_long_to_represent_are_invalid() {
+    assert_eq!(parse_duration("90m"), Ok(chrono::Duration::minutes(90)));
+    assert_eq!(parse_duration("2w"), Ok(chrono::Duration::days(14)));
+    assert!(parse_duration("99999999999999999m").is_err());
+    assert!(parse_duration("9223372036854775807w").is_err());
+    assert!(parse_duration("15250284452w").is_ok());
+    assert!(parse_duration("15250284453w").is_err());
+    assert!(parse_duration("7").is_err());
+    assert!(parse_duration("-7d").is_err());
+}
+
+#[test]
+fn fetch_saves_new_items_for_later() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/feed.xml" => Response::new("200 OK", FEED.as_bytes()),
+        "/oauth/v2/token" => Response::new("200 OK", br#"{"access_token": "token"}"#),
+        _ => Response::new("200 OK", b"{}"),
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        filters: Filters {
+            rules: vec![rules::Rule {
+                feed: None,
+                feed_url: None,
+                title: None,
+                category: None,
+                author: None,
+                action: rules::Action::ReadLater,
+            }],
+            ..Filters::default()
+        },
+        read_later: Some(later::Config::Wallabag {
+            url: server.url("/"),
+            client_id: "id".to_string(),
+            client_secret: "secret".to_string(),
+            username: "user".to_string(),
+            password: "password".to_string(),
+        }),
+        ..Config::default()
+    };
+
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+
+    let items = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(&feed_url)
+        .expect("failed to get items");
+    assert!(items.iter().all(|item| item.is_read));
+    // Items are saved once, when they're new.
+    let entries: Vec<Request> = server
+        .requests()
+        .into_iter()
+        .filter(|request| request.path == "/api/entries.json")
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        entries[0].headers.get("authorization").map(String::as_str),
+        Some("Bearer token")
+    );
+}
+
+#[test]
+fn fetch_repairs_encoding_if_enabled() {
+    let feed = FEED.replace("First post", "CafÃ© opens");
+    let server = MockServer::start(move |_| {
+        Response::new("200 OK", feed.as_bytes())
+            .header("Content-Type", "application/rss+xml; charset=utf-8")
+    });
+    let feed_url = server.url("/feed.xml");
+    for (repair_encoding, title) in [(false, "CafÃ© opens"), (true, "Café opens")] {
+        let database = open_database();
+        let config = Config {
+            sources: Sources {
+                feeds: vec![FeedConfig {
+                    repair_encoding,
+                    ..FeedConfig::new(&feed_url)
+                }],
+                ..Sources::default()
+            },
+            ..Config::default()
+        };
+        fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+        assert_eq!(item_titles(&database, &feed_url), [title, "Second post"]);
+    }
+}
+
+#[test]
+fn fetch_repairs_malformed_xml_if_enabled() {
+    let feed = FEED.replace("First post", "Q&A\u{b}&nbsp;time");
+    let server = MockServer::start(move |_| Response::new("200 OK", feed.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    for lenient_xml in [false, true] {
+        let database = open_database();
+        let config = Config {
+            sources: Sources {
+                feeds: vec![FeedConfig {
+                    lenient_xml,
+                    ..FeedConfig::new(&feed_url)
+                }],
+                ..Sources::default()
+            },
+            ..Config::default()
+        };
+        let result = fetch_feed(&config, &config.sources.feeds[0], None, None, &database);
+        if lenient_xml {
+            result.expect("fetch failed");
+            assert_eq!(
+                item_titles(&database, &feed_url),
+                ["Q&A&nbsp;time", "Second post"]
+            );
+        } else {
+            assert!(matches!(result, Err(Error::Parse(feed::Error::Parse))));
+        }
+    }
+}
+
+#[test]
+fn fetch_with_auth_caches_access_token_until_it_expires() {
+    std::env::set_var("SQUEAKMAIL_TEST_CLIENT_ID", "client");
+    std::env::set_var("SQUEAKMAIL_TEST_CLIENT_SECRET", "secret");
+    let tokens = Arc::new(Mutex::new(0));
+    let server = MockServer::start(move |request| {
+        if request.path == "/token" {
+            let mut tokens = tokens.lock().expect("thread panicked holding tokens mutex");
+            *tokens += 1;
+            let body = format!(r#"{{"access_token": "token{tokens}", "expires_in": 3600}}"#);
+            Response::new("200 OK", body.as_bytes())
+        } else {
+            Response::new("200 OK", FEED.as_bytes())
+        }
+    });
+    let auth = oauth::Config {
+        token_url: server.url("/token"),
+        client_id_env: "SQUEAKMAIL_TEST_CLIENT_ID".to_string(),
+        client_secret_env: "SQUEAKMAIL_TEST_CLIENT_SECRET".to_string(),
+        scope: Some("feeds".to_string()),
+    };
+    let feed_config = FeedConfig {
+        auth: Some(auth.clone()),
+        ..FeedConfig::new(&server.url("/feed.xml"))
+    };
+    let config = Config::default();
+    let database = open_database();
+    fetch_feed(&config, &feed_config, None, None, &database).expect("fetch failed");
+    fetch_feed(&config, &feed_config, None, None, &database).expect("fetch failed");
+    // Expire the cached token, as if an hour passed.
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .set_access_token(
+            &auth.token_url,
+            "client",
+            "feeds",
+            Some(&database::AccessToken {
+                token: "token1".to_string(),
+                expires_at: chrono::Utc::now() + chrono::Duration::seconds(30),
+            }),
+        )
+        .expect("failed to set access token");
+    fetch_feed(&config, &feed_config, None, None, &database).expect("fetch failed");
+
+    let requests = server.requests();
+    let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        ["/token", "/feed.xml", "/feed.xml", "/token", "/feed.xml"]
+    );
+    assert_eq!(
+        requests[0].headers.get("authorization").map(String::as_str),
+        Some("Basic Y2xpZW50OnNlY3JldA==")
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&requests[0].body),
+        "grant_type=client_credentials&scope=feeds"
+    );
+    let bearers: Vec<Option<&str>> = requests
+        .iter()
+        .filter(|r| r.path == "/feed.xml")
+        .map(|r| r.headers.get("authorization").map(String::as_str))
+        .collect();
+    assert_eq!(
+        bearers,
+        [
+            Some("Bearer token1"),
+            Some("Bearer token1"),
+            Some("Bearer token2")
+        ]
+    );
+}
+
+#[test]
+fn fetch_backs_off_after_challenge_page() {
+    let challenged = Arc::new(Mutex::new(true));
+    let server_challenged = challenged.clone();
+    let server = MockServer::start(move |_| {
+        if *server_challenged
+            .lock()
+            .expect("thread panicked while holding challenged mutex")
+        {
+            Response::new(
+                "403 Forbidden",
+                b"<!DOCTYPE html><html><head><title>Just a moment...</title></head>\
+                  <body><div id=\"cf-browser-verification\"></div></body></html>",
+            )
+            .header("Content-Type", "text/html; charset=UTF-8")
+        } else {
+            Response::new("200 OK", FEED.as_bytes())
+        }
+    });
+    let feed_url = server.url("/feed.xml");
+    let database = open_database();
+
+    let result = fetch(&database, &feed_url);
+    assert!(matches!(result, Err(Error::Challenge(provider)) if provider == "Cloudflare"));
+    // The host isn't asked again until the backoff is over.
+    let result = fetch(&database, &feed_url);
+    assert!(matches!(result, Err(Error::ChallengeBackoff(..))));
+    assert_eq!(server.requests().len(), 1);
+
+    *challenged
+        .lock()
+        .expect("thread panicked while holding challenged mutex") = false;
+    // Record a second challenge page in a row, longer ago than its two hour backoff.
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .set_challenge(
+            &feed_url,
+            chrono::Utc::now() - chrono::Duration::hours(3),
+            Some("Cloudflare"),
+        )
+        .expect("failed to set challenge");
+    fetch(&database, &feed_url).expect("fetch failed");
+    assert!(database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_challenge(&feed_url)
+        .expect("failed to get challenge")
+        .is_none());
+}
+
+#[test]
+fn exported_state_restores_into_new_database() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    let database = open_database();
+    fetch(&database, &feed_url).expect("fetch failed");
+    let json = {
+        let mut database = database
+            .lock()
+            .expect("thread panicked while holding database mutex");
+        database
+            .mark_items_read(&[], chrono::Utc::now())
+            .expect("failed to mark read");
+        database
+            .set_feed_muted(&feed_url, true)
+            .expect("failed to mute feed");
+        let mut export = state::Export::load(&mut database).expect("failed to export");
+        export.items.push(database::ItemState {
+            feed_url: feed_url.clone(),
+            guid: "gone".to_string(),
+            is_read: true,
+            is_highlighted: true,
+            is_saved: false,
+        });
+        serde_json::to_string(&export).expect("failed to serialize")
+    };
+
+    let database = open_database();
+    fetch(&database, &feed_url).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let export: state::Export = serde_json::from_str(&json).expect("failed to parse");
+    assert_eq!(export.restore(&mut database).expect("failed to restore"), 2);
+    assert!(database
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .iter()
+        .all(|item| item.is_read));
+    assert_eq!(
+        database.get_muted_feeds().expect("failed to get feeds"),
+        [feed_url]
+    );
+}
+
+#[test]
+fn fetch_identifies_items_by_configured_strategy() {
+    // The feed gives its items new GUIDs each time it's fetched.
+    let count = Mutex::new(0);
+    let server = MockServer::start(move |_| {
+        let mut count = count
+            .lock()
+            .expect("thread panicked while holding count mutex");
+        *count += 1;
+        let feed = FEED.replace("<guid>", &format!("<guid>{count}-"));
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let feed_url = server.url("/feed.xml");
+    let database = open_database();
+    let unread_guids = |database: &Mutex<database::Database>| -> Vec<String> {
+        database
+            .lock()
+            .expect("thread panicked while holding database mutex")
+            .get_unread_items(&feed_url, None, &[], chrono::Utc::now())
+            .expect("failed to get items")
+            .into_iter()
+            .map(|item| item.guid)
+            .collect()
+    };
+    let mut config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    assert_eq!(unread_guids(&database), ["1-1", "2-1", "1-2", "2-2"]);
+
+    // Switching strategies marks the items read instead of delivering them again.
+    config.sources.feeds[0].identity = r"link:/(\d+)$".parse().expect("invalid identity");
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    assert_eq!(unread_guids(&database), ["1-1", "2-1", "1-2", "2-2"]);
+    let guids: Vec<String> = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| item.guid)
+        .collect();
+    assert_eq!(guids.len(), 6);
+    assert!(guids.contains(&"1".to_string()) && guids.contains(&"2".to_string()));
+}
+
+#[test]
+fn archives_resurface_saved_items() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/feed.xml" => Response::new("200 OK", FEED.as_bytes()),
+        "/oauth/v2/token" => Response::new("200 OK", br#"{"access_token": "token"}"#),
+        _ => Response::new("200 OK", b"{}"),
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    let mut config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        filters: Filters {
+            rules: vec![
+                toml::from_str("title = \"First\"\naction = \"read_later\"").expect("invalid rule")
+            ],
+            ..Filters::default()
+        },
+        read_later: Some(later::Config::Wallabag {
+            url: server.url("/"),
+            client_id: "id".to_string(),
+            client_secret: "secret".to_string(),
+            username: "user".to_string(),
+            password: "password".to_string(),
+        }),
+        archives: Some(archive::Config {
+            count: 3,
+            min_age_days: 30,
+            max_age_days: None,
+        }),
+        ..Config::default()
+    };
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    database
+        .mark_items_read(&[], chrono::Utc::now())
+        .expect("failed to mark read");
+
+    // Only the saved item is resurfaced, though both are read.
+    let sections = digest_sections(&config, &mut database, None).expect("failed to load");
+    let titles: Vec<&str> = sections
+        .archives
+        .iter()
+        .map(|entry| entry.item.title.as_str())
+        .collect();
+    assert_eq!(titles, ["First post"]);
+    assert_eq!(sections.archives[0].feed_title, "Mock Feed");
+    let tera = load_templates(&config).expect("failed to load templates");
+    let html =
+        render_html(&config, &tera, "Digest", Vec::new(), &sections).expect("failed to render");
+    assert!(html.contains("From the archives"));
+
+    let resurfaced: Vec<database::DigestItem> = sections
+        .archives
+        .iter()
+        .map(|entry| database::DigestItem {
+            feed_url: entry.item.feed_url.clone(),
+            guid: entry.item.guid.clone(),
+            link: entry.item.link.clone(),
+        })
+        .collect();
+    database
+        .mark_items_resurfaced(&resurfaced)
+        .expect("failed to mark resurfaced");
+    let candidates = database
+        .get_archive_candidates(None, None, chrono::Utc::now())
+        .expect("failed to get candidates");
+    assert_eq!(candidates[0].resurfaced_count, 1);
+
+    // Items newer than the minimum age are left out.
+    config.archives = Some(archive::Config {
+        count: 3,
+        min_age_days: 365_000,
+        max_age_days: None,
+    });
+    let sections = digest_sections(&config, &mut database, None).expect("failed to load");
+    assert!(sections.archives.is_empty());
+}
+
+#[test]
+fn fetch_stores_hub_and_self_links() {
+    let server = MockServer::start(|_| {
+        let feed = FEED
+            .replace(
+                "<rss version=\"2.0\">",
+                "<rss version=\"2.0\" xmlns:a=\"http://www.w3.org/2005/Atom\">",
+            )
+            .replace(
+                "<channel>",
+                "<channel>\n    <a:link rel=\"self\" href=\"http://example.org/feed.xml\"/>\n    \
+                 <a:link rel=\"hub\" href=\"http://hub.example.org/\"/>",
+            );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    let mirror_url = server.url("/mirror.xml");
+
+    fetch(&database, &feed_url).expect("fetch failed");
+    fetch(&database, &mirror_url).expect("fetch failed");
+
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    let feed = database
+        .get_feed_by_url(&feed_url)
+        .expect("failed to get feed")
+        .expect("feed was not stored");
+    assert_eq!(feed.hub.as_deref(), Some("http://hub.example.org/"));
+    assert_eq!(
+        feed.self_url.as_deref(),
+        Some("http://example.org/feed.xml")
+    );
+    assert_eq!(
+        database
+            .get_feeds_with_self_url(&mirror_url, "http://example.org/feed.xml")
+            .expect("failed to get feeds"),
+        vec![feed_url]
+    );
+}
+
+#[test]
+fn fetch_translates_titles_once() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/feed.xml" => Response::new("200 OK", FEED.as_bytes()),
+        _ => Response::new("200 OK", br#"{"translatedText": "Beitrag"}"#),
+    });
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig {
+                translate: true,
+                ..FeedConfig::new(&feed_url)
+            }],
+            ..Sources::default()
+        },
+        translation: Some(translate::Config {
+            service: translate::Service::LibreTranslate,
+            url: Some(server.url("")),
+            api_key: None,
+            target_language: "de".to_string(),
+        }),
+        ..Config::default()
+    };
+
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+
+    let translated_titles: Vec<Option<String>> = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| item.translated_title)
+        .collect();
+    assert_eq!(
+        translated_titles,
+        vec![Some("Beitrag".to_string()), Some("Beitrag".to_string())]
+    );
+    // Titles are translated once and then reused.
+    let requests = server.requests();
+    assert_eq!(
+        requests.iter().filter(|r| r.path == "/translate").count(),
+        2
+    );
+}
+
+#[test]
+fn fetch_marks_items_of_large_fetches_as_bulk() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        filters: Filters {
+            bulk_threshold: Some(1),
+            ..Filters::default()
+        },
+        ..Config::default()
+    };
+
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    // Refetching finds no new items, but keeps the items collapsed.
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+
+    let is_bulk: Vec<bool> = database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| item.is_bulk)
+        .collect();
+    assert_eq!(is_bulk, vec![true, true]);
+}
+
+#[test]
+fn interrupted_deliveries_are_not_sent_again() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let item = database
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .remove(0);
+    let delivered = [database::DigestItem {
+        feed_url: item.feed_url.clone(),
+        guid: item.guid.clone(),
+        link: item.link.clone(),
+    }];
+    let recipient = config.delivery.to_email.to_string();
+    let digest_guids = |database: &mut database::Database| -> Vec<String> {
+        group_digests(&config, database, None).expect("failed to group digests")[&None]
+            .iter()
+            .flat_map(|feed| &feed.items)
+            .map(|item| item.guid.clone())
+            .collect()
+    };
+
+    // Sending was interrupted, so the item may have been delivered.
+    database
+        .start_deliveries(&recipient, "sendmail", chrono::Utc::now(), &delivered)
+        .expect("failed to start deliveries");
+    let guids = digest_guids(&mut database);
+    assert_eq!(guids.len(), 1);
+    assert!(!guids.contains(&item.guid));
+
+    // Sending failed, so the item is sent again.
+    database
+        .cancel_deliveries(&recipient, &delivered)
+        .expect("failed to cancel deliveries");
+    assert!(digest_guids(&mut database).contains(&item.guid));
+}
+
+#[test]
+fn content_changed_right_after_the_first_fetch_is_listed() {
+    let fetches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let server_fetches = fetches.clone();
+    let server = MockServer::start(move |_| {
+        let content = match server_fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+            0 => "First version.",
+            _ => "Corrected version.",
+        };
+        let feed = FEED.replace(
+            "<guid>1</guid>",
+            &format!("<guid>1</guid><description>{content}</description>"),
+        );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let feed_url = server.url("/feed.xml");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        updated_items: true,
+        ..Config::default()
+    };
+    let database = open_database();
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mailed = [database::DigestItem {
+        feed_url: feed_url.clone(),
+        guid: "1".to_string(),
+        link: "http://example.org/1".to_string(),
+    }];
+    {
+        let mut database = database
+            .lock()
+            .expect("thread panicked while holding database mutex");
+        database
+            .start_deliveries("me@example.com", "sendmail", chrono::Utc::now(), &mailed)
+            .expect("failed to start deliveries");
+        database
+            .mark_digest_items_read(&mailed)
+            .expect("failed to mark items read");
+    }
+
+    // The hash of the first version was stored with the new item, so the second fetch sees the
+    // correction.
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let updated = digest_sections(&config, &mut database, None)
+        .expect("failed to load sections")
+        .updated;
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated[0].item.title, "First post");
+}
+
+#[test]
+fn read_items_with_changed_content_are_listed_once() {
+    let fetches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let server_fetches = fetches.clone();
+    let server = MockServer::start(move |_| {
+        let content = match server_fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+            0 => "&lt;p&gt;First version.&lt;/p&gt;",
+            // Only the markup and spacing changed.
+            1 => "&lt;p&gt;First\n  version.&lt;/p&gt;&lt;br&gt;",
+            _ => "&lt;p&gt;Corrected version.&lt;/p&gt;",
+        };
+        let feed = FEED.replace(
+            "<guid>1</guid>",
+            &format!("<guid>1</guid><description>{content}</description>"),
+        );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let feed_url = server.url("/feed.xml");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        updated_items: true,
+        ..Config::default()
+    };
+    let database = open_database();
+    let fetch = || {
+        fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    };
+    let updated_titles = || -> Vec<String> {
+        let mut database = database
+            .lock()
+            .expect("thread panicked while holding database mutex");
+        digest_sections(&config, &mut database, None)
+            .expect("failed to load sections")
+            .updated
+            .into_iter()
+            .map(|entry| entry.item.title)
+            .collect()
+    };
+
+    fetch();
+    let mailed = [database::DigestItem {
+        feed_url: feed_url.clone(),
+        guid: "1".to_string(),
+        link: "http://example.org/1".to_string(),
+    }];
+    {
+        let mut database = database
+            .lock()
+            .expect("thread panicked while holding database mutex");
+        database
+            .start_deliveries("me@example.com", "sendmail", chrono::Utc::now(), &mailed)
+            .expect("failed to start deliveries");
+        database
+            .mark_digest_items_read(&mailed)
+            .expect("failed to mark items read");
+    }
+    fetch();
+    assert!(updated_titles().is_empty());
+
+    fetch();
+    assert_eq!(updated_titles(), ["First post"]);
+    let sections = {
+        let mut database = database
+            .lock()
+            .expect("thread panicked while holding database mutex");
+        digest_sections(&config, &mut database, None).expect("failed to load sections")
+    };
+    let tera = load_templates(&config).expect("failed to load templates");
+    let html =
+        render_html(&config, &tera, "Digest", Vec::new(), &sections).expect("failed to render");
+    assert!(html.contains("Updated articles"));
+    assert!(html.contains("First post"));
+
+    database
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .clear_items_updated(&mailed)
+        .expect("failed to clear updated items");
+    assert!(updated_titles().is_empty());
+}
+
+#[test]
+fn email_per_feed_mails_updated_items() {
+    let fetches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let server_fetches = fetches.clone();
+    let server = MockServer::start(move |_| {
+        let content = match server_fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+            0 => "&lt;p&gt;First version.&lt;/p&gt;",
+            _ => "&lt;p&gt;Corrected version.&lt;/p&gt;",
+        };
+        let feed = FEED.replace(
+            "<guid>1</guid>",
+            &format!("<guid>1</guid><description>{content}</description>"),
+        );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let feed_url = server.url("/feed.xml");
+    let mut config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        updated_items: true,
+        ..Config::default()
+    };
+    config.delivery.email_per_feed = true;
+    let database = open_database();
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mailed = [database::DigestItem {
+        feed_url: feed_url.clone(),
+        guid: "1".to_string(),
+        link: "http://example.org/1".to_string(),
+    }];
+    {
+        let mut database = database
+            .lock()
+            .expect("thread panicked while holding database mutex");
+        database
+            .start_deliveries("me@example.com", "sendmail", chrono::Utc::now(), &mailed)
+            .expect("failed to start deliveries");
+        database
+            .mark_digest_items_read(&mailed)
+            .expect("failed to mark items read");
+    }
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    // The updated item is listed in the email of the feed with a new item.
+    let digests = render_mails(&config, &mut database, None).expect("failed to render");
+    assert_eq!(digests.len(), 1);
+    assert_eq!(digests[0].items.len(), 1);
+    assert_eq!(digests[0].updated[0].guid, "1");
+
+    // Without new items, it's listed in an email of its own.
+    database
+        .mark_items_read(&[], chrono::Utc::now())
+        .expect("failed to mark read");
+    let digests = render_mails(&config, &mut database, None).expect("failed to render");
+    assert_eq!(digests.len(), 1);
+    assert!(digests[0].items.is_empty());
+    assert_eq!(digests[0].updated[0].guid, "1");
+}
+
+#[test]
+fn footer_lists_failed_feeds() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/feed.xml" => Response::new("200 OK", FEED.as_bytes()),
+        _ => Response::new("500 Internal Server Error", b""),
+    });
+    let feed_url = server.url("/feed.xml");
+    let broken_url = server.url("/broken.xml");
+    let config = Arc::new(Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url), FeedConfig::new(&broken_url)],
+            ..Sources::default()
+        },
+        footer: true,
+        ..Config::default()
+    });
+    let database = Arc::new(open_database());
+
+    fetch_feeds(&config, config.sources.feeds.clone(), &database, None).expect("fetch failed");
+
+    let mut database = Arc::try_unwrap(database)
+        .ok()
+        .expect("database still shared")
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let footer = digest_footer(&config, &mut database, None)
+        .expect("failed to load footer")
+        .expect("no footer");
+    assert_eq!(footer.version, env!("CARGO_PKG_VERSION"));
+    assert!(footer.fetched_at.is_some());
+    let failed: Vec<&str> = footer
+        .failed_feeds
+        .iter()
+        .map(|feed| feed.url.as_str())
+        .collect();
+    assert_eq!(failed, [broken_url.as_str()]);
+    // No digest was sent before, so the next one can't be expected yet.
+    assert_eq!(footer.next_send, None);
+
+    let feeds = group_digests(&config, &mut database, None)
+        .expect("failed to group digests")
+        .remove(&None)
+        .expect("no digest");
+    let tera = load_templates(&config).expect("failed to load templates");
+    let sections = Sections {
+        footer: Some(footer),
+        ..Sections::default()
+    };
+    let html = render_html(&config, &tera, "Digest", feeds, &sections).expect("failed to render");
+    assert!(html.contains("Failed to fetch:"));
+    assert!(html.contains("unexpected status code: 500"));
+}
+
+#[test]
+fn fetch_summary_is_posted_to_webhook() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/feed.xml" => Response::new("200 OK", FEED.as_bytes()),
+        "/webhook" => Response::new("204 No Content", b""),
+        _ => Response::new("500 Internal Server Error", b""),
+    });
+    let feed_url = server.url("/feed.xml");
+    let broken_url = server.url("/broken.xml");
+    let config = Arc::new(Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url), FeedConfig::new(&broken_url)],
+            ..Sources::default()
+        },
+        hooks: Some(hooks::Config {
+            pre_fetch: None,
+            post_fetch: None,
+            post_fetch_url: Some(server.url("/webhook")),
+            post_mail: None,
+        }),
+        ..Config::default()
+    });
+    let database = Arc::new(open_database());
+
+    fetch_feeds(&config, config.sources.feeds.clone(), &database, None).expect("fetch failed");
+    fetch_feeds(&config, config.sources.feeds.clone(), &database, None).expect("fetch failed");
+
+    let summaries: Vec<serde_json::Value> = server
+        .requests()
+        .into_iter()
+        .filter(|request| request.path == "/webhook")
+        .map(|request| {
+            assert_eq!(
+                request.headers.get("content-type").map(String::as_str),
+                Some("application/json")
+            );
+            serde_json::from_slice(&request.body).expect("invalid summary")
+        })
+        .collect();
+    assert_eq!(summaries.len(), 2);
+    for (summary, new_items) in summaries.iter().zip([2, 0]) {
+        assert!(summary["started_at"].is_string());
+        assert!(summary["duration_ms"].is_u64());
+        let mut feeds = summary["feeds"].as_array().expect("no feeds").clone();
+        feeds.sort_by_key(|feed| feed["url"].as_str().map(str::to_string));
+        assert_eq!(feeds[0]["url"], broken_url.as_str());
+        assert_eq!(feeds[0]["status"], "failed");
+        assert_eq!(feeds[0]["new_items"], 0);
+        assert_eq!(feeds[0]["error"], "unexpected status code: 500");
+        assert_eq!(feeds[1]["url"], feed_url.as_str());
+        assert_eq!(feeds[1]["status"], "fetched");
+        assert_eq!(feeds[1]["new_items"], new_items);
+        assert!(feeds[1]["error"].is_null());
+    }
+}
+
+#[test]
+fn fetch_starts_no_feeds_after_the_deadline() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/webhook" => Response::new("204 No Content", b""),
+        _ => Response::new("200 OK", FEED.as_bytes()),
+    });
+    let feed_url = server.url("/feed.xml");
+    let config = Arc::new(Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        hooks: Some(hooks::Config {
+            pre_fetch: None,
+            post_fetch: None,
+            post_fetch_url: Some(server.url("/webhook")),
+            post_mail: None,
+        }),
+        ..Config::default()
+    });
+    let database = Arc::new(open_database());
+
+    fetch_feeds(
+        &config,
+        config.sources.feeds.clone(),
+        &database,
+        Some(Instant::now() + Duration::from_secs(10)),
+    )
+    .expect("fetch failed");
+
+    let requests = server.requests();
+    assert!(requests.iter().all(|request| request.path == "/webhook"));
+    let summary: serde_json::Value =
+        serde_json::from_slice(&requests[0].body).expect("invalid summary");
+    assert_eq!(summary["feeds"][0]["url"], feed_url.as_str());
+    assert_eq!(summary["feeds"][0]["status"], "skipped");
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    assert!(database
+        .get_feed_by_url(&feed_url)
+        .expect("failed to get feed")
+        .is_none());
+}
+
+#[test]
+fn read_only_database_reads_but_does_not_write() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    let dir = std::env::temp_dir().join(format!("squeakmail-read-only-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create dir");
+    let (path, cache_path) = (dir.join("state?.db"), dir.join("cache#.db"));
+    let database =
+        Mutex::new(database::Database::open(&path, &cache_path).expect("failed to open database"));
+    fetch(&database, &feed_url).expect("fetch failed");
+
+    let read_only = Mutex::new(
+        database::Database::open_read_only(&path, &cache_path)
+            .expect("failed to open database read-only"),
+    );
+    assert_eq!(
+        item_titles(&read_only, &feed_url),
+        item_titles(&database, &feed_url)
+    );
+    assert_eq!(fetches(&read_only, &feed_url).len(), 1);
+    let muted = read_only
+        .lock()
+        .expect("thread panicked while holding database mutex")
+        .set_feed_muted(&feed_url, true);
+    std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    assert!(muted.is_err());
+}
+
+#[test]
+fn read_only_database_is_not_created() {
+    let dir = std::env::temp_dir().join(format!("squeakmail-missing-{}", std::process::id()));
+    assert!(
+        database::Database::open_read_only(&dir.join("state.db"), &dir.join("cache.db")).is_err()
+    );
+    assert!(!dir.exists());
+}
+
+#[test]
+fn only_the_configured_recipient_gets_mute_links() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        filters: Filters {
+            rules: vec![toml::from_str(
+                "title = \"First\"\naction = { route = \"team@example.com\" }",
+            )
+            .expect("invalid rule")],
+            ..Filters::default()
+        },
+        callback: Some(
+            toml::from_str(
+                "url = \"https://squeakmail.example.com\"\nlisten = \"127.0.0.1:0\"\n\
+                 secret = \"s3cret\"",
+            )
+            .expect("invalid callback"),
+        ),
+        ..Config::default()
+    };
+    let database = open_database();
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let digests = group_digests(&config, &mut database, None).expect("failed to group digests");
+
+    let mute_links = digests
+        .iter()
+        .map(|(recipient, feeds)| (recipient.as_deref(), feeds[0].mute_link.is_some()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        mute_links,
+        [(None, true), (Some("team@example.com"), false)]
+    );
+}
+
+#[test]
+fn added_feeds_that_fail_to_fetch_are_removed_again() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/feed.xml" => Response::new("200 OK", FEED.as_bytes()),
+        _ => Response::new("404 Not Found", b""),
+    });
+    let dir = std::env::temp_dir().join(format!("squeakmail-add-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create dir");
+    let config_path = dir.join("squeakmail.toml");
+    let original = "[sources]\nconcurrency = 1\nfeeds = []\n[delivery]\n\
+                    from_email = \"a@example.com\"\nto_email = \"b@example.com\"\n";
+    std::fs::write(&config_path, original).expect("failed to write config");
+    let config = Config::load(&config_path, false)
+        .expect("failed to load config")
+        .config;
+    let open = || {
+        database::Database::open(Path::new(":memory:"), Path::new(":memory:"))
+            .expect("failed to open database")
+    };
+
+    let missing = add_feed(
+        &config_path,
+        &config,
+        open(),
+        &server.url("/gone.xml"),
+        true,
+    );
+    let after_missing = std::fs::read_to_string(&config_path).expect("failed to read config");
+    let added = add_feed(
+        &config_path,
+        &config,
+        open(),
+        &server.url("/feed.xml"),
+        true,
+    );
+    let after_added = Config::load(&config_path, false);
+    std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    assert!(missing.is_err());
+    assert_eq!(after_missing, original);
+    added.expect("failed to add feed");
+    let feeds = after_added
+        .expect("failed to load config")
+        .config
+        .sources
+        .feeds;
+    assert_eq!(feeds.len(), 1);
+    assert_eq!(feeds[0].url, server.url("/feed.xml"));
+}
+
+#[test]
+fn feeds_imported_from_opml_folders_are_in_the_regular_digest() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    let dir = std::env::temp_dir().join(format!("squeakmail-opml-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create dir");
+    let config_path = dir.join("squeakmail.toml");
+    std::fs::write(
+        &config_path,
+        "[sources]\nconcurrency = 1\nfeeds = []\n[delivery]\nfrom_email = \"a@example.com\"\n\
+         to_email = \"b@example.com\"\n",
+    )
+    .expect("failed to write config");
+    let opml_path = dir.join("feeds.opml");
+    std::fs::write(
+        &opml_path,
+        format!(
+            "<opml version=\"2.0\"><body><outline text=\"Tech\">\
+             <outline type=\"rss\" text=\"Mock\" xmlUrl=\"{feed_url}\"/>\
+             </outline></body></opml>"
+        ),
+    )
+    .expect("failed to write OPML");
+
+    import_opml(&config_path, &opml_path).expect("import failed");
+    let config = Config::load(&config_path, false).expect("failed to load config");
+    std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    let config = config.config;
+    assert_eq!(config.sources.feeds[0].folder.as_deref(), Some("Tech"));
+    assert!(config.sources.feeds[0].tags.is_empty());
+    let database = open_database();
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let digests = render_mails(&config, &mut database, None).expect("failed to render");
+    assert_eq!(digests.len(), 1);
+    assert_eq!(digests[0].items.len(), 2);
+}
+
+#[test]
+fn tagged_items_are_in_the_regular_digest_unless_the_tag_is_exclusive() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    let mut config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig {
+                tags: vec!["news".to_string()],
+                ..FeedConfig::new(&feed_url)
+            }],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+    let database = open_database();
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let mut items = |config: &Config, tag| {
+        group_digests(config, &mut database, tag)
+            .expect("failed to group digests")
+            .into_values()
+            .flatten()
+            .map(|feed| feed.items.len())
+            .sum::<usize>()
+    };
+
+    assert_eq!(items(&config, None), 2);
+    assert_eq!(items(&config, Some("news")), 2);
+    config.filters.exclusive_tags = vec!["news".to_string()];
+    assert_eq!(items(&config, None), 0);
+    assert_eq!(items(&config, Some("news")), 2);
+}
+
+#[test]
+fn pipelines_mail_their_items_with_their_options() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    let maildir = std::env::temp_dir().join(format!("squeakmail-pipeline-{}", std::process::id()));
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        filters: Filters {
+            rules: vec![
+                toml::from_str("title = \"First\"\naction = { tag = \"alerts\" }")
+                    .expect("invalid rule"),
+            ],
+            ..Filters::default()
+        },
+        delivery: Delivery {
+            pipelines: vec![toml::from_str(&format!(
+                "name = \"alerts\"\n\
+             tag = \"alerts\"\n\
+             to_email = \"alerts@example.com\"\n\
+             transport = {{ type = \"maildir\", path = {:?} }}",
+                maildir.display().to_string()
+            ))
+            .expect("invalid pipeline")],
+            ..Delivery::default()
+        },
+        ..Config::default()
+    };
+    let database = open_database();
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let pipeline = &config.delivery.pipelines[0];
+    mail(
+        &pipeline.apply(&config),
+        &mut database,
+        false,
+        pipeline.tag.as_deref(),
+    )
+    .expect("mail failed");
+
+    let mails: Vec<String> = std::fs::read_dir(maildir.join("new"))
+        .expect("no mail written")
+        .map(|entry| {
+            std::fs::read_to_string(entry.expect("failed to read dir").path())
+                .expect("failed to read mail")
+        })
+        .collect();
+    std::fs::remove_dir_all(&maildir).expect("failed to remove maildir");
+    assert_eq!(mails.len(), 1);
+    assert!(mails[0].contains("To: <alerts@example.com>"));
+    assert!(mails[0].contains("First post"));
+    assert!(!mails[0].contains("Second post"));
+    let unread: Vec<String> = database
+        .get_unread_items(&feed_url, None, &[], chrono::Utc::now())
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| item.title)
+        .collect();
+    assert_eq!(unread, ["Second post"]);
+}
+
+#[test]
+fn tag_digests_leave_items_of_muted_feeds_unread() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_urls = [server.url("/a.xml"), server.url("/b.xml")];
+    let maildir = std::env::temp_dir().join(format!("squeakmail-muted-{}", std::process::id()));
+    let config = Config {
+        sources: Sources {
+            feeds: feed_urls.iter().map(|url| FeedConfig::new(url)).collect(),
+            ..Sources::default()
+        },
+        filters: Filters {
+            rules: vec![
+                toml::from_str("title = \"First\"\naction = { tag = \"alerts\" }")
+                    .expect("invalid rule"),
+            ],
+            ..Filters::default()
+        },
+        delivery: Delivery {
+            transport: Some(
+                toml::from_str(&format!(
+                    "type = \"maildir\"\npath = {:?}",
+                    maildir.display().to_string()
+                ))
+                .expect("invalid transport"),
+            ),
+            ..Delivery::default()
+        },
+        ..Config::default()
+    };
+    let database = open_database();
+    for feed in &config.sources.feeds {
+        fetch_feed(&config, feed, None, None, &database).expect("fetch failed");
+    }
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    database
+        .set_feed_muted(&feed_urls[1], true)
+        .expect("failed to mute feed");
+
+    mail(&config, &mut database, false, Some("alerts")).expect("mail failed");
+
+    std::fs::remove_dir_all(&maildir).expect("failed to remove maildir");
+    let mut unread = |feed_url: &str| -> Vec<String> {
+        database
+            .get_unread_items(feed_url, None, &[], chrono::Utc::now())
+            .expect("failed to get items")
+            .into_iter()
+            .map(|item| item.title)
+            .collect()
+    };
+    assert_eq!(unread(&feed_urls[0]), ["Second post"]);
+    assert_eq!(unread(&feed_urls[1]), ["First post", "Second post"]);
+}
+
+#[test]
+fn items_without_links_render_as_configured() {
+    let feed = FEED.replace("<link>http://example.org/1</link>", "");
+    let server = MockServer::start(move |_| Response::new("200 OK", feed.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let links: Vec<String> = database
+        .get_items(&feed_url)
+        .expect("failed to get items")
+        .into_iter()
+        .map(|item| item.link)
+        .collect();
+    assert!(links.contains(&String::new()));
+
+    let mut render = |missing_link| {
+        let config = Config {
+            sources: Sources {
+                feeds: vec![FeedConfig::new(&feed_url)],
+                ..Sources::default()
+            },
+            missing_link,
+            ..Config::default()
+        };
+        let feeds = group_digests(&config, &mut database, None)
+            .expect("failed to group digests")
+            .remove(&None)
+            .expect("no digest");
+        let tera = load_templates(&config).expect("failed to load templates");
+        render_html(&config, &tera, "Digest", feeds, &Sections::default())
+            .expect("failed to render")
+    };
+    let html = render(config::MissingLink::Text);
+    assert!(html.contains("First post"));
+    assert!(!html.contains(">First post</a>"));
+    let html = render(config::MissingLink::FeedLink);
+    assert!(html.contains(r#"<a href="http:&#x2F;&#x2F;example.org&#x2F;" target="_blank" rel="noopener">First post</a>"#));
+}
+
+#[test]
+fn qr_codes_render_for_items_with_links() {
+    let feed = FEED.replace("<link>http://example.org/1</link>", "");
+    let server = MockServer::start(move |_| Response::new("200 OK", feed.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let mut render = |qr_codes| {
+        let config = Config {
+            sources: Sources {
+                feeds: vec![FeedConfig::new(&feed_url)],
+                ..Sources::default()
+            },
+            qr_codes,
+            ..Config::default()
+        };
+        let feeds = group_digests(&config, &mut database, None)
+            .expect("failed to group digests")
+            .remove(&None)
+            .expect("no digest");
+        let tera = load_templates(&config).expect("failed to load templates");
+        render_html(&config, &tera, "Digest", feeds, &Sections::default())
+            .expect("failed to render")
+    };
+    let image = r#"<img src="data:image&#x2F;png;base64,"#;
+    assert_eq!(render(true).matches(image).count(), 1);
+    assert_eq!(render(false).matches(image).count(), 0);
+}
+
+#[test]
+fn feed_tiers_set_how_much_of_items_digests_show() {
+    let server = MockServer::start(|request| {
+        let feed = FEED.replace(
+            "<guid>1</guid>",
+            "<guid>1</guid><description>&lt;p&gt;Full &lt;b&gt;text&lt;/b&gt;&lt;/p&gt;\
+             &lt;p&gt;More&lt;/p&gt;</description>",
+        );
+        let feed = match request.path.as_str() {
+            "/primary.xml" => feed.replace("First post", "Primary post"),
+            _ => feed.replace("First post", "Firehose post"),
+        };
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let feed = |path: &str, tier| FeedConfig {
+        tier,
+        ..FeedConfig::new(&server.url(path))
+    };
+    let config = Config {
+        sources: Sources {
+            feeds: vec![
+                feed("/primary.xml", config::Tier::Primary),
+                feed("/firehose.xml", config::Tier::Firehose),
+                feed("/default.xml", config::Tier::Secondary),
+            ],
+            ..Sources::default()
+        },
+        include_content: true,
+        ..Config::default()
+    };
+    let database = open_database();
+    for feed_config in &config.sources.feeds {
+        fetch_feed(&config, feed_config, None, None, &database).expect("fetch failed");
+    }
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let feeds = group_digests(&config, &mut database, None)
+        .expect("failed to group digests")
+        .remove(&None)
+        .expect("no digest");
+    assert_eq!(feeds[0].items[0].content, ["Full text", "More"]);
+    assert!(feeds[0].items[0].excerpt.is_empty());
+    assert!(feeds[1].items[0].content.is_empty());
+    assert!(feeds[1].items[0].excerpt.is_empty());
+    // Secondary feeds show an excerpt with include_content, and not the content.
+    assert!(feeds[2].items[0].content.is_empty());
+    assert_eq!(feeds[2].items[0].excerpt, ["Full text", "More"]);
+    assert!(!feeds[2].items[0].is_truncated);
+    let tera = load_templates(&config).expect("failed to load templates");
+    let html = render_html(&config, &tera, "Digest", feeds, &Sections::default())
+        .expect("failed to render");
+    assert_eq!(html.matches(">Full text</p>").count(), 2);
+    assert!(!html.contains("Read more"));
+    assert_eq!(
+        html.matches("display: inline; margin-right: 1em;").count(),
+        2
+    );
+}
+
+#[test]
+fn long_excerpts_link_to_the_rest() {
+    let server = MockServer::start(|_| {
+        let description =
+            "&lt;script&gt;track();&lt;/script&gt;&lt;p&gt;Some words&lt;/p&gt;".repeat(100);
+        let feed = FEED.replace(
+            "<guid>1</guid>",
+            &format!("<guid>1</guid><description>{description}</description>"),
+        );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&server.url("/feed.xml"))],
+            ..Sources::default()
+        },
+        include_content: true,
+        ..Config::default()
+    };
+    let database = open_database();
+    fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let feeds = group_digests(&config, &mut database, None)
+        .expect("failed to group digests")
+        .remove(&None)
+        .expect("no digest");
+    let item = &feeds[0].items[0];
+    assert!(item.is_truncated);
+    assert!(item.excerpt.len() < 100);
+    assert!(item
+        .excerpt
+        .iter()
+        .all(|paragraph| !paragraph.contains("track")));
+    let tera = load_templates(&config).expect("failed to load templates");
+    let html = render_html(&config, &tera, "Digest", feeds, &Sections::default())
+        .expect("failed to render");
+    assert_eq!(html.matches(">Read more</a>").count(), 1);
+}
+
+#[test]
+fn primary_feeds_cut_content_short_to_their_budget() {
+    let server = MockServer::start(|_| {
+        let feed = FEED.replace(
+            "<guid>1</guid>",
+            "<guid>1</guid><description>&lt;p&gt;First paragraph&lt;/p&gt;\
+             &lt;p&gt;Second paragraph&lt;/p&gt;&lt;p&gt;Third&lt;/p&gt;</description>",
+        );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let render = |max_content_chars, max_paragraphs| {
+        let config = Config {
+            sources: Sources {
+                feeds: vec![FeedConfig {
+                    tier: config::Tier::Primary,
+                    max_content_chars: std::num::NonZeroUsize::new(max_content_chars),
+                    max_paragraphs: std::num::NonZeroUsize::new(max_paragraphs),
+                    ..FeedConfig::new(&server.url("/feed.xml"))
+                }],
+                ..Sources::default()
+            },
+            ..Config::default()
+        };
+        let database = open_database();
+        fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+        let mut database = database
+            .into_inner()
+            .expect("thread panicked while holding database mutex");
+        let feeds = group_digests(&config, &mut database, None)
+            .expect("failed to group digests")
+            .remove(&None)
+            .expect("no digest");
+        let content = feeds[0].items[0].content.clone();
+        let text = text::render(config.locale.strings(), "Digest", &feeds, None);
+        (content, text)
+    };
+
+    let (content, text) = render(0, 0);
+    assert_eq!(content, ["First paragraph", "Second paragraph", "Third"]);
+    assert!(!text.contains("Read more"));
+    let (content, text) = render(0, 1);
+    assert_eq!(content, ["First paragraph"]);
+    assert!(text.contains("  Read more: http"));
+    let (content, _) = render(25, 0);
+    assert_eq!(content, ["First paragraph", "Second…"]);
+}
+
+#[test]
+fn full_content_shows_the_article_and_caches_its_page() {
+    let server = MockServer::start(|request| {
+        if request.path == "/article" {
+            return Response::new(
+                "200 OK",
+                b"<html><body><nav>Menu</nav><article><p>Article text</p>\
+                  <aside>Related</aside></article></body></html>",
+            )
+            .header("ETag", ETAG);
+        }
+        let origin = format!("http://{}", request.headers["host"]);
+        let feed = FEED
+            .replace("http://example.org/1", &format!("{origin}/article"))
+            .replace(
+                "<guid>1</guid>",
+                "<guid>1</guid><description>Summary</description>",
+            );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig {
+                tier: config::Tier::Primary,
+                full_content: true,
+                ..FeedConfig::new(&server.url("/feed.xml"))
+            }],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+    let database = open_database();
+    for _ in 0..2 {
+        fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    }
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let feeds = group_digests(&config, &mut database, None)
+        .expect("failed to group digests")
+        .remove(&None)
+        .expect("no digest");
+    assert_eq!(feeds[0].items[0].content, ["Article text"]);
+    // The second fetch reuses the page from the first.
+    let article_requests = server
+        .requests()
+        .into_iter()
+        .filter(|request| request.path == "/article")
+        .count();
+    assert_eq!(article_requests, 1);
+    let article = database
+        .get_article(&server.url("/article"))
+        .expect("failed to get article")
+        .expect("article was not cached");
+    assert_eq!(article.etag.as_deref(), Some(ETAG));
+}
+
+#[test]
+fn long_items_are_summarized_once() {
+    let server = MockServer::start(|_| {
+        let feed = FEED.replace(
+            "<guid>1</guid>",
+            "<guid>1</guid><description>A long enough text</description>",
+        );
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let dir = std::env::temp_dir().join(format!("squeakmail-summary-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create dir");
+    let log = dir.join("summarized");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig {
+                tier: config::Tier::Primary,
+                ..FeedConfig::new(&server.url("/feed.xml"))
+            }],
+            ..Sources::default()
+        },
+        summarizer: Some(
+            toml::from_str(&format!(
+                "command = \"cat >> '{}'; echo; echo ' Short. '\"\nmin_chars = 10",
+                log.display()
+            ))
+            .expect("invalid summarizer"),
+        ),
+        ..Config::default()
+    };
+    let database = open_database();
+    for _ in 0..2 {
+        fetch_feed(&config, &config.sources.feeds[0], None, None, &database).expect("fetch failed");
+    }
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let feeds = group_digests(&config, &mut database, None)
+        .expect("failed to group digests")
+        .remove(&None)
+        .expect("no digest");
+    let item = feeds[0]
+        .items
+        .iter()
+        .find(|item| item.guid == "1")
+        .expect("no item");
+    assert_eq!(item.summary.as_deref(), Some("Short."));
+    assert_eq!(item.content, ["A long enough text"]);
+    // The second fetch doesn't summarize the same text again.
+    let summarized = std::fs::read_to_string(&log).expect("failed to read log");
+    std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    assert_eq!(summarized, "A long enough text");
+    let tera = load_templates(&config).expect("failed to load templates");
+    let html = render_html(&config, &tera, "Digest", feeds, &Sections::default())
+        .expect("failed to render");
+    assert!(html.contains("font-style: italic; margin: 0.5em 0;\">Short.</p>"));
+}
+
+#[test]
+fn article_cache_evicts_the_least_recently_fetched_pages() {
+    let database = open_database();
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    for (day, url) in [
+        (3, "https://example.org/a"),
+        (1, "https://example.org/b"),
+        (2, "https://example.org/c"),
+    ] {
+        let article = database::Article {
+            body: "x".repeat(10),
+            etag: None,
+            last_modified: None,
+            fetched_at: chrono::Utc.ymd(2019, 11, day).and_hms(0, 0, 0),
+        };
+        database
+            .insert_article(url, &article, 25)
+            .expect("failed to insert article");
+    }
+    let is_cached = |database: &mut database::Database, url| {
+        database
+            .get_article(url)
+            .expect("failed to get article")
+            .is_some()
+    };
+    assert!(is_cached(&mut database, "https://example.org/a"));
+    assert!(!is_cached(&mut database, "https://example.org/b"));
+    assert!(is_cached(&mut database, "https://example.org/c"));
+}
+
+#[test]
+fn sampled_feeds_show_some_items_and_mark_the_rest_read() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let feed_url = server.url("/feed.xml");
+    let database = open_database();
+    fetch(&database, &feed_url).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig {
+                sample: std::num::NonZeroUsize::new(1),
+                ..FeedConfig::new(&feed_url)
+            }],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+
+    let feeds = group_digests(&config, &mut database, None)
+        .expect("failed to group digests")
+        .remove(&None)
+        .expect("no digest");
+    assert_eq!(feeds[0].items.len(), 1);
+    assert_eq!(feeds[0].skipped_items, 1);
+    let tera = load_templates(&config).expect("failed to load templates");
+    let digest = render_mail(
+        &config,
+        &tera,
+        "Digest",
+        "b@example.com".to_string(),
+        feeds,
+        None,
+        &Sections::default(),
+    )
+    .expect("failed to render");
+    // Skipped items are marked read along with the digest.
+    assert_eq!(digest.items.len(), 2);
+    let message = digest
+        .mail
+        .message_to_string()
+        .expect("failed to read email");
+    assert!(message.contains("Skipped 1 more items"));
+}
+
+#[test]
+fn pinned_feeds_are_listed_first() {
+    let server = MockServer::start(|request| {
+        let feed = match request.path.as_str() {
+            "/a.xml" => FEED.replace("Mock Feed", "Alpha").replace(
+                "<guid>2</guid>",
+                "<guid>2</guid></item><item><guid>3</guid>",
+            ),
+            "/b.xml" => FEED.replace("Mock Feed", "Bravo"),
+            _ => FEED.replace("Mock Feed", "Charlie"),
+        };
+        Response::new("200 OK", feed.as_bytes())
+    });
+    let database = open_database();
+    let feed_urls = ["/c.xml", "/a.xml", "/b.xml"].map(|path| server.url(path));
+    for feed_url in &feed_urls {
+        fetch(&database, feed_url).expect("fetch failed");
+    }
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+
+    let mut titles = |feed_order| {
+        let config = Config {
+            sources: Sources {
+                feeds: vec![
+                    FeedConfig::new(&feed_urls[0]),
+                    FeedConfig::new(&feed_urls[1]),
+                    FeedConfig {
+                        pinned: true,
+                        ..FeedConfig::new(&feed_urls[2])
+                    },
+                ],
+                ..Sources::default()
+            },
+            feed_order,
+            ..Config::default()
+        };
+        group_digests(&config, &mut database, None).expect("failed to group digests")[&None]
+            .iter()
+            .map(|feed| feed.feed.title.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        titles(config::FeedOrder::Config),
+        ["Bravo", "Charlie", "Alpha"]
+    );
+    assert_eq!(
+        titles(config::FeedOrder::Alphabetical),
+        ["Bravo", "Alpha", "Charlie"]
+    );
+    assert_eq!(
+        titles(config::FeedOrder::UnreadCount),
+        ["Bravo", "Alpha", "Charlie"]
+    );
+}
+
+#[test]
+fn digest_diff_compares_with_last_digest() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        ..Config::default()
+    };
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let recipient = config.delivery.to_email.to_string();
+    assert!(database
+        .get_last_digest(&recipient)
+        .expect("failed to get last digest")
+        .is_none());
+    let sent_items = ["1", "gone"].map(|guid| database::DigestItem {
+        feed_url: feed_url.clone(),
+        guid: guid.to_string(),
+        link: String::new(),
+    });
+    database
+        .insert_digest(
+            &recipient,
+            chrono::Utc::now(),
+            "<1@example.com>",
+            &sent_items,
+        )
+        .expect("failed to insert digest");
+
+    let feeds = group_digests(&config, &mut database, None)
+        .expect("failed to group digests")
+        .remove(&None)
+        .expect("no digest");
+    let items: Vec<&database::Item> = feeds.iter().flat_map(|feed| &feed.items).collect();
+    let last = database
+        .get_last_digest(&recipient)
+        .expect("failed to get last digest")
+        .expect("no last digest");
+    let diff = diff::DigestDiff::new(&items, &last.items);
+    let guids = |items: &[&database::Item]| -> Vec<String> {
+        items.iter().map(|item| item.guid.clone()).collect()
+    };
+    assert_eq!(guids(&diff.new), ["2"]);
+    assert_eq!(guids(&diff.carried_over), ["1"]);
+    assert_eq!(diff.dropped, [&(feed_url.clone(), "gone".to_string())]);
+}
+
+#[test]
+fn fixed_clock_renders_the_same_mail_every_time() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        accessibility: Some(toml::from_str("plaintext = true").expect("invalid config")),
+        clock: clock::Clock::Fixed(chrono::Utc.ymd(2019, 11, 7).and_hms(12, 0, 0)),
+        ..Config::default()
+    };
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let mut render = || {
+        let mut digests = render_mails(&config, &mut database, None).expect("failed to render");
+        assert_eq!(digests.len(), 1);
+        digests
+            .remove(0)
+            .mail
+            .message_to_string()
+            .expect("failed to read email")
+    };
+
+    let message = render();
+    assert_eq!(message, render());
+    assert!(message.contains("Date: Thu, 07 Nov 2019 12:00:00 +0000\r\n"));
+    assert!(message.contains("Subject: SqueakMail for Thu Nov  7 12:00:00 2019\r\n"));
+    assert!(message.contains("boundary=boundary-1\r\n"));
+    assert!(!message.contains("lettre@localhost"));
+}
+
+#[test]
+fn custom_template_errors_name_the_template() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let template_dir = std::env::temp_dir().join(format!(
+        "squeakmail-broken-templates-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&template_dir).expect("failed to create dir");
+    std::fs::write(
+        template_dir.join("item.html"),
+        "<li>{{ item.missing }}</li>",
+    )
+    .expect("failed to write template");
+    let template_path = template_dir.join("digest.html");
+    std::fs::write(&template_path, "{{ missing_subject }}").expect("failed to write template");
+    let mut config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        template_dir: Some(template_dir.clone()),
+        ..Config::default()
+    };
+
+    let dir_result = render_mails(&config, &mut database, None);
+    config.template_path = Some(template_path.clone());
+    let path_result = render_mails(&config, &mut database, None);
+    std::fs::remove_dir_all(&template_dir).expect("failed to remove dir");
+    let (Err(dir_error), Err(path_error)) = (dir_result, path_result) else {
+        panic!("broken template rendered");
+    };
+    assert_eq!(dir_error.code(), "custom_template");
+    let message = dir_error.to_string();
+    let item_template = template_dir.join("item.html");
+    assert!(message.starts_with(&format!("template error in {}: ", item_template.display())));
+    assert!(message.contains("item.missing"));
+    assert!(message.ends_with("fix the template, or remove it to use the built-in one"));
+    let message = path_error.to_string();
+    assert!(message.starts_with(&format!("template error in {}: ", template_path.display())));
+    assert!(message.contains("missing_subject"));
+}
+
+#[test]
+fn feeds_with_separate_email_get_their_own_mail() {
+    let server = MockServer::start(|request| {
+        let title = if request.path == "/a.xml" {
+            "Alpha"
+        } else {
+            "Bravo"
+        };
+        Response::new("200 OK", FEED.replace("Mock Feed", title).as_bytes())
+    });
+    let database = open_database();
+    let feed_urls = ["/a.xml", "/b.xml"].map(|path| server.url(path));
+    for feed_url in &feed_urls {
+        fetch(&database, feed_url).expect("fetch failed");
+    }
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let mut config = Config {
+        sources: Sources {
+            feeds: vec![
+                FeedConfig::new(&feed_urls[0]),
+                FeedConfig {
+                    separate_email: true,
+                    ..FeedConfig::new(&feed_urls[1])
+                },
+            ],
+            ..Sources::default()
+        },
+        clock: clock::Clock::Fixed(chrono::Utc.ymd(2019, 11, 7).and_hms(12, 0, 0)),
+        ..Config::default()
+    };
+    let mut render = |config: &Config| {
+        render_mails(config, &mut database, None)
+            .expect("failed to render")
+            .into_iter()
+            .map(|digest| {
+                let feeds: HashSet<String> =
+                    digest.items.into_iter().map(|item| item.feed_url).collect();
+                let message = digest
+                    .mail
+                    .message_to_string()
+                    .expect("failed to read email");
+                let subject = message
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Subject: "))
+                    .expect("no subject")
+                    .to_string();
+                (subject, feeds.len())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        render(&config),
+        [
+            ("SqueakMail for Thu Nov  7 12:00:00 2019".to_string(), 1),
+            (
+                "[Bravo] SqueakMail for Thu Nov  7 12:00:00 2019".to_string(),
+                1
+            ),
+        ]
+    );
+
+    config.delivery.email_per_feed = true;
+    assert_eq!(
+        render(&config),
+        [
+            (
+                "[Alpha] SqueakMail for Thu Nov  7 12:00:00 2019".to_string(),
+                1
+            ),
+            (
+                "[Bravo] SqueakMail for Thu Nov  7 12:00:00 2019".to_string(),
+                1
+            ),
+        ]
+    );
+}
+
+#[test]
+fn bounced_digests_are_reported() {
+    let database = open_database();
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let sent_at = chrono::Utc.ymd(2019, 11, 7).and_hms(7, 0, 0);
+    for (day, message_id) in ["<1@example.com>", "<2@example.com>", "<3.lettre@localhost>"]
+        .iter()
+        .enumerate()
+    {
+        let sent_at = sent_at + chrono::Duration::days(i64::try_from(day).unwrap());
+        database
+            .insert_digest("reader@example.org", sent_at, message_id, &[])
+            .expect("failed to insert digest");
+    }
+    let bounce = |message_id: &str| {
+        format!(
+            "Subject: Undelivered Mail\r\n\r\n\
+             Status: 5.7.1\r\n\
+             Diagnostic-Code: smtp; 550 rejected\r\n\r\n\
+             Message-ID: {message_id}\r\n"
+        )
+    };
+    let (port, commands) = start_imap_server(vec![
+        (3, bounce("<2@example.com>")),
+        (5, bounce("<3.lettre@localhost>")),
+        (8, "Subject: Hello\r\n\r\nNot a bounce.\r\n".to_string()),
+    ]);
+    let inbox: inbox::Config = toml::from_str(&format!(
+        "host = \"127.0.0.1\"\nport = {port}\ntls = false\n\
+         username = \"user\"\npassword = \"pa\\\"ss\""
+    ))
+    .expect("invalid config");
+    let config = Config {
+        inbox: Some(inbox.clone()),
+        ..Config::default()
+    };
+
+    inbox::poll(&config, &inbox, &mut database).expect("failed to poll");
+    let failures = database
+        .get_delivery_failures()
+        .expect("failed to get failures");
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].recipient, "reader@example.org");
+    assert_eq!(failures[0].bounced, 2);
+    assert_eq!(
+        failures[0].last.sent_at,
+        sent_at + chrono::Duration::days(2)
+    );
+    assert_eq!(bounces::describe(&failures[0].last), "5.7.1 550 rejected");
+
+    // Messages that were read aren't read again.
+    inbox::poll(&config, &inbox, &mut database).expect("failed to poll");
+    let commands = commands
+        .lock()
+        .expect("thread panicked while holding commands mutex");
+    assert_eq!(commands[0], "LOGIN \"user\" \"pa\\\"ss\"");
+    assert!(commands.contains(&"UID SEARCH UID 1:*".to_string()));
+    assert!(commands.contains(&"UID SEARCH UID 9:*".to_string()));
+    assert_eq!(
+        commands
+            .iter()
+            .filter(|command| command.starts_with("UID FETCH"))
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn replies_to_digests_update_items_and_feeds() {
+    let server = MockServer::start(|_| Response::new("200 OK", FEED.as_bytes()));
+    let database = open_database();
+    let feed_url = server.url("/feed.xml");
+    fetch(&database, &feed_url).expect("fetch failed");
+    let mut database = database
+        .into_inner()
+        .expect("thread panicked while holding database mutex");
+    let mut config = Config {
+        sources: Sources {
+            feeds: vec![FeedConfig::new(&feed_url)],
+            ..Sources::default()
+        },
+        accessibility: Some(toml::from_str("plaintext = true").expect("invalid config")),
+        inbox: Some(
+            toml::from_str(
+                "host = \"127.0.0.1\"\nusername = \"user\"\npassword = \"password\"\n\
+                 replies = true",
+            )
+            .expect("invalid config"),
+        ),
+        ..Config::default()
+    };
+
+    // Feeds and items are numbered for replies to refer to.
+    let digest = render_mails(&config, &mut database, None)
+        .expect("failed to render")
+        .remove(0);
+    let message_id = message::message_id(digest.mail.message_id());
+    let message = digest
+        .mail
+        .message_to_string()
+        .expect("failed to read email");
+    assert!(message.contains("1. Mock Feed"));
+    assert!(message.contains("- 2. Second post"));
+    database
+        .insert_digest(
+            config.delivery.to_email.as_ref(),
+            chrono::Utc::now(),
+            &message_id,
+            &digest.items,
+        )
+        .expect("failed to insert digest");
+
+    let reply = |from: &str| {
+        format!(
+            "From: Reader <{from}>\r\nIn-Reply-To: {message_id}\r\n\r\n\
+             highlight 2\r\nmute feed 1\r\n\r\n> 1. Mock Feed\r\n"
+        )
+    };
+    let (port, _) = start_imap_server(vec![
+        (1, reply("someone@example.org")),
+        (2, reply(config.delivery.to_email.as_ref())),
+    ]);
+    let inbox = config.inbox.as_mut().expect("inbox is configured");
+    inbox.port = port;
+    inbox.tls = false;
+    let inbox = inbox.clone();
+    inbox::poll(&config, &inbox, &mut database).expect("failed to poll");
+
+    let highlighted: Vec<String> = database
+        .get_highlighted_items()
+        .expect("failed to get highlighted items")
+        .into_iter()
+        .map(|item| item.title)
+        .collect();
+    assert_eq!(highlighted, ["Second post"]);
+    assert_eq!(
+        database
+            .get_muted_feeds()
+            .expect("failed to get muted feeds"),
+        [feed_url]
+    );
+}
@@ -0,0 +1,129 @@
+use std::fmt::Write;
+
+use crate::{database, locale, FeedWithItems};
+
+/// Render a compact plaintext digest, dropping whole items to fit within `max_chars` characters.
+///
+/// Feeds are only listed if at least one of their items fits, and dropped items are counted at
+/// the end. Items that came in bulk are collapsed into one line per feed.
+pub fn render(
+    strings: &locale::Strings,
+    subject: &str,
+    feeds: &[FeedWithItems],
+    max_chars: Option<usize>,
+) -> String {
+    let max_chars = max_chars.unwrap_or(usize::MAX);
+    let total = feeds.iter().map(|feed| feed.items.len()).sum::<usize>();
+    let mut text = format!("{subject}\n");
+    let mut len = text.chars().count();
+    let mut included = 0;
+    'feeds: for feed in feeds {
+        let mut feed_header = Some(format!(
+            "\n{}{}\n",
+            number_prefix(feed.number),
+            feed.feed.title
+        ));
+        // Each entry with the number of items it lists.
+        let mut entries: Vec<(String, usize)> = feed
+            .items
+            .iter()
+            .filter(|item| !item.is_bulk)
+            .map(|item| (item_entry(strings, item, feed.fallback_link.as_deref()), 1))
+            .collect();
+        if feed.bulk_items > 0 {
+            let count = feed.bulk_items.to_string();
+            let bulk = strings.bulk.replace("{count}", &count);
+            entries.push((format!("- {bulk} {}\n", feed.feed.link), feed.bulk_items));
+        }
+        if feed.skipped_items > 0 {
+            let count = feed.skipped_items.to_string();
+            entries.push((
+                format!("- {}\n", strings.sampled.replace("{count}", &count)),
+                0,
+            ));
+        }
+        for (entry, count) in entries {
+            let entry = format!("{}{entry}", feed_header.as_deref().unwrap_or(""));
+            let entry_len = entry.chars().count();
+            let remaining = total - included - count;
+            if len + entry_len + omitted_line(strings, remaining).chars().count() > max_chars {
+                break 'feeds;
+            }
+            text.push_str(&entry);
+            len += entry_len;
+            included += count;
+            feed_header = None;
+        }
+    }
+    text.push_str(&omitted_line(strings, total - included));
+    // Last resort when even the subject doesn't fit.
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => text[..index].to_string(),
+        None => text,
+    }
+}
+
+fn item_entry(
+    strings: &locale::Strings,
+    item: &database::Item,
+    fallback_link: Option<&str>,
+) -> String {
+    let marker = if item.is_highlighted { '*' } else { '-' };
+    let title = match &item.translated_title {
+        Some(translated_title) => format!("{translated_title} ({})", item.title),
+        None => item.title.clone(),
+    };
+    let mut entry = format!(
+        "{marker} {}{title}{}\n",
+        number_prefix(item.number),
+        link_suffix(&item.link, fallback_link)
+    );
+    for part in &item.parts {
+        writeln!(
+            entry,
+            "  + {}{}{}",
+            number_prefix(part.number),
+            part.title,
+            link_suffix(&part.link, fallback_link)
+        )
+        .expect("writing to a string");
+    }
+    for paragraph in item
+        .summary
+        .iter()
+        .chain(&item.content)
+        .chain(&item.excerpt)
+    {
+        writeln!(entry, "  {paragraph}").expect("writing to a string");
+    }
+    let link = link_suffix(&item.link, fallback_link);
+    if item.is_truncated && !link.is_empty() {
+        writeln!(entry, "  {}:{link}", strings.read_more).expect("writing to a string");
+    }
+    entry
+}
+
+/// Number to write before a title, for replies to refer to, if it's numbered.
+fn number_prefix(number: Option<usize>) -> String {
+    number.map_or_else(String::new, |number| format!("{number}. "))
+}
+
+/// Link to write after a title, or nothing if there's no link.
+fn link_suffix(link: &str, fallback_link: Option<&str>) -> String {
+    match (link, fallback_link) {
+        ("", None) => String::new(),
+        ("", Some(fallback_link)) => format!(" {fallback_link}"),
+        (link, _) => format!(" {link}"),
+    }
+}
+
+fn omitted_line(strings: &locale::Strings, omitted: usize) -> String {
+    if omitted == 0 {
+        String::new()
+    } else {
+        format!(
+            "\n{}\n",
+            strings.more.replace("{count}", &omitted.to_string())
+        )
+    }
+}
@@ -0,0 +1,133 @@
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Number of recent fetches that are checked for failures.
+const WINDOW: usize = 8;
+// Delay before each fetch after first backing off, which is doubled each time after.
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Limits how many fetches run at once, backing off when many of them fail.
+///
+/// Backing off halves the limit and doubles a delay before each fetch, for the rest of the run.
+pub struct Throttle {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+struct State {
+    limit: usize,
+    active: usize,
+    delay: Duration,
+    /// Whether each recent fetch failed, oldest first.
+    recent: VecDeque<bool>,
+}
+
+impl Throttle {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                limit: max(limit, 1),
+                active: 0,
+                delay: Duration::default(),
+                recent: VecDeque::new(),
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until another fetch may start.
+    pub fn acquire(&self) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("thread panicked while holding throttle mutex");
+        while state.active >= state.limit {
+            state = self
+                .released
+                .wait(state)
+                .expect("thread panicked while holding throttle mutex");
+        }
+        state.active += 1;
+        let delay = state.delay;
+        drop(state);
+        thread::sleep(delay);
+    }
+
+    /// Record that a fetch finished, returning the new limit if it backed off.
+    pub fn release(&self, failed: bool) -> Option<usize> {
+        let mut state = self
+            .state
+            .lock()
+            .expect("thread panicked while holding throttle mutex");
+        state.active -= 1;
+        state.recent.push_back(failed);
+        if state.recent.len() > WINDOW {
+            state.recent.pop_front();
+        }
+        let failures = state.recent.iter().filter(|failed| **failed).count();
+        let limit = if failures >= WINDOW / 2 {
+            state.limit = max(state.limit / 2, 1);
+            state.delay = if state.delay == Duration::default() {
+                INITIAL_DELAY
+            } else {
+                min(state.delay * 2, MAX_DELAY)
+            };
+            // Start over, so the failures that caused this don't cause another.
+            state.recent.clear();
+            Some(state.limit)
+        } else {
+            None
+        };
+        self.released.notify_one();
+        limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch(throttle: &Throttle, failed: bool) -> Option<usize> {
+        // Skip acquire, which would sleep after backing off.
+        throttle.state.lock().unwrap().active += 1;
+        throttle.release(failed)
+    }
+
+    #[test]
+    fn throttle_ignores_occasional_failures() {
+        let throttle = Throttle::new(8);
+        for i in 0..32 {
+            assert_eq!(fetch(&throttle, i % 4 == 0), None);
+        }
+        assert_eq!(throttle.state.lock().unwrap().delay, Duration::default());
+    }
+
+    #[test]
+    fn throttle_backs_off_when_many_fetches_fail() {
+        let throttle = Throttle::new(8);
+        fetch(&throttle, false);
+        fetch(&throttle, true);
+        fetch(&throttle, true);
+        fetch(&throttle, true);
+        assert_eq!(fetch(&throttle, true), Some(4));
+        assert_eq!(throttle.state.lock().unwrap().delay, INITIAL_DELAY);
+        for _ in 0..3 {
+            assert_eq!(fetch(&throttle, true), None);
+        }
+        assert_eq!(fetch(&throttle, true), Some(2));
+        assert_eq!(throttle.state.lock().unwrap().delay, INITIAL_DELAY * 2);
+    }
+
+    #[test]
+    fn throttle_limit_stays_positive() {
+        let throttle = Throttle::new(1);
+        for _ in 0..3 {
+            fetch(&throttle, true);
+        }
+        assert_eq!(fetch(&throttle, true), Some(1));
+    }
+}
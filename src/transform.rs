@@ -0,0 +1,50 @@
+use std::io::{self, Write};
+use std::process::Stdio;
+use std::thread;
+
+use crate::feed::Item;
+use crate::hooks;
+
+/// Pipe each item as JSON through a shell command, replacing it with the item the command
+/// outputs, or dropping it if the command outputs nothing or `null`.
+pub fn apply(command: &str, items: Vec<Item>) -> io::Result<Vec<Item>> {
+    let mut transformed = Vec::new();
+    for item in items {
+        let input = serde_json::to_vec(&item).expect("failed to serialize item");
+        let output = run(command, input)?;
+        if output.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let item: Option<Item> = serde_json::from_slice(&output).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid item from {command:?}: {e}"),
+            )
+        })?;
+        transformed.extend(item);
+    }
+    Ok(transformed)
+}
+
+pub fn run(command: &str, input: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut child = hooks::shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Write from another thread, so the command can't block on a full stdout meanwhile.
+    let writer = thread::spawn(move || match stdin.write_all(&input) {
+        // Commands that drop items don't have to read them.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    });
+    let output = child.wait_with_output()?;
+    writer.join().expect("thread panicked")?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{command:?} exited with {}",
+            output.status
+        )));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use attohttpc::header::{AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use derive_more::{Display, From};
+use serde::{Deserialize, Serialize};
+
+/// Translation API to translate item titles with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    DeepL,
+    LibreTranslate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub service: Service,
+    /// URL of the API, by default the free API of `DeepL` or the main `LibreTranslate` instance.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// API key, which `DeepL` and some `LibreTranslate` instances require.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Language code to translate to, such as "en".
+    pub target_language: String,
+}
+
+#[derive(Debug, From, Display)]
+pub enum Error {
+    Http(attohttpc::Error),
+    #[display(fmt = "invalid response: {_0}")]
+    Json(serde_json::Error),
+    #[display(fmt = "unexpected status code: {_0}")]
+    UnexpectedStatusCode(u16),
+    #[display(fmt = "response has no translation")]
+    NoTranslation,
+}
+
+#[derive(Deserialize)]
+struct DeepLResponse {
+    translations: Vec<DeepLTranslation>,
+}
+
+#[derive(Deserialize)]
+struct DeepLTranslation {
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibreTranslateResponse {
+    translated_text: String,
+}
+
+/// Translate text to the target language, detecting its language.
+pub fn translate(config: &Config, text: &str) -> Result<String, Error> {
+    match config.service {
+        Service::DeepL => {
+            let url = config
+                .url
+                .as_deref()
+                .unwrap_or("https://api-free.deepl.com");
+            let body = url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("text", text)
+                .append_pair("target_lang", &config.target_language.to_uppercase())
+                .finish();
+            let mut builder = post(&format!("{}/v2/translate", url.trim_end_matches('/')))
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+            if let Some(api_key) = &config.api_key {
+                builder = builder.header(AUTHORIZATION, format!("DeepL-Auth-Key {api_key}"));
+            }
+            let resp: DeepLResponse = parse(builder.text(body).send()?)?;
+            resp.translations
+                .into_iter()
+                .next()
+                .map(|translation| translation.text)
+                .ok_or(Error::NoTranslation)
+        }
+        Service::LibreTranslate => {
+            let url = config
+                .url
+                .as_deref()
+                .unwrap_or("https://libretranslate.com");
+            let body = serde_json::json!({
+                "q": text,
+                "source": "auto",
+                "target": config.target_language,
+                "format": "text",
+                "api_key": config.api_key,
+            });
+            let resp: LibreTranslateResponse = parse(
+                post(&format!("{}/translate", url.trim_end_matches('/')))
+                    .header(CONTENT_TYPE, "application/json")
+                    .text(body.to_string())
+                    .send()?,
+            )?;
+            Ok(resp.translated_text)
+        }
+    }
+}
+
+fn post(url: &str) -> attohttpc::RequestBuilder {
+    attohttpc::post(url)
+        .header(USER_AGENT, env!("CARGO_PKG_NAME"))
+        .timeout(Duration::from_secs(30))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(resp: attohttpc::Response) -> Result<T, Error> {
+    if !resp.status().is_success() {
+        return Err(Error::UnexpectedStatusCode(resp.status().as_u16()));
+    }
+    Ok(serde_json::from_slice(&resp.bytes()?)?)
+}
@@ -0,0 +1,246 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::Utc;
+use derive_more::{Display, From};
+use lettre::sendmail::SendmailTransport;
+use lettre::smtp::authentication::Credentials;
+use lettre::smtp::error::Error as SmtpError;
+use lettre::smtp::{SMTP_PORT, SUBMISSIONS_PORT, SUBMISSION_PORT};
+use lettre::{ClientSecurity, ClientTlsParameters, SendableEmail, SmtpClient, SmtpTransport};
+use native_tls::TlsConnector;
+use serde::{Deserialize, Serialize};
+
+use crate::{mx, Result};
+
+/// How mail is sent when it isn't delivered directly to recipients' mail servers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Config {
+    /// Pipe mail to a sendmail command.
+    Sendmail {
+        /// Path of the sendmail command, instead of finding `sendmail` on the path.
+        #[serde(default)]
+        command: Option<String>,
+    },
+    /// Send mail through an SMTP server, such as an email provider's.
+    Smtp {
+        host: String,
+        /// Port to connect to, instead of the usual port for `security`.
+        #[serde(default)]
+        port: Option<u16>,
+        #[serde(default)]
+        security: Security,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        /// File with the password, instead of `password`, so it isn't written in the config.
+        #[serde(default)]
+        password_file: Option<PathBuf>,
+    },
+    /// Write mail to a Maildir, for a mail client to read.
+    Maildir { path: PathBuf },
+    /// Print mail to stdout in mbox format.
+    Stdout,
+}
+
+/// How the connection to an SMTP server is secured.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Security {
+    /// Upgrade the connection with STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// Connect with TLS, usually on port 465.
+    Tls,
+    /// Don't encrypt the connection, usually on port 25. Only use this for a local server.
+    None,
+}
+#[derive(Debug, From, Display)]
+pub enum Error {
+    #[display(fmt = "SMTP error: {_0}")]
+    Smtp(SmtpError),
+    #[display(fmt = "TLS error: {_0}")]
+    Tls(native_tls::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to read SMTP password file: {_0}")]
+    PasswordFile(io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to write to Maildir: {_0}")]
+    Maildir(io::Error),
+    #[from(ignore)]
+    #[display(fmt = "failed to print mail: {_0}")]
+    Stdout(io::Error),
+    #[display(
+        fmt = "sendmail isn't available on Windows, so add a [transport] section to the config \
+               file to send mail through an SMTP server, write it to a Maildir, or print it"
+    )]
+    NoTransport,
+}
+
+// Distinguishes Maildir files written by this process in the same second.
+static MAILDIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sends mail directly to recipients' mail servers if configured, and otherwise with the
+/// configured transport, or sendmail by default.
+pub enum Transport {
+    Sendmail(SendmailTransport),
+    Smtp(Box<SmtpTransport>),
+    Maildir(PathBuf),
+    Stdout,
+    Mx(mx::Transport),
+}
+impl Transport {
+    pub fn new(mx: Option<mx::Config>, config: Option<&Config>) -> Result<Self> {
+        if let Some(mx) = mx {
+            return Ok(Self::Mx(mx::Transport::new(mx)));
+        }
+        let transport = match config {
+            None if cfg!(windows) => return Err(Error::NoTransport.into()),
+            Some(Config::Sendmail { command: None }) | None => {
+                Self::Sendmail(SendmailTransport::new())
+            }
+            Some(Config::Sendmail {
+                command: Some(command),
+            }) => Self::Sendmail(SendmailTransport::new_with_command(command.as_str())),
+            Some(Config::Smtp {
+                host,
+                port,
+                security,
+                username,
+                password,
+                password_file,
+            }) => {
+                let password = match password_file {
+                    Some(path) => Some(read_password(path).map_err(Error::PasswordFile)?),
+                    None => password.clone(),
+                };
+                Self::Smtp(Box::new(smtp_transport(
+                    host,
+                    *port,
+                    *security,
+                    username.as_deref(),
+                    password.as_deref(),
+                )?))
+            }
+            Some(Config::Maildir { path }) => Self::Maildir(path.clone()),
+            Some(Config::Stdout) => Self::Stdout,
+        };
+        Ok(transport)
+    }
+
+    pub fn send(&mut self, mail: SendableEmail) -> Result<()> {
+        use lettre::Transport as _;
+
+        match self {
+            Self::Sendmail(transport) => transport.send(mail).map_err(Into::into),
+            Self::Smtp(transport) => transport
+                .send(mail)
+                .map(|_| ())
+                .map_err(|e| Error::Smtp(e).into()),
+            Self::Maildir(path) => write_maildir(path, mail).map_err(|e| Error::Maildir(e).into()),
+            Self::Stdout => print_mbox(mail).map_err(|e| Error::Stdout(e).into()),
+            Self::Mx(transport) => transport.send(mail).map_err(Into::into),
+        }
+    }
+}
+
+/// Name of the way mail is sent, as chosen by `Transport::new`, to record with deliveries.
+pub fn name(mx: Option<&mx::Config>, config: Option<&Config>) -> &'static str {
+    match (mx, config) {
+        (Some(_), _) => "mx",
+        (None, Some(Config::Sendmail { .. }) | None) => "sendmail",
+        (None, Some(Config::Smtp { .. })) => "smtp",
+        (None, Some(Config::Maildir { .. })) => "maildir",
+        (None, Some(Config::Stdout)) => "stdout",
+    }
+}
+
+fn smtp_transport(
+    host: &str,
+    port: Option<u16>,
+    security: Security,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> std::result::Result<SmtpTransport, Error> {
+    let tls_parameters = || -> std::result::Result<_, Error> {
+        Ok(ClientTlsParameters::new(
+            host.to_string(),
+            TlsConnector::new()?,
+        ))
+    };
+    let (security, default_port) = match security {
+        Security::Starttls => (ClientSecurity::Required(tls_parameters()?), SUBMISSION_PORT),
+        Security::Tls => (ClientSecurity::Wrapper(tls_parameters()?), SUBMISSIONS_PORT),
+        Security::None => (ClientSecurity::None, SMTP_PORT),
+    };
+    let mut client = SmtpClient::new((host, port.unwrap_or(default_port)), security)?;
+    if let Some(username) = username {
+        client = client.credentials(Credentials::new(
+            username.to_string(),
+            password.unwrap_or_default().to_string(),
+        ));
+    }
+    Ok(client.transport())
+}
+
+/// Read a password from a file, without the line ending that editors add.
+fn read_password(path: &Path) -> io::Result<String> {
+    let password = fs::read_to_string(path)?;
+    Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Write mail to the `new` directory of a Maildir, creating the Maildir if needed.
+fn write_maildir(path: &Path, mail: SendableEmail) -> io::Result<()> {
+    for dir in &["tmp", "new", "cur"] {
+        fs::create_dir_all(path.join(dir))?;
+    }
+    // Names can't contain colons, which Windows doesn't allow.
+    let name = format!(
+        "{}.P{}Q{}.squeakmail",
+        Utc::now().timestamp(),
+        process::id(),
+        MAILDIR_COUNTER.fetch_add(1, Ordering::SeqCst),
+    );
+    let message = message_string(mail)?;
+    let tmp_path = path.join("tmp").join(&name);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(message.as_bytes())?;
+    file.sync_all()?;
+    // Mail clients only read from `new`, so they never see a partly written file.
+    fs::rename(tmp_path, path.join("new").join(name))
+}
+
+/// Print mail to stdout as an mbox message, so it can be piped to a mail client.
+fn print_mbox(mail: SendableEmail) -> io::Result<()> {
+    let from = mail
+        .envelope()
+        .from()
+        .map_or_else(|| "MAILER-DAEMON".to_string(), ToString::to_string);
+    let message = message_string(mail)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(
+        stdout,
+        "From {from} {}",
+        Utc::now().format("%a %b %e %T %Y")
+    )?;
+    for line in message.lines() {
+        // Escape lines that would start a new message.
+        if line.trim_start_matches('>').starts_with("From ") {
+            write!(stdout, ">")?;
+        }
+        writeln!(stdout, "{line}")?;
+    }
+    writeln!(stdout)?;
+    stdout.flush()
+}
+
+fn message_string(mail: SendableEmail) -> io::Result<String> {
+    mail.message_to_string()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+use crate::database;
+
+/// Read item whose content changed since it was mailed, which templates get in `updated`.
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub item: database::Item,
+    pub feed_title: String,
+}
+
+/// Load the updated items of a recipient's digest, ordered by feed.
+pub fn load(
+    database: &mut database::Database,
+    recipient: Option<&str>,
+) -> Result<Vec<Entry>, database::Error> {
+    let mut entries = Vec::new();
+    for item in database.get_updated_items(recipient)? {
+        let feed_title = database
+            .get_feed_by_url(&item.feed_url)?
+            .map(|feed| feed.title)
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| item.feed_url.clone());
+        entries.push(Entry { item, feed_title });
+    }
+    Ok(entries)
+}
@@ -0,0 +1,268 @@
+use toml::value::{Table, Value};
+
+// Keys renamed since they were added, as dotted paths from the top of the config, oldest first.
+// A `*` segment matches each table of an array, such as each feed of `feeds`.
+const RENAMED_KEYS: &[(&str, &str)] = &[
+    // Split into sections.
+    ("feeds", "sources.feeds"),
+    ("concurrency", "sources.concurrency"),
+    ("respect_robots_txt", "sources.respect_robots_txt"),
+    ("raw_dir", "sources.raw_dir"),
+    ("raw_keep", "sources.raw_keep"),
+    ("rules", "filters.rules"),
+    ("dedup_days", "filters.dedup_days"),
+    ("bulk_threshold", "filters.bulk_threshold"),
+    ("merge_parts", "filters.merge_parts"),
+    ("resolve_links", "filters.resolve_links"),
+    ("from_email", "delivery.from_email"),
+    ("to_email", "delivery.to_email"),
+    ("mail_concurrency", "delivery.mail_concurrency"),
+    ("mail_per_minute", "delivery.mail_per_minute"),
+    ("email_per_item", "delivery.email_per_item"),
+    ("mx", "delivery.mx"),
+    ("transport", "delivery.transport"),
+    ("smime", "delivery.smime"),
+    ("dkim", "delivery.dkim"),
+    ("send_window", "delivery.send_window"),
+    ("pipelines", "delivery.pipelines"),
+];
+// Keys that no longer do anything, with what to do instead.
+const REMOVED_KEYS: &[(&str, &str)] = &[];
+
+/// Config with keys of older versions replaced by their current ones.
+pub struct Upgrade {
+    pub value: Value,
+    /// What was changed, and what has to be changed by hand.
+    pub warnings: Vec<String>,
+}
+
+/// Replace the renamed keys of a config, and drop the removed ones.
+pub fn upgrade(value: Value) -> Upgrade {
+    upgrade_with(value, RENAMED_KEYS, REMOVED_KEYS)
+}
+
+fn upgrade_with(mut value: Value, renamed: &[(&str, &str)], removed: &[(&str, &str)]) -> Upgrade {
+    let mut warnings = Vec::new();
+    for &(from, to) in renamed {
+        for (from_path, old) in take(&mut value, &path(from)) {
+            let to_path = renamed_path(&from_path, &path(to));
+            if get(&value, &to_path).is_some() {
+                warnings.push(format!(
+                    "`{}` was renamed to `{}`, which is also set, so it was dropped",
+                    from_path.join("."),
+                    to_path.join(".")
+                ));
+            } else {
+                warnings.push(format!(
+                    "`{}` was renamed to `{}`",
+                    from_path.join("."),
+                    to_path.join(".")
+                ));
+                insert(&mut value, &to_path, old);
+            }
+        }
+    }
+    for &(key, instead) in removed {
+        for (key_path, _) in take(&mut value, &path(key)) {
+            warnings.push(format!("`{}` was removed, {instead}", key_path.join(".")));
+        }
+    }
+    Upgrade { value, warnings }
+}
+
+fn path(key: &str) -> Vec<&str> {
+    key.split('.').collect()
+}
+
+/// Path a key was renamed to, with the indexes of arrays it's in taken from its old path.
+fn renamed_path(from_path: &[String], to: &[&str]) -> Vec<String> {
+    let mut indexes = from_path
+        .iter()
+        .filter(|segment| segment.parse::<usize>().is_ok());
+    to.iter()
+        .map(|&segment| match segment {
+            "*" => indexes.next().cloned().unwrap_or_default(),
+            segment => segment.to_string(),
+        })
+        .collect()
+}
+
+/// Remove the values at a path, returning them with their paths, with array indexes in place of
+/// `*` segments.
+fn take(value: &mut Value, path: &[&str]) -> Vec<(Vec<String>, Value)> {
+    let mut taken = Vec::new();
+    take_into(value, path, &mut Vec::new(), &mut taken);
+    taken
+}
+
+fn take_into(
+    value: &mut Value,
+    path: &[&str],
+    prefix: &mut Vec<String>,
+    taken: &mut Vec<(Vec<String>, Value)>,
+) {
+    let Some((&segment, rest)) = path.split_first() else {
+        return;
+    };
+    match (segment, value) {
+        ("*", Value::Array(array)) => {
+            for (i, element) in array.iter_mut().enumerate() {
+                prefix.push(i.to_string());
+                take_into(element, rest, prefix, taken);
+                prefix.pop();
+            }
+        }
+        (key, Value::Table(table)) if rest.is_empty() => {
+            if let Some(old) = table.remove(key) {
+                let mut key_path = prefix.clone();
+                key_path.push(key.to_string());
+                taken.push((key_path, old));
+            }
+        }
+        (key, Value::Table(table)) => {
+            if let Some(child) = table.get_mut(key) {
+                prefix.push(key.to_string());
+                take_into(child, rest, prefix, taken);
+                prefix.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn get<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match value {
+        Value::Array(array) => array.get(segment.parse::<usize>().ok()?),
+        Value::Table(table) => table.get(segment),
+        _ => None,
+    })
+}
+
+/// Set the value at a path, creating the tables it's in.
+fn insert(value: &mut Value, path: &[String], new: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut parent = value;
+    for segment in parents {
+        parent = match parent {
+            Value::Array(array) => match segment.parse::<usize>().ok() {
+                Some(i) if i < array.len() => &mut array[i],
+                _ => return,
+            },
+            Value::Table(table) => table
+                .entry(segment.clone())
+                .or_insert_with(|| Value::Table(Table::new())),
+            _ => return,
+        };
+    }
+    if let Value::Table(table) = parent {
+        table.insert(last.clone(), new);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgrade(config: &str, renamed: &[(&str, &str)], removed: &[(&str, &str)]) -> Upgrade {
+        upgrade_with(
+            toml::from_str(config).expect("invalid config"),
+            renamed,
+            removed,
+        )
+    }
+
+    #[test]
+    fn upgrade_renames_keys() {
+        let upgraded = upgrade(
+            "threads = 4\n\
+             [[feeds]]\nurl = \"a\"\nlabels = [\"x\"]\n\
+             [[feeds]]\nurl = \"b\"\n\
+             [callback]\nport = 8080\n",
+            &[
+                ("threads", "concurrency"),
+                ("feeds.*.labels", "feeds.*.tags"),
+                ("callback.port", "daemon.port"),
+            ],
+            &[],
+        );
+        let expected: Value = toml::from_str(
+            "concurrency = 4\n\
+             [[feeds]]\nurl = \"a\"\ntags = [\"x\"]\n\
+             [[feeds]]\nurl = \"b\"\n\
+             [callback]\n\
+             [daemon]\nport = 8080\n",
+        )
+        .expect("invalid config");
+        assert_eq!(upgraded.value, expected);
+        assert_eq!(
+            upgraded.warnings,
+            [
+                "`threads` was renamed to `concurrency`",
+                "`feeds.0.labels` was renamed to `feeds.0.tags`",
+                "`callback.port` was renamed to `daemon.port`",
+            ]
+        );
+    }
+
+    #[test]
+    fn upgrade_keeps_new_key_over_old_one() {
+        let upgraded = upgrade(
+            "threads = 4\nconcurrency = 2\n",
+            &[("threads", "concurrency")],
+            &[],
+        );
+        let expected: Value = toml::from_str("concurrency = 2\n").expect("invalid config");
+        assert_eq!(upgraded.value, expected);
+        assert_eq!(upgraded.warnings.len(), 1);
+    }
+
+    #[test]
+    fn upgrade_drops_removed_keys() {
+        let upgraded = upgrade(
+            "concurrency = 4\ninline_css = true\n",
+            &[],
+            &[("inline_css", "CSS is always inlined")],
+        );
+        let expected: Value = toml::from_str("concurrency = 4\n").expect("invalid config");
+        assert_eq!(upgraded.value, expected);
+        assert_eq!(
+            upgraded.warnings,
+            ["`inline_css` was removed, CSS is always inlined"]
+        );
+    }
+
+    #[test]
+    fn upgrade_moves_keys_into_sections() {
+        let upgraded = super::upgrade(
+            toml::from_str(
+                "concurrency = 4\n\
+                 to_email = \"a@example.com\"\n\
+                 [[rules]]\ntitle = \"x\"\naction = \"drop\"\n\
+                 [mx]\nhelo = \"example.com\"\n",
+            )
+            .expect("invalid config"),
+        );
+        let expected: Value = toml::from_str(
+            "[sources]\nconcurrency = 4\n\
+             [[filters.rules]]\ntitle = \"x\"\naction = \"drop\"\n\
+             [delivery]\nto_email = \"a@example.com\"\n\
+             [delivery.mx]\nhelo = \"example.com\"\n",
+        )
+        .expect("invalid config");
+        assert_eq!(upgraded.value, expected);
+        assert_eq!(upgraded.warnings.len(), 4);
+    }
+
+    #[test]
+    fn upgrade_leaves_current_config_unchanged() {
+        let config = "[sources]\nconcurrency = 4\nfeeds = [\"a\"]\n";
+        let upgraded = super::upgrade(toml::from_str(config).expect("invalid config"));
+        assert_eq!(
+            upgraded.value,
+            toml::from_str::<Value>(config).expect("invalid config")
+        );
+        assert!(upgraded.warnings.is_empty());
+    }
+}
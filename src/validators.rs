@@ -0,0 +1,126 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+// Preferred format of HTTP dates (RFC 7231 section 7.1.1.1).
+const IMF_FIXDATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+// Obsolete formats that recipients must still accept.
+const RFC_850_DATE: &str = "%A, %d-%b-%y %H:%M:%S GMT";
+const ASCTIME_DATE: &str = "%a %b %e %H:%M:%S %Y";
+
+/// Normalize an `ETag` header, returning `None` if it's empty.
+///
+/// The opaque part is kept exactly as received, since servers compare it byte for byte.
+pub fn normalize_etag(etag: &str) -> Option<String> {
+    let etag = etag.trim();
+    if etag.is_empty() {
+        return None;
+    }
+    match etag.strip_prefix("w/") {
+        Some(opaque) => Some(format!("W/{opaque}")),
+        None => Some(etag.to_string()),
+    }
+}
+
+/// Normalize a `Last-Modified` header to the preferred HTTP date format, returning `None` if it
+/// isn't a date.
+pub fn normalize_last_modified(last_modified: &str) -> Option<String> {
+    let last_modified = last_modified.trim();
+    let date = DateTime::parse_from_rfc2822(last_modified)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(last_modified, RFC_850_DATE)
+                .or_else(|_| NaiveDateTime::parse_from_str(last_modified, ASCTIME_DATE))
+                .map(|date| DateTime::from_utc(date, Utc))
+        })
+        .ok()?;
+    Some(date.format(IMF_FIXDATE).to_string())
+}
+
+/// Compare entity tags, ignoring whether they're weak (RFC 7232 section 2.3.2).
+///
+/// Servers that compress responses often weaken the tag of only some responses.
+pub fn etags_match_weakly(a: &str, b: &str) -> bool {
+    let opaque = |etag: &str| etag.strip_prefix("W/").unwrap_or(etag).to_string();
+    opaque(a) == opaque(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_etag_keeps_strong_and_weak_tags() {
+        assert_eq!(
+            normalize_etag("\"5e8f1a-3b2\"").as_deref(),
+            Some("\"5e8f1a-3b2\"")
+        );
+        assert_eq!(
+            normalize_etag("W/\"5e8f1a-3b2\"").as_deref(),
+            Some("W/\"5e8f1a-3b2\"")
+        );
+    }
+
+    #[test]
+    fn normalize_etag_fixes_quirks() {
+        // Lowercase weak prefix.
+        assert_eq!(normalize_etag("w/\"abc\"").as_deref(), Some("W/\"abc\""));
+        // Surrounding whitespace.
+        assert_eq!(normalize_etag("  \"abc\"\t").as_deref(), Some("\"abc\""));
+        // Unquoted tags are sent back as received.
+        assert_eq!(normalize_etag("abc123").as_deref(), Some("abc123"));
+        // Empty header.
+        assert_eq!(normalize_etag(""), None);
+        assert_eq!(normalize_etag("  "), None);
+        // Empty quoted tag is valid.
+        assert_eq!(normalize_etag("\"\"").as_deref(), Some("\"\""));
+    }
+
+    #[test]
+    fn normalize_last_modified_accepts_http_date_formats() {
+        let expected = Some("Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            normalize_last_modified("Sun, 06 Nov 1994 08:49:37 GMT").as_deref(),
+            expected
+        );
+        assert_eq!(
+            normalize_last_modified("Sunday, 06-Nov-94 08:49:37 GMT").as_deref(),
+            expected
+        );
+        assert_eq!(
+            normalize_last_modified("Sun Nov  6 08:49:37 1994").as_deref(),
+            expected
+        );
+    }
+
+    #[test]
+    fn normalize_last_modified_fixes_quirks() {
+        let expected = Some("Sun, 06 Nov 1994 08:49:37 GMT");
+        // Numeric offset instead of GMT.
+        assert_eq!(
+            normalize_last_modified("Sun, 06 Nov 1994 08:49:37 +0000").as_deref(),
+            expected
+        );
+        // Local time with an offset.
+        assert_eq!(
+            normalize_last_modified("Sun, 06 Nov 1994 03:49:37 -0500").as_deref(),
+            expected
+        );
+        // Single-digit day and trailing whitespace.
+        assert_eq!(
+            normalize_last_modified("Sun, 6 Nov 1994 08:49:37 GMT ").as_deref(),
+            expected
+        );
+        // Not a date.
+        assert_eq!(normalize_last_modified("0"), None);
+        assert_eq!(normalize_last_modified(""), None);
+        assert_eq!(normalize_last_modified("2019-11-06T08:49:37Z"), None);
+    }
+
+    #[test]
+    fn etags_match_weakly_ignores_weakness() {
+        assert!(etags_match_weakly("\"abc\"", "\"abc\""));
+        assert!(etags_match_weakly("W/\"abc\"", "\"abc\""));
+        assert!(etags_match_weakly("W/\"abc\"", "W/\"abc\""));
+        assert!(!etags_match_weakly("\"abc\"", "\"abd\""));
+        assert!(!etags_match_weakly("W/\"abc\"", "\"abd\""));
+    }
+}
@@ -0,0 +1,289 @@
+#[cfg(feature = "web")]
+use std::fmt::Write as _;
+use std::io;
+#[cfg(feature = "web")]
+use std::io::Write;
+use std::net::SocketAddr;
+#[cfg(feature = "web")]
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "web")]
+use crate::callback::{self, Request};
+use crate::database;
+
+/// Web page of unread items with buttons to mark them read, which the daemon serves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Address the daemon serves the page on.
+    pub listen: SocketAddr,
+    /// Secret that requests must give as the `token` parameter, such as in a bookmarked link.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Listener of the web page, if built with the `web` feature.
+pub struct Server {
+    #[cfg(feature = "web")]
+    listener: TcpListener,
+    #[cfg(not(feature = "web"))]
+    never: std::convert::Infallible,
+}
+impl Server {
+    /// Listen on the configured address, so a taken port fails the daemon as it starts.
+    #[cfg(feature = "web")]
+    pub fn bind(config: &Config) -> io::Result<Self> {
+        let listener = TcpListener::bind(config.listen)?;
+        eprintln!("Serving unread items at http://{}/", config.listen);
+        Ok(Self { listener })
+    }
+
+    #[cfg(not(feature = "web"))]
+    pub fn bind(_config: &Config) -> io::Result<Self> {
+        Err(io::Error::other(
+            "SqueakMail was built without the web page, rebuild it with `--features web`",
+        ))
+    }
+
+    /// Serve the page until the process is killed.
+    #[cfg(feature = "web")]
+    pub fn serve(self, config: &crate::Config, database: &Mutex<database::Database>) {
+        let web = config.web.as_ref().expect("web page is configured");
+        for stream in self.listener.incoming() {
+            let result = stream.and_then(|stream| {
+                callback::set_timeouts(&stream)?;
+                handle(config, web, &stream, database)
+            });
+            match result {
+                Ok(()) => {}
+                Err(e) => eprintln!("Failed to handle request: {e}"),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "web"))]
+    pub fn serve(self, _config: &crate::Config, _database: &Mutex<database::Database>) {
+        match self.never {}
+    }
+}
+
+#[cfg(feature = "web")]
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(feature = "web")]
+fn respond(
+    mut stream: &TcpStream,
+    status: &str,
+    location: Option<&str>,
+    body: &str,
+) -> io::Result<()> {
+    let page = format!(
+        "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>SqueakMail</title></head>\n<body>\n{body}</body>\n</html>\n"
+    );
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n",
+        page.len()
+    )?;
+    if let Some(location) = location {
+        write!(stream, "Location: {location}\r\n")?;
+    }
+    write!(stream, "\r\n{page}")
+}
+
+/// Query of links back to the page, with the token if one is configured.
+#[cfg(feature = "web")]
+fn token_query(web: &Config) -> String {
+    web.token.as_ref().map_or_else(String::new, |token| {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("token", token)
+            .finish();
+        format!("?{query}")
+    })
+}
+
+#[cfg(feature = "web")]
+fn handle(
+    config: &crate::Config,
+    web: &Config,
+    stream: &TcpStream,
+    database: &Mutex<database::Database>,
+) -> io::Result<()> {
+    let Some(request) = Request::read_from(stream)? else {
+        return respond(stream, "400 Bad Request", None, "<p>Bad request.</p>\n");
+    };
+    if web
+        .token
+        .as_deref()
+        .is_some_and(|token| request.param("token") != Some(token))
+    {
+        return respond(stream, "403 Forbidden", None, "<p>Invalid token.</p>\n");
+    }
+    let mut database = database
+        .lock()
+        .expect("thread panicked while holding database mutex");
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => unread_page(config, web, &mut database)
+            .map(|page| respond(stream, "200 OK", None, &page)),
+        ("POST", "/read") => mark_read(&request, &mut database).map(|found| {
+            if found {
+                respond(
+                    stream,
+                    "303 See Other",
+                    Some(&format!("/{}", token_query(web))),
+                    "",
+                )
+            } else {
+                respond(stream, "404 Not Found", None, "<p>Unknown item.</p>\n")
+            }
+        }),
+        (_, "/" | "/read") => Ok(respond(
+            stream,
+            "405 Method Not Allowed",
+            None,
+            "<p>Method not allowed.</p>\n",
+        )),
+        _ => Ok(respond(
+            stream,
+            "404 Not Found",
+            None,
+            "<p>Not found.</p>\n",
+        )),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Failed to serve web page: {e}");
+        respond(
+            stream,
+            "500 Internal Server Error",
+            None,
+            "<p>Database error.</p>\n",
+        )
+    })
+}
+
+/// Page of the unread items of each feed that isn't muted, which the next digest would
+/// include, with a button to mark each read.
+#[cfg(feature = "web")]
+fn unread_page(
+    config: &crate::Config,
+    web: &Config,
+    database: &mut database::Database,
+) -> Result<String, database::Error> {
+    let strings = config.locale.strings();
+    let token = web.token.as_deref().map_or_else(String::new, |token| {
+        format!(
+            "<input type=\"hidden\" name=\"token\" value=\"{}\">",
+            escape_html(token)
+        )
+    });
+    let exclusive_tags = config.exclusive_tags();
+    let mut page = String::new();
+    let mut unread = 0;
+    for feed_config in &config.sources.feeds {
+        let feed = database.get_feed_by_url(&feed_config.url)?;
+        if feed.as_ref().is_some_and(|feed| feed.is_muted) {
+            continue;
+        }
+        let items = database.get_unread_items(
+            &feed_config.url,
+            None,
+            &exclusive_tags,
+            config.clock.now(),
+        )?;
+        if items.is_empty() {
+            continue;
+        }
+        unread += items.len();
+        let title = feed
+            .map(|feed| feed.title)
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| feed_config.url.clone());
+        writeln!(page, "<h2>{}</h2>\n<ul>", escape_html(&title)).expect("writing to a string");
+        for item in items {
+            let title = if item.link.is_empty() {
+                escape_html(&item.title)
+            } else {
+                format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_html(&item.link),
+                    escape_html(&item.title)
+                )
+            };
+            writeln!(
+                page,
+                "<li>{title} <small>{}</small>\n\
+                 <form method=\"post\" action=\"/read\" style=\"display: inline;\">\
+                 <input type=\"hidden\" name=\"feed\" value=\"{}\">\
+                 <input type=\"hidden\" name=\"guid\" value=\"{}\">{token}\
+                 <button type=\"submit\">Mark read</button></form></li>",
+                escape_html(&strings.format_date(&config.clock.to_local(item.pub_date))),
+                escape_html(&item.feed_url),
+                escape_html(&item.guid),
+            )
+            .expect("writing to a string");
+        }
+        page.push_str("</ul>\n");
+    }
+    Ok(format!("<h1>{unread} unread</h1>\n{page}"))
+}
+
+/// Mark the item of a request read, returning whether it exists.
+#[cfg(feature = "web")]
+fn mark_read(
+    request: &Request,
+    database: &mut database::Database,
+) -> Result<bool, database::Error> {
+    let (Some(feed_url), Some(guid)) = (request.param("feed"), request.param("guid")) else {
+        return Ok(false);
+    };
+    let items: Vec<database::DigestItem> = database
+        .get_items_by_guid(guid, Some(feed_url))?
+        .into_iter()
+        .map(|item| database::DigestItem {
+            feed_url: item.feed_url,
+            guid: item.guid,
+            link: item.link,
+        })
+        .collect();
+    if items.is_empty() {
+        return Ok(false);
+    }
+    database.mark_digest_items_read(&items)?;
+    eprintln!("Marked {guid} of {feed_url} read from the web page");
+    Ok(true)
+}
+
+#[cfg(all(test, feature = "web"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_html_escapes_markup_and_attribute_quotes() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Q&A</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Q&amp;A&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn token_query_encodes_token() {
+        let mut web = Config {
+            listen: "127.0.0.1:0".parse().expect("invalid address"),
+            token: None,
+        };
+        assert_eq!(token_query(&web), "");
+        web.token = Some("a b&c".to_string());
+        assert_eq!(token_query(&web), "?token=a+b%26c");
+    }
+}
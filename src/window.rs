@@ -0,0 +1,116 @@
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Hours of the day that mail may be sent in, in local time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Start of the window, such as "07:00".
+    #[serde(
+        serialize_with = "serialize_time",
+        deserialize_with = "deserialize_time"
+    )]
+    pub start: NaiveTime,
+    /// End of the window, such as "22:00". The window passes midnight if it ends before it
+    /// starts.
+    #[serde(
+        serialize_with = "serialize_time",
+        deserialize_with = "deserialize_time"
+    )]
+    pub end: NaiveTime,
+}
+impl Config {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+
+    /// When mail may next be sent, or `None` if it may be sent now.
+    pub fn held_until<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Utc>> {
+        let local = now.naive_local();
+        if self.contains(local.time()) {
+            return None;
+        }
+        let mut start = local.date().and_time(self.start);
+        if start <= local {
+            start += Duration::days(1);
+        }
+        // Clocks skip over times when daylight saving time starts.
+        let start = now
+            .timezone()
+            .from_local_datetime(&start)
+            .earliest()
+            .or_else(|| {
+                now.timezone()
+                    .from_local_datetime(&(start + Duration::hours(1)))
+                    .earliest()
+            })?;
+        Some(start.with_timezone(&Utc))
+    }
+}
+
+// Serde passes fields by reference.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn serialize_time<S: Serializer>(
+    time: &NaiveTime,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(&time.format("%H:%M"))
+}
+
+pub fn deserialize_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<NaiveTime, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&s, "%H:%M")
+        .map_err(|_| de::Error::custom(format!("invalid time {s:?}, expected \"HH:MM\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    fn window(start: &str, end: &str) -> Config {
+        Config {
+            start: NaiveTime::parse_from_str(start, "%H:%M").expect("invalid time"),
+            end: NaiveTime::parse_from_str(end, "%H:%M").expect("invalid time"),
+        }
+    }
+
+    fn local(hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        FixedOffset::east(2 * 3600)
+            .ymd(2019, 11, 7)
+            .and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn held_until_next_start() {
+        let window = window("07:00", "22:00");
+        assert_eq!(window.held_until(&local(12, 0)), None);
+        assert_eq!(window.held_until(&local(7, 0)), None);
+        assert_eq!(
+            window.held_until(&local(3, 0)),
+            Some(Utc.ymd(2019, 11, 7).and_hms(5, 0, 0))
+        );
+        assert_eq!(
+            window.held_until(&local(22, 0)),
+            Some(Utc.ymd(2019, 11, 8).and_hms(5, 0, 0))
+        );
+    }
+
+    #[test]
+    fn held_until_with_window_past_midnight() {
+        let window = window("22:00", "02:00");
+        assert_eq!(window.held_until(&local(23, 0)), None);
+        assert_eq!(window.held_until(&local(1, 59)), None);
+        assert_eq!(
+            window.held_until(&local(2, 0)),
+            Some(Utc.ymd(2019, 11, 7).and_hms(20, 0, 0))
+        );
+    }
+}